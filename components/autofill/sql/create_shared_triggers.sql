@@ -48,3 +48,25 @@ BEGIN
     INSERT INTO credit_cards_tombstones(guid, time_deleted)
     VALUES (OLD.guid, now());
 END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS bank_accounts_data_afterinsert_trigger
+AFTER INSERT ON bank_accounts_data
+FOR EACH ROW WHEN NEW.guid IN (SELECT guid FROM bank_accounts_tombstones)
+BEGIN
+    SELECT RAISE(FAIL, 'guid exists in `bank_accounts_tombstones`');
+END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS bank_accounts_tombstones_afterinsert_trigger
+AFTER INSERT ON bank_accounts_tombstones
+WHEN NEW.guid IN (SELECT guid FROM bank_accounts_data)
+BEGIN
+    SELECT RAISE(FAIL, 'guid exists in `bank_accounts_data`');
+END;
+
+CREATE TEMP TRIGGER IF NOT EXISTS bank_accounts_tombstones_create_trigger
+AFTER DELETE ON bank_accounts_data
+WHEN OLD.guid IN (SELECT guid FROM bank_accounts_mirror)
+BEGIN
+    INSERT INTO bank_accounts_tombstones(guid, time_deleted)
+    VALUES (OLD.guid, now());
+END;
@@ -0,0 +1,35 @@
+-- This Source Code Form is subject to the terms of the Mozilla Public
+-- License, v. 2.0. If a copy of the MPL was not distributed with this
+-- file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+-- Adds the bank account (IBAN) tables.
+
+CREATE TABLE IF NOT EXISTS bank_accounts_data (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    nickname            TEXT NOT NULL,
+    -- Encrypted IBAN, stored as a JWE, exactly like `credit_cards_data.cc_number_enc`.
+    -- IBANs are at most 34 chars, and a JWE is always longer than that, so the
+    -- CHECK guards against accidentally storing an unencrypted IBAN.
+    -- A blank value indicates the IBAN was scrubbed because we lost the key.
+    iban_enc            TEXT NOT NULL CHECK(length(iban_enc) > 34 OR iban_enc == ''),
+    -- last 4 chars unencrypted, for display.
+    iban_last_4         TEXT NOT NULL CHECK(length(iban_last_4) <= 4),
+
+    time_created        INTEGER NOT NULL,
+    time_last_used      INTEGER,
+    time_last_modified  INTEGER NOT NULL,
+    times_used          INTEGER NOT NULL,
+
+    sync_change_counter INTEGER NOT NULL
+);
+
+-- As for credit-cards, the entire payload is encrypted with the local key.
+CREATE TABLE IF NOT EXISTS bank_accounts_mirror (
+    guid                TEXT NOT NULL PRIMARY KEY CHECK(length(guid) != 0),
+    payload             TEXT NOT NULL CHECK(length(payload) != 0)
+);
+
+CREATE TABLE IF NOT EXISTS bank_accounts_tombstones (
+    guid            TEXT PRIMARY KEY CHECK(length(guid) != 0),
+    time_deleted    INTEGER NOT NULL
+) WITHOUT ROWID;
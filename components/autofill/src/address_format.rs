@@ -0,0 +1,621 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Locale-aware address helpers, driven by per-country metadata in the style of
+// libaddressinput (https://github.com/google/libaddressinput).
+//
+// Addresses are stored exactly as the user entered them - these are used to
+// compare addresses which are formatted differently (eg, when looking for
+// duplicates) and to render an address for display. They are also registered
+// as SQL functions - see `db::schema::define_functions()`.
+//
+// We only carry metadata for the countries in `COUNTRIES`, rather than all of
+// libaddressinput's data. For any other country, the country name, region and
+// postal code are only trimmed (and the postal code upper-cased), addresses
+// are rendered with `DEFAULT_FMT`, and only phone numbers which are already
+// international - starting with "+" or `DEFAULT_EXIT_PREFIX` - are converted
+// to E.164.
+
+use crate::db::models::address::UpdatableAddressFields;
+use std::ops::RangeInclusive;
+
+struct CountryMetadata {
+    // The ISO 3166-1 alpha-2 code, which is what we use as the canonical
+    // `country`.
+    code: &'static str,
+    // Other names the country is commonly entered as.
+    names: &'static [&'static str],
+    // The libaddressinput display format. `%N` is the name, `%O` the
+    // organization, `%A` the street address, `%D` address-level3, `%C`
+    // address-level2, `%S` address-level1, `%Z` the postal code and `%n` a new
+    // line.
+    fmt: &'static str,
+    // The fields which are upper-cased for display.
+    upper: &'static str,
+    // The formats a postal code can take. `9` is a digit and `A` a letter; any
+    // other character is a separator which is optional in the input.
+    postal_codes: &'static [&'static str],
+    // The address-level1 regions, as (key, name) pairs. The key, which is what
+    // we use as the canonical region, may appear multiple times if the region
+    // has more than one name.
+    regions: &'static [(&'static str, &'static str)],
+    calling_code: &'static str,
+    // The prefix used when dialing a national number from within the country.
+    national_prefix: &'static str,
+    // The prefix used when dialing an international number from within the
+    // country - eg, "011" in North America and "00" in most of Europe.
+    exit_prefix: &'static str,
+    // The number of digits in a national number, without the prefix.
+    national_lengths: RangeInclusive<usize>,
+}
+
+// Used when we don't have metadata for the country. It includes every field
+// rather than risk hiding one which matters for the country.
+const DEFAULT_FMT: &str = "%N%n%O%n%A%n%D%n%C%n%S %Z";
+
+// Used when we don't have metadata for the country. This is the ITU
+// recommended prefix, which most countries use.
+const DEFAULT_EXIT_PREFIX: &str = "00";
+
+const COUNTRIES: &[CountryMetadata] = &[
+    CountryMetadata {
+        code: "US",
+        names: &["United States", "United States of America", "USA"],
+        fmt: "%N%n%O%n%A%n%C, %S %Z",
+        upper: "CS",
+        postal_codes: &["99999", "99999-9999"],
+        regions: &[
+            ("AL", "Alabama"),
+            ("AK", "Alaska"),
+            ("AS", "American Samoa"),
+            ("AZ", "Arizona"),
+            ("AR", "Arkansas"),
+            ("AA", "Armed Forces (AA)"),
+            ("AE", "Armed Forces (AE)"),
+            ("AP", "Armed Forces (AP)"),
+            ("CA", "California"),
+            ("CO", "Colorado"),
+            ("CT", "Connecticut"),
+            ("DE", "Delaware"),
+            ("DC", "District of Columbia"),
+            ("DC", "Washington DC"),
+            ("FL", "Florida"),
+            ("GA", "Georgia"),
+            ("GU", "Guam"),
+            ("HI", "Hawaii"),
+            ("ID", "Idaho"),
+            ("IL", "Illinois"),
+            ("IN", "Indiana"),
+            ("IA", "Iowa"),
+            ("KS", "Kansas"),
+            ("KY", "Kentucky"),
+            ("LA", "Louisiana"),
+            ("ME", "Maine"),
+            ("MH", "Marshall Islands"),
+            ("MD", "Maryland"),
+            ("MA", "Massachusetts"),
+            ("MI", "Michigan"),
+            ("FM", "Micronesia"),
+            ("MN", "Minnesota"),
+            ("MS", "Mississippi"),
+            ("MO", "Missouri"),
+            ("MT", "Montana"),
+            ("NE", "Nebraska"),
+            ("NV", "Nevada"),
+            ("NH", "New Hampshire"),
+            ("NJ", "New Jersey"),
+            ("NM", "New Mexico"),
+            ("NY", "New York"),
+            ("NC", "North Carolina"),
+            ("ND", "North Dakota"),
+            ("MP", "Northern Mariana Islands"),
+            ("OH", "Ohio"),
+            ("OK", "Oklahoma"),
+            ("OR", "Oregon"),
+            ("PW", "Palau"),
+            ("PA", "Pennsylvania"),
+            ("PR", "Puerto Rico"),
+            ("RI", "Rhode Island"),
+            ("SC", "South Carolina"),
+            ("SD", "South Dakota"),
+            ("TN", "Tennessee"),
+            ("TX", "Texas"),
+            ("UT", "Utah"),
+            ("VT", "Vermont"),
+            ("VI", "Virgin Islands"),
+            ("VA", "Virginia"),
+            ("WA", "Washington"),
+            ("WV", "West Virginia"),
+            ("WI", "Wisconsin"),
+            ("WY", "Wyoming"),
+        ],
+        calling_code: "1",
+        national_prefix: "1",
+        exit_prefix: "011",
+        national_lengths: 10..=10,
+    },
+    CountryMetadata {
+        code: "CA",
+        names: &["Canada"],
+        fmt: "%N%n%O%n%A%n%C %S %Z",
+        upper: "ACSZ",
+        postal_codes: &["A9A 9A9"],
+        regions: &[
+            ("AB", "Alberta"),
+            ("BC", "British Columbia"),
+            ("MB", "Manitoba"),
+            ("NB", "New Brunswick"),
+            ("NL", "Newfoundland and Labrador"),
+            ("NT", "Northwest Territories"),
+            ("NS", "Nova Scotia"),
+            ("NU", "Nunavut"),
+            ("ON", "Ontario"),
+            ("PE", "Prince Edward Island"),
+            ("QC", "Quebec"),
+            ("QC", "Québec"),
+            ("SK", "Saskatchewan"),
+            ("YT", "Yukon"),
+        ],
+        calling_code: "1",
+        national_prefix: "1",
+        exit_prefix: "011",
+        national_lengths: 10..=10,
+    },
+    CountryMetadata {
+        code: "AU",
+        names: &["Australia"],
+        fmt: "%O%n%N%n%A%n%C %S %Z",
+        upper: "CS",
+        postal_codes: &["9999"],
+        regions: &[
+            ("ACT", "Australian Capital Territory"),
+            ("NSW", "New South Wales"),
+            ("NT", "Northern Territory"),
+            ("QLD", "Queensland"),
+            ("SA", "South Australia"),
+            ("TAS", "Tasmania"),
+            ("VIC", "Victoria"),
+            ("WA", "Western Australia"),
+        ],
+        calling_code: "61",
+        national_prefix: "0",
+        exit_prefix: "0011",
+        national_lengths: 9..=9,
+    },
+    CountryMetadata {
+        code: "GB",
+        names: &["United Kingdom", "Great Britain", "UK"],
+        fmt: "%N%n%O%n%A%n%C%n%Z",
+        upper: "CZ",
+        postal_codes: &[
+            "A9 9AA", "A99 9AA", "AA9 9AA", "AA99 9AA", "A9A 9AA", "AA9A 9AA",
+        ],
+        regions: &[],
+        calling_code: "44",
+        national_prefix: "0",
+        exit_prefix: "00",
+        national_lengths: 9..=10,
+    },
+    CountryMetadata {
+        code: "DE",
+        names: &["Germany", "Deutschland"],
+        fmt: "%N%n%O%n%A%n%Z %C",
+        upper: "",
+        postal_codes: &["99999"],
+        regions: &[],
+        calling_code: "49",
+        national_prefix: "0",
+        exit_prefix: "00",
+        national_lengths: 6..=13,
+    },
+    CountryMetadata {
+        code: "FR",
+        names: &["France"],
+        fmt: "%O%n%N%n%A%n%Z %C",
+        upper: "C",
+        postal_codes: &["99999"],
+        regions: &[],
+        calling_code: "33",
+        national_prefix: "0",
+        exit_prefix: "00",
+        national_lengths: 9..=9,
+    },
+];
+
+// Returns a key for comparing names, ignoring case, punctuation and
+// whitespace - eg, "new york" and "New-York" are the same.
+fn name_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn find_country(country: &str) -> Option<&'static CountryMetadata> {
+    let key = name_key(country);
+    if key.is_empty() {
+        return None;
+    }
+    COUNTRIES.iter().find(|meta| {
+        name_key(meta.code) == key || meta.names.iter().any(|name| name_key(name) == key)
+    })
+}
+
+/// Returns the ISO 3166-1 code for the country, if it's one we have metadata
+/// for, or the country trimmed of whitespace otherwise.
+pub fn normalize_country(country: String) -> String {
+    match find_country(&country) {
+        Some(meta) => meta.code.to_string(),
+        None => country.trim().to_string(),
+    }
+}
+
+/// Returns the canonical key for a region (ie, `address_level1`) in the
+/// country - eg, "California" in the US is "CA". Regions we don't know about
+/// are returned trimmed of whitespace.
+pub fn normalize_region(country: String, region: String) -> String {
+    let key = name_key(&region);
+    find_country(&country)
+        .and_then(|meta| {
+            meta.regions
+                .iter()
+                .find(|(code, name)| name_key(code) == key || name_key(name) == key)
+        })
+        .map(|(code, _)| code.to_string())
+        .unwrap_or_else(|| region.trim().to_string())
+}
+
+// Matches the compact (upper-case and alphanumeric only) postal code against
+// a format, returning the postal code with the format's separators if it
+// matches.
+fn match_postal_code(compact: &str, format: &str) -> Option<String> {
+    let mut chars = compact.chars();
+    let mut result = String::new();
+    for f in format.chars() {
+        match f {
+            '9' | 'A' => {
+                let c = chars.next()?;
+                if (f == '9' && !c.is_ascii_digit()) || (f == 'A' && !c.is_ascii_uppercase()) {
+                    return None;
+                }
+                result.push(c);
+            }
+            sep => result.push(sep),
+        }
+    }
+    // The postal code must be entirely consumed by the format.
+    chars.next().is_none().then_some(result)
+}
+
+/// Returns the postal code in the country's canonical format - eg,
+/// "k1a0b1" in Canada is "K1A 0B1". Postal codes which don't match any of the
+/// country's formats, or for countries we don't know about, are upper-cased
+/// with their whitespace collapsed.
+pub fn normalize_postal_code(country: String, postal_code: String) -> String {
+    let upper = postal_code
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase();
+    let Some(meta) = find_country(&country) else {
+        return upper;
+    };
+    let compact: String = upper.chars().filter(|c| c.is_alphanumeric()).collect();
+    meta.postal_codes
+        .iter()
+        .find_map(|format| match_postal_code(&compact, format))
+        .unwrap_or(upper)
+}
+
+// Returns the E.164 form of the phone number, or None if it can't be
+// determined.
+fn tel_to_e164(country: &str, tel: &str) -> Option<String> {
+    let trimmed = tel.trim();
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    // Anything other than digits, whitespace and the usual punctuation means
+    // it's not a phone number we understand (eg, it has an extension).
+    if digits.is_empty()
+        || !trimmed
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_whitespace() || "+-.()/".contains(c))
+    {
+        return None;
+    }
+    let meta = find_country(country);
+    let exit_prefix = meta.map_or(DEFAULT_EXIT_PREFIX, |meta| meta.exit_prefix);
+    let international = if trimmed.starts_with('+') {
+        digits
+    } else if let Some(rest) = digits.strip_prefix(exit_prefix) {
+        rest.to_string()
+    } else {
+        let meta = meta?;
+        let national = match digits.strip_prefix(meta.national_prefix) {
+            Some(rest)
+                if !meta.national_prefix.is_empty()
+                    && meta.national_lengths.contains(&rest.len()) =>
+            {
+                rest
+            }
+            _ => &digits,
+        };
+        if !meta.national_lengths.contains(&national.len()) {
+            return None;
+        }
+        format!("{}{}", meta.calling_code, national)
+    };
+    // E.164 numbers are at most 15 digits, and no country has numbers much
+    // shorter than this.
+    (8..=15)
+        .contains(&international.len())
+        .then(|| format!("+{international}"))
+}
+
+/// Splits the phone number into its country calling code (eg, "+1") and the
+/// national number, or returns None if it can't be converted to E.164 or we
+/// don't know the calling code.
+pub(crate) fn split_tel(country: &str, tel: &str) -> Option<(String, String)> {
+    let e164 = tel_to_e164(country, tel)?;
+    let meta = find_country(country)?;
+    let national = e164.strip_prefix('+')?.strip_prefix(meta.calling_code)?;
+    Some((format!("+{}", meta.calling_code), national.to_string()))
+}
+
+/// Returns the phone number in E.164 format - eg, "(555) 555-0100" in the US
+/// is "+15555550100". Numbers we can't convert are returned trimmed of
+/// whitespace.
+pub fn normalize_tel(country: String, tel: String) -> String {
+    tel_to_e164(&country, &tel).unwrap_or_else(|| tel.trim().to_string())
+}
+
+/// Renders the address in the display format of its country, as multiple
+/// lines. The country itself is not included - consumers should add it if it
+/// differs from the user's own.
+pub fn format_address(address: UpdatableAddressFields) -> String {
+    let meta = find_country(&address.country);
+    let fmt = meta.map(|m| m.fmt).unwrap_or(DEFAULT_FMT);
+    let upper = meta.map(|m| m.upper).unwrap_or_default();
+    let field = |code: char| -> String {
+        let value = match code {
+            'N' => &address.name,
+            'O' => &address.organization,
+            'A' => &address.street_address,
+            'D' => &address.address_level3,
+            'C' => &address.address_level2,
+            'S' => &address.address_level1,
+            'Z' => &address.postal_code,
+            _ => return String::new(),
+        };
+        let value = value.trim();
+        if upper.contains(code) {
+            value.to_uppercase()
+        } else {
+            value.to_string()
+        }
+    };
+
+    let mut lines = Vec::new();
+    for line_fmt in fmt.split("%n") {
+        // A field is preceded by the separator immediately before it, but only
+        // when there's a non-empty field on both sides of it - eg, "%C, %S %Z"
+        // without a region is rendered as "%C %Z".
+        let mut line = String::new();
+        let mut separator = String::new();
+        let mut after_field = false;
+        let mut chars = line_fmt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                if after_field {
+                    separator.clear();
+                    after_field = false;
+                }
+                separator.push(c);
+                continue;
+            }
+            after_field = true;
+            let value = chars.next().map(field).unwrap_or_default();
+            if value.is_empty() {
+                continue;
+            }
+            if !line.is_empty() {
+                line.push_str(&separator);
+            }
+            separator.clear();
+            line.push_str(&value);
+        }
+        // The street address is multi-line.
+        lines.extend(
+            line.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string),
+        );
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_country() {
+        assert_eq!(normalize_country("US".to_string()), "US");
+        assert_eq!(normalize_country("us".to_string()), "US");
+        assert_eq!(normalize_country("United States".to_string()), "US");
+        assert_eq!(normalize_country("U.S.A.".to_string()), "US");
+        assert_eq!(normalize_country("Deutschland".to_string()), "DE");
+        assert_eq!(normalize_country(" Narnia ".to_string()), "Narnia");
+        assert_eq!(normalize_country("".to_string()), "");
+    }
+
+    #[test]
+    fn test_normalize_region() {
+        assert_eq!(
+            normalize_region("US".to_string(), "California".to_string()),
+            "CA"
+        );
+        assert_eq!(normalize_region("US".to_string(), "ca".to_string()), "CA");
+        assert_eq!(
+            normalize_region("United States".to_string(), "new york".to_string()),
+            "NY"
+        );
+        assert_eq!(
+            normalize_region("CA".to_string(), "Québec".to_string()),
+            "QC"
+        );
+        assert_eq!(
+            normalize_region("AU".to_string(), "Victoria".to_string()),
+            "VIC"
+        );
+        // "CA" isn't a region in Canada.
+        assert_eq!(normalize_region("CA".to_string(), "CA".to_string()), "CA");
+        assert_eq!(
+            normalize_region("CA".to_string(), "California".to_string()),
+            "California"
+        );
+        // Unknown country.
+        assert_eq!(
+            normalize_region("".to_string(), " California ".to_string()),
+            "California"
+        );
+    }
+
+    #[test]
+    fn test_normalize_postal_code() {
+        for (country, postal_code, expected) in [
+            ("US", "90210", "90210"),
+            ("US", " 90210 1234", "90210-1234"),
+            ("US", "902101234", "90210-1234"),
+            ("CA", "k1a0b1", "K1A 0B1"),
+            ("CA", "K1A-0B1", "K1A 0B1"),
+            ("GB", "sw1a1aa", "SW1A 1AA"),
+            ("GB", "M1 1AE", "M1 1AE"),
+            ("GB", "ec1a 1bb", "EC1A 1BB"),
+            ("DE", "10115", "10115"),
+            // Doesn't match a format for the country.
+            ("US", "9021", "9021"),
+            ("CA", "k1a  0b", "K1A 0B"),
+            // Unknown country.
+            ("", "k1a  0b1", "K1A 0B1"),
+        ] {
+            assert_eq!(
+                normalize_postal_code(country.to_string(), postal_code.to_string()),
+                expected,
+                "unexpected postal code for {postal_code} in {country}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_tel() {
+        for (country, tel, expected) in [
+            ("US", "(555) 555-0100", "+15555550100"),
+            ("US", "1-555-555-0100", "+15555550100"),
+            ("US", "555.555.0100", "+15555550100"),
+            ("CA", "555 555 0100", "+15555550100"),
+            ("GB", "020 7946 0018", "+442079460018"),
+            ("DE", "030 123456", "+4930123456"),
+            ("FR", "01 23 45 67 89", "+33123456789"),
+            ("AU", "(02) 9876 5432", "+61298765432"),
+            // Already international.
+            ("US", "+44 20 7946 0018", "+442079460018"),
+            ("", "+1 555-555-0100", "+15555550100"),
+            // Dialed with the country's exit prefix.
+            ("US", "011 44 20 7946 0018", "+442079460018"),
+            ("CA", "011 44 20 7946 0018", "+442079460018"),
+            ("AU", "0011 44 20 7946 0018", "+442079460018"),
+            ("GB", "00 1 555-555-0100", "+15555550100"),
+            ("DE", "0044 20 7946 0018", "+442079460018"),
+            ("", "0044 20 7946 0018", "+442079460018"),
+            ("XX", "0044 20 7946 0018", "+442079460018"),
+            // Can't be converted.
+            ("US", "0044 20 7946 0018", "0044 20 7946 0018"),
+            ("US", "555-0100", "555-0100"),
+            ("", "555 555 0100", "555 555 0100"),
+            ("US", "555-555-0100 ext. 12", "555-555-0100 ext. 12"),
+            ("US", " ", ""),
+        ] {
+            assert_eq!(
+                normalize_tel(country.to_string(), tel.to_string()),
+                expected,
+                "unexpected tel for {tel} in {country}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_tel() {
+        assert_eq!(
+            split_tel("US", "(555) 555-0100"),
+            Some(("+1".to_string(), "5555550100".to_string()))
+        );
+        assert_eq!(
+            split_tel("GB", "+44 20 7946 0018"),
+            Some(("+44".to_string(), "2079460018".to_string()))
+        );
+        // A number from another country.
+        assert_eq!(split_tel("US", "+44 20 7946 0018"), None);
+        assert_eq!(split_tel("", "+1 555-555-0100"), None);
+    }
+
+    #[test]
+    fn test_format_address() {
+        let address = UpdatableAddressFields {
+            name: "Jane Doe".to_string(),
+            organization: "Mozilla".to_string(),
+            street_address: "123 Main St\nApt 4".to_string(),
+            address_level2: "Springfield".to_string(),
+            address_level1: "il".to_string(),
+            postal_code: "62701".to_string(),
+            country: "US".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_address(address.clone()),
+            "Jane Doe\nMozilla\n123 Main St\nApt 4\nSPRINGFIELD, IL 62701"
+        );
+
+        // Separators are dropped along with missing fields.
+        assert_eq!(
+            format_address(UpdatableAddressFields {
+                organization: "".to_string(),
+                address_level2: "".to_string(),
+                ..address.clone()
+            }),
+            "Jane Doe\n123 Main St\nApt 4\nIL 62701"
+        );
+        assert_eq!(
+            format_address(UpdatableAddressFields {
+                address_level1: "".to_string(),
+                ..address.clone()
+            }),
+            "Jane Doe\nMozilla\n123 Main St\nApt 4\nSPRINGFIELD 62701"
+        );
+
+        assert_eq!(
+            format_address(UpdatableAddressFields {
+                name: "Erika Mustermann".to_string(),
+                organization: "".to_string(),
+                street_address: "Musterstraße 1".to_string(),
+                address_level2: "Berlin".to_string(),
+                address_level1: "".to_string(),
+                postal_code: "10115".to_string(),
+                country: "Germany".to_string(),
+                ..Default::default()
+            }),
+            "Erika Mustermann\nMusterstraße 1\n10115 Berlin"
+        );
+
+        // Unknown countries include every field.
+        assert_eq!(
+            format_address(UpdatableAddressFields {
+                country: "".to_string(),
+                address_level3: "Downtown".to_string(),
+                ..address
+            }),
+            "Jane Doe\nMozilla\n123 Main St\nApt 4\nDowntown\nSpringfield\nil 62701"
+        );
+    }
+}
@@ -0,0 +1,313 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Credit-card number helpers.
+//
+// The storage API only ever sees the encrypted card number, so the helpers
+// which need the number itself are exposed to consumers so they can validate
+// the card and derive `cc_number_last_4` and `cc_type` before encrypting. The
+// store then normalizes and validates the fields it can see - see
+// `db::credit_cards::normalize_credit_card_fields()`.
+
+use std::ops::RangeInclusive;
+
+// The card networks we support. These are the values used for `cc_type` as
+// defined in the link below
+// (https://searchfox.org/mozilla-central/rev/7ef5cefd0468b8f509efe38e0212de2398f4c8b3/toolkit/modules/CreditCard.jsm#9-22)
+const SUPPORTED_NETWORKS: &[&str] = &[
+    "amex",
+    "cartebancaire",
+    "diners",
+    "discover",
+    "jcb",
+    "mastercard",
+    "mir",
+    "unionpay",
+    "visa",
+];
+
+// Other names for the networks above, in the form returned by `network_key()`.
+const NETWORK_ALIASES: &[(&str, &str)] = &[
+    ("americanexpress", "amex"),
+    ("dinersclub", "diners"),
+    ("chinaunionpay", "unionpay"),
+];
+
+struct IinRange {
+    network: &'static str,
+    // The range of prefixes (the leading digits of the card number) issued to
+    // the network. The number of digits compared is the number of digits in
+    // `start`.
+    start: u32,
+    end: u32,
+    lengths: RangeInclusive<usize>,
+}
+
+const fn iin(
+    network: &'static str,
+    start: u32,
+    end: u32,
+    lengths: RangeInclusive<usize>,
+) -> IinRange {
+    IinRange {
+        network,
+        start,
+        end,
+        lengths,
+    }
+}
+
+// Some of these ranges overlap (eg, Carte Bancaire cards are co-branded Visa
+// cards), so the more specific ranges must come first.
+const IIN_RANGES: &[IinRange] = &[
+    iin("amex", 34, 34, 15..=15),
+    iin("amex", 37, 37, 15..=15),
+    iin("cartebancaire", 4035, 4035, 16..=16),
+    iin("cartebancaire", 4360, 4360, 16..=16),
+    iin("diners", 300, 305, 14..=19),
+    iin("diners", 3095, 3095, 14..=19),
+    iin("diners", 36, 36, 14..=19),
+    iin("diners", 38, 39, 14..=19),
+    iin("discover", 6011, 6011, 16..=19),
+    iin("discover", 622126, 622925, 16..=19),
+    iin("discover", 644, 649, 16..=19),
+    iin("discover", 65, 65, 16..=19),
+    iin("jcb", 3528, 3589, 16..=19),
+    iin("mastercard", 2221, 2720, 16..=16),
+    iin("mastercard", 51, 55, 16..=16),
+    iin("mir", 2200, 2204, 16..=19),
+    iin("unionpay", 62, 62, 16..=19),
+    iin("unionpay", 81, 81, 16..=19),
+    iin("visa", 4, 4, 13..=19),
+];
+
+/// Returns the card number with the spaces and dashes it is commonly written
+/// with removed.
+pub fn normalize_credit_card_number(number: String) -> String {
+    number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+/// Checks the card number is a plausible length and that its check digit is
+/// correct, as per the Luhn algorithm.
+pub fn is_valid_credit_card_number(number: String) -> bool {
+    let number = normalize_credit_card_number(number);
+    if !(12..=19).contains(&number.len()) || !number.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    // Starting from the check digit, double every second digit, subtracting 9
+    // if the result is more than 9. The number is valid if the sum of all the
+    // digits is a multiple of 10.
+    let sum: u32 = number
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| match (i % 2 == 1, d * 2) {
+            (false, _) => d,
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Returns the network which issued the card (one of the `cc_type` values),
+/// or an empty string if it's not one we know about.
+pub fn credit_card_network(number: String) -> String {
+    let number = normalize_credit_card_number(number);
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return String::new();
+    }
+    IIN_RANGES
+        .iter()
+        .find(|range| {
+            let prefix_len = range.start.to_string().len();
+            range.lengths.contains(&number.len())
+                && number
+                    .get(..prefix_len)
+                    .and_then(|prefix| prefix.parse::<u32>().ok())
+                    .is_some_and(|prefix| (range.start..=range.end).contains(&prefix))
+        })
+        .map(|range| range.network.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the value to store in `cc_number_last_4` for the card number.
+pub fn credit_card_last_4(number: String) -> String {
+    let number = normalize_credit_card_number(number);
+    let start = number.len().saturating_sub(4);
+    // The number might not be ascii, so find a char boundary.
+    let start = (start..=number.len())
+        .find(|i| number.is_char_boundary(*i))
+        .unwrap_or(number.len());
+    number[start..].to_string()
+}
+
+// Returns a key for the network name, ignoring case, whitespace and
+// punctuation - eg, "American Express" and "american-express" are the same.
+fn network_key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns the canonical `cc_type` for a card network name. Networks we don't
+/// know about are kept, lowercased, as other clients may know about them.
+pub(crate) fn normalize_credit_card_type(cc_type: &str) -> String {
+    let key = network_key(cc_type);
+    SUPPORTED_NETWORKS
+        .iter()
+        .copied()
+        .find(|network| *network == key)
+        .or_else(|| {
+            NETWORK_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == key)
+                .map(|(_, network)| *network)
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| cc_type.trim().to_lowercase())
+}
+
+/// Normalizes a card's expiry year, expanding 2-digit years, or returns None
+/// if it can't be a valid year. 0 means the year isn't known.
+pub(crate) fn normalize_credit_card_exp_year(year: i64) -> Option<i64> {
+    match year {
+        0 => Some(0),
+        1..=99 => Some(2000 + year),
+        1000..=9999 => Some(year),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_credit_card_number() {
+        assert_eq!(
+            normalize_credit_card_number("4111 1111-1111 1111".to_string()),
+            "4111111111111111"
+        );
+        assert_eq!(normalize_credit_card_number("".to_string()), "");
+    }
+
+    #[test]
+    fn test_valid_credit_card_numbers() {
+        for number in [
+            "4111111111111111",
+            "4111 1111 1111 1111",
+            "4222222222222",
+            "5555-5555-5555-4444",
+            "2223000048400011",
+            "378282246310005",
+            "6011111111111117",
+            "3530111333300000",
+            "30569309025904",
+            "6200000000000005",
+        ] {
+            assert!(
+                is_valid_credit_card_number(number.to_string()),
+                "{number} should be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_credit_card_numbers() {
+        for number in [
+            "",
+            // Bad check digit.
+            "4111111111111112",
+            // Too short and too long.
+            "41111111117",
+            "41111111111111111111",
+            // Not numeric.
+            "4111x11111111111",
+            "4111.1111.1111.1111",
+        ] {
+            assert!(
+                !is_valid_credit_card_number(number.to_string()),
+                "{number} should be invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn test_credit_card_network() {
+        for (number, expected) in [
+            ("4111111111111111", "visa"),
+            ("4222222222222", "visa"),
+            ("4035 5010 0000 0008", "cartebancaire"),
+            ("5555555555554444", "mastercard"),
+            ("2223000048400011", "mastercard"),
+            ("378282246310005", "amex"),
+            ("341111111111111", "amex"),
+            ("6011111111111117", "discover"),
+            ("6221260000000000", "discover"),
+            ("6200000000000005", "unionpay"),
+            ("3530111333300000", "jcb"),
+            ("30569309025904", "diners"),
+            ("3852000002323700", "diners"),
+            ("2200000000000004", "mir"),
+            // Right prefix, wrong length.
+            ("37828224631000", ""),
+            ("555555555555444", ""),
+            // Unknown prefix.
+            ("9111111111111111", ""),
+            ("", ""),
+            ("abc", ""),
+        ] {
+            assert_eq!(
+                credit_card_network(number.to_string()),
+                expected,
+                "unexpected network for {number}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_credit_card_last_4() {
+        assert_eq!(
+            credit_card_last_4("4111 1111 1111 1234".to_string()),
+            "1234"
+        );
+        assert_eq!(credit_card_last_4("123".to_string()), "123");
+        assert_eq!(credit_card_last_4("".to_string()), "");
+    }
+
+    #[test]
+    fn test_normalize_credit_card_type() {
+        assert_eq!(normalize_credit_card_type("visa"), "visa");
+        assert_eq!(normalize_credit_card_type("VISA"), "visa");
+        assert_eq!(normalize_credit_card_type("MasterCard"), "mastercard");
+        assert_eq!(normalize_credit_card_type("Master Card"), "mastercard");
+        assert_eq!(normalize_credit_card_type("American Express"), "amex");
+        assert_eq!(normalize_credit_card_type("Diners Club"), "diners");
+        assert_eq!(normalize_credit_card_type("Union-Pay"), "unionpay");
+        assert_eq!(
+            normalize_credit_card_type("Carte Bancaire"),
+            "cartebancaire"
+        );
+        assert_eq!(normalize_credit_card_type(" Maestro "), "maestro");
+        assert_eq!(normalize_credit_card_type(""), "");
+    }
+
+    #[test]
+    fn test_normalize_credit_card_exp_year() {
+        assert_eq!(normalize_credit_card_exp_year(0), Some(0));
+        assert_eq!(normalize_credit_card_exp_year(7), Some(2007));
+        assert_eq!(normalize_credit_card_exp_year(27), Some(2027));
+        assert_eq!(normalize_credit_card_exp_year(2027), Some(2027));
+        assert_eq!(normalize_credit_card_exp_year(-1), None);
+        assert_eq!(normalize_credit_card_exp_year(202), None);
+        assert_eq!(normalize_credit_card_exp_year(20270), None);
+    }
+}
@@ -9,19 +9,49 @@ use crate::db::{
         Metadata,
     },
     schema::{ADDRESS_COMMON_COLS, ADDRESS_COMMON_VALS},
+    DuplicateMatch,
 };
 use crate::error::*;
 
 use rusqlite::{Connection, Transaction};
+use sql_support::ConnExt;
 use sync_guid::Guid;
 use types::Timestamp;
 
+/// Adds a new address, unless it duplicates an existing one - see
+/// `find_duplicate_address()` - in which case the new fields are merged into
+/// the existing address, which is returned, and no new row is inserted.
 pub(crate) fn add_address(
     conn: &Connection,
     new: UpdatableAddressFields,
 ) -> Result<InternalAddress> {
     let tx = conn.unchecked_transaction()?;
     let now = Timestamp::now();
+    if let Some(mut existing) = find_duplicate_address(&tx, &new, DuplicateMatch::Subset)? {
+        // The fields the user just entered win - they may be formatted
+        // differently - but empty fields don't clear the existing ones.
+        for (field, new_field) in [
+            (&mut existing.name, new.name),
+            (&mut existing.organization, new.organization),
+            (&mut existing.street_address, new.street_address),
+            (&mut existing.address_level3, new.address_level3),
+            (&mut existing.address_level2, new.address_level2),
+            (&mut existing.address_level1, new.address_level1),
+            (&mut existing.postal_code, new.postal_code),
+            (&mut existing.country, new.country),
+            (&mut existing.tel, new.tel),
+            (&mut existing.email, new.email),
+        ] {
+            if !new_field.is_empty() {
+                *field = new_field;
+            }
+        }
+        existing.metadata.time_last_modified = now;
+        update_internal_address(&tx, &existing, true)?;
+        existing.metadata.sync_change_counter += 1;
+        tx.commit()?;
+        return Ok(existing);
+    }
 
     // We return an InternalAddress, so set it up first, including the missing
     // fields, before we insert it.
@@ -83,6 +113,88 @@ pub(crate) fn add_internal_address(tx: &Transaction<'_>, address: &InternalAddre
     Ok(())
 }
 
+/// Returns the existing address which `candidate` duplicates, if any. If
+/// multiple addresses match, the most recently used one wins.
+///
+/// The country, region, postal code and phone number are compared after
+/// normalizing them for the country - see `crate::address_format` - so eg, 'CA'
+/// and 'California' are the same region in the US.
+pub(crate) fn find_duplicate_address(
+    conn: &Connection,
+    candidate: &UpdatableAddressFields,
+    matching: DuplicateMatch<'_>,
+) -> Result<Option<InternalAddress>> {
+    let (guid, subset) = match matching {
+        DuplicateMatch::Unsynced(guid) => (Some(guid), false),
+        DuplicateMatch::Subset => (None, true),
+    };
+    let fields = [
+        &candidate.name,
+        &candidate.organization,
+        &candidate.street_address,
+        &candidate.address_level3,
+        &candidate.address_level2,
+        &candidate.address_level1,
+        &candidate.postal_code,
+        &candidate.country,
+        &candidate.tel,
+        &candidate.email,
+    ];
+    // An empty address is a subset of every address, but isn't a duplicate of any.
+    if subset && fields.iter().all(|f| f.is_empty()) {
+        return Ok(None);
+    }
+    // When the existing address has no country, its fields are normalized for
+    // the candidate's country.
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM addresses_data
+        WHERE (:guid IS NULL
+                OR (guid <> :guid AND guid NOT IN (SELECT guid FROM addresses_mirror)))
+            AND ((:subset AND :name = '') OR name == :name)
+            AND ((:subset AND :organization = '') OR organization == :organization)
+            AND ((:subset AND :street_address = '') OR street_address == :street_address)
+            AND ((:subset AND :address_level3 = '') OR address_level3 == :address_level3)
+            AND ((:subset AND :address_level2 = '') OR address_level2 == :address_level2)
+            AND ((:subset AND :address_level1 = '')
+                OR normalize_region(IIF(country = '', :country, country), address_level1)
+                    == normalize_region(IIF(country = '', :country, country), :address_level1))
+            AND ((:subset AND :postal_code = '')
+                OR normalize_postal_code(IIF(country = '', :country, country), postal_code)
+                    == normalize_postal_code(IIF(country = '', :country, country), :postal_code))
+            AND ((:subset AND :country = '')
+                OR normalize_country(country) == normalize_country(:country))
+            AND ((:subset AND :tel = '')
+                OR normalize_tel(IIF(country = '', :country, country), tel)
+                    == normalize_tel(IIF(country = '', :country, country), :tel))
+            AND ((:subset AND :email = '') OR email == :email)
+        ORDER BY time_last_used DESC, times_used DESC, time_created ASC
+        LIMIT 1",
+        common_cols = ADDRESS_COMMON_COLS
+    );
+    conn.try_query_row(
+        &sql,
+        rusqlite::named_params! {
+            ":guid": guid,
+            ":subset": subset,
+            ":name": candidate.name,
+            ":organization": candidate.organization,
+            ":street_address": candidate.street_address,
+            ":address_level3": candidate.address_level3,
+            ":address_level2": candidate.address_level2,
+            ":address_level1": candidate.address_level1,
+            ":postal_code": candidate.postal_code,
+            ":country": candidate.country,
+            ":tel": candidate.tel,
+            ":email": candidate.email,
+        },
+        |row| -> Result<InternalAddress> { Ok(InternalAddress::from_row(row)?) },
+        true,
+    )
+}
+
 pub(crate) fn get_address(conn: &Connection, guid: &Guid) -> Result<InternalAddress> {
     let sql = format!(
         "SELECT
@@ -217,6 +329,55 @@ pub(crate) fn update_internal_address(
     Ok(())
 }
 
+/// Merges the address identified by `other_guid` into the one identified by
+/// `guid`, then deletes `other_guid`.
+///
+/// Fields already set on `guid` win; empty fields are filled in from
+/// `other_guid`. The usage metadata of both records is combined, so the merged
+/// address keeps the earliest creation time, the latest use and the total
+/// number of uses.
+pub(crate) fn merge_addresses(
+    conn: &Connection,
+    guid: &Guid,
+    other_guid: &Guid,
+) -> Result<InternalAddress> {
+    let tx = conn.unchecked_transaction()?;
+    let mut address = get_address(&tx, guid)?;
+    if guid == other_guid {
+        return Ok(address);
+    }
+    let other = get_address(&tx, other_guid)?;
+    for (field, other_field) in [
+        (&mut address.name, other.name),
+        (&mut address.organization, other.organization),
+        (&mut address.street_address, other.street_address),
+        (&mut address.address_level3, other.address_level3),
+        (&mut address.address_level2, other.address_level2),
+        (&mut address.address_level1, other.address_level1),
+        (&mut address.postal_code, other.postal_code),
+        (&mut address.country, other.country),
+        (&mut address.tel, other.tel),
+        (&mut address.email, other.email),
+    ] {
+        if field.is_empty() {
+            *field = other_field;
+        }
+    }
+    address.metadata.combine_local(&other.metadata);
+    address.metadata.time_last_modified = Timestamp::now();
+    update_internal_address(&tx, &address, true)?;
+    tx.execute(
+        "DELETE FROM addresses_data
+            WHERE guid = :guid",
+        rusqlite::named_params! {
+            ":guid": other_guid,
+        },
+    )?;
+    tx.commit()?;
+    // Re-read so the change counter reflects what was written.
+    get_address(conn, guid)
+}
+
 pub(crate) fn delete_address(conn: &Connection, guid: &Guid) -> Result<bool> {
     let tx = conn.unchecked_transaction()?;
 
@@ -670,4 +831,184 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_address_add_duplicate() -> Result<()> {
+        let db = new_mem_db();
+        let fields = UpdatableAddressFields {
+            name: "jane doe".to_string(),
+            street_address: "123 Second Avenue".to_string(),
+            address_level2: "Chicago, IL".to_string(),
+            country: "United States".to_string(),
+            tel: "+13125550100".to_string(),
+            ..UpdatableAddressFields::default()
+        };
+        let saved_address = add_address(&db, fields.clone())?;
+
+        // Adding the exact same address returns the existing one.
+        let dupe = add_address(&db, fields.clone())?;
+        assert_eq!(dupe.guid, saved_address.guid);
+
+        // As does adding a subset of it.
+        let subset = add_address(
+            &db,
+            UpdatableAddressFields {
+                tel: "".to_string(),
+                ..fields.clone()
+            },
+        )?;
+        assert_eq!(subset.guid, saved_address.guid);
+        assert_eq!(subset.tel, "+13125550100");
+        assert_eq!(count_all_addresses(&db)?, 1);
+
+        // A conflicting field means it's a different address.
+        let different = add_address(
+            &db,
+            UpdatableAddressFields {
+                tel: "+13125550199".to_string(),
+                ..fields.clone()
+            },
+        )?;
+        assert_ne!(different.guid, saved_address.guid);
+
+        // And so is a superset.
+        let superset = add_address(
+            &db,
+            UpdatableAddressFields {
+                email: "jane@example.com".to_string(),
+                ..fields
+            },
+        )?;
+        assert_ne!(superset.guid, saved_address.guid);
+
+        // An empty address is never considered a duplicate.
+        add_address(&db, UpdatableAddressFields::default())?;
+        assert_eq!(count_all_addresses(&db)?, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_address_add_duplicate_normalized() -> Result<()> {
+        let db = new_mem_db();
+        let saved_address = add_address(
+            &db,
+            UpdatableAddressFields {
+                name: "jane doe".to_string(),
+                street_address: "3050 South La Brea Ave".to_string(),
+                address_level2: "Los Angeles".to_string(),
+                address_level1: "CA".to_string(),
+                postal_code: "90016".to_string(),
+                country: "US".to_string(),
+                tel: "+13235550100".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+
+        // The same address with the region, country and phone number written
+        // differently is a duplicate.
+        let dupe = add_address(
+            &db,
+            UpdatableAddressFields {
+                name: "jane doe".to_string(),
+                address_level1: "california".to_string(),
+                country: "United States".to_string(),
+                tel: "(323) 555-0100".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        assert_eq!(dupe.guid, saved_address.guid);
+
+        // Without a country, the existing address's country is used.
+        let dupe = add_address(
+            &db,
+            UpdatableAddressFields {
+                address_level1: "California".to_string(),
+                tel: "323.555.0100".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        assert_eq!(dupe.guid, saved_address.guid);
+        assert_eq!(count_all_addresses(&db)?, 1);
+
+        // The fields of the dupes are merged into the existing address, as
+        // they were last written, and it's flagged as changed.
+        let merged = get_address(&db, &saved_address.guid)?;
+        assert_eq!(merged.name, "jane doe");
+        assert_eq!(merged.street_address, "3050 South La Brea Ave");
+        assert_eq!(merged.address_level1, "California");
+        assert_eq!(merged.country, "United States");
+        assert_eq!(merged.tel, "323.555.0100");
+        assert_eq!(merged.metadata.sync_change_counter, 2);
+        assert!(merged.metadata.time_last_modified >= saved_address.metadata.time_last_modified);
+        assert_eq!(dupe.metadata.sync_change_counter, 2);
+
+        // But the same region in a different country is a different address.
+        let different = add_address(
+            &db,
+            UpdatableAddressFields {
+                address_level1: "California".to_string(),
+                country: "Canada".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        assert_ne!(different.guid, saved_address.guid);
+        Ok(())
+    }
+
+    #[test]
+    fn test_address_merge() -> Result<()> {
+        let db = new_mem_db();
+        let address = add_address(
+            &db,
+            UpdatableAddressFields {
+                name: "jane doe".to_string(),
+                street_address: "123 Second Avenue".to_string(),
+                address_level2: "Chicago".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        let other = add_address(
+            &db,
+            UpdatableAddressFields {
+                name: "Jane Doe".to_string(),
+                street_address: "123 Second Ave".to_string(),
+                address_level1: "IL".to_string(),
+                postal_code: "60601".to_string(),
+                ..UpdatableAddressFields::default()
+            },
+        )?;
+        touch(&db, &address.guid)?;
+        touch(&db, &other.guid)?;
+        touch(&db, &other.guid)?;
+
+        let merged = merge_addresses(&db, &address.guid, &other.guid)?;
+        assert_eq!(merged.guid, address.guid);
+        // Fields from the kept address win...
+        assert_eq!(merged.name, "jane doe");
+        assert_eq!(merged.street_address, "123 Second Avenue");
+        assert_eq!(merged.address_level2, "Chicago");
+        // ...and the empty ones are filled in from the other.
+        assert_eq!(merged.address_level1, "IL");
+        assert_eq!(merged.postal_code, "60601");
+        assert_eq!(merged.metadata.times_used, 3);
+        assert_eq!(merged.metadata.time_created, address.metadata.time_created);
+        assert_eq!(merged.metadata.sync_change_counter, 2);
+
+        assert!(matches!(
+            get_address(&db, &other.guid),
+            Err(Error::NoSuchRecord(_))
+        ));
+        assert_eq!(count_all_addresses(&db)?, 1);
+
+        // Merging an address with itself is a no-op.
+        let merged_again = merge_addresses(&db, &address.guid, &address.guid)?;
+        assert_eq!(merged_again.metadata.times_used, 3);
+
+        // Unknown guids are an error.
+        assert!(matches!(
+            merge_addresses(&db, &address.guid, &other.guid),
+            Err(Error::NoSuchRecord(_))
+        ));
+        Ok(())
+    }
 }
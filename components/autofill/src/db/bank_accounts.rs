@@ -0,0 +1,393 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use crate::db::{
+    models::{
+        bank_account::{InternalBankAccount, UpdatableBankAccountFields},
+        Metadata,
+    },
+    schema::{BANK_ACCOUNT_COMMON_COLS, BANK_ACCOUNT_COMMON_VALS},
+};
+use crate::error::*;
+
+use rusqlite::{Connection, Transaction};
+use sync_guid::Guid;
+use types::Timestamp;
+
+pub(crate) fn add_bank_account(
+    conn: &Connection,
+    new_bank_account_fields: UpdatableBankAccountFields,
+) -> Result<InternalBankAccount> {
+    let now = Timestamp::now();
+
+    // We return an InternalBankAccount, so set it up first, including the
+    // missing fields, before we insert it.
+    let bank_account = InternalBankAccount {
+        guid: Guid::random(),
+        nickname: new_bank_account_fields.nickname,
+        iban_enc: new_bank_account_fields.iban_enc,
+        iban_last_4: new_bank_account_fields.iban_last_4,
+        metadata: Metadata {
+            time_created: now,
+            time_last_modified: now,
+            ..Default::default()
+        },
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    add_internal_bank_account(&tx, &bank_account)?;
+    tx.commit()?;
+    Ok(bank_account)
+}
+
+pub(crate) fn add_internal_bank_account(
+    tx: &Transaction<'_>,
+    bank_account: &InternalBankAccount,
+) -> Result<()> {
+    tx.execute(
+        &format!(
+            "INSERT INTO bank_accounts_data (
+                {common_cols},
+                sync_change_counter
+            ) VALUES (
+                {common_vals},
+                :sync_change_counter
+            )",
+            common_cols = BANK_ACCOUNT_COMMON_COLS,
+            common_vals = BANK_ACCOUNT_COMMON_VALS,
+        ),
+        rusqlite::named_params! {
+            ":guid": bank_account.guid,
+            ":nickname": bank_account.nickname,
+            ":iban_enc": bank_account.iban_enc,
+            ":iban_last_4": bank_account.iban_last_4,
+            ":time_created": bank_account.metadata.time_created,
+            ":time_last_used": bank_account.metadata.time_last_used,
+            ":time_last_modified": bank_account.metadata.time_last_modified,
+            ":times_used": bank_account.metadata.times_used,
+            ":sync_change_counter": bank_account.metadata.sync_change_counter,
+        },
+    )?;
+    Ok(())
+}
+
+pub(crate) fn get_bank_account(conn: &Connection, guid: &Guid) -> Result<InternalBankAccount> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM bank_accounts_data
+        WHERE guid = :guid",
+        common_cols = BANK_ACCOUNT_COMMON_COLS
+    );
+
+    conn.query_row(&sql, [guid], InternalBankAccount::from_row)
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => Error::NoSuchRecord(guid.to_string()),
+            e => e.into(),
+        })
+}
+
+pub(crate) fn get_all_bank_accounts(conn: &Connection) -> Result<Vec<InternalBankAccount>> {
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM bank_accounts_data",
+        common_cols = BANK_ACCOUNT_COMMON_COLS
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let bank_accounts = stmt
+        .query_map([], InternalBankAccount::from_row)?
+        .collect::<std::result::Result<Vec<InternalBankAccount>, _>>()?;
+    Ok(bank_accounts)
+}
+
+pub(crate) fn count_all_bank_accounts(conn: &Connection) -> Result<i64> {
+    let sql = "SELECT COUNT(*)
+        FROM bank_accounts_data";
+
+    let mut stmt = conn.prepare(sql)?;
+    let count: i64 = stmt.query_row([], |row| row.get(0))?;
+    Ok(count)
+}
+
+/// Updates just the "updatable" columns - suitable for exposure as a public
+/// API.
+pub(crate) fn update_bank_account(
+    conn: &Connection,
+    guid: &Guid,
+    bank_account: &UpdatableBankAccountFields,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "UPDATE bank_accounts_data
+        SET nickname                    = :nickname,
+            iban_enc                    = :iban_enc,
+            iban_last_4                 = :iban_last_4,
+            time_last_modified          = :time_last_modified,
+            sync_change_counter         = sync_change_counter + 1
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":nickname": bank_account.nickname,
+            ":iban_enc": bank_account.iban_enc,
+            ":iban_last_4": bank_account.iban_last_4,
+            ":time_last_modified": Timestamp::now(),
+            ":guid": guid,
+        },
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Updates all fields including metadata - although the change counter gets
+/// slightly special treatment (eg, when called by Sync we don't want the
+/// change counter incremented).
+pub(crate) fn update_internal_bank_account(
+    tx: &Transaction<'_>,
+    bank_account: &InternalBankAccount,
+    flag_as_changed: bool,
+) -> Result<()> {
+    let change_counter_increment = flag_as_changed as u32; // will be 1 or 0
+    tx.execute(
+        "UPDATE bank_accounts_data
+        SET nickname                    = :nickname,
+            iban_enc                    = :iban_enc,
+            iban_last_4                 = :iban_last_4,
+            time_created                = :time_created,
+            time_last_used              = :time_last_used,
+            time_last_modified          = :time_last_modified,
+            times_used                  = :times_used,
+            sync_change_counter         = sync_change_counter + :change_incr
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":nickname": bank_account.nickname,
+            ":iban_enc": bank_account.iban_enc,
+            ":iban_last_4": bank_account.iban_last_4,
+            ":time_created": bank_account.metadata.time_created,
+            ":time_last_used": bank_account.metadata.time_last_used,
+            ":time_last_modified": bank_account.metadata.time_last_modified,
+            ":times_used": bank_account.metadata.times_used,
+            ":change_incr": change_counter_increment,
+            ":guid": bank_account.guid,
+        },
+    )?;
+    Ok(())
+}
+
+pub(crate) fn delete_bank_account(conn: &Connection, guid: &Guid) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+
+    // execute returns how many rows were affected.
+    let exists = tx.execute(
+        "DELETE FROM bank_accounts_data
+        WHERE guid = :guid",
+        rusqlite::named_params! {
+            ":guid": guid.as_str(),
+        },
+    )? != 0;
+
+    tx.commit()?;
+    Ok(exists)
+}
+
+pub(crate) fn scrub_encrypted_bank_account_data(conn: &Connection) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("UPDATE bank_accounts_data SET iban_enc = ''", [])?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub(crate) fn touch(conn: &Connection, guid: &Guid) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let now_ms = Timestamp::now();
+
+    tx.execute(
+        "UPDATE bank_accounts_data
+        SET time_last_used              = :time_last_used,
+            times_used                  = times_used + 1,
+            sync_change_counter         = sync_change_counter + 1
+        WHERE guid                      = :guid",
+        rusqlite::named_params! {
+            ":time_last_used": now_ms,
+            ":guid": guid.as_str(),
+        },
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::db::test::new_mem_db;
+    use crate::encryption::EncryptorDecryptor;
+    use nss::ensure_initialized;
+    use sync15::bso::IncomingBso;
+
+    pub(crate) fn test_insert_mirror_record(conn: &Connection, bso: IncomingBso) {
+        // As for credit-cards, this stores the raw payload with a cleartext
+        // IBAN rather than the encrypted payload the engine would store.
+        conn.execute(
+            "INSERT INTO bank_accounts_mirror (guid, payload)
+             VALUES (:guid, :payload)",
+            rusqlite::named_params! {
+                ":guid": &bso.envelope.id,
+                ":payload": &bso.payload,
+            },
+        )
+        .expect("should insert");
+    }
+
+    fn test_fields(encdec: &EncryptorDecryptor) -> UpdatableBankAccountFields {
+        UpdatableBankAccountFields {
+            nickname: "Joint account".to_string(),
+            iban_enc: encdec
+                .encrypt("DE89370400440532013000")
+                .expect("encrypt iban"),
+            iban_last_4: "3000".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bank_account_create_and_read() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_bank_account = add_bank_account(&db, test_fields(&encdec))?;
+
+        // check that the add function populated the guid field
+        assert_ne!(Guid::default(), saved_bank_account.guid);
+
+        // check that the time created and time last modified were set
+        assert_ne!(0, saved_bank_account.metadata.time_created.as_millis());
+        assert_ne!(
+            0,
+            saved_bank_account.metadata.time_last_modified.as_millis()
+        );
+        assert_eq!(0, saved_bank_account.metadata.sync_change_counter);
+
+        let retrieved_bank_account = get_bank_account(&db, &saved_bank_account.guid)?;
+        assert_eq!(saved_bank_account.guid, retrieved_bank_account.guid);
+        assert_eq!(retrieved_bank_account.nickname, "Joint account");
+        assert_eq!(retrieved_bank_account.iban_last_4, "3000");
+        assert_eq!(
+            encdec.decrypt(&retrieved_bank_account.iban_enc)?,
+            "DE89370400440532013000"
+        );
+
+        assert_eq!(get_all_bank_accounts(&db)?.len(), 1);
+        assert_eq!(count_all_bank_accounts(&db)?, 1);
+
+        assert!(delete_bank_account(&db, &saved_bank_account.guid)?);
+        assert!(matches!(
+            get_bank_account(&db, &saved_bank_account.guid),
+            Err(Error::NoSuchRecord(_))
+        ));
+        assert_eq!(count_all_bank_accounts(&db)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bank_account_unencrypted_iban() {
+        let db = new_mem_db();
+        let result = add_bank_account(
+            &db,
+            UpdatableBankAccountFields {
+                nickname: "oops".to_string(),
+                iban_enc: "DE89370400440532013000".to_string(),
+                iban_last_4: "3000".to_string(),
+            },
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bank_account_update() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_bank_account = add_bank_account(&db, test_fields(&encdec))?;
+        update_bank_account(
+            &db,
+            &saved_bank_account.guid,
+            &UpdatableBankAccountFields {
+                nickname: "Savings".to_string(),
+                ..test_fields(&encdec)
+            },
+        )?;
+
+        let updated_bank_account = get_bank_account(&db, &saved_bank_account.guid)?;
+        assert_eq!(updated_bank_account.nickname, "Savings");
+        assert_eq!(1, updated_bank_account.metadata.sync_change_counter);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bank_account_delete_creates_tombstone() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_bank_account = add_bank_account(&db, test_fields(&encdec))?;
+        let guid = saved_bank_account.guid.clone();
+        test_insert_mirror_record(
+            &db,
+            saved_bank_account.into_test_incoming_bso(&encdec, Default::default()),
+        );
+
+        assert!(delete_bank_account(&db, &guid)?);
+        let tombstone_exists: bool = db.query_row(
+            "SELECT EXISTS (
+                SELECT 1
+                FROM bank_accounts_tombstones
+                WHERE guid = :guid
+            )",
+            [&guid],
+            |row| row.get(0),
+        )?;
+        assert!(tombstone_exists);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scrub_encrypted_bank_account_data() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_bank_account = add_bank_account(&db, test_fields(&encdec))?;
+        scrub_encrypted_bank_account_data(&db)?;
+
+        let retrieved_bank_account = get_bank_account(&db, &saved_bank_account.guid)?;
+        assert!(retrieved_bank_account.has_scrubbed_data());
+        // The display value is kept.
+        assert_eq!(retrieved_bank_account.iban_last_4, "3000");
+        Ok(())
+    }
+
+    #[test]
+    fn test_bank_account_touch() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+
+        let saved_bank_account = add_bank_account(&db, test_fields(&encdec))?;
+        assert_eq!(saved_bank_account.metadata.times_used, 0);
+
+        touch(&db, &saved_bank_account.guid)?;
+
+        let touched_bank_account = get_bank_account(&db, &saved_bank_account.guid)?;
+        assert_eq!(touched_bank_account.metadata.sync_change_counter, 1);
+        assert_eq!(touched_bank_account.metadata.times_used, 1);
+        assert_ne!(touched_bank_account.metadata.time_last_used.as_millis(), 0);
+        Ok(())
+    }
+}
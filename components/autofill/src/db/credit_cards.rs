@@ -3,17 +3,20 @@
 * file, You can obtain one at http://mozilla.org/MPL/2.0/.
 */
 
+use crate::credit_card::{normalize_credit_card_exp_year, normalize_credit_card_type};
 use crate::db::{
     models::{
         credit_card::{InternalCreditCard, UpdatableCreditCardFields},
         Metadata,
     },
     schema::{CREDIT_CARD_COMMON_COLS, CREDIT_CARD_COMMON_VALS},
+    DuplicateMatch,
 };
 use crate::error::*;
 
 use jwcrypto::EncryptorDecryptor;
 use rusqlite::{Connection, Transaction};
+use sql_support::ConnExt;
 use sync_guid::Guid;
 use types::Timestamp;
 
@@ -21,11 +24,82 @@ pub struct CreditCardsDeletionMetrics {
     pub total_scrubbed_records: u64,
 }
 
+/// Normalizes the fields of a card being added or updated by a consumer, and
+/// checks they are valid. We can't check the card number itself as it's
+/// encrypted - consumers should use `crate::credit_card::is_valid_credit_card_number()`
+/// before encrypting it.
+pub(crate) fn normalize_credit_card_fields(
+    mut fields: UpdatableCreditCardFields,
+) -> Result<UpdatableCreditCardFields> {
+    if !fields.cc_number_last_4.is_empty()
+        && (fields.cc_number_last_4.len() != 4
+            || !fields.cc_number_last_4.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err(Error::InvalidRecord(
+            "cc_number_last_4 must be 4 digits".to_string(),
+        ));
+    }
+    // 0 means the month isn't known.
+    if !(0..=12).contains(&fields.cc_exp_month) {
+        return Err(Error::InvalidRecord(
+            "cc_exp_month must be between 1 and 12".to_string(),
+        ));
+    }
+    fields.cc_exp_year = normalize_credit_card_exp_year(fields.cc_exp_year)
+        .ok_or_else(|| Error::InvalidRecord("cc_exp_year is not a valid year".to_string()))?;
+    // Unknown networks are kept rather than rejected - the type is only a hint
+    // for the UI, and other clients may know about networks we don't.
+    fields.cc_type = normalize_credit_card_type(&fields.cc_type);
+    Ok(fields)
+}
+
+/// Adds a new credit card, unless it duplicates an existing one - see
+/// `find_duplicate_credit_card()` - in which case the new fields are merged
+/// into the existing card, which is returned, and no new row is inserted.
+///
+/// Finding duplicates needs `encdec` to compare the card numbers, as two
+/// different cards may well share their last 4 digits. Without it, the card is
+/// always added.
 pub(crate) fn add_credit_card(
     conn: &Connection,
     new_credit_card_fields: UpdatableCreditCardFields,
+    encdec: Option<&EncryptorDecryptor>,
 ) -> Result<InternalCreditCard> {
+    let new_credit_card_fields = normalize_credit_card_fields(new_credit_card_fields)?;
+    let tx = conn.unchecked_transaction()?;
     let now = Timestamp::now();
+    if let Some(encdec) = encdec {
+        if let Some(mut existing) = find_duplicate_credit_card(
+            &tx,
+            &new_credit_card_fields,
+            DuplicateMatch::Subset,
+            encdec,
+        )? {
+            // The fields the user just entered win, but empty fields don't
+            // clear the existing ones.
+            for (field, new_field) in [
+                (&mut existing.cc_name, new_credit_card_fields.cc_name),
+                (
+                    &mut existing.cc_number_enc,
+                    new_credit_card_fields.cc_number_enc,
+                ),
+                (&mut existing.cc_type, new_credit_card_fields.cc_type),
+            ] {
+                if !new_field.is_empty() {
+                    *field = new_field;
+                }
+            }
+            if new_credit_card_fields.cc_exp_month != 0 || new_credit_card_fields.cc_exp_year != 0 {
+                existing.cc_exp_month = new_credit_card_fields.cc_exp_month;
+                existing.cc_exp_year = new_credit_card_fields.cc_exp_year;
+            }
+            existing.metadata.time_last_modified = now;
+            update_internal_credit_card(&tx, &existing, true)?;
+            existing.metadata.sync_change_counter += 1;
+            tx.commit()?;
+            return Ok(existing);
+        }
+    }
 
     // We return an InternalCreditCard, so set it up first, including the
     // missing fields, before we insert it.
@@ -46,7 +120,6 @@ pub(crate) fn add_credit_card(
         },
     };
 
-    let tx = conn.unchecked_transaction()?;
     add_internal_credit_card(&tx, &credit_card)?;
     tx.commit()?;
     Ok(credit_card)
@@ -86,6 +159,64 @@ pub(crate) fn add_internal_credit_card(
     Ok(())
 }
 
+/// Returns the existing credit card which `candidate` duplicates, if any. If
+/// multiple cards match, the most recently used one wins.
+///
+/// The name, last 4 digits, expiry and type are compared in SQL, but the
+/// encrypted numbers are different each time a number is encrypted, so the
+/// numbers of the matching cards are decrypted with `encdec` and compared
+/// here. Cards whose numbers can't be decrypted, like scrubbed cards, are
+/// never duplicates.
+pub(crate) fn find_duplicate_credit_card(
+    conn: &Connection,
+    candidate: &UpdatableCreditCardFields,
+    matching: DuplicateMatch<'_>,
+    encdec: &EncryptorDecryptor,
+) -> Result<Option<InternalCreditCard>> {
+    let (guid, subset) = match matching {
+        DuplicateMatch::Unsynced(guid) => (Some(guid), false),
+        DuplicateMatch::Subset => (None, true),
+    };
+    // Without a number, we have nothing to identify the card by.
+    if candidate.cc_number_enc.is_empty() {
+        return Ok(None);
+    }
+    let sql = format!(
+        "SELECT
+            {common_cols},
+            sync_change_counter
+        FROM credit_cards_data
+        WHERE (:guid IS NULL
+                OR (guid <> :guid AND guid NOT IN (SELECT guid FROM credit_cards_mirror)))
+            AND ((:subset AND :cc_name = '') OR cc_name == :cc_name)
+            AND ((:subset AND :cc_number_last_4 = '') OR cc_number_last_4 == :cc_number_last_4)
+            AND ((:subset AND :cc_exp_month = 0 AND :cc_exp_year = 0)
+                OR (cc_exp_month == :cc_exp_month AND cc_exp_year == :cc_exp_year))
+            AND ((:subset AND :cc_type = '') OR cc_type == :cc_type)
+        ORDER BY time_last_used DESC, times_used DESC, time_created ASC",
+        common_cols = CREDIT_CARD_COMMON_COLS
+    );
+    let cards = conn.query_rows_and_then(
+        &sql,
+        rusqlite::named_params! {
+            ":guid": guid,
+            ":subset": subset,
+            ":cc_name": candidate.cc_name,
+            ":cc_number_last_4": candidate.cc_number_last_4,
+            ":cc_exp_month": candidate.cc_exp_month,
+            ":cc_exp_year": candidate.cc_exp_year,
+            ":cc_type": candidate.cc_type,
+        },
+        |row| -> Result<InternalCreditCard> { Ok(InternalCreditCard::from_row(row)?) },
+    )?;
+    let candidate_number = encdec.decrypt(&candidate.cc_number_enc)?;
+    Ok(cards.into_iter().find(|card| {
+        encdec
+            .decrypt(&card.cc_number_enc)
+            .is_ok_and(|number| number == candidate_number)
+    }))
+}
+
 pub(crate) fn get_credit_card(conn: &Connection, guid: &Guid) -> Result<InternalCreditCard> {
     let sql = format!(
         "SELECT
@@ -133,6 +264,7 @@ pub fn update_credit_card(
     guid: &Guid,
     credit_card: &UpdatableCreditCardFields,
 ) -> Result<()> {
+    let credit_card = &normalize_credit_card_fields(credit_card.clone())?;
     let tx = conn.unchecked_transaction()?;
     tx.execute(
         "UPDATE credit_cards_data
@@ -202,6 +334,54 @@ pub(crate) fn update_internal_credit_card(
     Ok(())
 }
 
+/// Merges the credit card identified by `other_guid` into the one identified by
+/// `guid`, then deletes `other_guid`.
+///
+/// Fields already set on `guid` win; empty fields (including a scrubbed card
+/// number) are filled in from `other_guid`. The usage metadata of both records
+/// is combined as for addresses.
+pub(crate) fn merge_credit_cards(
+    conn: &Connection,
+    guid: &Guid,
+    other_guid: &Guid,
+) -> Result<InternalCreditCard> {
+    let tx = conn.unchecked_transaction()?;
+    let mut card = get_credit_card(&tx, guid)?;
+    if guid == other_guid {
+        return Ok(card);
+    }
+    let other = get_credit_card(&tx, other_guid)?;
+    for (field, other_field) in [
+        (&mut card.cc_name, other.cc_name),
+        (&mut card.cc_number_enc, other.cc_number_enc),
+        (&mut card.cc_number_last_4, other.cc_number_last_4),
+        (&mut card.cc_type, other.cc_type),
+    ] {
+        if field.is_empty() {
+            *field = other_field;
+        }
+    }
+    // Only take the expiry as a whole, so we never mix the month of one card
+    // with the year of the other.
+    if card.cc_exp_month == 0 && card.cc_exp_year == 0 {
+        card.cc_exp_month = other.cc_exp_month;
+        card.cc_exp_year = other.cc_exp_year;
+    }
+    card.metadata.combine_local(&other.metadata);
+    card.metadata.time_last_modified = Timestamp::now();
+    update_internal_credit_card(&tx, &card, true)?;
+    tx.execute(
+        "DELETE FROM credit_cards_data
+        WHERE guid = :guid",
+        rusqlite::named_params! {
+            ":guid": other_guid.as_str(),
+        },
+    )?;
+    tx.commit()?;
+    // Re-read so the change counter reflects what was written.
+    get_credit_card(conn, guid)
+}
+
 pub fn delete_credit_card(conn: &Connection, guid: &Guid) -> Result<bool> {
     let tx = conn.unchecked_transaction()?;
 
@@ -362,6 +542,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2022,
                 cc_type: "visa".to_string(),
             },
+            None,
         )?;
 
         // check that the add function populated the guid field
@@ -435,6 +616,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2022,
                 cc_type: "visa".to_string(),
             },
+            None,
         )?;
 
         let saved_credit_card2 = add_credit_card(
@@ -447,6 +629,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2025,
                 cc_type: "mastercard".to_string(),
             },
+            None,
         )?;
 
         // creating a third credit card with a tombstone to ensure it's not returned
@@ -460,6 +643,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2024,
                 cc_type: "amex".to_string(),
             },
+            None,
         )?;
 
         let delete_result = delete_credit_card(&db, &saved_credit_card3.guid);
@@ -503,6 +687,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2025,
                 cc_type: "mastercard".to_string(),
             },
+            None,
         )?;
 
         let expected_cc_name = "john doe".to_string();
@@ -600,6 +785,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2025,
                 cc_type: "mastercard".to_string(),
             },
+            None,
         )?;
 
         let delete_result = delete_credit_card(&db, &saved_credit_card.guid);
@@ -616,6 +802,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2024,
                 cc_type: "visa".to_string(),
             },
+            None,
         )?;
 
         // create a mirror record to check that a tombstone record is created upon deletion
@@ -658,17 +845,18 @@ pub(crate) mod tests {
         let db = new_mem_db();
         let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
         let mut saved_credit_cards = Vec::with_capacity(10);
-        for _ in 0..5 {
+        for i in 0..5 {
             saved_credit_cards.push(add_credit_card(
                 &db,
                 UpdatableCreditCardFields {
                     cc_name: "john deer".to_string(),
-                    cc_number_enc: encdec.encrypt("1234567812345678")?,
-                    cc_number_last_4: "5678".to_string(),
+                    cc_number_enc: encdec.encrypt(&format!("123456781234567{i}"))?,
+                    cc_number_last_4: format!("567{i}"),
                     cc_exp_month: 10,
                     cc_exp_year: 2025,
                     cc_type: "mastercard".to_string(),
                 },
+                None,
             )?);
         }
 
@@ -700,6 +888,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2027,
                 cc_type: "visa".to_string(),
             },
+            None,
         )?;
 
         let encrypted_cc_number = encdec.encrypt("567812345678123456781")?;
@@ -713,6 +902,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2025,
                 cc_type: "mastercard".to_string(),
             },
+            None,
         )?;
 
         let metrics = scrub_undecryptable_credit_card_data_for_remote_replacement(&db.writer, key)?;
@@ -811,6 +1001,7 @@ pub(crate) mod tests {
                 cc_exp_year: 2024,
                 cc_type: "visa".to_string(),
             },
+            None,
         )?;
 
         assert_eq!(saved_credit_card.metadata.sync_change_counter, 0);
@@ -825,4 +1016,253 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_credit_card_add_same_last_4() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let fields = UpdatableCreditCardFields {
+            cc_name: "jane doe".to_string(),
+            cc_number_enc: encdec.encrypt("4111111111111111")?,
+            cc_number_last_4: "1111".to_string(),
+            cc_exp_month: 3,
+            cc_exp_year: 2030,
+            cc_type: "visa".to_string(),
+        };
+        let saved_credit_card = add_credit_card(&db, fields.clone(), None)?;
+
+        // A different card with the same last 4 digits, and no name or expiry,
+        // must not be mistaken for the first one.
+        let other_number_enc = encdec.encrypt("4012888888881111")?;
+        let other_credit_card = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "".to_string(),
+                cc_number_enc: other_number_enc.clone(),
+                cc_exp_month: 0,
+                cc_exp_year: 0,
+                ..fields.clone()
+            },
+            Some(&encdec),
+        )?;
+        assert_ne!(other_credit_card.guid, saved_credit_card.guid);
+        let retrieved = get_credit_card(&db, &other_credit_card.guid)?;
+        assert_eq!(retrieved.cc_number_enc, other_number_enc);
+        let retrieved = get_credit_card(&db, &saved_credit_card.guid)?;
+        assert_eq!(retrieved.cc_number_enc, fields.cc_number_enc);
+        assert_eq!(count_all_credit_cards(&db)?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit_card_add_duplicate() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let fields = UpdatableCreditCardFields {
+            cc_name: "jane doe".to_string(),
+            cc_number_enc: encdec.encrypt("4111111111111111")?,
+            cc_number_last_4: "1111".to_string(),
+            cc_exp_month: 3,
+            cc_exp_year: 2030,
+            cc_type: "visa".to_string(),
+        };
+        let saved_credit_card = add_credit_card(&db, fields.clone(), Some(&encdec))?;
+
+        // The same card is a dupe, even though the number encrypts differently.
+        let new_number_enc = encdec.encrypt("4111111111111111")?;
+        let dupe = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_number_enc: new_number_enc.clone(),
+                ..fields.clone()
+            },
+            Some(&encdec),
+        )?;
+        assert_eq!(dupe.guid, saved_credit_card.guid);
+        assert_eq!(count_all_credit_cards(&db)?, 1);
+        let retrieved = get_credit_card(&db, &saved_credit_card.guid)?;
+        assert_eq!(retrieved.cc_number_enc, new_number_enc);
+        assert_eq!(retrieved.metadata.sync_change_counter, 1);
+
+        // As is the card without a name or expiry, which keeps the existing ones.
+        let subset = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "".to_string(),
+                cc_number_enc: encdec.encrypt("4111111111111111")?,
+                cc_exp_month: 0,
+                cc_exp_year: 0,
+                ..fields.clone()
+            },
+            Some(&encdec),
+        )?;
+        assert_eq!(subset.guid, saved_credit_card.guid);
+        assert_eq!(subset.cc_name, "jane doe");
+        assert_eq!(subset.cc_exp_year, 2030);
+        assert_eq!(count_all_credit_cards(&db)?, 1);
+
+        // A different expiry is a different card.
+        let renewed = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_number_enc: encdec.encrypt("4111111111111111")?,
+                cc_exp_year: 2034,
+                ..fields.clone()
+            },
+            Some(&encdec),
+        )?;
+        assert_ne!(renewed.guid, saved_credit_card.guid);
+        assert_eq!(count_all_credit_cards(&db)?, 2);
+
+        // Without the key, the card can't be compared, so it's always added.
+        add_credit_card(&db, fields.clone(), None)?;
+        assert_eq!(count_all_credit_cards(&db)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit_card_merge() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+        let card = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "".to_string(),
+                cc_number_enc: encdec.encrypt("4111111111111111")?,
+                cc_number_last_4: "1111".to_string(),
+                cc_exp_month: 0,
+                cc_exp_year: 0,
+                cc_type: "visa".to_string(),
+            },
+            None,
+        )?;
+        let other = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: encdec.encrypt("4111111111111112")?,
+                cc_number_last_4: "1112".to_string(),
+                cc_exp_month: 3,
+                cc_exp_year: 2030,
+                cc_type: "".to_string(),
+            },
+            None,
+        )?;
+        touch(&db, &other.guid)?;
+
+        let merged = merge_credit_cards(&db, &card.guid, &other.guid)?;
+        assert_eq!(merged.guid, card.guid);
+        assert_eq!(merged.cc_name, "jane doe");
+        assert_eq!(merged.cc_number_last_4, "1111");
+        assert_eq!(encdec.decrypt(&merged.cc_number_enc)?, "4111111111111111");
+        assert_eq!(merged.cc_exp_month, 3);
+        assert_eq!(merged.cc_exp_year, 2030);
+        assert_eq!(merged.cc_type, "visa");
+        assert_eq!(merged.metadata.times_used, 1);
+        assert_eq!(merged.metadata.sync_change_counter, 1);
+
+        assert!(matches!(
+            get_credit_card(&db, &other.guid),
+            Err(Error::NoSuchRecord(_))
+        ));
+        assert_eq!(count_all_credit_cards(&db)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit_card_add_normalizes_fields() -> Result<()> {
+        ensure_initialized();
+        let db = new_mem_db();
+        let card = add_credit_card(
+            &db,
+            UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX".to_string(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: 3,
+                cc_exp_year: 30,
+                cc_type: "American Express".to_string(),
+            },
+            None,
+        )?;
+        assert_eq!(card.cc_exp_year, 2030);
+        assert_eq!(card.cc_type, "amex");
+
+        update_credit_card(
+            &db,
+            &card.guid,
+            &UpdatableCreditCardFields {
+                cc_name: "jane doe".to_string(),
+                cc_number_enc: "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX".to_string(),
+                cc_number_last_4: "1234".to_string(),
+                cc_exp_month: 4,
+                cc_exp_year: 31,
+                cc_type: "Maestro".to_string(),
+            },
+        )?;
+        let card = get_credit_card(&db, &card.guid)?;
+        assert_eq!(card.cc_exp_month, 4);
+        assert_eq!(card.cc_exp_year, 2031);
+        assert_eq!(card.cc_type, "maestro");
+        Ok(())
+    }
+
+    #[test]
+    fn test_credit_card_add_invalid() {
+        ensure_initialized();
+        let db = new_mem_db();
+        let valid = UpdatableCreditCardFields {
+            cc_name: "jane doe".to_string(),
+            cc_number_enc: "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX".to_string(),
+            cc_number_last_4: "1234".to_string(),
+            cc_exp_month: 3,
+            cc_exp_year: 2030,
+            cc_type: "visa".to_string(),
+        };
+        for invalid in [
+            UpdatableCreditCardFields {
+                cc_number_last_4: "123".to_string(),
+                ..valid.clone()
+            },
+            UpdatableCreditCardFields {
+                cc_number_last_4: "12a4".to_string(),
+                ..valid.clone()
+            },
+            UpdatableCreditCardFields {
+                cc_exp_month: 13,
+                ..valid.clone()
+            },
+            UpdatableCreditCardFields {
+                cc_exp_month: -1,
+                ..valid.clone()
+            },
+            UpdatableCreditCardFields {
+                cc_exp_year: 203,
+                ..valid.clone()
+            },
+        ] {
+            assert!(matches!(
+                add_credit_card(&db, invalid, None),
+                Err(Error::InvalidRecord(_))
+            ));
+        }
+        assert_eq!(count_all_credit_cards(&db).unwrap(), 0);
+
+        let card = add_credit_card(&db, valid.clone(), None).unwrap();
+        assert!(matches!(
+            update_credit_card(
+                &db,
+                &card.guid,
+                &UpdatableCreditCardFields {
+                    cc_exp_month: 13,
+                    ..valid
+                }
+            ),
+            Err(Error::InvalidRecord(_))
+        ));
+        assert_eq!(get_credit_card(&db, &card.guid).unwrap().cc_exp_month, 3);
+    }
 }
@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 pub mod addresses;
+pub mod bank_accounts;
 pub mod credit_cards;
 pub mod models;
 pub mod schema;
@@ -18,8 +19,25 @@ use std::{
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
 };
+use sync_guid::Guid;
 use url::Url;
 
+/// How the `find_duplicate_*()` functions compare a candidate record with the
+/// existing records.
+pub(crate) enum DuplicateMatch<'a> {
+    /// Every field of the candidate must match an existing record. Only
+    /// records which haven't been synced yet - ie, aren't in the mirror - and
+    /// which aren't `guid` itself are candidates. This is how the sync engines
+    /// find a local dupe for an incoming record.
+    Unsynced(&'a Guid),
+    /// The candidate is also a duplicate when it is a subset of an existing
+    /// record - ie, every non-empty field of the candidate matches the
+    /// existing record, but the existing record may have more fields filled
+    /// in. This is how `add_address()` and `add_credit_card()` find a
+    /// duplicate.
+    Subset,
+}
+
 pub struct AutofillDb {
     pub writer: Connection,
     interrupt_handle: Arc<SqlInterruptHandle>,
@@ -118,6 +136,7 @@ fn normalize_path(p: impl AsRef<Path>) -> Result<PathBuf> {
 }
 
 pub(crate) mod sql_fns {
+    use crate::address_format;
     use rusqlite::{functions::Context, Result};
     use sync_guid::Guid as SyncGuid;
     use types::Timestamp;
@@ -132,6 +151,29 @@ pub(crate) mod sql_fns {
     pub fn now(_ctx: &Context<'_>) -> Result<Timestamp> {
         Ok(Timestamp::now())
     }
+
+    #[inline(never)]
+    pub fn normalize_country(ctx: &Context<'_>) -> Result<String> {
+        Ok(address_format::normalize_country(ctx.get(0)?))
+    }
+
+    #[inline(never)]
+    pub fn normalize_region(ctx: &Context<'_>) -> Result<String> {
+        Ok(address_format::normalize_region(ctx.get(0)?, ctx.get(1)?))
+    }
+
+    #[inline(never)]
+    pub fn normalize_postal_code(ctx: &Context<'_>) -> Result<String> {
+        Ok(address_format::normalize_postal_code(
+            ctx.get(0)?,
+            ctx.get(1)?,
+        ))
+    }
+
+    #[inline(never)]
+    pub fn normalize_tel(ctx: &Context<'_>) -> Result<String> {
+        Ok(address_format::normalize_tel(ctx.get(0)?, ctx.get(1)?))
+    }
 }
 
 // Helpers for tests
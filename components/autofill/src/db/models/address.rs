@@ -119,4 +119,21 @@ impl InternalAddress {
             },
         })
     }
+
+    /// The fields of this address which a consumer could have specified, eg,
+    /// to compare it with other addresses.
+    pub fn updatable_fields(&self) -> UpdatableAddressFields {
+        UpdatableAddressFields {
+            name: self.name.clone(),
+            organization: self.organization.clone(),
+            street_address: self.street_address.clone(),
+            address_level3: self.address_level3.clone(),
+            address_level2: self.address_level2.clone(),
+            address_level1: self.address_level1.clone(),
+            postal_code: self.postal_code.clone(),
+            country: self.country.clone(),
+            tel: self.tel.clone(),
+            email: self.email.clone(),
+        }
+    }
 }
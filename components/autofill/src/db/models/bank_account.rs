@@ -0,0 +1,89 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use super::Metadata;
+use rusqlite::Row;
+use sync_guid::Guid;
+
+// UpdatableBankAccountFields contains the fields we support for creating a new
+// bank account or updating an existing one. As for credit-cards, the IBAN is
+// always passed encrypted - see `crate::iban` for helpers which can be used on
+// the cleartext before it is encrypted.
+#[derive(Debug, Clone, Default)]
+pub struct UpdatableBankAccountFields {
+    pub nickname: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+}
+
+// "BankAccount" is what we return to consumers and has most of the metadata.
+#[derive(Debug, Clone, Default)]
+pub struct BankAccount {
+    pub guid: String,
+    pub nickname: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+
+    // The metadata
+    pub time_created: i64,
+    pub time_last_used: Option<i64>,
+    pub time_last_modified: i64,
+    pub times_used: i64,
+}
+
+// This is used to "externalize" a bank account, suitable for handing back to
+// consumers.
+impl From<InternalBankAccount> for BankAccount {
+    fn from(iba: InternalBankAccount) -> Self {
+        BankAccount {
+            guid: iba.guid.to_string(),
+            nickname: iba.nickname,
+            iban_enc: iba.iban_enc,
+            iban_last_4: iba.iban_last_4,
+            // note we can't use u64 in uniffi
+            time_created: u64::from(iba.metadata.time_created) as i64,
+            time_last_used: if iba.metadata.time_last_used.0 == 0 {
+                None
+            } else {
+                Some(iba.metadata.time_last_used.0 as i64)
+            },
+            time_last_modified: u64::from(iba.metadata.time_last_modified) as i64,
+            times_used: iba.metadata.times_used,
+        }
+    }
+}
+
+// NOTE: No `PartialEq` here because, like credit-card numbers, the same IBAN
+// will encrypt to a different value each time it is encrypted.
+#[derive(Debug, Clone, Default)]
+pub struct InternalBankAccount {
+    pub guid: Guid,
+    pub nickname: String,
+    pub iban_enc: String,
+    pub iban_last_4: String,
+    pub metadata: Metadata,
+}
+
+impl InternalBankAccount {
+    pub fn from_row(row: &Row<'_>) -> Result<InternalBankAccount, rusqlite::Error> {
+        Ok(Self {
+            guid: Guid::from_string(row.get("guid")?),
+            nickname: row.get("nickname")?,
+            iban_enc: row.get("iban_enc")?,
+            iban_last_4: row.get("iban_last_4")?,
+            metadata: Metadata {
+                time_created: row.get("time_created")?,
+                time_last_used: row.get("time_last_used")?,
+                time_last_modified: row.get("time_last_modified")?,
+                times_used: row.get("times_used")?,
+                sync_change_counter: row.get("sync_change_counter")?,
+            },
+        })
+    }
+
+    pub fn has_scrubbed_data(&self) -> bool {
+        self.iban_enc.is_empty()
+    }
+}
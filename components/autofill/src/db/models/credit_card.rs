@@ -103,4 +103,17 @@ impl InternalCreditCard {
     pub fn has_scrubbed_data(&self) -> bool {
         self.cc_number_enc.is_empty()
     }
+
+    /// The fields of this card which a consumer could have specified, eg, to
+    /// compare it with other cards.
+    pub fn updatable_fields(&self) -> UpdatableCreditCardFields {
+        UpdatableCreditCardFields {
+            cc_name: self.cc_name.clone(),
+            cc_number_enc: self.cc_number_enc.clone(),
+            cc_number_last_4: self.cc_number_last_4.clone(),
+            cc_exp_month: self.cc_exp_month,
+            cc_exp_year: self.cc_exp_year,
+            cc_type: self.cc_type.clone(),
+        }
+    }
 }
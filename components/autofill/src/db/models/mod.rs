@@ -4,6 +4,7 @@
 */
 
 pub mod address;
+pub mod bank_account;
 pub mod credit_card;
 use types::Timestamp;
 
@@ -16,3 +17,14 @@ pub struct Metadata {
     pub times_used: i64,
     pub sync_change_counter: i64,
 }
+
+impl Metadata {
+    /// Folds the usage metadata of a local record being merged into this one.
+    /// Unlike the sync `merge()`, both records were used independently on this
+    /// device, so their usage counts are summed.
+    pub fn combine_local(&mut self, other: &Metadata) {
+        self.time_created = self.time_created.min(other.time_created);
+        self.time_last_used = self.time_last_used.max(other.time_last_used);
+        self.times_used += other.times_used;
+    }
+}
@@ -68,6 +68,26 @@ pub const CREDIT_CARD_COMMON_VALS: &str = "
     :time_last_modified,
     :times_used";
 
+pub const BANK_ACCOUNT_COMMON_COLS: &str = "
+    guid,
+    nickname,
+    iban_enc,
+    iban_last_4,
+    time_created,
+    time_last_used,
+    time_last_modified,
+    times_used";
+
+pub const BANK_ACCOUNT_COMMON_VALS: &str = "
+    :guid,
+    :nickname,
+    :iban_enc,
+    :iban_last_4,
+    :time_created,
+    :time_last_used,
+    :time_last_modified,
+    :times_used";
+
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
 const CREATE_SHARED_TRIGGERS_SQL: &str = include_str!("../../sql/create_shared_triggers.sql");
 const CREATE_SYNC_TEMP_TABLES_SQL: &str = include_str!("../../sql/create_sync_temp_tables.sql");
@@ -76,7 +96,7 @@ pub struct AutofillConnectionInitializer;
 
 impl ConnectionInitializer for AutofillConnectionInitializer {
     const NAME: &'static str = "autofill db";
-    const END_VERSION: u32 = 4;
+    const END_VERSION: u32 = 5;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> Result<()> {
         define_functions(conn)?;
@@ -107,6 +127,7 @@ impl ConnectionInitializer for AutofillConnectionInitializer {
             1 => upgrade_from_v1(db),
             2 => upgrade_from_v2(db),
             3 => upgrade_from_v3(db),
+            4 => upgrade_from_v4(db),
             _ => Err(Error::IncompatibleVersion(version)),
         }
     }
@@ -124,6 +145,27 @@ fn define_functions(c: &Connection) -> Result<()> {
         sql_fns::generate_guid,
     )?;
     c.create_scalar_function("now", 0, FunctionFlags::SQLITE_UTF8, sql_fns::now)?;
+    // Used to compare addresses - see `crate::address_format`.
+    let deterministic = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    c.create_scalar_function(
+        "normalize_country",
+        1,
+        deterministic,
+        sql_fns::normalize_country,
+    )?;
+    c.create_scalar_function(
+        "normalize_region",
+        2,
+        deterministic,
+        sql_fns::normalize_region,
+    )?;
+    c.create_scalar_function(
+        "normalize_postal_code",
+        2,
+        deterministic,
+        sql_fns::normalize_postal_code,
+    )?;
+    c.create_scalar_function("normalize_tel", 2, deterministic, sql_fns::normalize_tel)?;
 
     Ok(())
 }
@@ -238,6 +280,12 @@ fn upgrade_from_v3(db: &Connection) -> Result<()> {
     Ok(())
 }
 
+fn upgrade_from_v4(db: &Connection) -> Result<()> {
+    let migration_string: &str = include_str!("../../sql/migrations/v5_migration.sql");
+    db.execute_batch(migration_string)?;
+    Ok(())
+}
+
 pub fn create_empty_sync_temp_tables(db: &Connection) -> Result<()> {
     debug!("Initializing sync temp tables");
     db.execute_batch(CREATE_SYNC_TEMP_TABLES_SQL)?;
@@ -408,4 +456,26 @@ mod tests {
         assert_eq!(address.guid, "B");
         assert_eq!(address.address_level1, "ON");
     }
+
+    #[test]
+    fn test_upgrade_version_4() {
+        let db_file = MigratedDatabaseFile::new(AutofillConnectionInitializer, CREATE_V3_DB);
+        db_file.upgrade_to(4);
+        let db = db_file.open();
+
+        db.execute_batch("SELECT iban_enc from bank_accounts_data")
+            .expect_err("select should fail");
+
+        db_file.upgrade_to(5);
+
+        db.execute_batch("SELECT iban_enc from bank_accounts_data")
+            .expect("select iban_enc should now work");
+        db.execute_batch("SELECT payload from bank_accounts_mirror")
+            .expect("select from the mirror should now work");
+        db.execute_batch("SELECT time_deleted from bank_accounts_tombstones")
+            .expect("select from the tombstones should now work");
+        // The existing data is untouched.
+        let address = get_address(&db, &Guid::new("A")).unwrap();
+        assert_eq!(address.address_level1, "MA");
+    }
 }
@@ -3,8 +3,12 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::db::models::address::{Address, UpdatableAddressFields};
+use crate::db::models::bank_account::{BankAccount, UpdatableBankAccountFields};
 use crate::db::models::credit_card::{CreditCard, UpdatableCreditCardFields};
-use crate::db::{addresses, credit_cards, credit_cards::CreditCardsDeletionMetrics, AutofillDb};
+use crate::db::{
+    addresses, bank_accounts, credit_cards, credit_cards::CreditCardsDeletionMetrics, AutofillDb,
+};
+use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use error_support::handle_error;
 use rusqlite::{
@@ -73,8 +77,19 @@ impl Store {
     }
 
     #[handle_error(Error)]
-    pub fn add_credit_card(&self, fields: UpdatableCreditCardFields) -> ApiResult<CreditCard> {
-        let credit_card = credit_cards::add_credit_card(&self.db.lock().unwrap().writer, fields)?;
+    pub fn add_credit_card(
+        &self,
+        fields: UpdatableCreditCardFields,
+        local_encryption_key: Option<String>,
+    ) -> ApiResult<CreditCard> {
+        let encdec = local_encryption_key
+            .map(|key| EncryptorDecryptor::new(&key))
+            .transpose()?;
+        let credit_card = credit_cards::add_credit_card(
+            &self.db.lock().unwrap().writer,
+            fields,
+            encdec.as_ref(),
+        )?;
         Ok(credit_card.into())
     }
 
@@ -113,6 +128,16 @@ impl Store {
         )
     }
 
+    #[handle_error(Error)]
+    pub fn merge_credit_cards(&self, guid: String, other_guid: String) -> ApiResult<CreditCard> {
+        let credit_card = credit_cards::merge_credit_cards(
+            &self.db.lock().unwrap().writer,
+            &Guid::new(&guid),
+            &Guid::new(&other_guid),
+        )?;
+        Ok(credit_card.into())
+    }
+
     #[handle_error(Error)]
     pub fn delete_credit_card(&self, guid: String) -> ApiResult<bool> {
         credit_cards::delete_credit_card(&self.db.lock().unwrap().writer, &Guid::new(&guid))
@@ -153,6 +178,16 @@ impl Store {
         addresses::update_address(&self.db.lock().unwrap().writer, &Guid::new(&guid), &address)
     }
 
+    #[handle_error(Error)]
+    pub fn merge_addresses(&self, guid: String, other_guid: String) -> ApiResult<Address> {
+        Ok(addresses::merge_addresses(
+            &self.db.lock().unwrap().writer,
+            &Guid::new(&guid),
+            &Guid::new(&other_guid),
+        )?
+        .into())
+    }
+
     #[handle_error(Error)]
     pub fn delete_address(&self, guid: String) -> ApiResult<bool> {
         addresses::delete_address(&self.db.lock().unwrap().writer, &Guid::new(&guid))
@@ -163,14 +198,71 @@ impl Store {
         addresses::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
     }
 
+    #[handle_error(Error)]
+    pub fn add_bank_account(&self, fields: UpdatableBankAccountFields) -> ApiResult<BankAccount> {
+        let bank_account =
+            bank_accounts::add_bank_account(&self.db.lock().unwrap().writer, fields)?;
+        Ok(bank_account.into())
+    }
+
+    #[handle_error(Error)]
+    pub fn get_bank_account(&self, guid: String) -> ApiResult<BankAccount> {
+        let bank_account =
+            bank_accounts::get_bank_account(&self.db.lock().unwrap().writer, &Guid::new(&guid))?;
+        Ok(bank_account.into())
+    }
+
+    #[handle_error(Error)]
+    pub fn get_all_bank_accounts(&self) -> ApiResult<Vec<BankAccount>> {
+        let bank_accounts = bank_accounts::get_all_bank_accounts(&self.db.lock().unwrap().writer)?
+            .into_iter()
+            .map(|x| x.into())
+            .collect();
+        Ok(bank_accounts)
+    }
+
+    #[handle_error(Error)]
+    pub fn count_all_bank_accounts(&self) -> ApiResult<i64> {
+        let count = bank_accounts::count_all_bank_accounts(&self.db.lock().unwrap().writer)?;
+        Ok(count)
+    }
+
+    #[handle_error(Error)]
+    pub fn update_bank_account(
+        &self,
+        guid: String,
+        bank_account: UpdatableBankAccountFields,
+    ) -> ApiResult<()> {
+        bank_accounts::update_bank_account(
+            &self.db.lock().unwrap().writer,
+            &Guid::new(&guid),
+            &bank_account,
+        )
+    }
+
+    #[handle_error(Error)]
+    pub fn delete_bank_account(&self, guid: String) -> ApiResult<bool> {
+        bank_accounts::delete_bank_account(&self.db.lock().unwrap().writer, &Guid::new(&guid))
+    }
+
+    #[handle_error(Error)]
+    pub fn touch_bank_account(&self, guid: String) -> ApiResult<()> {
+        bank_accounts::touch(&self.db.lock().unwrap().writer, &Guid::new(&guid))
+    }
+
     #[handle_error(Error)]
     pub fn scrub_encrypted_data(self: Arc<Self>) -> ApiResult<()> {
         // scrub the data on disk
-        // Currently only credit cards have encrypted data
-        credit_cards::scrub_encrypted_credit_card_data(&self.db.lock().unwrap().writer)?;
-        // Force the sync engine to refetch data (only need to do this for the credit cards, since the
-        // addresses engine doesn't store encrypted data).
-        crate::sync::credit_card::create_engine(self).reset_local_sync_data()?;
+        // Credit cards and bank accounts have encrypted data
+        {
+            let db = &self.db.lock().unwrap().writer;
+            credit_cards::scrub_encrypted_credit_card_data(db)?;
+            bank_accounts::scrub_encrypted_bank_account_data(db)?;
+        }
+        // Force the sync engines to refetch data (only need to do this for the engines with
+        // encrypted data, since the addresses engine doesn't store encrypted data).
+        crate::sync::credit_card::create_engine(self.clone()).reset_local_sync_data()?;
+        crate::sync::bank_account::create_engine(self).reset_local_sync_data()?;
         Ok(())
     }
 
@@ -225,6 +317,12 @@ impl Store {
     pub fn create_addresses_sync_engine(self: Arc<Self>) -> Box<dyn SyncEngine> {
         Box::new(crate::sync::address::create_engine(self))
     }
+
+    // Until the server has a collection for bank accounts there's no
+    // `SyncEngineId` for them, so this is the only way to get the engine.
+    pub fn create_bank_accounts_sync_engine(self: Arc<Self>) -> Box<dyn SyncEngine> {
+        Box::new(crate::sync::bank_account::create_engine(self))
+    }
 }
 
 pub(crate) fn put_meta(conn: &Connection, key: &str, value: &dyn ToSql) -> Result<()> {
@@ -253,7 +351,6 @@ pub(crate) fn delete_meta(conn: &Connection, key: &str) -> Result<()> {
 mod tests {
     use super::*;
     use crate::db::test::new_mem_db;
-    use crate::encryption::EncryptorDecryptor;
     use nss::ensure_initialized;
 
     #[test]
@@ -322,16 +419,19 @@ mod tests {
         let encdec = EncryptorDecryptor::new(&key).expect("create EncryptorDecryptor");
 
         store
-            .add_credit_card(UpdatableCreditCardFields {
-                cc_name: "john deer".to_string(),
-                cc_number_enc: encdec
-                    .encrypt("567812345678123456781")
-                    .expect("encrypt cc number"),
-                cc_number_last_4: "6781".to_string(),
-                cc_exp_month: 10,
-                cc_exp_year: 2025,
-                cc_type: "mastercard".to_string(),
-            })
+            .add_credit_card(
+                UpdatableCreditCardFields {
+                    cc_name: "john deer".to_string(),
+                    cc_number_enc: encdec
+                        .encrypt("567812345678123456781")
+                        .expect("encrypt cc number"),
+                    cc_number_last_4: "6781".to_string(),
+                    cc_exp_month: 10,
+                    cc_exp_year: 2025,
+                    cc_type: "mastercard".to_string(),
+                },
+                None,
+            )
             .expect("add credit card to database");
 
         store
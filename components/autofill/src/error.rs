@@ -27,6 +27,9 @@ pub enum AutofillApiError {
     #[error("No record with guid exists: {guid}")]
     NoSuchRecord { guid: String },
 
+    #[error("Invalid record: {reason}")]
+    InvalidRecord { reason: String },
+
     #[error("Unexpected Error: {reason}")]
     UnexpectedAutofillApiError { reason: String },
 }
@@ -65,6 +68,10 @@ pub enum Error {
 
     #[error("No record with guid exists: {0}")]
     NoSuchRecord(String),
+
+    // The reason must not include the values of any fields, as they are PII.
+    #[error("Invalid record: {0}")]
+    InvalidRecord(String),
 }
 
 // Define how our internal errors are handled and converted to external errors
@@ -126,6 +133,13 @@ impl GetErrorHandling for Error {
                 ErrorHandling::convert(AutofillApiError::NoSuchRecord { guid: guid.clone() })
                     .log_warning()
             }
+
+            Self::InvalidRecord(reason) => {
+                ErrorHandling::convert(AutofillApiError::InvalidRecord {
+                    reason: reason.clone(),
+                })
+                .log_warning()
+            }
         }
     }
 }
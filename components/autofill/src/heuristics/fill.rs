@@ -0,0 +1,249 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Fill values for a classified form, taken from the records returned by
+// `Store::get_all_addresses()` and `Store::get_all_credit_cards()`.
+
+use super::FieldClassification;
+use crate::address_format::{normalize_country, split_tel};
+use crate::db::models::{address::Address, credit_card::CreditCard};
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::sync::address::name_utils::split_name;
+use error_support::handle_error;
+
+// Returns the street address lines to fill. Forms often have fewer line fields
+// than the address has lines, in which case the last line field gets all the
+// remaining lines.
+fn street_address_line(street_address: &str, line: usize, num_line_fields: usize) -> String {
+    let lines: Vec<&str> = street_address
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if line == num_line_fields {
+        lines.get(line - 1..).unwrap_or_default().join(" ")
+    } else {
+        lines.get(line - 1).copied().unwrap_or_default().to_string()
+    }
+}
+
+/// Returns the value to fill in each of the classified fields, in the same
+/// order as the classifications. Fields which aren't address fields are
+/// filled with an empty string.
+pub fn address_fill_values(
+    address: Address,
+    classifications: Vec<FieldClassification>,
+) -> Vec<String> {
+    let num_line_fields = ["address-line1", "address-line2", "address-line3"]
+        .iter()
+        .rposition(|line| classifications.iter().any(|c| c.field_type == *line))
+        .map_or(0, |i| i + 1);
+    let name_parts = split_name(&address.name);
+    let tel_parts = split_tel(&address.country, &address.tel);
+    classifications
+        .iter()
+        .map(|c| match c.field_type.as_str() {
+            "name" => address.name.clone(),
+            "given-name" => name_parts.given.clone(),
+            "additional-name" => name_parts.middle.clone(),
+            "family-name" => name_parts.family.clone(),
+            "organization" => address.organization.clone(),
+            "street-address" => address.street_address.clone(),
+            "address-line1" => street_address_line(&address.street_address, 1, num_line_fields),
+            "address-line2" => street_address_line(&address.street_address, 2, num_line_fields),
+            "address-line3" => street_address_line(&address.street_address, 3, num_line_fields),
+            "address-level3" => address.address_level3.clone(),
+            "address-level2" => address.address_level2.clone(),
+            "address-level1" => address.address_level1.clone(),
+            "postal-code" => address.postal_code.clone(),
+            "country" => normalize_country(address.country.clone()),
+            "country-name" => address.country.clone(),
+            "tel" => address.tel.clone(),
+            "tel-country-code" => tel_parts
+                .as_ref()
+                .map(|(code, _)| code.clone())
+                .unwrap_or_default(),
+            "tel-national" => tel_parts
+                .as_ref()
+                .map(|(_, national)| national.clone())
+                .unwrap_or_else(|| address.tel.clone()),
+            "email" => address.email.clone(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// Returns the value to fill in each of the classified fields, in the same
+/// order as the classifications. Fields which aren't credit-card fields are
+/// filled with an empty string. `key` is used to decrypt the card number, and
+/// must have come from `create_autofill_key()`.
+#[handle_error(Error)]
+pub fn credit_card_fill_values(
+    credit_card: CreditCard,
+    classifications: Vec<FieldClassification>,
+    key: String,
+) -> ApiResult<Vec<String>> {
+    let cc_number = if classifications.iter().any(|c| c.field_type == "cc-number") {
+        EncryptorDecryptor::new(&key)?.decrypt(&credit_card.cc_number_enc)?
+    } else {
+        String::new()
+    };
+    let name_parts = split_name(&credit_card.cc_name);
+    let month = if credit_card.cc_exp_month > 0 {
+        format!("{:02}", credit_card.cc_exp_month)
+    } else {
+        String::new()
+    };
+    let year = if credit_card.cc_exp_year > 0 {
+        credit_card.cc_exp_year.to_string()
+    } else {
+        String::new()
+    };
+    Ok(classifications
+        .iter()
+        .map(|c| match c.field_type.as_str() {
+            "cc-name" => credit_card.cc_name.clone(),
+            "cc-given-name" => name_parts.given.clone(),
+            "cc-additional-name" => name_parts.middle.clone(),
+            "cc-family-name" => name_parts.family.clone(),
+            "cc-number" => cc_number.clone(),
+            "cc-exp-month" => month.clone(),
+            "cc-exp-year" => year.clone(),
+            // The 2 digit year is by far the most common format.
+            "cc-exp" if !month.is_empty() && year.len() == 4 => {
+                format!("{}/{}", month, &year[2..])
+            }
+            "cc-type" => credit_card.cc_type.clone(),
+            _ => String::new(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristics::{classify_form_fields, FormField};
+    use nss::ensure_initialized;
+
+    fn fields(names: &[&str]) -> Vec<FormField> {
+        names
+            .iter()
+            .map(|name| FormField {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_address_fill_values() {
+        let address = Address {
+            name: "Jane Q Doe".to_string(),
+            street_address: "123 Main St\nApt 4\nBuilding 5".to_string(),
+            address_level2: "Springfield".to_string(),
+            address_level1: "IL".to_string(),
+            postal_code: "62701".to_string(),
+            country: "United States".to_string(),
+            tel: "(217) 555-0100".to_string(),
+            ..Default::default()
+        };
+        let classifications = classify_form_fields(fields(&[
+            "first_name",
+            "last_name",
+            "address1",
+            "address2",
+            "city",
+            "state",
+            "zip",
+            "country",
+            "phone",
+            "card_number",
+        ]));
+        assert_eq!(
+            address_fill_values(address.clone(), classifications),
+            vec![
+                "Jane",
+                "Doe",
+                "123 Main St",
+                "Apt 4 Building 5",
+                "Springfield",
+                "IL",
+                "62701",
+                "US",
+                "(217) 555-0100",
+                "",
+            ]
+        );
+
+        let classifications = classify_form_fields(vec![
+            FormField {
+                autocomplete: "address-line1".to_string(),
+                ..Default::default()
+            },
+            FormField {
+                autocomplete: "tel-country-code".to_string(),
+                ..Default::default()
+            },
+            FormField {
+                autocomplete: "tel-national".to_string(),
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(
+            address_fill_values(address, classifications),
+            vec!["123 Main St Apt 4 Building 5", "+1", "2175550100"]
+        );
+    }
+
+    #[test]
+    fn test_credit_card_fill_values() {
+        ensure_initialized();
+        let key = EncryptorDecryptor::create_key().unwrap();
+        let encdec = EncryptorDecryptor::new(&key).unwrap();
+        let credit_card = CreditCard {
+            cc_name: "jane doe".to_string(),
+            cc_number_enc: encdec.encrypt("4111111111111111").unwrap(),
+            cc_number_last_4: "1111".to_string(),
+            cc_exp_month: 3,
+            cc_exp_year: 2030,
+            cc_type: "visa".to_string(),
+            ..Default::default()
+        };
+        let classifications = classify_form_fields(fields(&[
+            "cardholder",
+            "cardnumber",
+            "exp_month",
+            "exp_year",
+            "expiry",
+            "cvc",
+            "email",
+        ]));
+        assert_eq!(
+            credit_card_fill_values(credit_card.clone(), classifications, key).unwrap(),
+            vec![
+                "jane doe",
+                "4111111111111111",
+                "03",
+                "2030",
+                "03/30",
+                "",
+                ""
+            ]
+        );
+
+        // The key is only needed for the card number.
+        let classifications = classify_form_fields(fields(&["cardholder"]));
+        assert_eq!(
+            credit_card_fill_values(credit_card.clone(), classifications, "".to_string()).unwrap(),
+            vec!["jane doe"]
+        );
+        let classifications = classify_form_fields(fields(&["cardnumber"]));
+        assert!(matches!(
+            credit_card_fill_values(credit_card, classifications, "".to_string()),
+            Err(AutofillApiError::CryptoError { .. })
+        ));
+    }
+}
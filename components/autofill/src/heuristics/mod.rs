@@ -0,0 +1,403 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// Form-field classification, based on desktop's `FormAutofillHeuristics`.
+//
+// Consumers describe the fields of a form and we work out which of them are
+// addresses and credit-card fields, using the autocomplete-attribute tokens
+// (https://html.spec.whatwg.org/multipage/form-control-infrastructure.html#autofill-field)
+// as the field types. The fill values for a classified form can then be taken
+// from an address or credit card - see the `fill` module.
+
+mod fill;
+mod rules;
+
+pub use fill::{address_fill_values, credit_card_fill_values};
+
+use rules::{
+    is_address_field_type, is_credit_card_field_type, is_known_field_type, IGNORED_KEYWORDS,
+    KEYWORD_RULES,
+};
+
+/// A field in a form, as described by the consumer.
+#[derive(Debug, Clone, Default)]
+pub struct FormField {
+    pub id: String,
+    pub name: String,
+    // The text of the field's `<label>`, or its `aria-label`.
+    pub label: String,
+    pub placeholder: String,
+    pub autocomplete: String,
+    // The `type` attribute of an `<input>`, or "select-one" for a `<select>`.
+    pub input_type: String,
+}
+
+/// What we think a field is. `field_type` is an autocomplete-attribute token
+/// (eg, "cc-number" or "address-line1"), or empty if the field isn't one we can
+/// fill.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldClassification {
+    pub field_type: String,
+    pub confidence: f64,
+}
+
+// How confident we are, depending on where the field type came from.
+const CONFIDENCE_AUTOCOMPLETE: f64 = 1.0;
+const CONFIDENCE_INPUT_TYPE: f64 = 0.9;
+const CONFIDENCE_NAME_OR_ID: f64 = 0.8;
+const CONFIDENCE_LABEL: f64 = 0.7;
+const CONFIDENCE_PLACEHOLDER: f64 = 0.6;
+const CONFIDENCE_CONTEXT: f64 = 0.5;
+
+// Input types which never hold a value we can fill.
+const UNFILLABLE_INPUT_TYPES: &[&str] = &[
+    "button", "checkbox", "file", "hidden", "image", "password", "radio", "reset", "submit",
+];
+
+impl FieldClassification {
+    fn new(field_type: &str, confidence: f64) -> Self {
+        Self {
+            field_type: field_type.to_string(),
+            confidence,
+        }
+    }
+
+    fn unknown() -> Self {
+        Self::default()
+    }
+
+    fn is_unknown(&self) -> bool {
+        self.field_type.is_empty()
+    }
+}
+
+// Splits text into lower-case words, including at camelCase boundaries - eg,
+// "billingZip_code" is ["billing", "zip", "code"].
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+// Returns the field type for some text (eg, the field's name) if it matches a
+// keyword rule.
+fn match_keywords(text: &str) -> Option<&'static str> {
+    let tokens = tokenize(text);
+    if tokens.is_empty() {
+        return None;
+    }
+    let compact = tokens.concat();
+    if IGNORED_KEYWORDS.iter().any(|k| compact.contains(k)) {
+        return None;
+    }
+    KEYWORD_RULES
+        .iter()
+        .find(|rule| {
+            rule.keywords.iter().any(|k| compact.contains(k))
+                || rule
+                    .tokens
+                    .iter()
+                    .any(|t| tokens.iter().any(|tok| tok == t))
+        })
+        .map(|rule| rule.field_type)
+}
+
+// Returns the field type from the autocomplete attribute, if it has one - the
+// field type is the last token, after any section and shipping/billing tokens.
+fn match_autocomplete(autocomplete: &str) -> Option<&'static str> {
+    let token = autocomplete.split_whitespace().last()?.to_lowercase();
+    rules::canonical_field_type(&token)
+}
+
+fn classify_field(field: &FormField) -> FieldClassification {
+    let input_type = field.input_type.trim().to_lowercase();
+    if UNFILLABLE_INPUT_TYPES.contains(&input_type.as_str()) {
+        return FieldClassification::unknown();
+    }
+    if let Some(field_type) = match_autocomplete(&field.autocomplete) {
+        return FieldClassification::new(field_type, CONFIDENCE_AUTOCOMPLETE);
+    }
+    match input_type.as_str() {
+        "email" => return FieldClassification::new("email", CONFIDENCE_INPUT_TYPE),
+        "tel" => return FieldClassification::new("tel", CONFIDENCE_INPUT_TYPE),
+        _ => (),
+    }
+    [
+        (&field.name, CONFIDENCE_NAME_OR_ID),
+        (&field.id, CONFIDENCE_NAME_OR_ID),
+        (&field.label, CONFIDENCE_LABEL),
+        (&field.placeholder, CONFIDENCE_PLACEHOLDER),
+    ]
+    .into_iter()
+    .find_map(|(text, confidence)| {
+        match_keywords(text).map(|field_type| FieldClassification::new(field_type, confidence))
+    })
+    .unwrap_or_else(FieldClassification::unknown)
+}
+
+// Adjusts the classifications based on the rest of the form - eg, a "name"
+// field in a form with a card number but no address is the name on the card.
+fn apply_form_context(fields: &[FormField], classifications: &mut [FieldClassification]) {
+    let has_cc_number = classifications.iter().any(|c| c.field_type == "cc-number");
+    // Names, emails and phone numbers are often part of a payment form, so
+    // they don't make it an address form.
+    let has_address = classifications.iter().any(|c| {
+        is_address_field_type(&c.field_type)
+            && !matches!(
+                c.field_type.as_str(),
+                "name" | "given-name" | "additional-name" | "family-name" | "email" | "tel"
+            )
+    });
+
+    if has_cc_number {
+        for (field, classification) in fields.iter().zip(classifications.iter_mut()) {
+            // Only fix up fields we guessed at - the autocomplete attribute is
+            // always right.
+            if classification.confidence >= CONFIDENCE_AUTOCOMPLETE {
+                continue;
+            }
+            if classification.is_unknown() {
+                let tokens: Vec<String> =
+                    [&field.name, &field.id, &field.label, &field.placeholder]
+                        .into_iter()
+                        .flat_map(|text| tokenize(text))
+                        .collect();
+                let has_token = |words: &[&str]| tokens.iter().any(|t| words.contains(&t.as_str()));
+                if has_token(&["month", "mm"]) {
+                    *classification = FieldClassification::new("cc-exp-month", CONFIDENCE_CONTEXT);
+                } else if has_token(&["year", "yy", "yyyy"]) {
+                    *classification = FieldClassification::new("cc-exp-year", CONFIDENCE_CONTEXT);
+                }
+            } else if !has_address {
+                let cc_type = match classification.field_type.as_str() {
+                    "name" => Some("cc-name"),
+                    "given-name" => Some("cc-given-name"),
+                    "additional-name" => Some("cc-additional-name"),
+                    "family-name" => Some("cc-family-name"),
+                    _ => None,
+                };
+                if let Some(cc_type) = cc_type {
+                    classification.field_type = cc_type.to_string();
+                    classification.confidence = classification.confidence.min(CONFIDENCE_CONTEXT);
+                }
+            }
+        }
+    }
+
+    // Forms often split the street address over multiple fields which all look
+    // like "address" - consecutive ones are the individual lines.
+    let mut i = 0;
+    while i < classifications.len() {
+        let run = classifications[i..]
+            .iter()
+            .take_while(|c| {
+                c.field_type == "street-address" && c.confidence < CONFIDENCE_AUTOCOMPLETE
+            })
+            .count();
+        if run > 1 {
+            for (line, classification) in classifications[i..i + run.min(3)].iter_mut().enumerate()
+            {
+                classification.field_type = format!("address-line{}", line + 1);
+            }
+        }
+        i += run.max(1);
+    }
+}
+
+/// Classifies each field of a form, returning the classifications in the same
+/// order as the fields.
+pub fn classify_form_fields(fields: Vec<FormField>) -> Vec<FieldClassification> {
+    let mut classifications: Vec<FieldClassification> = fields.iter().map(classify_field).collect();
+    apply_form_context(&fields, &mut classifications);
+    // Just in case the context produced something odd, only return types we
+    // know how to fill.
+    for classification in classifications.iter_mut() {
+        if !is_known_field_type(&classification.field_type) {
+            *classification = FieldClassification::unknown();
+        }
+    }
+    classifications
+}
+
+/// Returns true if any of the classified fields are credit-card fields - ie,
+/// the fill values should come from `credit_card_fill_values()` rather than
+/// `address_fill_values()`.
+pub fn is_credit_card_form(classifications: Vec<FieldClassification>) -> bool {
+    classifications
+        .iter()
+        .any(|c| is_credit_card_field_type(&c.field_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str) -> FormField {
+        FormField {
+            name: name.to_string(),
+            input_type: "text".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn field_types(fields: Vec<FormField>) -> Vec<String> {
+        classify_form_fields(fields)
+            .into_iter()
+            .map(|c| c.field_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("billingZip_code"), vec!["billing", "zip", "code"]);
+        assert_eq!(tokenize("CC-Number"), vec!["cc", "number"]);
+        assert_eq!(tokenize("address1"), vec!["address1"]);
+        assert!(tokenize("  ").is_empty());
+    }
+
+    #[test]
+    fn test_autocomplete() {
+        let classifications = classify_form_fields(vec![
+            FormField {
+                autocomplete: "section-foo shipping address-line1".to_string(),
+                name: "q".to_string(),
+                ..Default::default()
+            },
+            FormField {
+                autocomplete: "CC-Number".to_string(),
+                ..Default::default()
+            },
+            // Not a field type.
+            FormField {
+                autocomplete: "off".to_string(),
+                name: "email".to_string(),
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(
+            classifications,
+            vec![
+                FieldClassification::new("address-line1", 1.0),
+                FieldClassification::new("cc-number", 1.0),
+                FieldClassification::new("email", 0.8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_address_form() {
+        assert_eq!(
+            field_types(vec![
+                field("firstName"),
+                field("last_name"),
+                field("company"),
+                field("address"),
+                field("address"),
+                field("city"),
+                field("state"),
+                field("zip"),
+                field("country"),
+                FormField {
+                    name: "contact".to_string(),
+                    input_type: "tel".to_string(),
+                    ..Default::default()
+                },
+                field("e-mail"),
+                field("username"),
+                FormField {
+                    name: "address".to_string(),
+                    input_type: "hidden".to_string(),
+                    ..Default::default()
+                },
+            ]),
+            vec![
+                "given-name",
+                "family-name",
+                "organization",
+                "address-line1",
+                "address-line2",
+                "address-level2",
+                "address-level1",
+                "postal-code",
+                "country",
+                "tel",
+                "email",
+                "",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_credit_card_form() {
+        let fields = vec![
+            FormField {
+                label: "Name on card".to_string(),
+                ..field("")
+            },
+            field("cardNumber"),
+            field("month"),
+            field("year"),
+            FormField {
+                placeholder: "CVC".to_string(),
+                ..field("")
+            },
+        ];
+        let classifications = classify_form_fields(fields);
+        assert_eq!(
+            classifications,
+            vec![
+                FieldClassification::new("cc-name", 0.7),
+                FieldClassification::new("cc-number", 0.8),
+                FieldClassification::new("cc-exp-month", 0.5),
+                FieldClassification::new("cc-exp-year", 0.5),
+                FieldClassification::new("cc-csc", 0.6),
+            ]
+        );
+        assert!(is_credit_card_form(classifications));
+
+        // A plain "name" is the name on the card in a card form...
+        assert_eq!(
+            field_types(vec![field("name"), field("ccnum"), field("exp_date")]),
+            vec!["cc-name", "cc-number", "cc-exp"]
+        );
+        // ...but not when the form also has an address.
+        assert_eq!(
+            field_types(vec![field("name"), field("ccnum"), field("zip")]),
+            vec!["name", "cc-number", "postal-code"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let classifications = classify_form_fields(vec![
+            field("q"),
+            field("coupon_code"),
+            field("month"),
+            field(""),
+        ]);
+        assert!(classifications.iter().all(|c| c.is_unknown()));
+        assert!(classifications.iter().all(|c| c.confidence == 0.0));
+        assert!(!is_credit_card_form(classifications));
+    }
+}
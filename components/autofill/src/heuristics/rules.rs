@@ -0,0 +1,300 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// The field types we classify fields as, and the rules for doing so.
+
+// The autocomplete-attribute tokens we can fill from an address.
+const ADDRESS_FIELD_TYPES: &[&str] = &[
+    "name",
+    "given-name",
+    "additional-name",
+    "family-name",
+    "organization",
+    "street-address",
+    "address-line1",
+    "address-line2",
+    "address-line3",
+    "address-level3",
+    "address-level2",
+    "address-level1",
+    "postal-code",
+    "country",
+    "country-name",
+    "tel",
+    "tel-country-code",
+    "tel-national",
+    "email",
+];
+
+// The autocomplete-attribute tokens we can fill from a credit card. We never
+// store the security code, but still classify the field so the form is
+// recognized as a credit-card form.
+const CREDIT_CARD_FIELD_TYPES: &[&str] = &[
+    "cc-name",
+    "cc-given-name",
+    "cc-additional-name",
+    "cc-family-name",
+    "cc-number",
+    "cc-exp",
+    "cc-exp-month",
+    "cc-exp-year",
+    "cc-type",
+    "cc-csc",
+];
+
+pub(super) fn is_address_field_type(field_type: &str) -> bool {
+    ADDRESS_FIELD_TYPES.contains(&field_type)
+}
+
+pub(super) fn is_credit_card_field_type(field_type: &str) -> bool {
+    CREDIT_CARD_FIELD_TYPES.contains(&field_type)
+}
+
+pub(super) fn is_known_field_type(field_type: &str) -> bool {
+    field_type.is_empty()
+        || is_address_field_type(field_type)
+        || is_credit_card_field_type(field_type)
+}
+
+/// Returns the static version of a field type, or None if it's not one we know.
+pub(super) fn canonical_field_type(field_type: &str) -> Option<&'static str> {
+    ADDRESS_FIELD_TYPES
+        .iter()
+        .chain(CREDIT_CARD_FIELD_TYPES)
+        .copied()
+        .find(|t| *t == field_type)
+}
+
+pub(super) struct KeywordRule {
+    pub field_type: &'static str,
+    // Matched anywhere in the text, after it has been lower-cased and had
+    // everything other than letters and digits removed.
+    pub keywords: &'static [&'static str],
+    // Matched against whole words only - for short keywords which would
+    // otherwise match too much.
+    pub tokens: &'static [&'static str],
+}
+
+const fn rule(
+    field_type: &'static str,
+    keywords: &'static [&'static str],
+    tokens: &'static [&'static str],
+) -> KeywordRule {
+    KeywordRule {
+        field_type,
+        keywords,
+        tokens,
+    }
+}
+
+// Text matching any of these is never a field we fill, whatever else it
+// matches (eg, "username" isn't a name and "giftcardnumber" isn't a card).
+pub(super) const IGNORED_KEYWORDS: &[&str] = &[
+    "username", "login", "search", "captcha", "coupon", "promo", "voucher", "gift", "password",
+];
+
+// The first rule which matches wins, so more specific rules must come before
+// more general ones - eg, "nameoncard" before "name" and "address2" before
+// "address".
+pub(super) const KEYWORD_RULES: &[KeywordRule] = &[
+    rule(
+        "cc-name",
+        &[
+            "nameoncard",
+            "cardholder",
+            "ccname",
+            "cardname",
+            "holdername",
+            "cardowner",
+            "karteninhaber",
+        ],
+        &[],
+    ),
+    rule(
+        "cc-csc",
+        &["cvc", "cvv", "csc", "securitycode", "cvn", "ccv"],
+        &[],
+    ),
+    rule(
+        "cc-exp-month",
+        &[
+            "expmonth",
+            "expirymonth",
+            "expirationmonth",
+            "ccmonth",
+            "cardmonth",
+            "expmm",
+        ],
+        &[],
+    ),
+    rule(
+        "cc-exp-year",
+        &[
+            "expyear",
+            "expiryyear",
+            "expirationyear",
+            "ccyear",
+            "cardyear",
+            "expyy",
+        ],
+        &[],
+    ),
+    rule(
+        "cc-exp",
+        &[
+            "expiry",
+            "expiration",
+            "expdate",
+            "ccexp",
+            "validthru",
+            "validuntil",
+            "mmyy",
+            "ablaufdatum",
+        ],
+        &[],
+    ),
+    rule(
+        "cc-type",
+        &["cardtype", "cctype", "cardbrand", "cardnetwork"],
+        &[],
+    ),
+    rule(
+        "cc-number",
+        &[
+            "cardnumber",
+            "ccnumber",
+            "ccnum",
+            "cardno",
+            "creditcard",
+            "debitcard",
+            "numerodecarte",
+            "kartennummer",
+            "numerotarjeta",
+        ],
+        &[],
+    ),
+    rule("email", &["email", "courriel", "correo"], &["mail"]),
+    rule(
+        "tel",
+        &["phone", "mobile", "telephone", "telefon"],
+        &["tel", "cell", "mob"],
+    ),
+    rule(
+        "organization",
+        &[
+            "company",
+            "organization",
+            "organisation",
+            "business",
+            "firma",
+            "empresa",
+            "societe",
+        ],
+        &["org"],
+    ),
+    rule(
+        "postal-code",
+        &["zip", "postal", "postcode", "postleitzahl", "codepostal"],
+        &["plz", "cp"],
+    ),
+    rule("country", &["country", "countries"], &["pays", "land"]),
+    rule(
+        "address-line1",
+        &["address1", "addressline1", "addrline1", "line1", "street1"],
+        &[],
+    ),
+    rule(
+        "address-line2",
+        &[
+            "address2",
+            "addressline2",
+            "addrline2",
+            "line2",
+            "street2",
+            "apartment",
+            "suite",
+        ],
+        &["apt", "unit"],
+    ),
+    rule(
+        "address-line3",
+        &["address3", "addressline3", "addrline3", "line3", "street3"],
+        &[],
+    ),
+    rule(
+        "address-level3",
+        &[
+            "neighborhood",
+            "neighbourhood",
+            "district",
+            "dependentlocality",
+        ],
+        &[],
+    ),
+    rule(
+        "address-level2",
+        &[
+            "city",
+            "town",
+            "suburb",
+            "municipality",
+            "locality",
+            "ciudad",
+            "ville",
+            "stadt",
+        ],
+        &["ort"],
+    ),
+    rule(
+        "address-level1",
+        &["state", "province", "region", "bundesland"],
+        &[],
+    ),
+    rule(
+        "street-address",
+        &[
+            "address",
+            "street",
+            "addr",
+            "strasse",
+            "direccion",
+            "adresse",
+        ],
+        &[],
+    ),
+    rule(
+        "given-name",
+        &["firstname", "givenname", "forename", "prenom", "vorname"],
+        &["first", "fname"],
+    ),
+    rule(
+        "additional-name",
+        &["middlename", "additionalname"],
+        &["middle", "mname"],
+    ),
+    rule(
+        "family-name",
+        &["lastname", "familyname", "surname", "nachname", "apellido"],
+        &["last", "lname"],
+    ),
+    rule("name", &["fullname", "yourname", "name"], &[]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_are_known_field_types() {
+        for rule in KEYWORD_RULES {
+            assert_eq!(
+                canonical_field_type(rule.field_type),
+                Some(rule.field_type),
+                "{} is not a known field type",
+                rule.field_type
+            );
+        }
+    }
+}
@@ -0,0 +1,220 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+// IBAN (ISO 13616) helpers.
+//
+// Like credit-card numbers, the storage API only ever sees the encrypted IBAN,
+// so these are exposed to consumers so they can validate and derive the
+// display value before encrypting.
+
+// The length of an IBAN for each country which uses them, as per the SWIFT
+// IBAN registry.
+const IBAN_LENGTHS: &[(&str, usize)] = &[
+    ("AD", 24),
+    ("AE", 23),
+    ("AL", 28),
+    ("AT", 20),
+    ("AZ", 28),
+    ("BA", 20),
+    ("BE", 16),
+    ("BG", 22),
+    ("BH", 22),
+    ("BI", 27),
+    ("BR", 29),
+    ("BY", 28),
+    ("CH", 21),
+    ("CR", 22),
+    ("CY", 28),
+    ("CZ", 24),
+    ("DE", 22),
+    ("DJ", 27),
+    ("DK", 18),
+    ("DO", 28),
+    ("EE", 20),
+    ("EG", 29),
+    ("ES", 24),
+    ("FI", 18),
+    ("FK", 18),
+    ("FO", 18),
+    ("FR", 27),
+    ("GB", 22),
+    ("GE", 22),
+    ("GI", 23),
+    ("GL", 18),
+    ("GR", 27),
+    ("GT", 28),
+    ("HR", 21),
+    ("HU", 28),
+    ("IE", 22),
+    ("IL", 23),
+    ("IQ", 23),
+    ("IS", 26),
+    ("IT", 27),
+    ("JO", 30),
+    ("KW", 30),
+    ("KZ", 20),
+    ("LB", 28),
+    ("LC", 32),
+    ("LI", 21),
+    ("LT", 20),
+    ("LU", 20),
+    ("LV", 21),
+    ("LY", 25),
+    ("MC", 27),
+    ("MD", 24),
+    ("ME", 22),
+    ("MK", 19),
+    ("MN", 20),
+    ("MR", 27),
+    ("MT", 31),
+    ("MU", 30),
+    ("NI", 28),
+    ("NL", 18),
+    ("NO", 15),
+    ("OM", 23),
+    ("PK", 24),
+    ("PL", 28),
+    ("PS", 29),
+    ("PT", 25),
+    ("QA", 29),
+    ("RO", 24),
+    ("RS", 22),
+    ("RU", 33),
+    ("SA", 24),
+    ("SC", 31),
+    ("SD", 18),
+    ("SE", 24),
+    ("SI", 19),
+    ("SK", 24),
+    ("SM", 27),
+    ("SO", 23),
+    ("ST", 25),
+    ("SV", 28),
+    ("TL", 23),
+    ("TN", 24),
+    ("TR", 26),
+    ("UA", 29),
+    ("VA", 22),
+    ("VG", 24),
+    ("XK", 20),
+    ("YE", 30),
+];
+
+/// Returns the IBAN in its "electronic" format - ie, upper-case with all
+/// whitespace removed. IBANs are commonly written in groups of 4 characters.
+pub fn normalize_iban(iban: String) -> String {
+    iban.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Checks an IBAN is well-formed for its country and that its ISO 13616
+/// (mod-97) check digits are correct. The IBAN may be in either its printed
+/// or electronic format.
+pub fn is_valid_iban(iban: String) -> bool {
+    let iban = normalize_iban(iban);
+    if !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let Some(country) = iban.get(0..2) else {
+        return false;
+    };
+    match IBAN_LENGTHS.iter().find(|(code, _)| *code == country) {
+        Some((_, len)) if *len == iban.len() => (),
+        _ => return false,
+    }
+    if !iban[2..4].chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    // Move the country code and check digits to the end, then replace each
+    // letter with 2 digits (A = 10, ..., Z = 35). The IBAN is valid if the
+    // resulting number mod 97 is 1. The number is far too big to fit in an
+    // integer, so we compute the remainder as we go.
+    let remainder = iban[4..]
+        .chars()
+        .chain(iban[..4].chars())
+        .fold(0u32, |acc, c| {
+            let value = c.to_digit(36).expect("checked alphanumeric above");
+            if value < 10 {
+                (acc * 10 + value) % 97
+            } else {
+                (acc * 100 + value) % 97
+            }
+        });
+    remainder == 1
+}
+
+/// Returns the value to store in `iban_last_4` for the given IBAN.
+pub fn iban_last_4(iban: String) -> String {
+    let iban = normalize_iban(iban);
+    let start = iban.len().saturating_sub(4);
+    // `normalize_iban()` doesn't guarantee ascii, so find a char boundary.
+    let start = (start..=iban.len())
+        .find(|i| iban.is_char_boundary(*i))
+        .unwrap_or(iban.len());
+    iban[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_iban() {
+        assert_eq!(
+            normalize_iban("de89 3704 0044 0532 0130 00".to_string()),
+            "DE89370400440532013000"
+        );
+        assert_eq!(normalize_iban("".to_string()), "");
+    }
+
+    #[test]
+    fn test_valid_ibans() {
+        for iban in [
+            "DE89 3704 0044 0532 0130 00",
+            "GB29 NWBK 6016 1331 9268 19",
+            "FR14 2004 1010 0505 0001 3M02 606",
+            "NL91ABNA0417164300",
+            "be68 5390 0754 7034",
+            "NO9386011117947",
+        ] {
+            assert!(is_valid_iban(iban.to_string()), "{iban} should be valid");
+        }
+    }
+
+    #[test]
+    fn test_invalid_ibans() {
+        for iban in [
+            "",
+            "DE",
+            // Bad check digits.
+            "DE88 3704 0044 0532 0130 00",
+            // Transposed digits.
+            "DE89 3704 0044 0532 0103 00",
+            // Wrong length for the country.
+            "DE89 3704 0044 0532 0130 0",
+            // Unknown country.
+            "ZZ89 3704 0044 0532 0130 00",
+            // Non-numeric check digits.
+            "DEXX 3704 0044 0532 0130 00",
+            // Not alphanumeric.
+            "DE89-3704-0044-0532-0130-00",
+            "DE89 3704 0044 0532 0130 0é",
+        ] {
+            assert!(!is_valid_iban(iban.to_string()), "{iban} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_iban_last_4() {
+        assert_eq!(
+            iban_last_4("DE89 3704 0044 0532 0130 00".to_string()),
+            "3000"
+        );
+        assert_eq!(iban_last_4("abc".to_string()), "ABC");
+        assert_eq!(iban_last_4("".to_string()), "");
+    }
+}
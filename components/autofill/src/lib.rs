@@ -6,20 +6,37 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+pub mod address_format;
+pub mod credit_card;
 pub mod db;
 pub mod encryption;
 pub mod error;
+pub mod heuristics;
+pub mod iban;
 pub mod sync;
 
 // Re-export stuff the sync manager needs.
 pub use crate::db::store::get_registered_sync_engine;
 
 // Expose stuff needed by the uniffi generated code.
+use crate::address_format::{
+    format_address, normalize_country, normalize_postal_code, normalize_region, normalize_tel,
+};
+use crate::credit_card::{
+    credit_card_last_4, credit_card_network, is_valid_credit_card_number,
+    normalize_credit_card_number,
+};
 use crate::db::credit_cards::CreditCardsDeletionMetrics;
 use crate::db::models::address::*;
+use crate::db::models::bank_account::*;
 use crate::db::models::credit_card::*;
 use crate::db::store::Store;
 use crate::encryption::{create_autofill_key, decrypt_string, encrypt_string};
+use crate::heuristics::{
+    address_fill_values, classify_form_fields, credit_card_fill_values, is_credit_card_form,
+    FieldClassification, FormField,
+};
+use crate::iban::{iban_last_4, is_valid_iban, normalize_iban};
 pub use error::{ApiResult, AutofillApiError, Error, Result};
 
 uniffi::include_scaffolding!("autofill");
@@ -4,9 +4,9 @@
 */
 
 use super::AddressPayload;
-use crate::db::addresses::{add_internal_address, update_internal_address};
+use crate::db::addresses::{add_internal_address, find_duplicate_address, update_internal_address};
 use crate::db::models::address::InternalAddress;
-use crate::db::schema::ADDRESS_COMMON_COLS;
+use crate::db::DuplicateMatch;
 use crate::error::*;
 use crate::sync::address::name_utils::{join_name_parts, split_name, NameParts};
 use crate::sync::common::*;
@@ -15,7 +15,7 @@ use crate::sync::{
     ProcessIncomingRecordImpl, ServerTimestamp, SyncRecord,
 };
 use interrupt_support::Interruptee;
-use rusqlite::{named_params, Transaction};
+use rusqlite::Transaction;
 use sql_support::ConnExt;
 use sync_guid::Guid as SyncGuid;
 
@@ -251,56 +251,11 @@ impl ProcessIncomingRecordImpl for IncomingAddressesImpl {
         tx: &Transaction<'_>,
         incoming: &Self::Record,
     ) -> Result<Option<Self::Record>> {
-        let sql = format!("
-            SELECT
-                {common_cols},
-                sync_change_counter
-            FROM addresses_data
-            WHERE
-                -- `guid <> :guid` is a pre-condition for this being called, but...
-                guid <> :guid
-                -- only non-synced records are candidates, which means can't already be in the mirror.
-                AND guid NOT IN (
-                    SELECT guid
-                    FROM addresses_mirror
-                )
-                -- and sql can check the field values.
-                AND name == :name
-                AND organization == :organization
-                AND street_address == :street_address
-                AND address_level3 == :address_level3
-                AND address_level2 == :address_level2
-                AND address_level1 == :address_level1
-                AND postal_code == :postal_code
-                AND country == :country
-                AND tel == :tel
-                AND email == :email", common_cols = ADDRESS_COMMON_COLS);
-
-        let params = named_params! {
-            ":guid": incoming.guid,
-            ":name": incoming.name,
-            ":organization": incoming.organization,
-            ":street_address": incoming.street_address,
-            ":address_level3": incoming.address_level3,
-            ":address_level2": incoming.address_level2,
-            ":address_level1": incoming.address_level1,
-            ":postal_code": incoming.postal_code,
-            ":country": incoming.country,
-            ":tel": incoming.tel,
-            ":email": incoming.email,
-        };
-
-        let result = tx.query_row(&sql, params, |row| {
-            Ok(Self::Record::from_row(row).expect("wtf? '?' doesn't work :("))
-        });
-
-        match result {
-            Ok(r) => Ok(Some(r)),
-            Err(e) => match e {
-                rusqlite::Error::QueryReturnedNoRows => Ok(None),
-                _ => Err(Error::SqlError(e)),
-            },
-        }
+        find_duplicate_address(
+            tx,
+            &incoming.updatable_fields(),
+            DuplicateMatch::Unsynced(&incoming.guid),
+        )
     }
 
     fn update_local_record(
@@ -582,4 +537,35 @@ mod tests {
         let bso = record.clone().into_test_incoming_bso();
         do_test_staged_to_mirror(&ai, &tx, record, bso, "addresses_mirror");
     }
+
+    #[test]
+    fn test_get_local_dupe_normalized() {
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ai = IncomingAddressesImpl {};
+        let local_record = InternalAddress {
+            address_level1: "California".to_string(),
+            postal_code: "90016".to_string(),
+            country: "US".to_string(),
+            tel: "(323) 555-0100".to_string(),
+            ..test_record('C')
+        };
+        ai.insert_local_record(&tx, local_record.clone()).unwrap();
+
+        // The same address, formatted differently.
+        let mut incoming_record = InternalAddress {
+            address_level1: "CA".to_string(),
+            postal_code: "90016 ".to_string(),
+            country: "United States".to_string(),
+            tel: "+1 323 555 0100".to_string(),
+            ..test_record('C')
+        };
+        incoming_record.guid = SyncGuid::random();
+        let dupe = ai.get_local_dupe(&tx, &incoming_record).unwrap().unwrap();
+        assert_eq!(dupe.guid, local_record.guid);
+
+        // But a different phone number is a different address.
+        incoming_record.tel = "+1 323 555 0199".to_string();
+        assert!(ai.get_local_dupe(&tx, &incoming_record).unwrap().is_none());
+    }
 }
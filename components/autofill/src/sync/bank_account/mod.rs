@@ -0,0 +1,282 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+pub mod incoming;
+pub mod outgoing;
+
+use super::engine::{ConfigSyncEngine, EngineConfig, SyncEngineStorageImpl};
+use super::{
+    MergeResult, Metadata, ProcessIncomingRecordImpl, ProcessOutgoingRecordImpl, SyncRecord,
+    UnknownFields,
+};
+use crate::db::models::bank_account::InternalBankAccount;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::iban::{iban_last_4, normalize_iban};
+use crate::sync_merge_field_check;
+use incoming::IncomingBankAccountsImpl;
+use outgoing::OutgoingBankAccountsImpl;
+use rusqlite::Transaction;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use sync_guid::Guid;
+use types::Timestamp;
+
+// The engine.
+// Note that there's no `SyncEngineId` for this engine yet, as the server
+// collection doesn't exist - so it's not available via the sync manager, only
+// via `Store::create_bank_accounts_sync_engine()`.
+pub(crate) fn create_engine(store: Arc<crate::Store>) -> ConfigSyncEngine<InternalBankAccount> {
+    ConfigSyncEngine::new(
+        EngineConfig {
+            namespace: "bank_accounts".to_string(),
+            collection: "bankaccounts".into(),
+        },
+        store,
+        Box::new(BankAccountsEngineStorageImpl {}),
+    )
+}
+
+pub(super) struct BankAccountsEngineStorageImpl {}
+
+impl SyncEngineStorageImpl<InternalBankAccount> for BankAccountsEngineStorageImpl {
+    fn get_incoming_impl(
+        &self,
+        enc_key: &Option<String>,
+    ) -> Result<Box<dyn ProcessIncomingRecordImpl<Record = InternalBankAccount>>> {
+        let enc_key = match enc_key {
+            None => return Err(Error::MissingEncryptionKey),
+            Some(enc_key) => enc_key,
+        };
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        Ok(Box::new(IncomingBankAccountsImpl { encdec }))
+    }
+
+    fn reset_storage(&self, tx: &Transaction<'_>) -> Result<()> {
+        tx.execute_batch(
+            "DELETE FROM bank_accounts_mirror;
+            DELETE FROM bank_accounts_tombstones;",
+        )?;
+        Ok(())
+    }
+
+    fn get_outgoing_impl(
+        &self,
+        enc_key: &Option<String>,
+    ) -> Result<Box<dyn ProcessOutgoingRecordImpl<Record = InternalBankAccount>>> {
+        let enc_key = match enc_key {
+            None => return Err(Error::MissingEncryptionKey),
+            Some(enc_key) => enc_key,
+        };
+        let encdec = EncryptorDecryptor::new(enc_key)?;
+        Ok(Box::new(OutgoingBankAccountsImpl { encdec }))
+    }
+}
+
+// These structs are a representation of what's stored on the sync server for non-tombstone records.
+// (The actual server doesn't have `id` in the payload but instead in the envelope)
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub(crate) struct BankAccountPayload {
+    id: Guid,
+
+    // We use the same 'entry' object as addresses and credit-cards, so desktop
+    // can handle all autofill records the same way.
+    pub(super) entry: PayloadEntry,
+}
+
+// As for credit-cards, the sync payload contains the "unencrypted" IBAN, while
+// our internal structs have the iban_enc/iban_last_4 pair.
+#[derive(Default, Debug, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub(super) struct PayloadEntry {
+    pub nickname: String,
+    pub iban: String,
+    // metadata, named as for the other autofill records.
+    #[serde(rename = "timeCreated")]
+    pub time_created: Timestamp,
+    #[serde(rename = "timeLastUsed")]
+    pub time_last_used: Timestamp,
+    #[serde(rename = "timeLastModified")]
+    pub time_last_modified: Timestamp,
+    #[serde(rename = "timesUsed")]
+    pub times_used: i64,
+    pub version: u32, // always 1 for bank accounts
+    // Fields that the current schema did not expect, we store them only internally
+    // to round-trip them back to sync without processing them in any way
+    #[serde(flatten)]
+    pub unknown_fields: UnknownFields,
+}
+
+impl InternalBankAccount {
+    fn from_payload(p: BankAccountPayload, encdec: &EncryptorDecryptor) -> Result<Self> {
+        if p.entry.version != 1 {
+            return Err(Error::InvalidSyncPayload(format!(
+                "invalid version - {}",
+                p.entry.version
+            )));
+        }
+        let iban = normalize_iban(p.entry.iban);
+        // need to encrypt the cleartext in the sync record.
+        let iban_enc = encdec.encrypt(&iban)?;
+        let iban_last_4 = iban_last_4(iban);
+
+        Ok(InternalBankAccount {
+            guid: p.id,
+            nickname: p.entry.nickname,
+            iban_enc,
+            iban_last_4,
+            metadata: Metadata {
+                time_created: p.entry.time_created,
+                time_last_used: p.entry.time_last_used,
+                time_last_modified: p.entry.time_last_modified,
+                times_used: p.entry.times_used,
+                sync_change_counter: 0,
+            },
+        })
+    }
+
+    pub(crate) fn into_payload(self, encdec: &EncryptorDecryptor) -> Result<BankAccountPayload> {
+        let iban = encdec.decrypt(&self.iban_enc)?;
+        Ok(BankAccountPayload {
+            id: self.guid,
+            entry: PayloadEntry {
+                nickname: self.nickname,
+                iban,
+                time_created: self.metadata.time_created,
+                time_last_used: self.metadata.time_last_used,
+                time_last_modified: self.metadata.time_last_modified,
+                times_used: self.metadata.times_used,
+                version: 1,
+                unknown_fields: Default::default(),
+            },
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn into_test_incoming_bso(
+        self,
+        encdec: &EncryptorDecryptor,
+        unknown_fields: UnknownFields,
+    ) -> sync15::bso::IncomingBso {
+        let mut payload = self.into_payload(encdec).expect("is json");
+        payload.entry.unknown_fields = unknown_fields;
+        sync15::bso::IncomingBso::from_test_content(payload)
+    }
+}
+
+impl SyncRecord for InternalBankAccount {
+    fn record_name() -> &'static str {
+        "BankAccount"
+    }
+
+    fn id(&self) -> &Guid {
+        &self.guid
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Performs a three-way merge between an incoming, local, and mirror record.
+    /// If a merge cannot be successfully completed (ie, if we find the same
+    /// field has changed both locally and remotely since the last sync), the
+    /// local record data is returned with a new guid and updated sync metadata.
+    fn merge(incoming: &Self, local: &Self, mirror: &Option<Self>) -> MergeResult<Self> {
+        let mut merged_record: Self = Default::default();
+        // guids must be identical
+        assert_eq!(incoming.guid, local.guid);
+
+        if let Some(m) = mirror {
+            assert_eq!(incoming.guid, m.guid)
+        };
+
+        merged_record.guid = incoming.guid.clone();
+
+        sync_merge_field_check!(nickname, incoming, local, mirror, merged_record);
+        // As for credit-cards, the encrypted value differs each time it's
+        // encrypted, so this effectively compares the iban via the mirror.
+        sync_merge_field_check!(iban_enc, incoming, local, mirror, merged_record);
+        sync_merge_field_check!(iban_last_4, incoming, local, mirror, merged_record);
+
+        merged_record.metadata = incoming.metadata;
+        merged_record
+            .metadata
+            .merge(&local.metadata, mirror.as_ref().map(|m| m.metadata()));
+
+        MergeResult::Merged {
+            merged: merged_record,
+        }
+    }
+}
+
+/// Returns a record with the given local record's data but with a new guid and
+/// fresh sync metadata.
+fn get_forked_record(local_record: InternalBankAccount) -> InternalBankAccount {
+    let mut local_record_data = local_record;
+    local_record_data.guid = Guid::random();
+    local_record_data.metadata.time_created = Timestamp::now();
+    local_record_data.metadata.time_last_used = Timestamp::now();
+    local_record_data.metadata.time_last_modified = Timestamp::now();
+    local_record_data.metadata.times_used = 0;
+    local_record_data.metadata.sync_change_counter = 1;
+
+    local_record_data
+}
+
+#[test]
+fn test_to_from_payload() {
+    nss::ensure_initialized();
+    let key = crate::encryption::create_autofill_key().unwrap();
+    let iban = "GB29NWBK60161331926819";
+    let iban_enc = crate::encryption::encrypt_string(key.clone(), iban.to_string()).unwrap();
+    let bank_account = InternalBankAccount {
+        nickname: "Current account".to_string(),
+        iban_enc,
+        iban_last_4: "6819".to_string(),
+        ..Default::default()
+    };
+    let encdec = EncryptorDecryptor::new(&key).unwrap();
+    let payload: BankAccountPayload = bank_account.clone().into_payload(&encdec).unwrap();
+
+    assert_eq!(payload.id, bank_account.guid);
+    assert_eq!(payload.entry.nickname, "Current account".to_string());
+    assert_eq!(payload.entry.iban, iban.to_string());
+    assert_eq!(payload.entry.version, 1);
+
+    // and back.
+    let bank_account2 = InternalBankAccount::from_payload(payload, &encdec).unwrap();
+    assert_eq!(bank_account2.guid, bank_account.guid);
+    assert_eq!(bank_account2.nickname, bank_account.nickname);
+    assert_eq!(bank_account2.iban_last_4, bank_account.iban_last_4);
+    assert_eq!(
+        crate::encryption::decrypt_string(key, bank_account2.iban_enc.clone()).unwrap(),
+        iban
+    );
+}
+
+#[test]
+fn test_from_payload_normalizes_iban() {
+    nss::ensure_initialized();
+    let encdec = EncryptorDecryptor::new_with_random_key().unwrap();
+    let payload: BankAccountPayload = serde_json::from_value(serde_json::json!({
+        "id": "AAAAAAAAAAAA",
+        "entry": {
+            "nickname": "Savings",
+            "iban": "gb29 nwbk 6016 1331 9268 19",
+            "version": 1,
+        }
+    }))
+    .unwrap();
+    let bank_account = InternalBankAccount::from_payload(payload, &encdec).unwrap();
+    assert_eq!(bank_account.iban_last_4, "6819");
+    assert_eq!(
+        encdec.decrypt(&bank_account.iban_enc).unwrap(),
+        "GB29NWBK60161331926819"
+    );
+}
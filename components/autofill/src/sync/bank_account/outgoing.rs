@@ -0,0 +1,217 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+use crate::db::models::bank_account::InternalBankAccount;
+use crate::db::schema::BANK_ACCOUNT_COMMON_COLS;
+use crate::encryption::EncryptorDecryptor;
+use crate::error::*;
+use crate::sync::common::*;
+use crate::sync::{bank_account::BankAccountPayload, OutgoingBso, ProcessOutgoingRecordImpl};
+use rusqlite::{Row, Transaction};
+use sync_guid::Guid as SyncGuid;
+
+const DATA_TABLE_NAME: &str = "bank_accounts_data";
+const MIRROR_TABLE_NAME: &str = "bank_accounts_mirror";
+const STAGING_TABLE_NAME: &str = "bank_accounts_sync_outgoing_staging";
+
+pub(super) struct OutgoingBankAccountsImpl {
+    pub(super) encdec: EncryptorDecryptor,
+}
+
+impl ProcessOutgoingRecordImpl for OutgoingBankAccountsImpl {
+    type Record = InternalBankAccount;
+
+    /// Gets the local records that have unsynced changes or don't have corresponding mirror
+    /// records and upserts them to the mirror table
+    fn fetch_outgoing_records(&self, tx: &Transaction<'_>) -> anyhow::Result<Vec<OutgoingBso>> {
+        let data_sql = format!(
+            "SELECT
+                l.{common_cols},
+                m.payload,
+                l.sync_change_counter
+            FROM bank_accounts_data l
+            LEFT JOIN bank_accounts_mirror m
+            ON l.guid = m.guid
+            WHERE
+                l.iban_enc <> ''
+            AND
+                (
+                    sync_change_counter > 0 OR
+                    l.guid NOT IN (
+                        SELECT m.guid
+                        FROM bank_accounts_mirror m
+                    )
+                )",
+            common_cols = BANK_ACCOUNT_COMMON_COLS,
+        );
+        let record_from_data_row: &dyn Fn(&Row<'_>) -> Result<(OutgoingBso, i64)> = &|row| {
+            let mut record = InternalBankAccount::from_row(row)?.into_payload(&self.encdec)?;
+            // If the server had unknown fields we fetch it and add it to the record
+            if let Some(enc_s) = row.get::<_, Option<String>>("payload")? {
+                // The full payload in the bank accounts mirror is encrypted
+                let mirror_payload: BankAccountPayload =
+                    serde_json::from_str(&self.encdec.decrypt(&enc_s)?)?;
+                record.entry.unknown_fields = mirror_payload.entry.unknown_fields;
+            };
+
+            Ok((
+                OutgoingBso::from_content_with_id(record)?,
+                row.get::<_, i64>("sync_change_counter")?,
+            ))
+        };
+
+        let tombstones_sql = "SELECT guid FROM bank_accounts_tombstones";
+
+        // save outgoing records to the mirror table
+        let staging_records = common_get_outgoing_staging_records(
+            tx,
+            &data_sql,
+            tombstones_sql,
+            record_from_data_row,
+        )?
+        .into_iter()
+        .map(|(bso, change_counter)| {
+            // Turn the record into an encrypted repr to save in the mirror.
+            let encrypted = self.encdec.encrypt(&bso.payload)?;
+            Ok((bso.envelope.id, encrypted, change_counter))
+        })
+        .collect::<Result<_>>()?;
+        common_save_outgoing_records(tx, STAGING_TABLE_NAME, staging_records)?;
+
+        // return outgoing changes
+        Ok(
+            common_get_outgoing_records(tx, &data_sql, tombstones_sql, record_from_data_row)?
+                .into_iter()
+                .map(|(bso, _change_counter)| bso)
+                .collect::<Vec<OutgoingBso>>(),
+        )
+    }
+
+    fn finish_synced_items(
+        &self,
+        tx: &Transaction<'_>,
+        records_synced: Vec<SyncGuid>,
+    ) -> anyhow::Result<()> {
+        common_finish_synced_items(
+            tx,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+            records_synced,
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::bank_accounts::{add_internal_bank_account, tests::test_insert_mirror_record};
+    use crate::sync::{common::tests::*, test::new_syncable_mem_db};
+    use nss::ensure_initialized;
+    use sync_guid::Guid;
+    use types::Timestamp;
+
+    fn test_record(encdec: &EncryptorDecryptor) -> InternalBankAccount {
+        InternalBankAccount {
+            guid: Guid::new(&expand_test_guid('C')),
+            nickname: "Current account".to_string(),
+            iban_enc: encdec.encrypt("DE89370400440532013000").unwrap(),
+            iban_last_4: "3000".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_outgoing_never_synced() {
+        ensure_initialized();
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ro = OutgoingBankAccountsImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let test_record = test_record(&ro.encdec);
+
+        // create data record
+        assert!(add_internal_bank_account(&tx, &test_record).is_ok());
+        do_test_outgoing_never_synced(
+            &tx,
+            &ro,
+            &test_record.guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+
+    #[test]
+    fn test_outgoing_tombstone() {
+        ensure_initialized();
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ro = OutgoingBankAccountsImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+        let test_record = test_record(&ro.encdec);
+
+        // create tombstone record
+        assert!(tx
+            .execute(
+                "INSERT INTO bank_accounts_tombstones (
+                    guid,
+                    time_deleted
+                ) VALUES (
+                    :guid,
+                    :time_deleted
+                )",
+                rusqlite::named_params! {
+                    ":guid": test_record.guid,
+                    ":time_deleted": Timestamp::now(),
+                },
+            )
+            .is_ok());
+        do_test_outgoing_tombstone(
+            &tx,
+            &ro,
+            &test_record.guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+
+    #[test]
+    fn test_outgoing_synced_with_local_change() {
+        ensure_initialized();
+        let mut db = new_syncable_mem_db();
+        let tx = db.transaction().expect("should get tx");
+        let ro = OutgoingBankAccountsImpl {
+            encdec: EncryptorDecryptor::new_with_random_key().unwrap(),
+        };
+
+        // create synced record with non-zero sync_change_counter
+        let mut test_record = test_record(&ro.encdec);
+        let initial_change_counter_val = 2;
+        test_record.metadata.sync_change_counter = initial_change_counter_val;
+        assert!(add_internal_bank_account(&tx, &test_record).is_ok());
+        let guid = test_record.guid.clone();
+        // test_insert_mirror_record doesn't encrypt the mirror payload, but in reality we do
+        // so we encrypt here so our fetch_outgoing_records doesn't break
+        let mut bso = test_record.into_test_incoming_bso(&ro.encdec, Default::default());
+        bso.payload = ro.encdec.encrypt(&bso.payload).unwrap();
+        test_insert_mirror_record(&tx, bso);
+        exists_with_counter_value_in_table(&tx, DATA_TABLE_NAME, &guid, initial_change_counter_val);
+
+        do_test_outgoing_synced_with_local_change(
+            &tx,
+            &ro,
+            &guid,
+            DATA_TABLE_NAME,
+            MIRROR_TABLE_NAME,
+            STAGING_TABLE_NAME,
+        );
+    }
+}
@@ -4,9 +4,11 @@
 */
 
 use super::CreditCardPayload;
-use crate::db::credit_cards::{add_internal_credit_card, update_internal_credit_card};
+use crate::db::credit_cards::{
+    add_internal_credit_card, find_duplicate_credit_card, update_internal_credit_card,
+};
 use crate::db::models::credit_card::InternalCreditCard;
-use crate::db::schema::CREDIT_CARD_COMMON_COLS;
+use crate::db::DuplicateMatch;
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
 use crate::sync::common::*;
@@ -15,7 +17,7 @@ use crate::sync::{
     ProcessIncomingRecordImpl, ServerTimestamp, SyncRecord,
 };
 use interrupt_support::Interruptee;
-use rusqlite::{named_params, Transaction};
+use rusqlite::Transaction;
 use sql_support::ConnExt;
 use sync_guid::Guid as SyncGuid;
 
@@ -172,49 +174,12 @@ impl ProcessIncomingRecordImpl for IncomingCreditCardsImpl {
         tx: &Transaction<'_>,
         incoming: &Self::Record,
     ) -> Result<Option<Self::Record>> {
-        let sql = format!("
-            SELECT
-                {common_cols},
-                sync_change_counter
-            FROM credit_cards_data
-            WHERE
-                -- `guid <> :guid` is a pre-condition for this being called, but...
-                guid <> :guid
-                -- only non-synced records are candidates, which means can't already be in the mirror.
-                AND guid NOT IN (
-                    SELECT guid
-                    FROM credit_cards_mirror
-                )
-                -- and sql can check the field values (but note we can not meaningfully
-                -- check the encrypted value, as it's different each time it is encrypted)
-                AND cc_name == :cc_name
-                AND cc_number_last_4 == :cc_number_last_4
-                AND cc_exp_month == :cc_exp_month
-                AND cc_exp_year == :cc_exp_year
-                AND cc_type == :cc_type", common_cols = CREDIT_CARD_COMMON_COLS);
-
-        let params = named_params! {
-            ":guid": incoming.guid,
-            ":cc_name": incoming.cc_name,
-            ":cc_number_last_4": incoming.cc_number_last_4,
-            ":cc_exp_month": incoming.cc_exp_month,
-            ":cc_exp_year": incoming.cc_exp_year,
-            ":cc_type": incoming.cc_type,
-        };
-
-        // Because we can't check the number in the sql, we fetch all matching
-        // rows and decrypt the numbers here.
-        let records = tx.query_rows_and_then(&sql, params, |row| -> Result<Self::Record> {
-            Ok(Self::Record::from_row(row)?)
-        })?;
-
-        let incoming_cc_number = self.encdec.decrypt(&incoming.cc_number_enc)?;
-        for record in records {
-            if self.encdec.decrypt(&record.cc_number_enc)? == incoming_cc_number {
-                return Ok(Some(record));
-            }
-        }
-        Ok(None)
+        find_duplicate_credit_card(
+            tx,
+            &incoming.updatable_fields(),
+            DuplicateMatch::Unsynced(&incoming.guid),
+            &self.encdec,
+        )
     }
 
     fn update_local_record(
@@ -11,6 +11,7 @@ use super::{
     MergeResult, Metadata, ProcessIncomingRecordImpl, ProcessOutgoingRecordImpl, SyncRecord,
     UnknownFields,
 };
+use crate::credit_card::{normalize_credit_card_exp_year, normalize_credit_card_type};
 use crate::db::models::credit_card::InternalCreditCard;
 use crate::encryption::EncryptorDecryptor;
 use crate::error::*;
@@ -125,6 +126,12 @@ impl InternalCreditCard {
         // need to encrypt the cleartext in the sync record.
         let cc_number_enc = encdec.encrypt(&p.entry.cc_number)?;
         let cc_number_last_4 = get_last_4(&p.entry.cc_number);
+        // Normalize the fields as we do for cards added locally, so that the
+        // same card from different clients compares equal. Unlike local cards,
+        // a year we can't make sense of is kept rather than failing the sync.
+        let cc_exp_year =
+            normalize_credit_card_exp_year(p.entry.cc_exp_year).unwrap_or(p.entry.cc_exp_year);
+        let cc_type = normalize_credit_card_type(&p.entry.cc_type);
 
         Ok(InternalCreditCard {
             guid: p.id,
@@ -132,8 +139,8 @@ impl InternalCreditCard {
             cc_number_enc,
             cc_number_last_4,
             cc_exp_month: p.entry.cc_exp_month,
-            cc_exp_year: p.entry.cc_exp_year,
-            cc_type: p.entry.cc_type,
+            cc_exp_year,
+            cc_type,
             metadata: Metadata {
                 time_created: p.entry.time_created,
                 time_last_used: p.entry.time_last_used,
@@ -297,3 +304,30 @@ fn test_to_from_payload() {
     // But the encrypted value should not.
     assert_ne!(cc2.cc_number_enc, cc.cc_number_enc);
 }
+
+#[test]
+fn test_from_payload_normalizes() {
+    nss::ensure_initialized();
+    let key = crate::encryption::create_autofill_key().unwrap();
+    let encdec = EncryptorDecryptor::new(&key).unwrap();
+    let payload = |cc_exp_year: i64, cc_type: &str| {
+        InternalCreditCard {
+            cc_number_enc: encdec.encrypt("4111111111111111").unwrap(),
+            cc_exp_year,
+            cc_type: cc_type.to_string(),
+            ..Default::default()
+        }
+        .into_payload(&encdec)
+        .unwrap()
+    };
+
+    let cc = InternalCreditCard::from_payload(payload(31, "American Express"), &encdec).unwrap();
+    assert_eq!(cc.cc_exp_year, 2031);
+    assert_eq!(cc.cc_type, "amex");
+
+    // Networks we don't know about are kept, and years we can't make sense of
+    // don't fail the sync.
+    let cc = InternalCreditCard::from_payload(payload(-1, "Maestro"), &encdec).unwrap();
+    assert_eq!(cc.cc_exp_year, -1);
+    assert_eq!(cc.cc_type, "maestro");
+}
@@ -4,6 +4,7 @@
 */
 
 pub mod address;
+pub mod bank_account;
 mod common;
 pub mod credit_card;
 pub mod engine;
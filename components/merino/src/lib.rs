@@ -7,10 +7,13 @@
 //!
 //! It provides a [`CuratedRecommendationsClient`](curated_recommendations::CuratedRecommendationsClient)
 //! that fetches curated content recommendations (articles, stories) from the Merino backend,
-//! powering features like Firefox's New Tab page.
+//! powering features like Firefox's New Tab page, and a
+//! [`MerinoSuggestClient`](suggest::MerinoSuggestClient) that fetches online search suggestions
+//! as the user types in the address bar.
 //!
 //! This crate uses [UniFFI](https://mozilla.github.io/uniffi-rs/) to generate cross-platform
 //! bindings for Android and other targets.
 
 pub mod curated_recommendations;
+pub mod suggest;
 uniffi::setup_scaffolding!("merino");
@@ -0,0 +1,81 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use error_support::{ErrorHandling, GetErrorHandling};
+// Re-export logging helpers.
+pub use error_support::trace;
+
+/// Internal convenience wrapper for `std::Result`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Public API result type using [`MerinoSuggestApiError`], exposed via UniFFI.
+pub type ApiResult<T> = std::result::Result<T, MerinoSuggestApiError>;
+
+/// Public error type exposed to consumers via UniFFI.
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+pub enum MerinoSuggestApiError {
+    /// A network-level failure (e.g. DNS resolution, connection timeout).
+    #[error("Merino suggest network error: {reason}")]
+    Network { reason: String },
+
+    /// Any other error, including HTTP errors and deserialization failures.
+    #[error("Merino suggest error: code {code:?}, reason: {reason}")]
+    Other { code: Option<u16>, reason: String },
+}
+
+/// Internal error type with fine-grained variants for different failure modes.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Failed to parse a URL.
+    #[error("URL parse error: {0}")]
+    UrlParse(#[from] url::ParseError),
+
+    /// Failed to send the HTTP request.
+    #[error("Error sending request: {0}")]
+    Request(#[from] viaduct::ViaductError),
+
+    /// Failed to deserialize the response.
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The server returned an HTTP error status.
+    #[error("HTTP error ({code}): {message}")]
+    Http { code: u16, message: String },
+
+    /// An OHTTP channel was requested, but this build doesn't support OHTTP.
+    #[error("OHTTP is not supported in this build (channel: {0})")]
+    OhttpUnsupported(String),
+
+    /// The client was configured incorrectly.
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+impl GetErrorHandling for Error {
+    type ExternalError = MerinoSuggestApiError;
+
+    fn get_error_handling(&self) -> ErrorHandling<Self::ExternalError> {
+        match self {
+            Self::Request { .. } => ErrorHandling::convert(MerinoSuggestApiError::Network {
+                reason: self.to_string(),
+            })
+            .log_warning(),
+
+            Self::Http { code, .. } => ErrorHandling::convert(MerinoSuggestApiError::Other {
+                code: Some(*code),
+                reason: self.to_string(),
+            })
+            .report_error("merino-suggest-http-error"),
+
+            Self::UrlParse(_)
+            | Self::Json(_)
+            | Self::OhttpUnsupported(_)
+            | Self::InvalidConfig(_) => ErrorHandling::convert(MerinoSuggestApiError::Other {
+                code: None,
+                reason: self.to_string(),
+            })
+            .report_error("merino-suggest-unexpected"),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::error::{trace, Error, Result};
+use super::models::{MerinoSuggestRequest, MerinoSuggestResponse};
+use url::Url;
+use viaduct::{header_names, Client, ClientSettings, Request};
+
+/// Sends requests to the Merino suggest API via `viaduct`, optionally through an OHTTP channel.
+pub struct HttpClient {
+    client: Client,
+}
+
+impl HttpClient {
+    pub fn new(user_agent_header: &str, ohttp_channel: Option<&str>) -> Result<Self> {
+        let settings = ClientSettings {
+            user_agent: Some(user_agent_header.to_string()),
+            ..ClientSettings::default()
+        };
+        let client = match ohttp_channel {
+            #[cfg(feature = "ohttp")]
+            Some(channel) => Client::with_ohttp_channel(channel, settings)?,
+            #[cfg(not(feature = "ohttp"))]
+            Some(channel) => return Err(Error::OhttpUnsupported(channel.to_string())),
+            None => Client::new(settings),
+        };
+        Ok(Self { client })
+    }
+
+    /// Sends a GET request to the suggest endpoint and parses the response.
+    pub fn make_suggest_request(
+        &self,
+        request: &MerinoSuggestRequest,
+        mut url: Url,
+    ) -> Result<MerinoSuggestResponse> {
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("q", &request.query);
+            if !request.providers.is_empty() {
+                query.append_pair("providers", &request.providers.join(","));
+            }
+            if !request.client_variants.is_empty() {
+                query.append_pair("client_variants", &request.client_variants.join(","));
+            }
+            if let Some(session_id) = &request.session_id {
+                query.append_pair("sid", session_id);
+            }
+            if let Some(sequence_number) = request.sequence_number {
+                query.append_pair("seq", &sequence_number.to_string());
+            }
+        }
+        trace!("making request: {url}");
+        let response = self
+            .client
+            .send_sync(Request::get(url).header(header_names::ACCEPT, "application/json")?)?;
+
+        let status = response.status;
+        if status >= 400 {
+            return Err(Error::Http {
+                code: status,
+                message: response.text().to_string(),
+            });
+        }
+        Ok(response.json()?)
+    }
+}
@@ -0,0 +1,74 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+* License, v. 2.0. If a copy of the MPL was not distributed with this
+* file, You can obtain one at http://mozilla.org/MPL/2.0/.
+*/
+
+//! Client for fetching online search suggestions from the Merino service.
+//!
+//! This module provides [`MerinoSuggestClient`], which queries the Merino `/api/v1/suggest`
+//! endpoint as the user types, the same way Firefox Desktop's address bar does. Requests can
+//! be sent directly or through one of viaduct's OHTTP channels, so that Merino can't link the
+//! queries to the user's IP address.
+
+mod error;
+mod http;
+pub mod models;
+#[cfg(test)]
+mod tests;
+
+pub use error::{ApiResult, Error, MerinoSuggestApiError, Result};
+use error_support::handle_error;
+pub use models::{
+    MerinoSuggestConfig, MerinoSuggestRequest, MerinoSuggestResponse, MerinoSuggestion,
+};
+use url::Url;
+
+/// Default base host for the Merino suggest API.
+const DEFAULT_BASE_HOST: &str = "https://merino.services.mozilla.com";
+
+/// Client for fetching online suggestions from the Merino service.
+///
+/// Construct using [`MerinoSuggestClient::new`] with a [`MerinoSuggestConfig`], then call
+/// [`fetch`](MerinoSuggestClient::fetch) for each query.
+#[derive(uniffi::Object)]
+pub struct MerinoSuggestClient {
+    http_client: http::HttpClient,
+    endpoint_url: Url,
+}
+
+#[uniffi::export]
+impl MerinoSuggestClient {
+    /// Creates a new client from the given configuration.
+    ///
+    /// Returns an error if the `user_agent_header` is empty, the base host is invalid, or the
+    /// OHTTP channel isn't configured.
+    #[uniffi::constructor]
+    #[handle_error(Error)]
+    pub fn new(config: MerinoSuggestConfig) -> ApiResult<Self> {
+        if config.user_agent_header.is_empty() {
+            return Err(Error::InvalidConfig(
+                "user_agent_header must be provided".to_string(),
+            ));
+        }
+        let base_host = config
+            .base_host
+            .unwrap_or_else(|| DEFAULT_BASE_HOST.to_string());
+        let endpoint_url = Url::parse(&format!("{}/api/v1/suggest", base_host))?;
+        Ok(Self {
+            http_client: http::HttpClient::new(
+                &config.user_agent_header,
+                config.ohttp_channel.as_deref(),
+            )?,
+            endpoint_url,
+        })
+    }
+
+    /// Fetches suggestions for a query from the Merino API.
+    ///
+    /// This blocks until the request completes, so it should be called from a worker thread.
+    #[handle_error(Error)]
+    pub fn fetch(&self, request: &MerinoSuggestRequest) -> ApiResult<MerinoSuggestResponse> {
+        self.http_client
+            .make_suggest_request(request, self.endpoint_url.clone())
+    }
+}
@@ -0,0 +1,97 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use serde::{Deserialize, Deserializer};
+
+/// Configuration options for initializing a [`MerinoSuggestClient`](crate::suggest::MerinoSuggestClient).
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct MerinoSuggestConfig {
+    /// Optional custom base host URL. Defaults to the production Merino service if `None`.
+    #[uniffi(default = None)]
+    pub base_host: Option<String>,
+    /// The `User-Agent` header value to send with API requests.
+    pub user_agent_header: String,
+    /// Optional name of a viaduct OHTTP channel (e.g. `"merino"`) to send requests through.
+    /// The channel must already be configured, for example with
+    /// `configure_default_ohttp_channels()`.
+    #[uniffi(default = None)]
+    pub ohttp_channel: Option<String>,
+}
+
+/// Parameters for requesting suggestions from the Merino API.
+#[derive(Debug, Clone, Default, PartialEq, uniffi::Record)]
+pub struct MerinoSuggestRequest {
+    /// The query typed by the user.
+    pub query: String,
+    /// Providers to request suggestions from (e.g. `"wikipedia"`, `"adm"`). Merino uses its
+    /// default providers if this is empty.
+    #[uniffi(default)]
+    pub providers: Vec<String>,
+    /// Client-side experiment variants, for server-side A/B testing.
+    #[uniffi(default)]
+    pub client_variants: Vec<String>,
+    /// Optional identifier for the search session, shared by all the requests in the session.
+    #[uniffi(default = None)]
+    pub session_id: Option<String>,
+    /// Optional position of this request within the search session, starting at 0.
+    #[uniffi(default = None)]
+    pub sequence_number: Option<u32>,
+}
+
+/// Suggestions returned from the Merino API.
+#[derive(Debug, Clone, PartialEq, Deserialize, uniffi::Record)]
+pub struct MerinoSuggestResponse {
+    /// Identifier for the request, used for telemetry.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// The suggestions, in the order Merino ranked them.
+    pub suggestions: Vec<MerinoSuggestion>,
+}
+
+/// A single suggestion returned from the Merino API.
+#[derive(Debug, Clone, PartialEq, Deserialize, uniffi::Record)]
+pub struct MerinoSuggestion {
+    /// The provider that produced the suggestion (e.g. `"adm"`, `"wikipedia"`).
+    pub provider: String,
+    /// Title to display for the suggestion.
+    pub title: String,
+    /// Destination URL of the suggestion.
+    pub url: String,
+    /// Ranking score assigned by Merino.
+    pub score: f64,
+    /// Whether the suggestion is sponsored.
+    #[serde(default)]
+    pub is_sponsored: bool,
+    /// Optional URL of the suggestion's icon.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Optional full keyword matched by the query.
+    #[serde(default)]
+    pub full_keyword: Option<String>,
+    /// Optional block identifier for sponsored suggestions.
+    #[serde(default)]
+    pub block_id: Option<i64>,
+    /// Optional advertiser name for sponsored suggestions.
+    #[serde(default)]
+    pub advertiser: Option<String>,
+    /// Optional URL to ping when the suggestion is shown.
+    #[serde(default)]
+    pub impression_url: Option<String>,
+    /// Optional URL to ping when the suggestion is clicked.
+    #[serde(default)]
+    pub click_url: Option<String>,
+    /// Optional provider-specific details, as a JSON string.
+    #[serde(default, deserialize_with = "deserialize_json_string")]
+    pub custom_details: Option<String>,
+}
+
+/// Deserializes any JSON value into its string form, so that provider-specific
+/// objects can be passed through to consumers as-is.
+fn deserialize_json_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<serde_json::Value>::deserialize(deserializer)?.map(|value| value.to_string()))
+}
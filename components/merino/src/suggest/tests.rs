@@ -0,0 +1,151 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+use super::*;
+use mockito::{mock, Matcher};
+
+fn client() -> MerinoSuggestClient {
+    viaduct_dev::init_backend_dev();
+    MerinoSuggestClient::new(MerinoSuggestConfig {
+        base_host: Some(mockito::server_url()),
+        user_agent_header: "test-agent/1.0".to_string(),
+        ohttp_channel: None,
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_fetch() {
+    let m = mock("GET", "/api/v1/suggest")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("q".into(), "los pollos".into()),
+            Matcher::UrlEncoded("providers".into(), "adm,wikipedia".into()),
+            Matcher::UrlEncoded("sid".into(), "session-1".into()),
+            Matcher::UrlEncoded("seq".into(), "2".into()),
+        ]))
+        .match_header("accept", "application/json")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{
+                "request_id": "abc",
+                "suggestions": [
+                    {
+                        "provider": "adm",
+                        "title": "Los Pollos Hermanos",
+                        "url": "https://www.lph-nm.biz",
+                        "score": 0.3,
+                        "is_sponsored": true,
+                        "block_id": 100,
+                        "advertiser": "Los Pollos Hermanos",
+                        "custom_details": {"amo": {"rating": "4.5"}},
+                        "unknown_field": 1
+                    },
+                    {
+                        "provider": "wikipedia",
+                        "title": "Los Pollos Hermanos - Wikipedia",
+                        "url": "https://en.wikipedia.org/wiki/Los_Pollos_Hermanos",
+                        "score": 0.2
+                    }
+                ]
+            }"#,
+        )
+        .create();
+
+    let response = client()
+        .fetch(&MerinoSuggestRequest {
+            query: "los pollos".to_string(),
+            providers: vec!["adm".to_string(), "wikipedia".to_string()],
+            session_id: Some("session-1".to_string()),
+            sequence_number: Some(2),
+            ..MerinoSuggestRequest::default()
+        })
+        .unwrap();
+    m.expect(1).assert();
+
+    assert_eq!(response.request_id.as_deref(), Some("abc"));
+    assert_eq!(
+        response.suggestions,
+        vec![
+            MerinoSuggestion {
+                provider: "adm".to_string(),
+                title: "Los Pollos Hermanos".to_string(),
+                url: "https://www.lph-nm.biz".to_string(),
+                score: 0.3,
+                is_sponsored: true,
+                icon: None,
+                full_keyword: None,
+                block_id: Some(100),
+                advertiser: Some("Los Pollos Hermanos".to_string()),
+                impression_url: None,
+                click_url: None,
+                custom_details: Some(r#"{"amo":{"rating":"4.5"}}"#.to_string()),
+            },
+            MerinoSuggestion {
+                provider: "wikipedia".to_string(),
+                title: "Los Pollos Hermanos - Wikipedia".to_string(),
+                url: "https://en.wikipedia.org/wiki/Los_Pollos_Hermanos".to_string(),
+                score: 0.2,
+                is_sponsored: false,
+                icon: None,
+                full_keyword: None,
+                block_id: None,
+                advertiser: None,
+                impression_url: None,
+                click_url: None,
+                custom_details: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_fetch_http_error() {
+    let m = mock("GET", "/api/v1/suggest")
+        .match_query(Matcher::UrlEncoded("q".into(), "error".into()))
+        .with_status(500)
+        .with_body("Internal Server Error")
+        .create();
+
+    let err = client()
+        .fetch(&MerinoSuggestRequest {
+            query: "error".to_string(),
+            ..MerinoSuggestRequest::default()
+        })
+        .unwrap_err();
+    m.expect(1).assert();
+    assert!(matches!(
+        err,
+        MerinoSuggestApiError::Other {
+            code: Some(500),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_invalid_config() {
+    let result = MerinoSuggestClient::new(MerinoSuggestConfig {
+        base_host: None,
+        user_agent_header: String::new(),
+        ohttp_channel: None,
+    });
+    assert!(matches!(
+        result,
+        Err(MerinoSuggestApiError::Other { code: None, .. })
+    ));
+}
+
+#[test]
+fn test_unconfigured_ohttp_channel() {
+    // Without the `ohttp` feature, OHTTP isn't supported at all; with it,
+    // the channel must be configured before creating the client.
+    let result = MerinoSuggestClient::new(MerinoSuggestConfig {
+        base_host: None,
+        user_agent_header: "test-agent/1.0".to_string(),
+        ohttp_channel: Some("unconfigured-channel".to_string()),
+    });
+    assert!(result.is_err());
+}
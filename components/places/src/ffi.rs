@@ -522,6 +522,21 @@ impl PlacesConnection {
         })
     }
 
+    #[handle_error(crate::Error)]
+    pub fn bookmarks_get_bookmarked_urls(&self, urls: Vec<String>) -> ApiResult<Vec<String>> {
+        self.with_conn(|conn| {
+            // There are no bookmarks with invalid URLs.
+            let urls = urls
+                .iter()
+                .filter_map(|url| Url::parse(url).ok())
+                .collect::<Vec<_>>();
+            Ok(bookmarks::fetch::fetch_bookmarked_urls(conn, &urls)?
+                .into_iter()
+                .map(String::from)
+                .collect())
+        })
+    }
+
     #[handle_error(crate::Error)]
     pub fn bookmarks_search(&self, query: String, limit: i32) -> ApiResult<Vec<BookmarkItem>> {
         self.with_conn(|conn| {
@@ -286,6 +286,32 @@ pub fn fetch_bookmarks_by_url(db: &PlacesDb, url: &Url) -> Result<Vec<BookmarkDa
     Ok(nodes)
 }
 
+/// Returns the URLs in `urls` that have at least one bookmark.
+pub fn fetch_bookmarked_urls(db: &PlacesDb, urls: &[Url]) -> Result<Vec<Url>> {
+    let mut bookmarked = vec![];
+    sql_support::each_chunk_mapped(
+        urls,
+        |url| url.as_str(),
+        |chunk, _| -> Result<()> {
+            let rows = db.query_rows_and_then(
+                &format!(
+                    "WITH urls(url) AS (VALUES {})
+                     SELECT DISTINCT h.url
+                     FROM urls u
+                     JOIN moz_places h ON h.url_hash = hash(u.url) AND h.url = u.url
+                     WHERE EXISTS(SELECT 1 FROM moz_bookmarks b WHERE b.fk = h.id)",
+                    sql_support::repeat_sql_values(chunk.len())
+                ),
+                rusqlite::params_from_iter(chunk),
+                |row| row.get::<_, String>(0),
+            )?;
+            bookmarked.extend(rows.iter().filter_map(|url| Url::parse(url).ok()));
+            Ok(())
+        },
+    )?;
+    Ok(bookmarked)
+}
+
 /// This is similar to fetch_tree, but does not recursively fetch children of
 /// folders.
 ///
@@ -489,6 +515,46 @@ mod test {
         Ok(())
     }
     #[test]
+    fn test_get_bookmarked_urls() -> Result<()> {
+        let conns = new_mem_connections();
+        insert_json_tree(
+            &conns.write,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "url": "https://www.example1.com/",
+                        "title": "one",
+                    },
+                    {
+                        "guid": "bookmark2___",
+                        "url": "https://www.example2.com/",
+                        "title": "two",
+                    },
+                    {
+                        "guid": "bookmark3___",
+                        "url": "https://www.example2.com/",
+                        "title": "two again",
+                    },
+                ]
+            }),
+        );
+        let urls = [
+            "https://www.example1.com/",
+            "https://www.example2.com/",
+            "https://no.bookmark.com/",
+        ]
+        .into_iter()
+        .map(url::Url::parse)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut bookmarked = fetch_bookmarked_urls(&conns.read, &urls)?;
+        bookmarked.sort();
+        assert_eq!(bookmarked, urls[..2]);
+        assert!(fetch_bookmarked_urls(&conns.read, &[])?.is_empty());
+        Ok(())
+    }
+    #[test]
     fn test_search() -> Result<()> {
         let conns = new_mem_connections();
         insert_json_tree(
@@ -11,6 +11,7 @@
 //! - Delete existing subscriptions
 //! - Update native tokens with autopush server
 //! - routinely check subscriptions to make sure they are in a good state.
+//! - Subscribe to broadcasts, and find out which ones changed when a broadcast message arrives
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use std::collections::{HashMap, HashSet};
@@ -20,11 +21,15 @@ use crate::internal::communications::{Connection, PersistedRateLimiter};
 use crate::internal::config::PushConfiguration;
 use crate::internal::crypto::KeyV1 as Key;
 use crate::internal::storage::{PushRecord, Storage};
-use crate::{KeyInfo, PushSubscriptionChanged, SubscriptionInfo, SubscriptionResponse};
+use crate::{
+    BroadcastChanged, KeyInfo, PushSubscriptionChanged, SubscriptionInfo, SubscriptionResponse,
+};
 
 use super::crypto::{Cryptography, PushPayload};
 const UPDATE_RATE_LIMITER_INTERVAL: u64 = 24 * 60 * 60; // 24 hours.
 const UPDATE_RATE_LIMITER_MAX_CALLS: u16 = 500; // 500
+/// The meta key for the broadcast subscriptions, stored as a JSON object of IDs to versions.
+const BROADCASTS_META_KEY: &str = "broadcasts";
 
 impl From<Key> for KeyInfo {
     fn from(key: Key) -> Self {
@@ -287,6 +292,63 @@ impl<Co: Connection, Cr: Cryptography, S: Storage> PushManager<Co, Cr, S> {
         })
     }
 
+    pub fn subscribe_broadcast(&mut self, broadcast_id: &str, version: &str) -> Result<()> {
+        let mut broadcasts = self.get_broadcast_subscriptions()?;
+        if broadcasts.contains_key(broadcast_id) {
+            debug!("already subscribed to broadcast '{}'", broadcast_id);
+            return Ok(());
+        }
+        broadcasts.insert(broadcast_id.to_string(), version.to_string());
+        self.set_broadcast_subscriptions(&broadcasts)
+    }
+
+    pub fn unsubscribe_broadcast(&mut self, broadcast_id: &str) -> Result<bool> {
+        let mut broadcasts = self.get_broadcast_subscriptions()?;
+        if broadcasts.remove(broadcast_id).is_none() {
+            return Ok(false);
+        }
+        self.set_broadcast_subscriptions(&broadcasts)?;
+        Ok(true)
+    }
+
+    pub fn get_broadcast_subscriptions(&self) -> Result<HashMap<String, String>> {
+        Ok(match self.store.get_meta(BROADCASTS_META_KEY)? {
+            Some(json) => serde_json::from_str(&json)?,
+            None => HashMap::new(),
+        })
+    }
+
+    pub fn receive_broadcasts(
+        &mut self,
+        broadcasts: HashMap<String, String>,
+    ) -> Result<Vec<BroadcastChanged>> {
+        let mut subscriptions = self.get_broadcast_subscriptions()?;
+        let mut changed = Vec::new();
+        for (broadcast_id, version) in broadcasts {
+            // The server can send broadcasts that we didn't subscribe to, like the initial
+            // versions of all broadcasts; we only care about ours.
+            match subscriptions.get_mut(&broadcast_id) {
+                Some(subscribed_version) if *subscribed_version != version => {
+                    subscribed_version.clone_from(&version);
+                    changed.push(BroadcastChanged {
+                        broadcast_id,
+                        version,
+                    });
+                }
+                _ => (),
+            }
+        }
+        if !changed.is_empty() {
+            self.set_broadcast_subscriptions(&subscriptions)?;
+        }
+        Ok(changed)
+    }
+
+    fn set_broadcast_subscriptions(&self, broadcasts: &HashMap<String, String>) -> Result<()> {
+        self.store
+            .set_meta(BROADCASTS_META_KEY, &serde_json::to_string(broadcasts)?)
+    }
+
     fn wipe_local_registrations(&mut self) -> error::Result<()> {
         self.store.delete_all_records()?;
         self.auth = None;
@@ -1010,4 +1072,46 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_broadcasts() -> Result<()> {
+        let _m = get_lock(&MTX);
+        let ctx = MockConnection::connect_context();
+        ctx.expect().returning(|_| Default::default());
+        let mut pm = get_test_manager()?;
+
+        pm.subscribe_broadcast("remote-settings/monitor_changes", "\"1000\"")?;
+        // Subscribing again keeps the version that we last received.
+        pm.subscribe_broadcast("remote-settings/monitor_changes", "\"0\"")?;
+        assert_eq!(
+            pm.get_broadcast_subscriptions()?,
+            HashMap::from([(
+                "remote-settings/monitor_changes".to_string(),
+                "\"1000\"".to_string()
+            )])
+        );
+
+        let broadcasts = HashMap::from([
+            (
+                "remote-settings/monitor_changes".to_string(),
+                "\"2000\"".to_string(),
+            ),
+            ("not-subscribed".to_string(), "v1".to_string()),
+        ]);
+        let changed = pm.receive_broadcasts(broadcasts.clone())?;
+        assert_eq!(
+            changed,
+            vec![BroadcastChanged {
+                broadcast_id: "remote-settings/monitor_changes".to_string(),
+                version: "\"2000\"".to_string(),
+            }]
+        );
+        // The same versions again aren't changes.
+        assert!(pm.receive_broadcasts(broadcasts)?.is_empty());
+
+        assert!(pm.unsubscribe_broadcast("remote-settings/monitor_changes")?);
+        assert!(!pm.unsubscribe_broadcast("remote-settings/monitor_changes")?);
+        assert!(pm.get_broadcast_subscriptions()?.is_empty());
+        Ok(())
+    }
 }
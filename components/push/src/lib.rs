@@ -378,6 +378,87 @@ impl PushManager {
     pub fn decrypt(&self, payload: HashMap<String, String>) -> ApiResult<DecryptResponse> {
         self.internal.lock().unwrap().decrypt(payload)
     }
+
+    /// Subscribes to a broadcast
+    ///
+    /// Broadcasts announce new versions of data that's shared by all clients, like
+    /// `remote-settings/monitor_changes` for Remote Settings. Broadcasts are only delivered over
+    /// the autopush WebSocket, which the consumer manages: it sends
+    /// [`PushManager::get_broadcast_subscriptions`] in its `hello` or `broadcast_subscribe`
+    /// message, and passes the `broadcast` messages it receives to
+    /// [`PushManager::receive_broadcasts`]. The HTTP interface that we use to subscribe has no
+    /// broadcast API, so this only records the subscription.
+    ///
+    /// # Arguments
+    ///   - `broadcast_id` - The ID of the broadcast
+    ///   - `version` - The version of the data that the consumer already has. This is
+    ///     ignored if we're already subscribed to the broadcast.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn subscribe_broadcast(&self, broadcast_id: &str, version: &str) -> ApiResult<()> {
+        self.internal
+            .lock()
+            .unwrap()
+            .subscribe_broadcast(broadcast_id, version)
+    }
+
+    /// Unsubscribes from a broadcast
+    ///
+    /// Like [`PushManager::subscribe_broadcast`], this only changes the subscriptions that the
+    /// consumer sends the next time it connects to the WebSocket.
+    ///
+    /// # Returns
+    /// Returns a boolean. Boolean is False if we weren't subscribed to the broadcast.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn unsubscribe_broadcast(&self, broadcast_id: &str) -> ApiResult<bool> {
+        self.internal
+            .lock()
+            .unwrap()
+            .unsubscribe_broadcast(broadcast_id)
+    }
+
+    /// Gets the broadcasts that we're subscribed to
+    ///
+    /// # Returns
+    /// A map of broadcast IDs to the last versions that we received. This is the
+    /// `broadcasts` map to send to the server when connecting.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn get_broadcast_subscriptions(&self) -> ApiResult<HashMap<String, String>> {
+        self.internal.lock().unwrap().get_broadcast_subscriptions()
+    }
+
+    /// Handles a broadcast message
+    ///
+    /// This accepts the `broadcasts` of a broadcast message from the autopush WebSocket.
+    /// # Arguments:
+    ///   - `broadcasts` - A map of broadcast IDs to their new versions
+    ///
+    /// # Returns
+    /// Returns a list of [`BroadcastChanged`], one entry for each subscribed broadcast
+    /// whose version changed. The new versions are persisted, so the same message
+    /// won't be reported twice.
+    ///
+    /// # Errors
+    /// Returns an error in the following cases:
+    ///   - An error occurred accessing the PushManager's persisted storage
+    #[handle_error(PushError)]
+    pub fn receive_broadcasts(
+        &self,
+        broadcasts: HashMap<String, String>,
+    ) -> ApiResult<Vec<BroadcastChanged>> {
+        self.internal.lock().unwrap().receive_broadcasts(broadcasts)
+    }
 }
 
 /// Key Information that can be used to encrypt payloads. These are encoded as base64
@@ -411,3 +492,12 @@ pub struct PushSubscriptionChanged {
     pub channel_id: String,
     pub scope: String,
 }
+
+/// A dictionary describing a broadcast that changed, the caller will receive a
+/// list of [`BroadcastChanged`] when calling [`PushManager::receive_broadcasts`],
+/// one entry for each subscribed broadcast with a new version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastChanged {
+    pub broadcast_id: String,
+    pub version: String,
+}
@@ -149,7 +149,7 @@ impl ToSql for Interest {
 ///
 /// Here "vector" refers to the mathematical object, not a Rust `Vec`.  It always has a fixed
 /// number of elements.
-#[derive(Debug, Default, PartialEq, Eq, uniffi::Record)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
 pub struct InterestVector {
     pub inconclusive: u32,
     pub animals: u32,
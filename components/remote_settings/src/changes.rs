@@ -0,0 +1,162 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Record-level change notifications.
+//!
+//! Syncing a collection applies a changeset diff to its stored records. The
+//! IDs of the records that the diff created, updated, and deleted are passed
+//! to the [RemoteSettingsChangeListener]s subscribed to the collection's
+//! client or to the service, so that consumers can update themselves without
+//! re-reading the whole collection.
+
+use std::collections::BTreeMap;
+
+use crate::listeners::Listeners;
+
+/// The IDs of the records that a sync changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct RemoteSettingsChanges {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl RemoteSettingsChanges {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+
+    /// Combines these changes with `later` ones, as if both had been applied
+    /// at once. For example, a record that was created and then deleted isn't
+    /// changed at all.
+    pub fn then(self, later: RemoteSettingsChanges) -> RemoteSettingsChanges {
+        let mut kinds = self.into_kinds();
+        for (id, later_kind) in later.into_kinds() {
+            match (kinds.get(&id), later_kind) {
+                (Some(ChangeKind::Created), ChangeKind::Deleted) => {
+                    kinds.remove(&id);
+                }
+                (Some(ChangeKind::Created), _) => (),
+                (Some(ChangeKind::Deleted), _) => {
+                    kinds.insert(id, ChangeKind::Updated);
+                }
+                _ => {
+                    kinds.insert(id, later_kind);
+                }
+            }
+        }
+        let mut changes = RemoteSettingsChanges::default();
+        for (id, kind) in kinds {
+            match kind {
+                ChangeKind::Created => changes.created.push(id),
+                ChangeKind::Updated => changes.updated.push(id),
+                ChangeKind::Deleted => changes.deleted.push(id),
+            }
+        }
+        changes
+    }
+
+    fn into_kinds(self) -> BTreeMap<String, ChangeKind> {
+        let with_kind = |ids: Vec<String>, kind| ids.into_iter().map(move |id| (id, kind));
+        with_kind(self.created, ChangeKind::Created)
+            .chain(with_kind(self.updated, ChangeKind::Updated))
+            .chain(with_kind(self.deleted, ChangeKind::Deleted))
+            .collect()
+    }
+}
+
+/// Receives the changes to a collection's records after each sync that
+/// changed them.
+///
+/// Changes are computed against the records stored before the sync. They
+/// aren't reported when storage is reset outside of a sync, for example by
+/// [crate::RemoteSettingsService::update_config], so consumers should re-read
+/// the collection after calling that.
+#[uniffi::export(callback_interface)]
+pub trait RemoteSettingsChangeListener: Send + Sync {
+    fn on_changes(&self, collection_name: String, changes: RemoteSettingsChanges);
+}
+
+/// The change listeners subscribed to a client or service.
+pub(crate) type ChangeListeners = Listeners<dyn RemoteSettingsChangeListener>;
+
+impl ChangeListeners {
+    /// Passes the changes to each listener, unless they're empty.
+    pub fn notify(&self, collection_name: &str, changes: &RemoteSettingsChanges) {
+        if changes.is_empty() {
+            return;
+        }
+        for listener in self.listeners() {
+            listener.on_changes(collection_name.to_owned(), changes.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    fn changes(created: &[&str], updated: &[&str], deleted: &[&str]) -> RemoteSettingsChanges {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect();
+        RemoteSettingsChanges {
+            created: ids(created),
+            updated: ids(updated),
+            deleted: ids(deleted),
+        }
+    }
+
+    #[test]
+    fn test_then() {
+        assert_eq!(
+            changes(&["a", "b"], &["c", "d"], &["e", "f"]).then(changes(
+                &["e", "g"],
+                &["a", "c"],
+                &["b", "d", "h"]
+            )),
+            changes(&["a", "g"], &["c", "e"], &["d", "f", "h"]),
+        );
+        assert!(changes(&["a"], &[], &[])
+            .then(changes(&[], &[], &["a"]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_listeners() {
+        struct Recorder(Arc<Mutex<Vec<(String, RemoteSettingsChanges)>>>);
+
+        impl RemoteSettingsChangeListener for Recorder {
+            fn on_changes(&self, collection_name: String, changes: RemoteSettingsChanges) {
+                self.0.lock().push((collection_name, changes));
+            }
+        }
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let listeners = ChangeListeners::default();
+        let id = listeners.add(Box::new(Recorder(received.clone())));
+
+        listeners.notify("regions", &changes(&["a"], &[], &[]));
+        // Empty changes aren't passed on.
+        listeners.notify("regions", &RemoteSettingsChanges::default());
+        assert_eq!(
+            *received.lock(),
+            vec![("regions".to_string(), changes(&["a"], &[], &[]))],
+        );
+
+        assert!(listeners.remove(id));
+        assert!(!listeners.remove(id));
+        listeners.notify("regions", &changes(&["b"], &[], &[]));
+        assert_eq!(received.lock().len(), 1);
+    }
+}
@@ -2,9 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::changes::RemoteSettingsChanges;
 use crate::config::{BaseUrl, RemoteSettingsConfig};
-use crate::error::{debug, trace, Error, Result};
-use crate::jexl_filter::JexlFilter;
+use crate::error::{debug, trace, warn, Error, Result};
+use crate::jexl_filter::{FilterExplanation, JexlFilter};
+use crate::reports::{
+    SignatureVerification, SyncReportBuilder, SyncReportListeners, SyncRetryPath,
+};
 #[cfg(feature = "signatures")]
 use crate::signatures;
 use crate::storage::Storage;
@@ -15,6 +19,8 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
     borrow::Cow,
+    collections::HashMap,
+    sync::Arc,
     time::{Duration, Instant},
 };
 use url::Url;
@@ -45,17 +51,46 @@ fn epoch_seconds() -> u64 {
     MOCK_TIME.with(|mock_time| mock_time.get().unwrap_or(0))
 }
 
-const HEADER_BACKOFF: &str = "Backoff";
-const HEADER_ETAG: &str = "ETag";
-const HEADER_RETRY_AFTER: &str = "Retry-After";
+#[cfg(feature = "signatures")]
+#[cfg(test)]
+thread_local! {
+    static MOCK_ROOT_CERT_SHA256_HASH: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) }
+}
 
-/// Hard-coded SHA256 of our root certificates. This is used by rc_crypto/pkixc to verify that the
-/// certificates chains used in content signatures verification were produced from our root certificate.
-/// See https://bugzilla.mozilla.org/show_bug.cgi?id=1940903 to align with desktop implementation.
+/// Verify signatures on this thread against a different root certificate, at the current time
+///
+/// This lets tests sync signed data from a test server, like `remote-settings-test-server`,
+/// whose certificate chain ends at a test root.
 #[cfg(feature = "signatures")]
-const ROOT_CERT_SHA256_HASH_PROD: &str = "C8:A8:0E:9A:FA:EF:4E:21:9B:6F:B5:D7:A7:1D:0F:10:12:23:BA:C5:00:1A:C2:8F:9B:0D:43:DC:59:A1:06:DB";
+#[cfg(test)]
+pub(crate) fn mock_root_cert_sha256_hash(root_hash: &'static str) {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    MOCK_TIME.with(|cell| cell.set(Some(now)));
+    MOCK_ROOT_CERT_SHA256_HASH.with(|cell| cell.set(Some(root_hash)));
+}
+
+/// Get the SHA256 hash of the root certificate that signatures must chain to
 #[cfg(feature = "signatures")]
-const ROOT_CERT_SHA256_HASH_NONPROD: &str = "3C:01:44:6A:BE:90:36:CE:A9:A0:9A:CA:A3:A5:20:AC:62:8F:20:A7:AE:32:CE:86:1C:B2:EF:B7:0F:A0:C7:45";
+fn root_cert_sha256_hash(is_prod_server: bool) -> &'static str {
+    #[cfg(test)]
+    if let Some(root_hash) = MOCK_ROOT_CERT_SHA256_HASH.with(|cell| cell.get()) {
+        return root_hash;
+    }
+    if is_prod_server {
+        signatures::ROOT_CERT_SHA256_HASH_PROD
+    } else {
+        signatures::ROOT_CERT_SHA256_HASH_NONPROD
+    }
+}
+
+const HEADER_BACKOFF: &str = "Backoff";
+const HEADER_ETAG: &str = "ETag";
+const HEADER_RETRY_AFTER: &str = "Retry-After";
 
 #[derive(Debug, Clone, Deserialize)]
 struct CollectionData {
@@ -74,6 +109,9 @@ pub struct RemoteSettingsClient<C = ViaductApiClient> {
     // Config that we got from `update_config`.  This should be applied to
     // `RemoteSettingsClientInner` the next time it's used.
     pending_config: Mutex<Option<RemoteSettingsClientConfig>>,
+    // Listeners for the report of each sync.  These are shared with the service that created the
+    // client.
+    sync_report_listeners: Arc<SyncReportListeners>,
 }
 
 struct RemoteSettingsClientInner<C> {
@@ -93,7 +131,11 @@ struct RemoteSettingsClientConfig {
 //
 // Then add the entry here.
 //
-// For subsequent updates, run the command above again.
+// For subsequent updates, run the command above again, or update every dump with:
+//   $ cargo remote-settings dump-sync
+//
+// To check that the dumps are up to date and match their signatures and attachments, run:
+//   $ cargo remote-settings dump-check
 impl<C: ApiClient> RemoteSettingsClient<C> {
     // One line per bucket + collection
     packaged_collections! {
@@ -215,6 +257,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                 jexl_filter,
             }),
             pending_config: Mutex::new(None),
+            sync_report_listeners: Arc::default(),
         }
     }
 
@@ -267,12 +310,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
     ) -> Vec<RemoteSettingsRecord> {
         records
             .into_iter()
-            .filter(|record| match record.fields.get("filter_expression") {
-                Some(serde_json::Value::String(filter_expr)) => {
-                    inner.jexl_filter.evaluate(filter_expr).unwrap_or(false)
-                }
-                _ => true, // Include records without a valid filter expression by default
-            })
+            .filter(|record| matches_filter_expression(record, &inner.jexl_filter))
             .collect()
     }
 
@@ -337,7 +375,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
             (Some(cached_records), _) => Some(self.filter_records(cached_records, &inner)),
             // Case 3: sync_if_empty=true
             (None, true) => {
-                let changeset = inner.api_client.fetch_changeset(None)?;
+                let changeset = inner.api_client.fetch_changeset(None, 0)?;
                 inner.storage.insert_collection_content(
                     &collection_url,
                     &changeset.changes,
@@ -351,6 +389,78 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         })
     }
 
+    /// Get the records that match `options`.
+    ///
+    /// This works like [Self::get_records], but the options' filters, sort order, and limit are
+    /// applied by querying the cached records in storage, so that only the matching records are
+    /// loaded.
+    pub fn query_records(
+        &self,
+        options: &GetItemsOptions,
+        sync_if_empty: bool,
+    ) -> Result<Option<Vec<RemoteSettingsRecord>>> {
+        let mut inner = self.lock_inner()?;
+        let RemoteSettingsClientInner {
+            storage,
+            api_client,
+            jexl_filter,
+        } = &mut *inner;
+        let collection_url = api_client.collection_url();
+
+        // Store the packaged data if it's newer than the cache, like `get_records`.
+        if api_client.is_prod_server()? {
+            if let Some(packaged_data) =
+                self.get_packaged_data_if_newer(storage, &collection_url)?
+            {
+                storage.empty()?;
+                storage.insert_collection_content(
+                    &collection_url,
+                    &packaged_data.data,
+                    packaged_data.timestamp,
+                    CollectionMetadata::default(),
+                )?;
+            }
+        }
+
+        if sync_if_empty
+            && storage
+                .get_last_modified_timestamp(&collection_url)?
+                .is_none()
+        {
+            let changeset = api_client.fetch_changeset(None, 0)?;
+            storage.insert_collection_content(
+                &collection_url,
+                &changeset.changes,
+                changeset.timestamp,
+                changeset.metadata,
+            )?;
+        }
+
+        storage.query_records(&collection_url, options, |record| {
+            matches_filter_expression(record, jexl_filter)
+        })
+    }
+
+    /// Explain why each cached record's `filter_expression` does or doesn't match, by record ID
+    ///
+    /// This includes the records that [Self::get_records] filters out.  It doesn't make any
+    /// network requests, so it returns an empty list if the collection hasn't been synced.
+    pub fn explain_records(&self) -> Result<Vec<(String, FilterExplanation)>> {
+        let mut inner = self.lock_inner()?;
+        let collection_url = inner.api_client.collection_url();
+        let records = inner
+            .storage
+            .get_records(&collection_url)?
+            .unwrap_or_default();
+        Ok(records
+            .iter()
+            .map(|record| {
+                let explanation = inner.jexl_filter.explain(filter_expression(record));
+                (record.id.clone(), explanation)
+            })
+            .collect())
+    }
+
     pub fn get_last_modified_timestamp(&self) -> Result<Option<u64>> {
         let mut inner = self.lock_inner()?;
         let collection_url = inner.api_client.collection_url();
@@ -360,12 +470,27 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
     /// Synchronizes the local collection with the remote server by performing the following steps:
     /// 1. Fetches the last modified timestamp of the collection from local storage.
     /// 2. Fetches the changeset from the remote server based on the last modified timestamp.
-    /// 3. Inserts the fetched changeset into local storage.
-    fn perform_sync_operation(&self) -> Result<()> {
+    /// 3. Fetches the certificates for the changeset's signatures, so that failing to fetch them
+    ///    doesn't leave unverified records behind.
+    /// 4. Inserts the fetched changeset into local storage.
+    ///
+    /// `expected` is the collection's timestamp on the server, or `0` if we don't know it.
+    ///
+    /// Returns the IDs of the records that the changeset changed.
+    fn perform_sync_operation(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<RemoteSettingsChanges> {
         let mut inner = self.lock_inner()?;
         let collection_url = inner.api_client.collection_url();
         let timestamp = inner.storage.get_last_modified_timestamp(&collection_url)?;
-        let changeset = inner.api_client.fetch_changeset(timestamp)?;
+        let changeset = inner.api_client.fetch_changeset(timestamp, expected)?;
+        report.fetched_changeset(timestamp.is_some(), changeset.size);
+        #[cfg(feature = "signatures")]
+        for signature in &changeset.metadata.signatures {
+            inner.api_client.fetch_cert(&signature.x5u)?;
+        }
         debug!(
             "{0}: apply {1} change(s) locally.",
             self.collection_name,
@@ -379,48 +504,127 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         )
     }
 
-    pub fn sync(&self) -> Result<()> {
+    /// Syncs the collection, and returns the IDs of the records that changed.
+    ///
+    /// The sync's report is passed to the sync report listeners, whether it succeeds or not.
+    pub fn sync(&self) -> Result<RemoteSettingsChanges> {
+        self.sync_with_expected(0)
+    }
+
+    /// Syncs the collection, like [Self::sync].
+    ///
+    /// `expected` is the collection's timestamp from the `monitor/changes` endpoint, or `0` if we
+    /// don't know it.  It busts the CDN cache, so that we get the data that the service saw.
+    pub fn sync_with_expected(&self, expected: u64) -> Result<RemoteSettingsChanges> {
+        let mut report = SyncReportBuilder::start(&self.collection_name);
+        let result = self.sync_with_report(expected, &mut report);
+        self.sync_report_listeners
+            .notify(&report.finish(result.as_ref()));
+        result
+    }
+
+    fn sync_with_report(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<RemoteSettingsChanges> {
         // First attempt
-        self.perform_sync_operation()?;
+        let mut changes = self.perform_sync_operation(expected, report)?;
         // Verify that inserted data has valid signature
-        if self.verify_signature().is_err() {
-            debug!(
-                "{0}: signature verification failed. Reset and retry.",
-                self.collection_name
-            );
-            // Retry with packaged dataset as base
-            self.reset_storage()?;
-            self.perform_sync_operation()?;
-            // Verify signature again
-            self.verify_signature().inspect_err(|_| {
-                // And reset with packaged data if it fails again.
-                self.reset_storage()
-                    .expect("Failed to reset storage after verification failure");
-            })?;
+        match self.verify_signature() {
+            Ok(verification) => report.verified_signature(verification),
+            Err(e) if !e.is_invalid_signature() => return Err(self.reset_unverified_storage(e)),
+            Err(_) => {
+                debug!(
+                    "{0}: signature verification failed. Reset and retry.",
+                    self.collection_name
+                );
+                report.verified_signature(SignatureVerification::Invalid);
+                report.retry_path(SyncRetryPath::Retried);
+                // Retry with packaged dataset as base
+                changes = changes
+                    .then(self.reset_storage_with_changes()?)
+                    .then(self.perform_sync_operation(expected, report)?);
+                // Verify signature again
+                self.verify_signature()
+                    .map_err(|e| self.reset_after_retry(report, e))?;
+            }
         }
         trace!("{0}: sync done.", self.collection_name);
-        Ok(())
+        Ok(changes)
+    }
+
+    /// Resets the storage after an error that kept the sync from checking the stored records'
+    /// signature, like failing to fetch a certificate, and returns the error.
+    ///
+    /// The records can't be trusted, but unlike with an invalid signature, the sync isn't
+    /// retried, since the retry would likely fail the same way.
+    fn reset_unverified_storage(&self, error: Error) -> Error {
+        debug!(
+            "{0}: signature couldn't be verified ({1}). Reset.",
+            self.collection_name, error
+        );
+        self.reset_storage_after(error)
+    }
+
+    /// Resets the storage after the retried sync's records failed verification, and returns the
+    /// error.
+    fn reset_after_retry(&self, report: &mut SyncReportBuilder, error: Error) -> Error {
+        // Only an invalid signature means that the retry didn't help.
+        if error.is_invalid_signature() {
+            report.retry_path(SyncRetryPath::ResetAfterRetry);
+        }
+        // And reset with packaged data if it fails again.
+        self.reset_storage_after(error)
+    }
+
+    /// Resets the storage after a verification error, and returns the error to fail the sync
+    /// with.
+    ///
+    /// If the reset fails, the unverified records are still stored, so that error is returned
+    /// instead, to be reported.
+    fn reset_storage_after(&self, error: Error) -> Error {
+        match self.reset_storage() {
+            Ok(()) => error,
+            Err(reset_error) => {
+                warn!(
+                    "{0}: failed to reset storage after verification error ({1}): {2}",
+                    self.collection_name, error, reset_error
+                );
+                reset_error
+            }
+        }
     }
 
     pub fn reset_storage(&self) -> Result<()> {
+        self.reset_storage_with_changes()?;
+        Ok(())
+    }
+
+    /// Resets the storage, and returns the IDs of the records that changed.
+    fn reset_storage_with_changes(&self) -> Result<RemoteSettingsChanges> {
         trace!("{0}: reset local storage.", self.collection_name);
         let mut inner = self.lock_inner()?;
         let collection_url = inner.api_client.collection_url();
+        let mut changes = RemoteSettingsChanges {
+            deleted: inner.storage.get_record_ids(&collection_url)?,
+            ..RemoteSettingsChanges::default()
+        };
         // Clear existing storage
         inner.storage.empty()?;
         // Load packaged data only for production
         if inner.api_client.is_prod_server()? {
             if let Some(packaged_data) = self.load_packaged_data() {
                 trace!("{0}: restore packaged dump.", self.collection_name);
-                inner.storage.insert_collection_content(
+                changes = changes.then(inner.storage.insert_collection_content(
                     &collection_url,
                     &packaged_data.data,
                     packaged_data.timestamp,
                     CollectionMetadata::default(),
-                )?;
+                )?);
             }
         }
-        Ok(())
+        Ok(changes)
     }
 
     pub fn shutdown(&self) {
@@ -428,13 +632,16 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
     }
 
     #[cfg(not(feature = "signatures"))]
-    fn verify_signature(&self) -> Result<()> {
+    fn verify_signature(&self) -> Result<SignatureVerification> {
         debug!("{0}: signature verification skipped.", self.collection_name);
-        Ok(())
+        Ok(SignatureVerification::Skipped)
     }
 
+    /// Verify the stored records against their signatures
+    ///
+    /// Returns [SignatureVerification::Valid], or an error if no signature is valid.
     #[cfg(feature = "signatures")]
-    fn verify_signature(&self) -> Result<()> {
+    fn verify_signature(&self) -> Result<SignatureVerification> {
         let mut inner = self.lock_inner()?;
         let collection_url = inner.api_client.collection_url();
         let timestamp = inner.storage.get_last_modified_timestamp(&collection_url)?;
@@ -443,11 +650,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
         match (timestamp, &records, metadata) {
             (Some(timestamp), Some(records), Some(metadata)) => {
                 // rc_crypto verifies that the provided certificates chain leads to our root certificate.
-                let expected_root_hash = if inner.api_client.is_prod_server()? {
-                    ROOT_CERT_SHA256_HASH_PROD
-                } else {
-                    ROOT_CERT_SHA256_HASH_NONPROD
-                };
+                let expected_root_hash = root_cert_sha256_hash(inner.api_client.is_prod_server()?);
                 // Iterate through the list of signatures, and verify that at least one of them is valid.
                 // This allows for key rotation without breaking clients that have an old certificate chain cached.
                 let mut result = Err(Error::IncompleteSignatureDataError(
@@ -456,18 +659,7 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                 for signature in &metadata.signatures {
                     let cert_chain_bytes = inner.api_client.fetch_cert(&signature.x5u)?;
 
-                    // The signer name is hard-coded. This would have to be modified in the very (very)
-                    // unlikely situation where we would add a new collection signer.
-                    // And clients code would have to be modified to handle this new collection anyway.
-                    // https://searchfox.org/mozilla-central/rev/df850fa290fe962c2c5ae8b63d0943ce768e3cc4/services/settings/remote-settings.sys.mjs#40-48
-                    let expected_leaf_cname = format!(
-                        "{}.content-signature.mozilla.org",
-                        if metadata.bucket.contains("security-state") {
-                            "onecrl"
-                        } else {
-                            "remote-settings"
-                        }
-                    );
+                    let expected_leaf_cname = signatures::signer_name(&metadata.bucket);
 
                     result = signatures::verify_signature(
                         timestamp,
@@ -487,11 +679,11 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                     // If verification succeeds, then we exit!
                     if result.is_ok() {
                         trace!("{0}: signature verification success.", self.collection_name);
-                        return Ok(());
+                        return Ok(SignatureVerification::Valid);
                     }
                 }
                 // If we tried all signatures and none worked, then we return an error.
-                result
+                result.map(|()| SignatureVerification::Valid)
             }
             _ => {
                 let missing_field = if timestamp.is_none() {
@@ -509,20 +701,31 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
     /// Downloads an attachment from [attachment_location]. NOTE: there are no guarantees about a
     /// maximum size, so use care when fetching potentially large attachments.
     pub fn get_attachment(&self, record: &RemoteSettingsRecord) -> Result<Vec<u8>> {
-        let metadata = record
-            .attachment
-            .as_ref()
-            .ok_or_else(|| Error::RecordAttachmentMismatchError("No attachment metadata".into()))?;
-
+        let metadata = attachment_metadata(record)?;
         let mut inner = self.lock_inner()?;
-        let collection_url = inner.api_client.collection_url();
+        if let Some(data) = self.get_local_attachment(&mut inner, record, metadata)? {
+            return Ok(data);
+        }
+
+        // Try to download the attachment because neither the storage nor the local data had it
+        let attachment = inner.api_client.fetch_attachment(&metadata.location)?;
+        verify_attachment(metadata, &attachment)?;
+
+        // Store verified download in storage
+        inner.storage.set_attachment(metadata, &attachment)?;
+        Ok(attachment)
+    }
 
+    /// Get attachment data from storage, or from the packaged data
+    fn get_local_attachment(
+        &self,
+        inner: &mut RemoteSettingsClientInner<C>,
+        record: &RemoteSettingsRecord,
+        metadata: &Attachment,
+    ) -> Result<Option<Vec<u8>>> {
         // First try storage - it will only return data that matches our metadata
-        if let Some(data) = inner
-            .storage
-            .get_attachment(&collection_url, metadata.clone())?
-        {
-            return Ok(data);
+        if let Some(data) = inner.storage.get_attachment(metadata.clone())? {
+            return Ok(Some(data));
         }
 
         // Then try packaged data if we're in prod
@@ -533,36 +736,13 @@ impl<C: ApiClient> RemoteSettingsClient<C> {
                         && metadata.size == manifest_data["size"].as_u64().unwrap_or_default()
                     {
                         // Store valid packaged data in storage because it was either empty or outdated
-                        inner
-                            .storage
-                            .set_attachment(&collection_url, &metadata.location, data)?;
-                        return Ok(data.to_vec());
+                        inner.storage.set_attachment(metadata, data)?;
+                        return Ok(Some(data.to_vec()));
                     }
                 }
             }
         }
-
-        // Try to download the attachment because neither the storage nor the local data had it
-        let attachment = inner.api_client.fetch_attachment(&metadata.location)?;
-
-        // Verify downloaded data
-        if attachment.len() as u64 != metadata.size {
-            return Err(Error::RecordAttachmentMismatchError(
-                "Downloaded attachment size mismatch".into(),
-            ));
-        }
-        let hash = format!("{:x}", Sha256::digest(&attachment));
-        if hash != metadata.hash {
-            return Err(Error::RecordAttachmentMismatchError(
-                "Downloaded attachment hash mismatch".into(),
-            ));
-        }
-
-        // Store verified download in storage
-        inner
-            .storage
-            .set_attachment(&collection_url, &metadata.location, &attachment)?;
-        Ok(attachment)
+        Ok(None)
     }
 
     pub fn update_config(
@@ -587,14 +767,276 @@ impl RemoteSettingsClient<ViaductApiClient> {
         collection_name: String,
         context: Option<RemoteSettingsContext>,
         storage: Storage,
+        sync_report_listeners: Arc<SyncReportListeners>,
     ) -> Self {
         let api_client = ViaductApiClient::new(server_url, &bucket_name, &collection_name);
         let jexl_filter = JexlFilter::new(context);
 
-        Self::new_from_parts(collection_name, storage, jexl_filter, api_client)
+        Self {
+            sync_report_listeners,
+            ..Self::new_from_parts(collection_name, storage, jexl_filter, api_client)
+        }
+    }
+
+    /// Send a request to `url`, without holding the lock while it's in flight.
+    ///
+    /// The async methods only change the client's state after their requests finish, so dropping
+    /// their futures cancels them without leaving partial changes behind.
+    async fn send_async(&self, url: Url) -> Result<Response> {
+        let (http_client, request) = {
+            let mut inner = self.lock_inner()?;
+            let request = inner.api_client.prepare_request(url)?;
+            (inner.api_client.http_client.clone(), request)
+        };
+        let response = http_client.send(request).await?;
+        self.lock_inner()?.api_client.handle_response(response)
+    }
+
+    /// Async version of [Self::perform_sync_operation]
+    async fn perform_sync_operation_async(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<RemoteSettingsChanges> {
+        let pending = self.fetch_changeset_async(expected, report).await?;
+        self.apply_changeset(pending)
+    }
+
+    /// Fetch the changeset for the stored collection, without storing it
+    async fn fetch_changeset_async(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<PendingChangeset> {
+        let (collection_url, timestamp, url) = {
+            let mut inner = self.lock_inner()?;
+            let collection_url = inner.api_client.collection_url();
+            let timestamp = inner.storage.get_last_modified_timestamp(&collection_url)?;
+            let url = inner.api_client.changeset_url(timestamp, expected);
+            (collection_url, timestamp, url)
+        };
+        let changeset = parse_changeset(self.send_async(url).await?)?;
+        report.fetched_changeset(timestamp.is_some(), changeset.size);
+        Ok(PendingChangeset {
+            collection_url,
+            timestamp,
+            changeset,
+        })
+    }
+
+    /// Store a changeset from [Self::fetch_changeset_async]
+    fn apply_changeset(&self, pending: PendingChangeset) -> Result<RemoteSettingsChanges> {
+        let PendingChangeset {
+            collection_url,
+            timestamp,
+            changeset,
+        } = pending;
+        let mut inner = self.lock_inner()?;
+        // The changeset is a diff against the data we had when we sent the request.  If another
+        // sync or a config change updated the storage since then, it doesn't apply anymore.
+        if inner.api_client.collection_url() != collection_url
+            || inner.storage.get_last_modified_timestamp(&collection_url)? != timestamp
+        {
+            debug!(
+                "{0}: storage changed during sync, dropping changeset.",
+                self.collection_name
+            );
+            return Ok(RemoteSettingsChanges::default());
+        }
+        debug!(
+            "{0}: apply {1} change(s) locally.",
+            self.collection_name,
+            changeset.changes.len()
+        );
+        inner.storage.insert_collection_content(
+            &collection_url,
+            &changeset.changes,
+            changeset.timestamp,
+            changeset.metadata,
+        )
+    }
+
+    /// Fetch the certificates for a changeset's signatures, so that [Self::verify_signature]
+    /// doesn't block on them.
+    #[cfg(feature = "signatures")]
+    async fn fetch_certs_async(&self, metadata: &CollectionMetadata) -> Result<()> {
+        let x5us = {
+            let inner = self.lock_inner()?;
+            metadata
+                .signatures
+                .iter()
+                .map(|signature| signature.x5u.clone())
+                .filter(|x5u| !inner.api_client.certs.contains_key(x5u))
+                .collect::<Vec<_>>()
+        };
+        for x5u in x5us {
+            let cert = self.send_async(Url::parse(&x5u)?).await?.body;
+            self.lock_inner()?.api_client.certs.insert(x5u, cert);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "signatures"))]
+    async fn fetch_certs_async(&self, _metadata: &CollectionMetadata) -> Result<()> {
+        Ok(())
+    }
+
+    /// Fetch a changeset and the certificates for its signatures, then store and verify it
+    ///
+    /// Nothing is stored until every request has finished, and nothing awaits between storing
+    /// the records and verifying them, so dropping the future never leaves unverified records
+    /// behind.  Returns the changes and the result of verifying them.  If the certificates can't
+    /// be fetched, the changeset isn't stored, and the error is returned.
+    async fn fetch_and_verify_async(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<(RemoteSettingsChanges, Result<SignatureVerification>)> {
+        let pending = self.fetch_changeset_async(expected, report).await?;
+        self.fetch_certs_async(&pending.changeset.metadata).await?;
+        let changes = self.apply_changeset(pending)?;
+        Ok((changes, self.verify_signature()))
+    }
+
+    /// Async version of [Self::sync]
+    ///
+    /// If the future is dropped before the sync finishes, no report is passed to the listeners.
+    pub async fn sync_async(&self) -> Result<RemoteSettingsChanges> {
+        self.sync_async_with_expected(0).await
+    }
+
+    /// Async version of [Self::sync_with_expected]
+    pub async fn sync_async_with_expected(&self, expected: u64) -> Result<RemoteSettingsChanges> {
+        let mut report = SyncReportBuilder::start(&self.collection_name);
+        let result = self.sync_async_with_report(expected, &mut report).await;
+        self.sync_report_listeners
+            .notify(&report.finish(result.as_ref()));
+        result
+    }
+
+    async fn sync_async_with_report(
+        &self,
+        expected: u64,
+        report: &mut SyncReportBuilder,
+    ) -> Result<RemoteSettingsChanges> {
+        // First attempt
+        let (mut changes, verification) = self.fetch_and_verify_async(expected, report).await?;
+        match verification {
+            Ok(verification) => report.verified_signature(verification),
+            Err(e) if !e.is_invalid_signature() => return Err(self.reset_unverified_storage(e)),
+            Err(_) => {
+                debug!(
+                    "{0}: signature verification failed. Reset and retry.",
+                    self.collection_name
+                );
+                report.verified_signature(SignatureVerification::Invalid);
+                report.retry_path(SyncRetryPath::Retried);
+                // Retry with packaged dataset as base
+                changes = changes.then(self.reset_storage_with_changes()?);
+                let (retry_changes, verification) =
+                    self.fetch_and_verify_async(expected, report).await?;
+                changes = changes.then(retry_changes);
+                // Verify signature again
+                verification.map_err(|e| self.reset_after_retry(report, e))?;
+            }
+        }
+        trace!("{0}: sync done.", self.collection_name);
+        Ok(changes)
+    }
+
+    /// Async version of [Self::get_records]
+    pub async fn get_records_async(
+        &self,
+        sync_if_empty: bool,
+    ) -> Result<Option<Vec<RemoteSettingsRecord>>> {
+        // Only fetching records for an empty cache makes network requests.
+        let records = self.get_records(false)?;
+        if records.is_some() || !sync_if_empty {
+            return Ok(records);
+        }
+        // This isn't a full sync, so it isn't reported.
+        self.perform_sync_operation_async(0, &mut SyncReportBuilder::start(&self.collection_name))
+            .await?;
+        self.get_records(false)
+    }
+
+    /// Async version of [Self::get_attachment]
+    pub async fn get_attachment_async(&self, record: &RemoteSettingsRecord) -> Result<Vec<u8>> {
+        let metadata = attachment_metadata(record)?;
+        let local_data = {
+            let mut inner = self.lock_inner()?;
+            self.get_local_attachment(&mut inner, record, metadata)?
+        };
+        if let Some(data) = local_data {
+            return Ok(data);
+        }
+
+        let attachments_base_url = self.lock_inner()?.api_client.attachments_base_url();
+        let attachments_base_url = match attachments_base_url {
+            Some(attachments_base_url) => attachments_base_url,
+            None => {
+                let root_url = self.lock_inner()?.api_client.endpoints.root_url.clone();
+                let server_info = self.send_async(root_url).await?.json::<ServerInfo>()?;
+                let attachments_base_url = server_info.attachments_base_url()?;
+                self.lock_inner()?
+                    .api_client
+                    .remote_state
+                    .attachments_base_url = Some(attachments_base_url.clone());
+                attachments_base_url
+            }
+        };
+        let attachment = self
+            .send_async(attachments_base_url.join(&metadata.location)?)
+            .await?
+            .body;
+        verify_attachment(metadata, &attachment)?;
+
+        self.lock_inner()?
+            .storage
+            .set_attachment(metadata, &attachment)?;
+        Ok(attachment)
     }
 }
 
+/// A changeset that [RemoteSettingsClient::fetch_changeset_async] fetched, and the storage
+/// state that it's a diff against
+struct PendingChangeset {
+    collection_url: String,
+    timestamp: Option<u64>,
+    changeset: ChangesetResponse,
+}
+
+/// Parse a changeset response, and remember its size
+fn parse_changeset(resp: Response) -> Result<ChangesetResponse> {
+    let mut changeset = resp.json::<ChangesetResponse>()?;
+    changeset.size = resp.body.len() as u64;
+    Ok(changeset)
+}
+
+/// Returns a record's attachment metadata, or an error if it doesn't have an attachment
+fn attachment_metadata(record: &RemoteSettingsRecord) -> Result<&Attachment> {
+    record
+        .attachment
+        .as_ref()
+        .ok_or_else(|| Error::RecordAttachmentMismatchError("No attachment metadata".into()))
+}
+
+/// Verify downloaded attachment data against its metadata
+fn verify_attachment(metadata: &Attachment, attachment: &[u8]) -> Result<()> {
+    if attachment.len() as u64 != metadata.size {
+        return Err(Error::RecordAttachmentMismatchError(
+            "Downloaded attachment size mismatch".into(),
+        ));
+    }
+    let hash = format!("{:x}", Sha256::digest(attachment));
+    if hash != metadata.hash {
+        return Err(Error::RecordAttachmentMismatchError(
+            "Downloaded attachment hash mismatch".into(),
+        ));
+    }
+    Ok(())
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait ApiClient {
     /// Create a new instance of the client
@@ -610,7 +1052,15 @@ pub trait ApiClient {
     fn collection_url(&self) -> String;
 
     /// Fetch records from the server
-    fn fetch_changeset(&mut self, timestamp: Option<u64>) -> Result<ChangesetResponse>;
+    ///
+    /// `timestamp` is the timestamp of the records that we have, to only fetch the changes since
+    /// then.  `expected` is the collection's timestamp from the `monitor/changes` endpoint, or
+    /// `0` if we don't know it.
+    fn fetch_changeset(
+        &mut self,
+        timestamp: Option<u64>,
+        expected: u64,
+    ) -> Result<ChangesetResponse>;
 
     /// Fetch an attachment from the server
     fn fetch_attachment(&mut self, attachment_location: &str) -> Result<Vec<u8>>;
@@ -626,6 +1076,12 @@ pub trait ApiClient {
 pub struct ViaductApiClient {
     endpoints: RemoteSettingsEndpoints,
     remote_state: RemoteState,
+    /// Server certificates, by URL.  These are fetched ahead of time by
+    /// [RemoteSettingsClient::sync_async], and never change.
+    certs: HashMap<String, Vec<u8>>,
+    /// Sends the async requests.  This is shared with [RemoteSettingsClient::send_async], so
+    /// that it can send requests without holding the lock.
+    http_client: Arc<viaduct::Client>,
 }
 
 impl ViaductApiClient {
@@ -633,16 +1089,26 @@ impl ViaductApiClient {
         Self {
             endpoints: RemoteSettingsEndpoints::new(&base_url, bucket_name, collection_name),
             remote_state: RemoteState::default(),
+            certs: HashMap::new(),
+            http_client: Arc::new(viaduct::Client::new(viaduct::ClientSettings::default())),
         }
     }
 
     fn make_request(&mut self, url: Url) -> Result<Response> {
+        let req = self.prepare_request(url)?;
+        let resp = req.send()?;
+        self.handle_response(resp)
+    }
+
+    /// Build a request for `url`, unless the server asked us to back off
+    fn prepare_request(&mut self, url: Url) -> Result<Request> {
         trace!("make_request: {url}");
         self.remote_state.ensure_no_backoff()?;
+        Ok(Request::get(url))
+    }
 
-        let req = Request::get(url);
-        let resp = req.send()?;
-
+    /// Handle the response to a request from [Self::prepare_request]
+    fn handle_response(&mut self, resp: Response) -> Result<Response> {
         self.remote_state.handle_backoff_hint(&resp)?;
 
         if resp.is_success() {
@@ -654,6 +1120,25 @@ impl ViaductApiClient {
             ))
         }
     }
+
+    fn changeset_url(&self, timestamp: Option<u64>, expected: u64) -> Url {
+        let mut url = self.endpoints.changeset_url.clone();
+        // `_expected` is the collection's timestamp from the monitor/changes endpoint, which busts
+        // the CDN cache, or 0 if we don't know it, to use the cached data.  More details:
+        //
+        // https://remote-settings.readthedocs.io/en/latest/client-specifications.html#cache-busting
+        url.query_pairs_mut()
+            .append_pair("_expected", &expected.to_string());
+        if let Some(timestamp) = timestamp {
+            url.query_pairs_mut()
+                .append_pair("_since", &format!("\"{}\"", timestamp));
+        }
+        url
+    }
+
+    fn attachments_base_url(&self) -> Option<Url> {
+        self.remote_state.attachments_base_url.clone()
+    }
 }
 
 impl ApiClient for ViaductApiClient {
@@ -665,23 +1150,15 @@ impl ApiClient for ViaductApiClient {
         self.endpoints.collection_url.to_string()
     }
 
-    fn fetch_changeset(&mut self, timestamp: Option<u64>) -> Result<ChangesetResponse> {
-        let mut url = self.endpoints.changeset_url.clone();
-        // 0 is used as an arbitrary value for `_expected` because the current implementation does
-        // not leverage push timestamps or polling from the monitor/changes endpoint. More
-        // details:
-        //
-        // https://remote-settings.readthedocs.io/en/latest/client-specifications.html#cache-busting
-        url.query_pairs_mut().append_pair("_expected", "0");
-        if let Some(timestamp) = timestamp {
-            url.query_pairs_mut()
-                .append_pair("_since", &format!("\"{}\"", timestamp));
-        }
-
-        let resp = self.make_request(url)?;
+    fn fetch_changeset(
+        &mut self,
+        timestamp: Option<u64>,
+        expected: u64,
+    ) -> Result<ChangesetResponse> {
+        let resp = self.make_request(self.changeset_url(timestamp, expected))?;
 
         if resp.is_success() {
-            Ok(resp.json::<ChangesetResponse>()?)
+            parse_changeset(resp)
         } else {
             Err(Error::response_error(
                 &resp.url,
@@ -697,10 +1174,7 @@ impl ApiClient for ViaductApiClient {
                 let server_info = self
                     .make_request(self.endpoints.root_url.clone())?
                     .json::<ServerInfo>()?;
-                let attachments_base_url = match server_info.capabilities.attachments {
-                    Some(capability) => Url::parse(&capability.base_url)?,
-                    None => Err(Error::AttachmentsUnsupportedError)?,
-                };
+                let attachments_base_url = server_info.attachments_base_url()?;
                 self.remote_state.attachments_base_url = Some(attachments_base_url.clone());
                 attachments_base_url
             }
@@ -719,7 +1193,11 @@ impl ApiClient for ViaductApiClient {
     }
 
     fn fetch_cert(&mut self, x5u: &str) -> Result<Vec<u8>> {
+        if let Some(cert) = self.certs.get(x5u) {
+            return Ok(cert.clone());
+        }
         let resp = self.make_request(Url::parse(x5u)?)?;
+        self.certs.insert(x5u.to_string(), resp.body.clone());
         Ok(resp.body)
     }
 }
@@ -837,10 +1315,7 @@ impl Client {
                 let server_info = self
                     .make_request(self.endpoints.root_url.clone())?
                     .json::<ServerInfo>()?;
-                let attachments_base_url = match server_info.capabilities.attachments {
-                    Some(capability) => Url::parse(&capability.base_url)?,
-                    None => Err(Error::AttachmentsUnsupportedError)?,
-                };
+                let attachments_base_url = server_info.attachments_base_url()?;
                 self.remote_state.lock().attachments_base_url = Some(attachments_base_url.clone());
                 attachments_base_url
             }
@@ -955,6 +1430,9 @@ pub struct ChangesetResponse {
     changes: Vec<RemoteSettingsRecord>,
     timestamp: u64,
     metadata: CollectionMetadata,
+    /// Size of the response body, in bytes.  This isn't part of the response data.
+    #[serde(skip)]
+    size: u64,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
@@ -1088,6 +1566,15 @@ struct ServerInfo {
     capabilities: Capabilities,
 }
 
+impl ServerInfo {
+    fn attachments_base_url(&self) -> Result<Url> {
+        match &self.capabilities.attachments {
+            Some(capability) => Ok(Url::parse(&capability.base_url)?),
+            None => Err(Error::AttachmentsUnsupportedError),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Capabilities {
     attachments: Option<AttachmentsCapability>,
@@ -1098,13 +1585,29 @@ struct AttachmentsCapability {
     base_url: String,
 }
 
+/// Returns true if a record doesn't have a `filter_expression`, or if it has one that matches.
+fn matches_filter_expression(record: &RemoteSettingsRecord, jexl_filter: &JexlFilter) -> bool {
+    match filter_expression(record) {
+        Some(filter_expr) => jexl_filter.matches(filter_expr),
+        None => true, // Include records without a valid filter expression by default
+    }
+}
+
+/// Returns a record's `filter_expression`, if it has one
+fn filter_expression(record: &RemoteSettingsRecord) -> Option<&str> {
+    match record.fields.get("filter_expression") {
+        Some(serde_json::Value::String(filter_expr)) => Some(filter_expr),
+        _ => None,
+    }
+}
+
 /// Options for requests to endpoints that return multiple items.
 #[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct GetItemsOptions {
-    filters: Vec<Filter>,
-    sort: Vec<Sort>,
-    fields: Vec<String>,
-    limit: Option<u64>,
+    pub(crate) filters: Vec<Filter>,
+    pub(crate) sort: Vec<Sort>,
+    pub(crate) fields: Vec<String>,
+    pub(crate) limit: Option<u64>,
 }
 
 impl GetItemsOptions {
@@ -1250,7 +1753,7 @@ impl GetItemsOptions {
 }
 
 /// The order in which to return items.
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, uniffi::Enum)]
 pub enum SortOrder {
     /// Smaller values first.
     Ascending,
@@ -1259,7 +1762,7 @@ pub enum SortOrder {
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-enum Filter {
+pub(crate) enum Filter {
     Eq(String, String),
     Not(String, String),
     Contains(String, String),
@@ -1292,7 +1795,7 @@ impl Filter {
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-struct Sort(String, SortOrder);
+pub(crate) struct Sort(pub(crate) String, pub(crate) SortOrder);
 
 impl Sort {
     fn as_query_value(&self) -> Cow<'_, str> {
@@ -1934,6 +2437,7 @@ mod test_new_client {
 #[cfg(test)]
 mod jexl_tests {
     use super::*;
+    use std::collections::HashMap;
     use std::sync::{Arc, Weak};
 
     #[test]
@@ -1955,13 +2459,14 @@ mod jexl_tests {
             changes: records.clone(),
             timestamp: 42,
             metadata: CollectionMetadata::default(),
+            size: 0,
         };
         api_client.expect_collection_url().returning(|| {
             "http://rs.example.com/v1/buckets/main/collections/test-collection".into()
         });
         api_client.expect_fetch_changeset().returning({
             let changeset = changeset.clone();
-            move |timestamp| {
+            move |timestamp, _| {
                 assert_eq!(timestamp, None);
                 Ok(changeset.clone())
             }
@@ -1994,6 +2499,71 @@ mod jexl_tests {
         );
     }
 
+    #[test]
+    fn test_explain_records() {
+        let mut api_client = MockApiClient::new();
+        let record = |id: &str, fields: serde_json::Value| RemoteSettingsRecord {
+            id: id.into(),
+            last_modified: 100,
+            deleted: false,
+            attachment: None,
+            fields: fields.as_object().unwrap().clone(),
+        };
+        let records = vec![
+            record("no-filter", serde_json::json!({})),
+            record(
+                "too-low",
+                serde_json::json!({
+                    "filter_expression":
+                        "env.channel == 'beta' && env.version|versionCompare('130.0') >= 0"
+                }),
+            ),
+        ];
+        api_client.expect_collection_url().returning(|| {
+            "http://rs.example.com/v1/buckets/main/collections/test-collection".into()
+        });
+        api_client.expect_is_prod_server().returning(|| Ok(false));
+
+        let mut storage = Storage::new(":memory:".into());
+        storage
+            .insert_collection_content(
+                "http://rs.example.com/v1/buckets/main/collections/test-collection",
+                &records,
+                42,
+                CollectionMetadata::default(),
+            )
+            .unwrap();
+
+        let rs_client = RemoteSettingsClient::new_from_parts(
+            "test-collection".into(),
+            storage,
+            JexlFilter::new(Some(RemoteSettingsContext {
+                app_version: Some("129.0.0".to_string()),
+                channel: Some("beta".to_string()),
+                ..Default::default()
+            })),
+            api_client,
+        );
+
+        let explanations: HashMap<_, _> =
+            rs_client.explain_records().unwrap().into_iter().collect();
+        assert_eq!(explanations.len(), 2);
+        assert_eq!(
+            explanations["no-filter"].result,
+            crate::FilterResult::NoExpression
+        );
+        assert!(!explanations["too-low"].matches);
+        assert_eq!(
+            explanations["too-low"].result,
+            crate::FilterResult::NotMatched {
+                clauses: vec![crate::FilterClause {
+                    expression: "env.version|versionCompare('130.0') >= 0".into(),
+                    result: "false".into(),
+                }]
+            }
+        );
+    }
+
     #[test]
     fn test_get_records_filtered_app_version_too_low() {
         let mut api_client = MockApiClient::new();
@@ -2013,13 +2583,14 @@ mod jexl_tests {
             changes: records.clone(),
             timestamp: 42,
             metadata: CollectionMetadata::default(),
+            size: 0,
         };
         api_client.expect_collection_url().returning(|| {
             "http://rs.example.com/v1/buckets/main/collections/test-collection".into()
         });
         api_client.expect_fetch_changeset().returning({
             let changeset = changeset.clone();
-            move |timestamp| {
+            move |timestamp, _| {
                 assert_eq!(timestamp, None);
                 Ok(changeset.clone())
             }
@@ -2071,13 +2642,14 @@ mod jexl_tests {
             changes: records.clone(),
             timestamp: 42,
             metadata: CollectionMetadata::default(),
+            size: 0,
         };
         api_client.expect_collection_url().returning(|| {
             "http://rs.example.com/v1/buckets/main/collections/test-collection".into()
         });
         api_client.expect_fetch_changeset().returning({
             let changeset = changeset.clone();
-            move |timestamp| {
+            move |timestamp, _| {
                 assert_eq!(timestamp, None);
                 Ok(changeset.clone())
             }
@@ -2184,6 +2756,7 @@ mod test_signatures {
     use crate::RemoteSettingsContext;
 
     use super::*;
+    use crate::reports::{testing::SyncReportRecorder, RemoteSettingsSyncReport};
     use nss::ensure_initialized;
 
     const VALID_CERTIFICATE: &str = "\
@@ -2292,7 +2865,27 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
         signatures: &[CollectionSignature],
         epoch_secs: u64,
         bucket: &str,
-    ) -> Result<()> {
+    ) -> Result<RemoteSettingsChanges> {
+        run_client_sync_with_report(
+            diff_records,
+            full_records,
+            certificate,
+            signatures,
+            epoch_secs,
+            bucket,
+        )
+        .0
+    }
+
+    /// Run a sync like [run_client_sync], and also return its report
+    fn run_client_sync_with_report(
+        diff_records: &[RemoteSettingsRecord],
+        full_records: &[RemoteSettingsRecord],
+        certificate: &str,
+        signatures: &[CollectionSignature],
+        epoch_secs: u64,
+        bucket: &str,
+    ) -> (Result<RemoteSettingsChanges>, RemoteSettingsSyncReport) {
         let collection_name = "pioneer-study-addons";
 
         MOCK_TIME.with(|cell| cell.set(Some(epoch_secs)));
@@ -2306,12 +2899,14 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             changes: diff_records.to_vec(),
             timestamp: 1603992731957,
             metadata: some_metadata.clone(),
+            size: 100,
         };
         // Changeset for when client retries from scratch.
         let full_changeset = ChangesetResponse {
             changes: full_records.to_vec(),
             timestamp: 1603992731957,
             metadata: some_metadata.clone(),
+            size: 1000,
         };
 
         let mut api_client = MockApiClient::new();
@@ -2319,13 +2914,15 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             .expect_collection_url()
             .returning(move || format!("http://server/{}", collection_name));
         api_client.expect_is_prod_server().returning(|| Ok(false));
-        api_client.expect_fetch_changeset().returning(move |since| {
-            Ok(if since.is_some() {
-                diff_changeset.clone()
-            } else {
-                full_changeset.clone()
-            })
-        });
+        api_client
+            .expect_fetch_changeset()
+            .returning(move |since, _| {
+                Ok(if since.is_some() {
+                    diff_changeset.clone()
+                } else {
+                    full_changeset.clone()
+                })
+            });
 
         let certificate = certificate.to_string();
         api_client
@@ -2340,14 +2937,21 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             jexl_filter,
             api_client,
         );
+        let recorder = SyncReportRecorder::default();
+        rs_client
+            .sync_report_listeners
+            .add(Box::new(recorder.clone()));
 
-        rs_client.sync()
+        let result = rs_client.sync();
+        let mut reports = recorder.take();
+        assert_eq!(reports.len(), 1);
+        (result, reports.remove(0))
     }
 
     #[test]
     fn test_valid_signature() -> Result<()> {
         ensure_initialized();
-        run_client_sync(
+        let (result, report) = run_client_sync_with_report(
             &[],
             &[],
             VALID_CERTIFICATE,
@@ -2357,8 +2961,12 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             }],
             VALID_CERT_EPOCH_SECONDS,
             "main",
-        )
-        .expect("Valid signature");
+        );
+        result.expect("Valid signature");
+        assert_eq!(report.signature, SignatureVerification::Valid);
+        assert_eq!(report.retry, SyncRetryPath::NotRetried);
+        assert!(!report.delta);
+        assert_eq!(report.bytes, 1000);
         Ok(())
     }
 
@@ -2389,7 +2997,7 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
     #[test]
     fn test_valid_signature_after_retry() -> Result<()> {
         ensure_initialized();
-        run_client_sync(
+        let (result, report) = run_client_sync_with_report(
             &[RemoteSettingsRecord {
                 id: "bad-record".to_string(),
                 last_modified: 9999,
@@ -2405,15 +3013,19 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             }],
             VALID_CERT_EPOCH_SECONDS,
             "main",
-        )
-        .expect("Valid signature");
+        );
+        result.expect("Valid signature");
+        assert_eq!(report.signature, SignatureVerification::Invalid);
+        assert_eq!(report.retry, SyncRetryPath::Retried);
+        assert_eq!(report.bytes, 1000);
+        assert_eq!(report.error, None);
         Ok(())
     }
 
     #[test]
     fn test_invalid_signature_value() -> Result<()> {
         ensure_initialized();
-        let err = run_client_sync(
+        let (result, report) = run_client_sync_with_report(
             &[],
             &[],
             VALID_CERTIFICATE,
@@ -2423,10 +3035,13 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
             }],
             VALID_CERT_EPOCH_SECONDS,
             "main",
-        )
-        .unwrap_err();
+        );
+        let err = result.unwrap_err();
         assert!(matches!(err, Error::SignatureError(_)));
         assert_eq!(format!("{}", err), "Signature could not be verified: Signature content error: Encoded text cannot have a 6-bit remainder.");
+        assert_eq!(report.signature, SignatureVerification::Invalid);
+        assert_eq!(report.retry, SyncRetryPath::ResetAfterRetry);
+        assert_eq!(report.error, Some(err.to_string()));
 
         Ok(())
     }
@@ -2536,6 +3151,72 @@ IKdcFKAt3fFrpyMhlfIKkLfmm0iDjmfmIXbDGBJw9SE=
 
         Ok(())
     }
+
+    #[test]
+    fn test_fetch_cert_error_fails_sync() -> Result<()> {
+        ensure_initialized();
+        let collection_name = "pioneer-study-addons";
+        MOCK_TIME.with(|cell| cell.set(Some(VALID_CERT_EPOCH_SECONDS)));
+
+        let mut api_client = MockApiClient::new();
+        api_client
+            .expect_collection_url()
+            .returning(move || format!("http://server/{}", collection_name));
+        api_client.expect_is_prod_server().returning(|| Ok(false));
+        api_client
+            .expect_fetch_changeset()
+            .times(1)
+            .returning(|_, _| {
+                Ok(ChangesetResponse {
+                    changes: vec![RemoteSettingsRecord {
+                        id: "unverified".to_string(),
+                        last_modified: 100,
+                        deleted: false,
+                        attachment: None,
+                        fields: serde_json::Map::new(),
+                    }],
+                    timestamp: 100,
+                    metadata: CollectionMetadata {
+                        bucket: "main".into(),
+                        signatures: vec![CollectionSignature {
+                            signature: VALID_SIGNATURE.to_string(),
+                            x5u: "http://mocked".into(),
+                        }],
+                    },
+                    size: 100,
+                })
+            });
+        api_client.expect_fetch_cert().returning(|url| {
+            Err(Error::ResponseError {
+                url: url.to_string(),
+                message: "503".to_string(),
+            })
+        });
+
+        let rs_client = RemoteSettingsClient::new_from_parts(
+            collection_name.to_string(),
+            Storage::new(":memory:".into()),
+            JexlFilter::new(Some(RemoteSettingsContext::default())),
+            api_client,
+        );
+        let recorder = SyncReportRecorder::default();
+        rs_client
+            .sync_report_listeners
+            .add(Box::new(recorder.clone()));
+
+        // Failing to fetch the certificate is a sync error, not an invalid signature, so the
+        // client doesn't retry, and doesn't keep the unverified records.
+        let err = rs_client.sync().unwrap_err();
+        assert!(matches!(err, Error::ResponseError { .. }));
+        let reports = recorder.take();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].signature, SignatureVerification::Skipped);
+        assert_eq!(reports[0].retry, SyncRetryPath::NotRetried);
+        assert_eq!(reports[0].error, Some(err.to_string()));
+        assert_eq!(rs_client.get_records(false)?, None);
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2572,13 +3253,13 @@ mod test_reset_storage {
             .expect("Failed to insert records");
 
         storage
-            .set_attachment(collection_url, "attachments/test-file.bin", b"data")
+            .set_attachment(records[0].attachment.as_ref().unwrap(), b"data")
             .expect("Failed to insert attachment");
 
         // Verify data is present before reset
         assert!(storage.get_records(collection_url).unwrap().is_some());
         assert!(storage
-            .get_attachment(collection_url, records[0].attachment.clone().unwrap())
+            .get_attachment(records[0].attachment.clone().unwrap())
             .unwrap()
             .is_some());
 
@@ -2601,7 +3282,7 @@ mod test_reset_storage {
         assert_eq!(
             inner
                 .storage
-                .get_attachment(collection_url, records[0].attachment.clone().unwrap(),)
+                .get_attachment(records[0].attachment.clone().unwrap())
                 .unwrap(),
             None,
             "Attachments should be deleted after reset_storage"
@@ -2670,3 +3351,274 @@ mod test_reset_storage {
         );
     }
 }
+
+#[cfg(test)]
+#[cfg(not(feature = "signatures"))]
+mod test_sync_changes {
+    use super::*;
+    use crate::reports::testing::SyncReportRecorder;
+
+    fn record(id: &str, last_modified: u64, deleted: bool) -> RemoteSettingsRecord {
+        RemoteSettingsRecord {
+            id: id.into(),
+            last_modified,
+            deleted,
+            attachment: None,
+            fields: serde_json::Map::new(),
+        }
+    }
+
+    #[test]
+    fn test_sync_returns_changes() {
+        let collection_url = "http://rs.example.com/v1/buckets/main/collections/test-collection";
+
+        let mut api_client = MockApiClient::new();
+        api_client
+            .expect_collection_url()
+            .returning(|| collection_url.into());
+        api_client.expect_is_prod_server().returning(|| Ok(false));
+        api_client.expect_fetch_changeset().returning(|since, _| {
+            assert_eq!(since, Some(100));
+            Ok(ChangesetResponse {
+                changes: vec![
+                    record("updated", 200, false),
+                    record("deleted", 200, true),
+                    record("created", 200, false),
+                    // Tombstones for records we never stored aren't changes.
+                    record("unknown", 200, true),
+                ],
+                timestamp: 200,
+                metadata: CollectionMetadata::default(),
+                size: 500,
+            })
+        });
+
+        let mut storage = Storage::new(":memory:".into());
+        storage
+            .insert_collection_content(
+                collection_url,
+                &[record("updated", 100, false), record("deleted", 100, false)],
+                100,
+                CollectionMetadata::default(),
+            )
+            .expect("Failed to insert records");
+
+        let rs_client = RemoteSettingsClient::new_from_parts(
+            "test-collection".into(),
+            storage,
+            JexlFilter::new(None),
+            api_client,
+        );
+
+        let recorder = SyncReportRecorder::default();
+        rs_client
+            .sync_report_listeners
+            .add(Box::new(recorder.clone()));
+
+        assert_eq!(
+            rs_client.sync().expect("Failed to sync"),
+            RemoteSettingsChanges {
+                created: vec!["created".into()],
+                updated: vec!["updated".into()],
+                deleted: vec!["deleted".into()],
+            }
+        );
+        let reports = recorder.take();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].collection_name, "test-collection");
+        assert!(reports[0].delta);
+        assert_eq!(reports[0].bytes, 500);
+        assert_eq!(reports[0].changed_records, 3);
+        assert_eq!(reports[0].signature, SignatureVerification::Skipped);
+        assert_eq!(reports[0].retry, SyncRetryPath::NotRetried);
+        assert_eq!(reports[0].error, None);
+    }
+}
+
+#[cfg(test)]
+mod test_async {
+    use super::*;
+    use mockito::mock;
+
+    fn new_client(collection_name: &str) -> RemoteSettingsClient<ViaductApiClient> {
+        viaduct_dev::init_backend_dev();
+        RemoteSettingsClient::new(
+            BaseUrl::parse(&format!("{}/v1", mockito::server_url())).unwrap(),
+            "main".into(),
+            collection_name.into(),
+            None,
+            Storage::new(":memory:".into()),
+            Arc::default(),
+        )
+    }
+
+    #[test]
+    fn test_get_records_async() {
+        let rs_client = new_client("async-records");
+        let m = mock(
+            "GET",
+            "/v1/buckets/main/collections/async-records/changeset?_expected=0",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{"id": "record-1", "last_modified": 100}],
+                "timestamp": 100,
+                "metadata": {"bucket": "main", "signatures": []},
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+
+        // Without `sync_if_empty`, an empty cache doesn't make any requests.
+        assert_eq!(
+            pollster::block_on(rs_client.get_records_async(false)).unwrap(),
+            None
+        );
+
+        let records = pollster::block_on(rs_client.get_records_async(true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "record-1");
+
+        // Records are cached now, so this shouldn't sync again.
+        let records = pollster::block_on(rs_client.get_records_async(true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        m.assert();
+    }
+
+    // Without signatures, nothing awaits between storing the changeset and verifying it.
+    #[cfg(feature = "signatures")]
+    #[test]
+    fn test_drop_sync_async_before_verifying() {
+        use std::{
+            future::Future,
+            io::Write,
+            pin::pin,
+            sync::mpsc,
+            task::{Context, Waker},
+        };
+
+        let rs_client = new_client("async-drop");
+        let m_changeset = mock(
+            "GET",
+            "/v1/buckets/main/collections/async-drop/changeset?_expected=0",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{"id": "unverified", "last_modified": 100}],
+                "timestamp": 100,
+                "metadata": {
+                    "bucket": "main",
+                    "signatures": [{
+                        "signature": "signature",
+                        "x5u": format!("{}/async-drop.pem", mockito::server_url()),
+                    }],
+                },
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+        // Hold the certificate response until the sync is dropped.
+        let (release_cert, cert_released) = mpsc::channel::<()>();
+        let cert_released = Mutex::new(cert_released);
+        let m_cert = mock("GET", "/async-drop.pem")
+            .with_status(200)
+            .with_body_from_fn(move |w| {
+                let _ = cert_released.lock().recv_timeout(Duration::from_secs(10));
+                w.write_all(b"certificate")
+            })
+            .create();
+
+        {
+            let mut sync = pin!(rs_client.sync_async());
+            let mut cx = Context::from_waker(Waker::noop());
+            while !m_cert.matched() {
+                assert!(sync.as_mut().poll(&mut cx).is_pending());
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        release_cert.send(()).unwrap();
+
+        // The changeset was fetched, but dropping the sync while it waited for the certificate
+        // shouldn't store the unverified records.
+        m_changeset.assert();
+        assert_eq!(rs_client.get_records(false).unwrap(), None);
+        assert_eq!(rs_client.get_last_modified_timestamp().unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_attachment_async() {
+        let rs_client = new_client("async-attachments");
+        let attachment_data = b"async attachment".to_vec();
+        let record = RemoteSettingsRecord {
+            id: "record-1".into(),
+            last_modified: 100,
+            deleted: false,
+            attachment: Some(Attachment {
+                filename: "async.txt".into(),
+                mimetype: "text/plain".into(),
+                location: "async-attachments/async.txt".into(),
+                hash: format!("{:x}", Sha256::digest(&attachment_data)),
+                size: attachment_data.len() as u64,
+            }),
+            fields: serde_json::Map::new(),
+        };
+
+        let server_info_m = mock("GET", "/v1/")
+            .with_body(
+                serde_json::json!({
+                    "capabilities": {
+                        "attachments": {
+                            "base_url": format!("{}/attachments/", mockito::server_url()),
+                        },
+                    },
+                })
+                .to_string(),
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .create();
+        let attachment_m = mock("GET", "/attachments/async-attachments/async.txt")
+            .with_body(attachment_data.clone())
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        assert_eq!(
+            pollster::block_on(rs_client.get_attachment_async(&record)).unwrap(),
+            attachment_data
+        );
+        // The second call should read the attachment from storage.
+        assert_eq!(
+            pollster::block_on(rs_client.get_attachment_async(&record)).unwrap(),
+            attachment_data
+        );
+        server_info_m.expect(1).assert();
+        attachment_m.assert();
+
+        // Attachments that don't match their metadata aren't stored.
+        let mut mismatched_record = record.clone();
+        if let Some(attachment) = &mut mismatched_record.attachment {
+            attachment.location = "async-attachments/mismatched.txt".into();
+            attachment.hash = "0".repeat(64);
+        }
+        let mismatched_m = mock("GET", "/attachments/async-attachments/mismatched.txt")
+            .with_body(attachment_data.clone())
+            .with_status(200)
+            .create();
+        assert!(matches!(
+            pollster::block_on(rs_client.get_attachment_async(&mismatched_record)),
+            Err(Error::RecordAttachmentMismatchError(_))
+        ));
+        mismatched_m.assert();
+    }
+}
@@ -51,6 +51,22 @@ pub struct RemoteSettingsContext {
     /// added to the official list and supported by both the Rust and Gecko clients.
     #[uniffi(default = None)]
     pub custom_targetting_attributes: Option<HashMap<String, String>>,
+    /// A stable, random ID for the profile, which is the input for `bucketSample` and
+    /// `stableSample` in filter expressions, as `env.samplingId`.
+    #[uniffi(default = None)]
+    pub sampling_id: Option<String>,
+    /// Preference values for the `preferenceValue` and `preferenceExists` transforms, by name.
+    ///
+    /// Values are stringified JSON, like `true`, `3`, or `"value"`.  Other strings are treated as
+    /// bare strings.  Preferences aren't included in the env.
+    #[uniffi(default = None)]
+    pub preferences: Option<HashMap<String, String>>,
+    /// Names of the preferences that the user has changed from their default values, for the
+    /// `preferenceIsUserSet` transform.
+    ///
+    /// These are usually also in [Self::preferences], with their user values.
+    #[uniffi(default = None)]
+    pub user_set_preferences: Option<Vec<String>>,
 }
 
 impl RemoteSettingsContext {
@@ -94,6 +110,9 @@ impl RemoteSettingsContext {
         if let Some(country) = self.country {
             v.insert("country".to_string(), country.into());
         }
+        if let Some(sampling_id) = self.sampling_id {
+            v.insert("samplingId".to_string(), sampling_id.into());
+        }
         if let Some(custom) = self.custom_targetting_attributes {
             v.extend(custom.into_iter().map(|(k, v)| (k, v.into())));
         }
@@ -121,6 +140,9 @@ mod test {
             form_factor: Some("tablet".into()),
             country: Some("US".into()),
             custom_targetting_attributes: Some(HashMap::from([("extra".into(), "test".into())])),
+            sampling_id: Some("abc".into()),
+            preferences: Some(HashMap::from([("pref".into(), "true".into())])),
+            user_set_preferences: Some(vec!["pref".into()]),
         };
         assert_eq!(
             context.into_env(),
@@ -141,6 +163,7 @@ mod test {
                 // into official fields that both the Gecko and Rust client support.
                 "formFactor": "tablet",
                 "country": "US",
+                "samplingId": "abc",
                 "extra": "test",
             })
         );
@@ -59,7 +59,6 @@ pub enum Error {
     RecordAttachmentMismatchError(String),
     #[error("Incomplete signature data: {0}")]
     IncompleteSignatureDataError(String),
-    #[cfg(feature = "signatures")]
     #[error("Data could not be serialized: {0}")]
     SerializationError(#[from] canonical_json::CanonicalJSONError),
     #[cfg(feature = "signatures")]
@@ -68,6 +67,17 @@ pub enum Error {
 }
 
 impl Error {
+    /// True if this error means that the stored records don't match their signature, rather than
+    /// that the signature couldn't be checked, like when the certificates couldn't be fetched.
+    pub(crate) fn is_invalid_signature(&self) -> bool {
+        match self {
+            Self::IncompleteSignatureDataError(_) | Self::SerializationError(_) => true,
+            #[cfg(feature = "signatures")]
+            Self::SignatureError(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn response_error(url: &url::Url, message: impl Into<String>) -> Self {
         Self::ResponseError {
             url: url.to_string(),
@@ -2,10 +2,38 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+//! JEXL filter expressions.
+//!
+//! Records can have a `filter_expression`, which is evaluated against an `env` built from the
+//! [RemoteSettingsContext]. Records are only returned if their expression is true.
+//!
+//! The transforms match the ones that Desktop and Nimbus support, where they make sense without
+//! a Gecko profile:
+//!
+//! * `versionCompare`: `env.version|versionCompare('128.0') >= 0`
+//! * `bucketSample` and `stableSample`, which hash their input like Nimbus and Normandy do:
+//!   `[env.samplingId, 'my-rollout']|bucketSample(0, 100, 10000)`,
+//!   `env.samplingId|stableSample(0.5)`
+//! * `date`, which converts an ISO 8601 date into milliseconds since the epoch, so that it can be
+//!   compared to `env.currentDate`: `'2026-01-01'|date <= env.currentDate`
+//! * `preferenceValue` and `preferenceExists`, which look up
+//!   [RemoteSettingsContext::preferences]: `'browser.foo.enabled'|preferenceValue(false)`
+//! * `preferenceIsUserSet`, which looks up [RemoteSettingsContext::user_set_preferences]:
+//!   `'browser.foo.enabled'|preferenceIsUserSet`
+//! * `keys`, `length`, `mapToProperty`, and `regExpMatch`, which work like Desktop's.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
 use crate::RemoteSettingsContext;
+use anyhow::anyhow;
 use firefox_versioning::compare::version_compare;
 use jexl_eval::Evaluator;
-use serde_json::{json, Value};
+use regex::RegexBuilder;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -20,26 +48,113 @@ pub(crate) enum ParseError {
 pub struct JexlFilter {
     /// a JEXL `Evaluator` to run transforms and evaluations on.
     evaluator: Evaluator<'static>,
-    /// The transformed `RemoteSettingsContext` as a `serde_json::Value`
-    context: Value,
+    /// The transformed `RemoteSettingsContext`, without `currentDate`, which is added for each
+    /// evaluation.
+    env: Map<String, Value>,
+}
+
+/// Why a `filter_expression` did or didn't match
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct FilterExplanation {
+    /// The expression, or `None` if the record doesn't have one
+    pub filter_expression: Option<String>,
+    /// True if the record is included
+    pub matches: bool,
+    pub result: FilterResult,
+    /// The `env` that the expression was evaluated against, as JSON
+    pub env: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum FilterResult {
+    /// There's no expression, so the record is always included
+    NoExpression,
+    /// The expression evaluated to true
+    Matched,
+    /// The expression evaluated to false.  `clauses` are the top-level `&&` clauses of the
+    /// expression that didn't evaluate to true, so that it's clear which part excluded the record.
+    NotMatched { clauses: Vec<FilterClause> },
+    /// The expression didn't evaluate to a boolean, which excludes the record.  `result` is what
+    /// it evaluated to, as JSON.
+    NotBoolean { result: String },
+    /// The expression couldn't be evaluated, which excludes the record.
+    Error { message: String },
+}
+
+/// A clause of a `filter_expression`, and what it evaluated to
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct FilterClause {
+    pub expression: String,
+    /// The clause's value as JSON, or the error that it failed with
+    pub result: String,
 }
 
 impl JexlFilter {
     /// Creating a new `JEXL` filter. If no `context` is set, all future `records` are being
     /// evaluated as `true` by default.
-    pub(crate) fn new(context: Option<RemoteSettingsContext>) -> Self {
-        let env_context = match context {
-            Some(ctx) => json!({ "env": ctx.into_env() }),
-            None => json!({ "env": {} }),
+    pub fn new(context: Option<RemoteSettingsContext>) -> Self {
+        let (env, preferences, user_set_preferences) = match context {
+            Some(mut ctx) => {
+                let preferences = ctx.preferences.take().unwrap_or_default();
+                let user_set_preferences = ctx.user_set_preferences.take().unwrap_or_default();
+                let env = match ctx.into_env() {
+                    Value::Object(env) => env,
+                    _ => Map::new(),
+                };
+                (env, preferences, user_set_preferences)
+            }
+            None => (Map::new(), HashMap::new(), vec![]),
         };
+        let preferences: Arc<HashMap<String, Value>> = Arc::new(
+            preferences
+                .into_iter()
+                .map(|(name, value)| {
+                    // Preference values are stringified JSON, but bare strings are allowed too.
+                    let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                    (name, value)
+                })
+                .collect(),
+        );
+        let user_set_preferences: HashSet<String> = user_set_preferences.into_iter().collect();
 
         Self {
             evaluator: Evaluator::new()
-                .with_transform("versionCompare", |args| Ok(version_compare(args)?)),
-            context: env_context,
+                .with_transform("versionCompare", |args| Ok(version_compare(args)?))
+                .with_transform("bucketSample", bucket_sample)
+                .with_transform("stableSample", stable_sample)
+                .with_transform("date", date)
+                .with_transform("keys", keys)
+                .with_transform("length", length)
+                .with_transform("mapToProperty", map_to_property)
+                .with_transform("regExpMatch", reg_exp_match)
+                .with_transform("preferenceValue", {
+                    let preferences = preferences.clone();
+                    move |args| {
+                        let name = preference_name(args)?;
+                        Ok(preferences
+                            .get(name)
+                            .or_else(|| args.get(1))
+                            .cloned()
+                            .unwrap_or(Value::Null))
+                    }
+                })
+                .with_transform("preferenceIsUserSet", move |args| {
+                    Ok(user_set_preferences.contains(preference_name(args)?).into())
+                })
+                .with_transform("preferenceExists", move |args| {
+                    Ok(preferences.contains_key(preference_name(args)?).into())
+                }),
+            env,
         }
     }
 
+    /// The context to evaluate expressions in
+    fn context(&self) -> Value {
+        let mut env = self.env.clone();
+        env.insert("currentDate".to_string(), now_millis().into());
+        json!({ "env": env })
+    }
+
     /// Evaluates the given filter expression in the provided context.
     /// Returns `Ok(true)` if the expression evaluates to true, `Ok(false)` otherwise.
     pub(crate) fn evaluate(&self, filter_expr: &str) -> Result<bool, ParseError> {
@@ -47,13 +162,469 @@ impl JexlFilter {
             return Ok(true);
         }
 
-        let result = self
-            .evaluator
-            .eval_in_context(filter_expr, &self.context)
+        let result = self.evaluate_value(filter_expr, &self.context())?;
+        result.as_bool().ok_or(ParseError::InvalidResultType)
+    }
+
+    fn evaluate_value(&self, filter_expr: &str, context: &Value) -> Result<Value, ParseError> {
+        self.evaluator
+            .eval_in_context(filter_expr, context)
             .map_err(|e| {
                 ParseError::EvaluationError(format!("Failed to evaluate '{}': {}", filter_expr, e))
-            })?;
+            })
+    }
 
-        result.as_bool().ok_or(ParseError::InvalidResultType)
+    /// Returns whether a record with the given filter expression should be included.
+    ///
+    /// Expressions that fail to evaluate, or don't evaluate to a boolean, exclude the record.
+    pub fn matches(&self, filter_expr: &str) -> bool {
+        self.evaluate(filter_expr).unwrap_or(false)
+    }
+
+    /// Explains why a record with the given filter expression is or isn't included.
+    ///
+    /// This is meant for debugging.  It evaluates each clause of expressions that don't match
+    /// separately, so it's slower than [Self::matches].
+    pub fn explain(&self, filter_expr: Option<&str>) -> FilterExplanation {
+        let context = self.context();
+        let env = serde_json::to_string(&context["env"]).unwrap_or_default();
+        let Some(filter_expr) = filter_expr.filter(|expr| !expr.trim().is_empty()) else {
+            return FilterExplanation {
+                filter_expression: None,
+                matches: true,
+                result: FilterResult::NoExpression,
+                env,
+            };
+        };
+        let result = match self.evaluate_value(filter_expr, &context) {
+            Ok(Value::Bool(true)) => FilterResult::Matched,
+            Ok(Value::Bool(false)) => FilterResult::NotMatched {
+                clauses: split_clauses(filter_expr)
+                    .into_iter()
+                    .filter_map(|clause| {
+                        let result = match self.evaluate_value(clause, &context) {
+                            Ok(Value::Bool(true)) => return None,
+                            Ok(value) => value.to_string(),
+                            Err(e) => e.to_string(),
+                        };
+                        Some(FilterClause {
+                            expression: clause.to_string(),
+                            result,
+                        })
+                    })
+                    .collect(),
+            },
+            Ok(value) => FilterResult::NotBoolean {
+                result: value.to_string(),
+            },
+            Err(e) => FilterResult::Error {
+                message: e.to_string(),
+            },
+        };
+        FilterExplanation {
+            filter_expression: Some(filter_expr.to_string()),
+            matches: result == FilterResult::Matched,
+            result,
+            env,
+        }
+    }
+}
+
+/// Splits an expression into its top-level `&&` clauses
+fn split_clauses(filter_expr: &str) -> Vec<&str> {
+    let mut clauses = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    let mut chars = filter_expr.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            '&' if depth == 0 && chars.peek().map(|(_, c)| *c) == Some('&') => {
+                clauses.push(filter_expr[start..i].trim());
+                chars.next();
+                start = i + 2;
+            }
+            _ => (),
+        }
+    }
+    clauses.push(filter_expr[start..].trim());
+    clauses
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn preference_name(args: &[Value]) -> anyhow::Result<&str> {
+    args.first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("preference name is not a string"))
+}
+
+fn number_arg(args: &[Value], idx: usize, name: &str) -> anyhow::Result<f64> {
+    args.get(idx)
+        .ok_or_else(|| anyhow!("{} doesn't exist in jexl transform", name))?
+        .as_f64()
+        .ok_or_else(|| anyhow!("{} is not a number", name))
+}
+
+/// The number of bits of the input hash that sampling uses, like Nimbus and Normandy
+const HASH_BITS: u32 = 48;
+
+/// Hashes a sampling input into the range [0, 2^48)
+fn sample_hash(input: &Value) -> u64 {
+    let hash = Sha256::digest(input.to_string().as_bytes());
+    hash[..6]
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+}
+
+/// Maps a fraction in the range [0, 1] onto the range of [sample_hash]
+fn fraction_to_key(fraction: f64) -> anyhow::Result<u64> {
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(anyhow!("{fraction} is not between 0 and 1"));
+    }
+    Ok((fraction * (2u64.pow(HASH_BITS) - 1) as f64).floor() as u64)
+}
+
+/// `input|bucketSample(start, count, total)`: true if the input is in `count` of `total` buckets,
+/// starting at `start` and wrapping around.
+fn bucket_sample(args: &[Value]) -> anyhow::Result<Value> {
+    let input = args
+        .first()
+        .ok_or_else(|| anyhow!("input doesn't exist in jexl transform"))?;
+    let start = number_arg(args, 1, "start")? as u64;
+    let count = number_arg(args, 2, "count")? as u64;
+    let total = number_arg(args, 3, "total")? as u64;
+    if total == 0 {
+        return Err(anyhow!("total is 0"));
+    }
+
+    let hash = sample_hash(input);
+    let in_buckets = |min: u64, max: u64| -> anyhow::Result<bool> {
+        let min = fraction_to_key(min as f64 / total as f64)?;
+        let max = fraction_to_key(max as f64 / total as f64)?;
+        Ok(min <= hash && hash < max)
+    };
+    let start = start % total;
+    let end = start + count;
+    let result = if end > total {
+        in_buckets(0, end % total)? || in_buckets(start, total)?
+    } else {
+        in_buckets(start, end)?
+    };
+    Ok(result.into())
+}
+
+/// `input|stableSample(rate)`: true for `rate` of all inputs
+fn stable_sample(args: &[Value]) -> anyhow::Result<Value> {
+    let input = args
+        .first()
+        .ok_or_else(|| anyhow!("input doesn't exist in jexl transform"))?;
+    let rate = number_arg(args, 1, "rate")?;
+    Ok((sample_hash(input) < fraction_to_key(rate)?).into())
+}
+
+/// `'2026-01-01T12:00:00Z'|date`: the date as milliseconds since the epoch
+fn date(args: &[Value]) -> anyhow::Result<Value> {
+    match args.first() {
+        Some(Value::Number(n)) => Ok(Value::Number(n.clone())),
+        Some(Value::String(s)) => parse_date(s)
+            .map(Value::from)
+            .ok_or_else(|| anyhow!("invalid date: {s}")),
+        _ => Err(anyhow!("date is not a string")),
+    }
+}
+
+/// Parses an ISO 8601 date or date-time into milliseconds since the epoch
+///
+/// Times without an offset are treated as UTC.
+fn parse_date(s: &str) -> Option<i64> {
+    fn number(s: &str, len: usize) -> Option<i64> {
+        if s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    let s = s.trim();
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let mut date_parts = date.split('-');
+    let year = number(date_parts.next()?, 4)?;
+    let month = number(date_parts.next()?, 2)?;
+    let day = number(date_parts.next()?, 2)?;
+    if date_parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+    if let Some(time) = time {
+        let (time, offset_millis) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+            (time, 0)
+        } else if let Some(i) = time.rfind(['+', '-']) {
+            let (hours, minutes) = time[i + 1..].split_once(':')?;
+            let offset = (number(hours, 2)? * 60 + number(minutes, 2)?) * 60_000;
+            let sign = if time[i..].starts_with('-') { -1 } else { 1 };
+            (&time[..i], sign * offset)
+        } else {
+            (time, 0)
+        };
+        let (time, fraction) = match time.split_once('.') {
+            Some((time, fraction)) => (time, Some(fraction)),
+            None => (time, None),
+        };
+        let mut time_parts = time.split(':');
+        let hours = number(time_parts.next()?, 2)?;
+        let minutes = number(time_parts.next()?, 2)?;
+        let seconds = time_parts.next().map_or(Some(0), |s| number(s, 2))?;
+        if time_parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+            return None;
+        }
+        let fraction_millis = match fraction {
+            Some(fraction) => {
+                let digits = &fraction[..fraction.len().min(3)];
+                number(digits, digits.len())? * 10_i64.pow(3 - digits.len() as u32)
+            }
+            None => 0,
+        };
+        millis += ((hours * 60 + minutes) * 60 + seconds) * 1000 + fraction_millis;
+        millis -= offset_millis;
+    }
+    Some(millis)
+}
+
+/// Days since 1970-01-01 for a date in the proleptic Gregorian calendar
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// `obj|keys`: the keys of an object
+fn keys(args: &[Value]) -> anyhow::Result<Value> {
+    match args.first() {
+        Some(Value::Object(obj)) => Ok(obj.keys().cloned().collect::<Vec<_>>().into()),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `value|length`: the length of an array, string, or object
+fn length(args: &[Value]) -> anyhow::Result<Value> {
+    match args.first() {
+        Some(Value::Array(array)) => Ok(array.len().into()),
+        Some(Value::String(s)) => Ok(s.chars().count().into()),
+        Some(Value::Object(obj)) => Ok(obj.len().into()),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `array|mapToProperty('name')`: the `name` property of each object in an array
+fn map_to_property(args: &[Value]) -> anyhow::Result<Value> {
+    let property = args
+        .get(1)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("property is not a string"))?;
+    match args.first() {
+        Some(Value::Array(array)) => Ok(array
+            .iter()
+            .map(|item| item.get(property).cloned().unwrap_or(Value::Null))
+            .collect::<Vec<_>>()
+            .into()),
+        _ => Ok(Value::Null),
+    }
+}
+
+/// `string|regExpMatch(pattern, flags)`: the match and its groups, or null if the string doesn't
+/// match.  The `i`, `m`, and `s` flags are supported.
+fn reg_exp_match(args: &[Value]) -> anyhow::Result<Value> {
+    let Some(Value::String(input)) = args.first() else {
+        return Ok(Value::Null);
+    };
+    let pattern = args
+        .get(1)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("pattern is not a string"))?;
+    let flags = args.get(2).and_then(Value::as_str).unwrap_or_default();
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(flags.contains('i'))
+        .multi_line(flags.contains('m'))
+        .dot_matches_new_line(flags.contains('s'))
+        .build()?;
+    Ok(match regex.captures(input) {
+        Some(captures) => captures
+            .iter()
+            .map(|m| m.map_or(Value::Null, |m| m.as_str().into()))
+            .collect::<Vec<_>>()
+            .into(),
+        None => Value::Null,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filter() -> JexlFilter {
+        JexlFilter::new(Some(RemoteSettingsContext {
+            app_version: Some("129.0.1".into()),
+            channel: Some("beta".into()),
+            country: Some("US".into()),
+            sampling_id: Some("e5a2c4b0-3f5e-4f5a-9c3e-1d2b3c4d5e6f".into()),
+            preferences: Some(HashMap::from([
+                ("browser.feature.enabled".into(), "true".into()),
+                ("browser.feature.name".into(), "Alpha".into()),
+                ("browser.feature.count".into(), "3".into()),
+            ])),
+            user_set_preferences: Some(vec!["browser.feature.enabled".into()]),
+            ..RemoteSettingsContext::default()
+        }))
+    }
+
+    #[test]
+    fn test_transforms() {
+        let filter = filter();
+        for expr in [
+            "env.version|versionCompare('128.0') >= 0",
+            "'browser.feature.enabled'|preferenceValue(false)",
+            "'browser.feature.name'|preferenceValue == 'Alpha'",
+            "'browser.feature.count'|preferenceValue(0) > 2",
+            "'browser.missing'|preferenceValue('default') == 'default'",
+            "'browser.feature.enabled'|preferenceIsUserSet",
+            // Preferences with default values exist, but aren't user-set.
+            "'browser.feature.name'|preferenceExists",
+            "!('browser.feature.name'|preferenceIsUserSet)",
+            "!('browser.missing'|preferenceIsUserSet)",
+            "!('browser.missing'|preferenceExists)",
+            "'2020-01-01'|date < env.currentDate",
+            "'2999-12-31T23:59:59Z'|date > env.currentDate",
+            "[env.samplingId, 'rollout']|bucketSample(0, 10000, 10000)",
+            "!([env.samplingId, 'rollout']|bucketSample(0, 0, 10000))",
+            "env.samplingId|stableSample(1)",
+            "!(env.samplingId|stableSample(0))",
+            "env|keys|length > 0",
+            "['a', 'b']|length == 2",
+            "env.channel|regExpMatch('^BETA$', 'i')[0] == 'beta'",
+            "!(env.channel|regExpMatch('^release$'))",
+        ] {
+            assert!(filter.matches(expr), "{expr}");
+        }
+    }
+
+    #[test]
+    fn test_bucket_sample() {
+        // Every input is in exactly one of the buckets, including ranges that wrap around.
+        for input in ["a", "b", "c", "d", "e"] {
+            let in_range = |start: u32, count: u32| {
+                bucket_sample(&[json!(input), json!(start), json!(count), json!(4)])
+                    .unwrap()
+                    .as_bool()
+                    .unwrap()
+            };
+            let matches = (0..4).filter(|start| in_range(*start, 1)).count();
+            assert_eq!(matches, 1, "{input}");
+            assert!(in_range(2, 2) || in_range(0, 2), "{input}");
+            assert_eq!(in_range(3, 2), in_range(3, 1) || in_range(0, 1), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_map_to_property() {
+        assert_eq!(
+            map_to_property(&[json!([{"name": "a"}, {"id": "b"}]), json!("name")]).unwrap(),
+            json!(["a", null])
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(951_868_800_000));
+        assert_eq!(
+            parse_date("2024-02-29T12:34:56.789Z"),
+            Some(1_709_210_096_789)
+        );
+        assert_eq!(
+            parse_date("2024-02-29T14:34:56+02:00"),
+            Some(1_709_210_096_000)
+        );
+        assert_eq!(parse_date("1969-12-31T23:00"), Some(-3_600_000));
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_split_clauses() {
+        assert_eq!(
+            split_clauses("env.a == 'x&&y' && (env.b || env.c && env.d) && [1&&2]"),
+            vec!["env.a == 'x&&y'", "(env.b || env.c && env.d)", "[1&&2]"]
+        );
+        assert_eq!(split_clauses("env.a"), vec!["env.a"]);
+    }
+
+    #[test]
+    fn test_explain() {
+        let filter = filter();
+        assert_eq!(filter.explain(None).result, FilterResult::NoExpression);
+        assert!(filter.explain(Some("env.channel == 'beta'")).matches);
+
+        let explanation = filter.explain(Some(
+            "env.channel == 'beta' && env.country == 'DE' && env.missing",
+        ));
+        assert!(!explanation.matches);
+        assert_eq!(
+            explanation.result,
+            FilterResult::NotMatched {
+                clauses: vec![
+                    FilterClause {
+                        expression: "env.country == 'DE'".into(),
+                        result: "false".into(),
+                    },
+                    FilterClause {
+                        expression: "env.missing".into(),
+                        result: "null".into(),
+                    },
+                ]
+            }
+        );
+        let env: Value = serde_json::from_str(&explanation.env).unwrap();
+        assert_eq!(env["country"], "US");
+        assert!(env["currentDate"].is_u64());
+
+        assert_eq!(
+            filter.explain(Some("env.country")).result,
+            FilterResult::NotBoolean {
+                result: "\"US\"".into()
+            }
+        );
+        assert!(matches!(
+            filter.explain(Some("env.version|unknownTransform")).result,
+            FilterResult::Error { .. }
+        ));
     }
 }
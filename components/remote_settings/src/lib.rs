@@ -7,26 +7,42 @@ use std::{collections::HashMap, fs::File, io::prelude::Write, sync::Arc};
 use error_support::{convert_log_report_error, handle_error};
 
 pub mod cache;
+pub mod changes;
 pub mod client;
 pub mod config;
 pub mod context;
 pub mod error;
+pub mod query;
+pub mod reports;
 pub mod schema;
 pub mod service;
-#[cfg(feature = "signatures")]
-pub(crate) mod signatures;
+pub mod signatures;
 pub mod storage;
 
 pub(crate) mod jexl_filter;
+mod listeners;
 mod macros;
 
-pub use client::{Attachment, RemoteSettingsRecord, RemoteSettingsResponse, RsJsonObject};
+pub use changes::{RemoteSettingsChangeListener, RemoteSettingsChanges};
+pub use client::{
+    Attachment, GetItemsOptions, RemoteSettingsRecord, RemoteSettingsResponse, RsJsonObject,
+    SortOrder,
+};
 pub use config::{BaseUrl, RemoteSettingsConfig, RemoteSettingsConfig2, RemoteSettingsServer};
 pub use context::RemoteSettingsContext;
 pub use error::{trace, ApiResult, RemoteSettingsError, Result};
-
+pub use jexl_filter::{FilterClause, FilterExplanation, FilterResult, JexlFilter};
+pub use query::{RemoteSettingsFilter, RemoteSettingsQuery, RemoteSettingsSort};
+pub use reports::{
+    RemoteSettingsSyncReport, RemoteSettingsSyncReportListener, SignatureVerification,
+    SyncRetryPath,
+};
+pub use service::BROADCAST_ID;
+
+use changes::ChangeListeners;
 use client::Client;
 use error::Error;
+use reports::SyncReportListeners;
 use storage::Storage;
 
 uniffi::setup_scaffolding!("remote_settings");
@@ -50,9 +66,10 @@ impl RemoteSettingsService {
     /// This method performs no IO or network requests and is safe to run in a main thread that
     /// can't be blocked.
     ///
-    /// `storage_dir` is a directory to store SQLite files in -- one per collection. If the
-    /// directory does not exist, it will be created when the storage is first used. Only the
-    /// directory and the SQLite files will be created, any parent directories must already exist.
+    /// `storage_dir` is a directory to store the SQLite database in, which is shared by all
+    /// collections. If the directory does not exist, it will be created when the storage is first
+    /// used. Only the directory and the SQLite files will be created, any parent directories must
+    /// already exist.
     #[uniffi::constructor]
     pub fn new(storage_dir: String, config: RemoteSettingsConfig2) -> Self {
         Self {
@@ -67,6 +84,21 @@ impl RemoteSettingsService {
         self.internal.make_client(collection_name)
     }
 
+    /// Create a new Remote Settings client for a collection in a different bucket, like
+    /// `security-state`
+    ///
+    /// The service's bucket is used for clients created with [Self::make_client].
+    ///
+    /// This method performs no IO or network requests and is safe to run in a main thread that can't be blocked.
+    pub fn make_client_for_bucket(
+        &self,
+        collection_name: String,
+        bucket_name: String,
+    ) -> Arc<RemoteSettingsClient> {
+        self.internal
+            .make_client_for_bucket(collection_name, bucket_name)
+    }
+
     /// Sync collections for all active clients
     ///
     /// The returned list is the list of collections for which updates were seen
@@ -76,6 +108,65 @@ impl RemoteSettingsService {
         self.internal.sync()
     }
 
+    /// Async version of [Self::sync]
+    ///
+    /// This can be called from the main thread, since it doesn't block while waiting for the
+    /// network.  Cancelling it stops the sync.  Collections that finished syncing before that keep
+    /// their new records, but listeners aren't notified about them.
+    pub async fn sync_async(&self) -> ApiResult<Vec<String>> {
+        self.internal
+            .sync_async()
+            .await
+            .map_err(convert_log_report_error)
+    }
+
+    /// Sync collections for all active clients after a push broadcast
+    ///
+    /// `version` is the version of the `remote-settings/monitor_changes` broadcast.  If it's no
+    /// newer than the data from the last sync, this returns an empty list without making any
+    /// network requests.  Otherwise, it syncs like [Self::sync], but fetches the data that the
+    /// broadcast announced rather than a cached copy.
+    #[handle_error(Error)]
+    pub fn sync_for_broadcast(&self, version: String) -> ApiResult<Vec<String>> {
+        self.internal.sync_for_broadcast(&version)
+    }
+
+    /// Subscribe to changes to the records of all synced collections.
+    ///
+    /// `listener` is called after [Self::sync] changes a collection's records, with the IDs of
+    /// the records that were created, updated, and deleted. Returns an ID that can be passed to
+    /// [Self::unsubscribe].
+    pub fn subscribe(&self, listener: Box<dyn RemoteSettingsChangeListener>) -> u64 {
+        self.internal.subscribe(listener)
+    }
+
+    /// Unsubscribe a listener added with [Self::subscribe]
+    ///
+    /// Returns false if the listener wasn't subscribed.
+    pub fn unsubscribe(&self, subscription_id: u64) -> bool {
+        self.internal.unsubscribe(subscription_id)
+    }
+
+    /// Subscribe to the reports of collection syncs.
+    ///
+    /// `listener` is called after each collection sync, whether it succeeded or not, with how long
+    /// it took, how much data it fetched, and how its signature verification went.  This includes
+    /// syncs started by [RemoteSettingsClient::sync].  Returns an ID that can be passed to
+    /// [Self::unsubscribe_sync_reports].
+    pub fn subscribe_sync_reports(
+        &self,
+        listener: Box<dyn RemoteSettingsSyncReportListener>,
+    ) -> u64 {
+        self.internal.subscribe_sync_reports(listener)
+    }
+
+    /// Unsubscribe a listener added with [Self::subscribe_sync_reports]
+    ///
+    /// Returns false if the listener wasn't subscribed.
+    pub fn unsubscribe_sync_reports(&self, subscription_id: u64) -> bool {
+        self.internal.unsubscribe_sync_reports(subscription_id)
+    }
+
     /// Update the remote settings config
     ///
     /// This will cause all current and future clients to use new config and will delete any stored
@@ -88,6 +179,23 @@ impl RemoteSettingsService {
         self.internal.update_config(config)
     }
 
+    /// Turn preview mode on or off
+    ///
+    /// In preview mode, all current and future clients use the preview version of their bucket,
+    /// like `main-preview` for `main`, to test changes before they're published.  Clients whose
+    /// bucket changes delete their stored records and attachments before they're next used, so
+    /// consumers should sync after calling this.
+    ///
+    /// Only intended for QA/debugging.
+    pub fn set_preview_mode(&self, enabled: bool) {
+        self.internal.set_preview_mode(enabled)
+    }
+
+    /// Check if preview mode is on
+    pub fn preview_mode(&self) -> bool {
+        self.internal.preview_mode()
+    }
+
     pub fn client_url(&self) -> String {
         self.internal.client_url().to_string()
     }
@@ -100,6 +208,7 @@ impl RemoteSettingsService {
 pub struct RemoteSettingsClient {
     // This struct adapts client::RemoteSettingsClient into the public API
     internal: client::RemoteSettingsClient,
+    listeners: ChangeListeners,
 }
 
 #[uniffi::export]
@@ -140,6 +249,50 @@ impl RemoteSettingsClient {
         }
     }
 
+    /// Async version of [Self::get_records]
+    ///
+    /// This only makes a network request when `sync_if_empty = true` and records have not yet
+    /// been synced, and doesn't block while waiting for it.  Cancelling it leaves the cache empty.
+    #[uniffi::method(default(sync_if_empty = false))]
+    pub async fn get_records_async(
+        &self,
+        sync_if_empty: bool,
+    ) -> Option<Vec<RemoteSettingsRecord>> {
+        match self.internal.get_records_async(sync_if_empty).await {
+            Ok(records) => records,
+            Err(e) => {
+                // Log/report the error, like `get_records`
+                trace!("get_records_async error: {e}");
+                convert_log_report_error(e);
+                None
+            }
+        }
+    }
+
+    /// Get the records that match a query.
+    ///
+    /// This works like [Self::get_records], but only returns the records that match the query's
+    /// filters, in its sort order, up to its limit.  The query runs against the cached records, so
+    /// consumers of large collections don't need to load every record.
+    ///
+    /// None is returned in the same cases as [Self::get_records].
+    #[uniffi::method(default(sync_if_empty = false))]
+    pub fn query_records(
+        &self,
+        query: RemoteSettingsQuery,
+        sync_if_empty: bool,
+    ) -> Option<Vec<RemoteSettingsRecord>> {
+        match self.internal.query_records(&query.into(), sync_if_empty) {
+            Ok(records) => records,
+            Err(e) => {
+                // Log/report the error, like `get_records`
+                trace!("query_records error: {e}");
+                convert_log_report_error(e);
+                None
+            }
+        }
+    }
+
     /// Get the current set of records as a map of record_id -> record.
     ///
     /// See [Self::get_records] for an explanation of when this makes network requests, error
@@ -153,6 +306,18 @@ impl RemoteSettingsClient {
             .map(|records| records.into_iter().map(|r| (r.id.clone(), r)).collect())
     }
 
+    /// Explain why each cached record's `filter_expression` does or doesn't match the app
+    /// context, by record ID.
+    ///
+    /// This is meant for developers debugging targeting.  It includes the records that
+    /// [Self::get_records] filters out, and for records that don't match, the parts of their
+    /// expression that evaluated to false.  It doesn't make any network requests, so the result is
+    /// empty if the collection hasn't been synced.
+    #[handle_error(Error)]
+    pub fn explain_records(&self) -> ApiResult<HashMap<String, FilterExplanation>> {
+        Ok(self.internal.explain_records()?.into_iter().collect())
+    }
+
     /// Get attachment data for a remote settings record
     ///
     /// Attachments are large binary blobs used for data that doesn't fit in a normal record.  They
@@ -167,9 +332,49 @@ impl RemoteSettingsClient {
         self.internal.get_attachment(record)
     }
 
+    /// Async version of [Self::get_attachment]
+    ///
+    /// This doesn't block while downloading the attachment.  Cancelling it stops the download,
+    /// and nothing is cached.
+    pub async fn get_attachment_async(&self, record: RemoteSettingsRecord) -> ApiResult<Vec<u8>> {
+        self.internal
+            .get_attachment_async(&record)
+            .await
+            .map_err(convert_log_report_error)
+    }
+
     #[handle_error(Error)]
     pub fn sync(&self) -> ApiResult<()> {
-        self.internal.sync()
+        let changes = self.internal.sync()?;
+        self.notify_changes(&changes);
+        Ok(())
+    }
+
+    /// Async version of [Self::sync]
+    pub async fn sync_async(&self) -> ApiResult<()> {
+        let changes = self
+            .internal
+            .sync_async()
+            .await
+            .map_err(convert_log_report_error)?;
+        self.notify_changes(&changes);
+        Ok(())
+    }
+
+    /// Subscribe to changes to this collection's records.
+    ///
+    /// `listener` is called after a sync changes the records, with the IDs of the records that
+    /// were created, updated, and deleted.  This includes syncs started by
+    /// [RemoteSettingsService::sync].  Returns an ID that can be passed to [Self::unsubscribe].
+    pub fn subscribe(&self, listener: Box<dyn RemoteSettingsChangeListener>) -> u64 {
+        self.listeners.add(listener)
+    }
+
+    /// Unsubscribe a listener added with [Self::subscribe]
+    ///
+    /// Returns false if the listener wasn't subscribed.
+    pub fn unsubscribe(&self, subscription_id: u64) -> bool {
+        self.listeners.remove(subscription_id)
     }
 
     #[handle_error(Error)]
@@ -192,6 +397,7 @@ impl RemoteSettingsClient {
         collection_name: String,
         #[allow(unused)] context: Option<RemoteSettingsContext>,
         storage: Storage,
+        sync_report_listeners: Arc<SyncReportListeners>,
     ) -> Self {
         Self {
             internal: client::RemoteSettingsClient::new(
@@ -200,9 +406,17 @@ impl RemoteSettingsClient {
                 collection_name,
                 context,
                 storage,
+                sync_report_listeners,
             ),
+            listeners: ChangeListeners::default(),
         }
     }
+
+    /// Pass changes from a sync to this client's listeners
+    pub(crate) fn notify_changes(&self, changes: &RemoteSettingsChanges) {
+        self.listeners
+            .notify(self.internal.collection_name(), changes);
+    }
 }
 
 #[derive(uniffi::Object)]
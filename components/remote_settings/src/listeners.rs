@@ -0,0 +1,57 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+
+/// The listeners subscribed to a client or service, for a listener interface `T`.
+///
+/// Each kind of listener adds its own `notify` method, which calls the listeners with
+/// [Self::listeners].
+pub(crate) struct Listeners<T: ?Sized> {
+    next_id: AtomicU64,
+    listeners: Mutex<Vec<(u64, Arc<T>)>>,
+}
+
+impl<T: ?Sized> Default for Listeners<T> {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::default(),
+            listeners: Mutex::default(),
+        }
+    }
+}
+
+impl<T: ?Sized> Listeners<T> {
+    /// Adds a listener, and returns an ID that can be passed to [Self::remove].
+    pub fn add(&self, listener: Box<T>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.listeners.lock().push((id, listener.into()));
+        id
+    }
+
+    /// Removes a listener, and returns true if it was subscribed.
+    pub fn remove(&self, id: u64) -> bool {
+        let mut listeners = self.listeners.lock();
+        let len = listeners.len();
+        listeners.retain(|(listener_id, _)| *listener_id != id);
+        listeners.len() != len
+    }
+
+    /// Returns the subscribed listeners.
+    ///
+    /// This doesn't hold the lock after it returns, so listeners can subscribe and unsubscribe
+    /// while they're called.
+    pub fn listeners(&self) -> Vec<Arc<T>> {
+        self.listeners
+            .lock()
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,266 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Local queries over cached records.
+//!
+//! [GetItemsOptions] filters, sorts, and limits the records that the server returns. The same
+//! options can be applied to the records cached in [crate::storage::Storage], using SQLite's JSON
+//! functions on the stored record data, so that consumers of large collections don't need to
+//! load every record.
+
+use rusqlite::types::Value;
+
+use crate::{
+    client::{Filter, GetItemsOptions, Sort, SortOrder},
+    RemoteSettingsRecord,
+};
+
+/// The stored record data, as JSON text.  Records are stored as blobs, which SQLite's JSON
+/// functions would otherwise treat as binary JSON.
+const RECORD_JSON: &str = "CAST(data AS TEXT)";
+
+/// A filter for [crate::RemoteSettingsClient::query_records]
+///
+/// These match records like the filters for the server's
+/// [filtering API](https://docs.kinto-storage.org/en/latest/api/1.x/filtering.html).  `field` can
+/// be a simple or dotted field name, like `author` or `author.name`.  `value` can be a bare number
+/// or string (like `2` or `Ben`), or a stringified JSON value (`"2.0"`, `[1, 2]`,
+/// `{"checked": true}`).
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum RemoteSettingsFilter {
+    /// The field is equal to the value
+    Eq { field: String, value: String },
+    /// The field is not equal to the value
+    Not { field: String, value: String },
+    /// The field is an array that contains the value.  If the value is a stringified JSON array,
+    /// the field must contain all its elements.
+    Contains { field: String, value: String },
+    /// The field is strictly less than the value
+    Lt { field: String, value: String },
+    /// The field is strictly greater than the value
+    Gt { field: String, value: String },
+    /// The field is less than or equal to the value
+    Max { field: String, value: String },
+    /// The field is greater than or equal to the value
+    Min { field: String, value: String },
+    /// The field is a string that contains the value, which can contain `*` wildcards
+    Like { field: String, value: String },
+    /// The record has the field
+    Has { field: String },
+    /// The record doesn't have the field
+    HasNot { field: String },
+}
+
+/// A field to sort the results of [crate::RemoteSettingsClient::query_records] by
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct RemoteSettingsSort {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+/// Query for [crate::RemoteSettingsClient::query_records]
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct RemoteSettingsQuery {
+    /// Only return records that match all of these filters
+    #[uniffi(default)]
+    pub filters: Vec<RemoteSettingsFilter>,
+    /// Fields to sort the records by, in order of precedence
+    #[uniffi(default)]
+    pub sort: Vec<RemoteSettingsSort>,
+    /// Only return these fields of each record.  The `id`, `last_modified`, and `deleted` fields
+    /// are always returned.  If this is empty, all fields are returned.
+    #[uniffi(default)]
+    pub fields: Vec<String>,
+    /// Return at most this many records
+    #[uniffi(default = None)]
+    pub limit: Option<u64>,
+}
+
+impl From<RemoteSettingsQuery> for GetItemsOptions {
+    fn from(query: RemoteSettingsQuery) -> Self {
+        let mut options = GetItemsOptions::new();
+        for filter in query.filters {
+            match filter {
+                RemoteSettingsFilter::Eq { field, value } => options.filter_eq(field, value),
+                RemoteSettingsFilter::Not { field, value } => options.filter_not(field, value),
+                RemoteSettingsFilter::Contains { field, value } => {
+                    options.filter_contains(field, value)
+                }
+                RemoteSettingsFilter::Lt { field, value } => options.filter_lt(field, value),
+                RemoteSettingsFilter::Gt { field, value } => options.filter_gt(field, value),
+                RemoteSettingsFilter::Max { field, value } => options.filter_max(field, value),
+                RemoteSettingsFilter::Min { field, value } => options.filter_min(field, value),
+                RemoteSettingsFilter::Like { field, value } => options.filter_like(field, value),
+                RemoteSettingsFilter::Has { field } => options.filter_has(field),
+                RemoteSettingsFilter::HasNot { field } => options.filter_has_not(field),
+            };
+        }
+        for sort in query.sort {
+            options.sort(sort.field, sort.order);
+        }
+        for field in query.fields {
+            options.field(field);
+        }
+        if let Some(limit) = query.limit {
+            options.limit(limit);
+        }
+        options
+    }
+}
+
+impl GetItemsOptions {
+    /// Returns SQL to append to a `WHERE` clause for the `records` table, which applies these
+    /// options' filters and sort order, and the parameters for it.
+    ///
+    /// The limit isn't included, so that callers can filter the records further before applying it.
+    pub(crate) fn records_sql(&self) -> (String, Vec<Value>) {
+        let mut sql = String::new();
+        let mut params = vec![];
+        for filter in &self.filters {
+            match filter {
+                Filter::Eq(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) IS ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Not(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) IS NOT ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Contains(field, value) => {
+                    let values = match serde_json::from_str(value) {
+                        Ok(serde_json::Value::Array(values)) => values,
+                        _ => vec![json_value(value)],
+                    };
+                    sql.push_str(&format!(" AND json_type({RECORD_JSON}, ?) = 'array'"));
+                    params.push(json_path(field));
+                    for value in values {
+                        sql.push_str(&format!(
+                            " AND EXISTS (SELECT 1 FROM json_each({RECORD_JSON}, ?) WHERE value IS ?)"
+                        ));
+                        params.extend([json_path(field), json_to_sql(value)]);
+                    }
+                }
+                Filter::Lt(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) < ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Gt(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) > ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Max(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) <= ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Min(field, value) => {
+                    sql.push_str(&format!(" AND json_extract({RECORD_JSON}, ?) >= ?"));
+                    params.extend([json_path(field), sql_value(value)]);
+                }
+                Filter::Like(field, value) => {
+                    sql.push_str(&format!(
+                        " AND json_extract({RECORD_JSON}, ?) LIKE ? ESCAPE '\\'"
+                    ));
+                    params.extend([json_path(field), Value::Text(like_pattern(value))]);
+                }
+                Filter::Has(field) => {
+                    sql.push_str(&format!(" AND json_type({RECORD_JSON}, ?) IS NOT NULL"));
+                    params.push(json_path(field));
+                }
+                Filter::HasNot(field) => {
+                    sql.push_str(&format!(" AND json_type({RECORD_JSON}, ?) IS NULL"));
+                    params.push(json_path(field));
+                }
+            }
+        }
+        if !self.sort.is_empty() {
+            let order_by = self
+                .sort
+                .iter()
+                .map(|Sort(field, order)| {
+                    params.push(json_path(field));
+                    match order {
+                        SortOrder::Ascending => format!("json_extract({RECORD_JSON}, ?) ASC"),
+                        SortOrder::Descending => format!("json_extract({RECORD_JSON}, ?) DESC"),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            sql.push_str(&format!(" ORDER BY {order_by}"));
+        }
+        (sql, params)
+    }
+
+    /// The most records to return, if the options have a limit
+    pub(crate) fn record_limit(&self) -> Option<usize> {
+        self.limit
+            .map(|limit| usize::try_from(limit).unwrap_or(usize::MAX))
+    }
+
+    /// Removes the fields that these options don't select from a record
+    pub(crate) fn select_fields(&self, record: &mut RemoteSettingsRecord) {
+        if self.fields.is_empty() {
+            return;
+        }
+        // Dotted fields select their top-level field.
+        let selected = |name: &str| {
+            self.fields
+                .iter()
+                .any(|field| field.split('.').next() == Some(name))
+        };
+        record.fields.retain(|name, _| selected(name));
+        if !selected("attachment") {
+            record.attachment = None;
+        }
+    }
+}
+
+/// Converts a simple or dotted field name into a JSON path, like `$."author"."name"`
+fn json_path(field: &str) -> Value {
+    let path = std::iter::once("$".to_owned())
+        .chain(field.split('.').map(|key| format!("\"{key}\"")))
+        .collect::<Vec<_>>()
+        .join(".");
+    Value::Text(path)
+}
+
+/// Parses a filter value like the server does: stringified JSON values are parsed, and anything
+/// else is a bare string.
+fn json_value(value: &str) -> serde_json::Value {
+    serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_owned()))
+}
+
+fn sql_value(value: &str) -> Value {
+    json_to_sql(json_value(value))
+}
+
+/// Converts a JSON value into the SQL value that `json_extract` returns for it
+fn json_to_sql(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(b.into()),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Value::Integer(n),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s),
+        value => Value::Text(value.to_string()),
+    }
+}
+
+/// Converts a `like_` filter value into a `LIKE` pattern that matches strings containing it
+fn like_pattern(value: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in value.chars() {
+        match c {
+            '*' => pattern.push('%'),
+            '%' | '_' | '\\' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            c => pattern.push(c),
+        }
+    }
+    pattern.push('%');
+    pattern
+}
@@ -0,0 +1,171 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Sync reports, for monitoring.
+//!
+//! Each collection sync, whether it's started by [crate::RemoteSettingsService::sync] or by a
+//! client, produces a [RemoteSettingsSyncReport] with how long it took, how much data it fetched,
+//! and how its signature verification went.  The reports are passed to the
+//! [RemoteSettingsSyncReportListener]s subscribed to the service, so that consumers can record
+//! them in Glean.
+
+use std::time::Instant;
+
+use crate::{changes::RemoteSettingsChanges, error::Error, listeners::Listeners};
+
+/// What happened during a collection sync
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct RemoteSettingsSyncReport {
+    pub collection_name: String,
+    /// Time the sync took, in microseconds
+    pub duration: u64,
+    /// True if the first changeset was a diff against the cached records, rather than the whole
+    /// collection
+    pub delta: bool,
+    /// Size of the changeset responses, in bytes
+    pub bytes: u64,
+    /// Number of records that the sync created, updated, or deleted
+    pub changed_records: u64,
+    /// Outcome of verifying the signature of the first changeset
+    pub signature: SignatureVerification,
+    /// What the client did after the signature verification
+    pub retry: SyncRetryPath,
+    /// The error that the sync failed with, or `None` if it succeeded
+    pub error: Option<String>,
+}
+
+/// Outcome of verifying a changeset's signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum SignatureVerification {
+    /// The signature wasn't verified, because signature verification is disabled or the sync
+    /// failed before verifying it
+    Skipped,
+    Valid,
+    Invalid,
+}
+
+/// What a client does after verifying the first changeset's signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum SyncRetryPath {
+    /// The signature was valid or wasn't verified, so the client didn't retry
+    NotRetried,
+    /// The signature was invalid, so the client reset its records to the packaged data and synced
+    /// again.  If the report has an error, the second sync failed.
+    Retried,
+    /// The second sync's signature was also invalid, so the client reset its records to the
+    /// packaged data again and the sync failed
+    ResetAfterRetry,
+}
+
+impl RemoteSettingsSyncReport {
+    fn new(collection_name: &str) -> Self {
+        Self {
+            collection_name: collection_name.to_owned(),
+            duration: 0,
+            delta: false,
+            bytes: 0,
+            changed_records: 0,
+            signature: SignatureVerification::Skipped,
+            retry: SyncRetryPath::NotRetried,
+            error: None,
+        }
+    }
+}
+
+/// Builds the [RemoteSettingsSyncReport] for a sync, while the sync runs
+pub(crate) struct SyncReportBuilder {
+    report: RemoteSettingsSyncReport,
+    fetched_changesets: usize,
+    timer: Instant,
+}
+
+impl SyncReportBuilder {
+    pub fn start(collection_name: &str) -> Self {
+        Self {
+            report: RemoteSettingsSyncReport::new(collection_name),
+            fetched_changesets: 0,
+            timer: Instant::now(),
+        }
+    }
+
+    /// Record a changeset response.  `delta` is true if it was a diff against cached records.
+    pub fn fetched_changeset(&mut self, delta: bool, bytes: u64) {
+        if self.fetched_changesets == 0 {
+            self.report.delta = delta;
+        }
+        self.fetched_changesets += 1;
+        self.report.bytes += bytes;
+    }
+
+    /// Record the outcome of verifying the first changeset's signature
+    pub fn verified_signature(&mut self, verification: SignatureVerification) {
+        self.report.signature = verification;
+    }
+
+    pub fn retry_path(&mut self, retry: SyncRetryPath) {
+        self.report.retry = retry;
+    }
+
+    /// Finish the report, with the result of the sync
+    pub fn finish(
+        mut self,
+        result: Result<&RemoteSettingsChanges, &Error>,
+    ) -> RemoteSettingsSyncReport {
+        self.report.duration = self.timer.elapsed().as_micros() as u64;
+        match result {
+            Ok(changes) => {
+                self.report.changed_records =
+                    (changes.created.len() + changes.updated.len() + changes.deleted.len()) as u64;
+            }
+            Err(e) => self.report.error = Some(e.to_string()),
+        }
+        self.report
+    }
+}
+
+/// Receives a report after each collection sync, whether it succeeded or not
+#[uniffi::export(callback_interface)]
+pub trait RemoteSettingsSyncReportListener: Send + Sync {
+    fn on_sync_report(&self, report: RemoteSettingsSyncReport);
+}
+
+/// The sync report listeners subscribed to a service.
+///
+/// The service shares these with its clients, so that syncs started by a client are reported too.
+pub(crate) type SyncReportListeners = Listeners<dyn RemoteSettingsSyncReportListener>;
+
+impl SyncReportListeners {
+    /// Passes a report to each listener.
+    pub fn notify(&self, report: &RemoteSettingsSyncReport) {
+        for listener in self.listeners() {
+            listener.on_sync_report(report.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    /// Listener that keeps the reports that it receives
+    #[derive(Clone, Default)]
+    pub struct SyncReportRecorder(Arc<Mutex<Vec<RemoteSettingsSyncReport>>>);
+
+    impl SyncReportRecorder {
+        /// Returns the reports received so far, and forgets them
+        pub fn take(&self) -> Vec<RemoteSettingsSyncReport> {
+            std::mem::take(&mut self.0.lock())
+        }
+    }
+
+    impl RemoteSettingsSyncReportListener for SyncReportRecorder {
+        fn on_sync_report(&self, report: RemoteSettingsSyncReport) {
+            self.0.lock().push(report);
+        }
+    }
+}
@@ -13,20 +13,62 @@ use sql_support::open_database::{self, ConnectionInitializer};
 ///  1. Bump this version.
 ///  2. Add a migration from the old version to the new version in
 ///     [`RemoteSettingsConnectionInitializer::upgrade_from`].
-pub const VERSION: u32 = 2;
+pub const VERSION: u32 = 7;
 
 /// The current remote settings database schema.
 pub const SQL: &str = r#"
 CREATE TABLE IF NOT EXISTS records (
-    id TEXT PRIMARY KEY,
+    collection TEXT NOT NULL,
+    id TEXT NOT NULL,
     collection_url TEXT NOT NULL,
-    data BLOB NOT NULL);
+    data BLOB NOT NULL,
+    attachment_hash TEXT,
+    PRIMARY KEY (collection, id));
+CREATE INDEX IF NOT EXISTS records_attachment_hash ON records(attachment_hash);
 CREATE TABLE IF NOT EXISTS attachments (
-    id TEXT PRIMARY KEY,
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    last_used INTEGER NOT NULL,
+    data BLOB NOT NULL);
+CREATE INDEX IF NOT EXISTS attachments_last_used ON attachments(last_used);
+CREATE TABLE IF NOT EXISTS collection_metadata (
+    collection TEXT PRIMARY KEY,
     collection_url TEXT NOT NULL,
+    last_modified INTEGER, bucket TEXT, signatures TEXT);
+CREATE TABLE IF NOT EXISTS changes_timestamps (
+    base_url TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    preview_mode INTEGER NOT NULL,
+    last_modified INTEGER NOT NULL,
+    PRIMARY KEY (base_url, bucket, preview_mode));
+CREATE TABLE IF NOT EXISTS changes_collections (
+    base_url TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    preview_mode INTEGER NOT NULL,
+    collection_bucket TEXT NOT NULL,
+    collection TEXT NOT NULL,
+    PRIMARY KEY (base_url, bucket, preview_mode, collection_bucket, collection));
+"#;
+
+/// The v4 schema, which migration 3 creates.
+///
+/// This is frozen, rather than [`SQL`], so that the later migrations can add to it.
+const V4_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS records (
+    collection TEXT NOT NULL,
+    id TEXT NOT NULL,
+    collection_url TEXT NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (collection, id));
+CREATE TABLE IF NOT EXISTS attachments (
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    last_used INTEGER NOT NULL,
     data BLOB NOT NULL);
+CREATE INDEX IF NOT EXISTS attachments_last_used ON attachments(last_used);
 CREATE TABLE IF NOT EXISTS collection_metadata (
-    collection_url TEXT PRIMARY KEY,
+    collection TEXT PRIMARY KEY,
+    collection_url TEXT NOT NULL,
     last_modified INTEGER, bucket TEXT, signatures TEXT);
 "#;
 
@@ -37,7 +79,7 @@ pub struct RemoteSettingsConnectionInitializer;
 
 impl ConnectionInitializer for RemoteSettingsConnectionInitializer {
     const NAME: &'static str = "remote_settings";
-    const END_VERSION: u32 = 3;
+    const END_VERSION: u32 = VERSION;
 
     fn prepare(&self, conn: &Connection, _db_empty: bool) -> open_database::Result<()> {
         let initial_pragmas = "
@@ -99,6 +141,62 @@ impl ConnectionInitializer for RemoteSettingsConnectionInitializer {
                 tx.execute("ALTER TABLE collection_metadata DROP COLUMN x5u", ())?;
                 Ok(())
             }
+            3 => {
+                // Records are now keyed by collection, so that several collections can share a
+                // database, and attachments are keyed by their hash.  Everything here is a cache,
+                // so drop the old tables rather than migrating them.
+                tx.execute_batch(
+                    "
+                    DROP TABLE records;
+                    DROP TABLE attachments;
+                    DROP TABLE collection_metadata;
+                    ",
+                )?;
+                tx.execute_batch(V4_SQL)?;
+                Ok(())
+            }
+            4 => {
+                tx.execute_batch(
+                    "
+                    CREATE TABLE IF NOT EXISTS changes_timestamps (
+                        base_url TEXT NOT NULL,
+                        bucket TEXT NOT NULL,
+                        preview_mode INTEGER NOT NULL,
+                        last_modified INTEGER NOT NULL,
+                        PRIMARY KEY (base_url, bucket, preview_mode));
+                    ",
+                )?;
+                Ok(())
+            }
+            5 => {
+                // Store the hash of each record's attachment in its own column, so that finding
+                // unused attachments doesn't need to parse every record.
+                tx.execute_batch(
+                    "
+                    ALTER TABLE records ADD COLUMN attachment_hash TEXT;
+                    UPDATE records
+                    SET attachment_hash = json_extract(CAST(data AS TEXT), '$.attachment.hash');
+                    CREATE INDEX records_attachment_hash ON records(attachment_hash);
+                    ",
+                )?;
+                Ok(())
+            }
+            6 => {
+                // Remember which collections each changes timestamp was synced for, so that
+                // clients created since then aren't skipped.
+                tx.execute_batch(
+                    "
+                    CREATE TABLE IF NOT EXISTS changes_collections (
+                        base_url TEXT NOT NULL,
+                        bucket TEXT NOT NULL,
+                        preview_mode INTEGER NOT NULL,
+                        collection_bucket TEXT NOT NULL,
+                        collection TEXT NOT NULL,
+                        PRIMARY KEY (base_url, bucket, preview_mode, collection_bucket, collection));
+                    ",
+                )?;
+                Ok(())
+            }
             _ => Err(open_database::Error::IncompatibleVersion(version)),
         }
     }
@@ -168,4 +266,48 @@ PRAGMA user_version=0;
         let signatures2: String = stmt.query_row([], |row| row.get(0)).unwrap();
         assert_eq!(signatures2, r#"[{"signature":"sig2","x5u":"uri2"}]"#)
     }
+
+    #[test]
+    fn test_5_to_6_attachment_hashes() {
+        let db_file = MigratedDatabaseFile::new(RemoteSettingsConnectionInitializer, V0_SCHEMA);
+        db_file.upgrade_to(5);
+        let conn = db_file.open();
+        conn.execute(
+            "INSERT INTO records (collection, id, collection_url, data) VALUES (?, ?, ?, ?)",
+            (
+                "a",
+                "1",
+                "https://example.com/api",
+                r#"{"id":"1","attachment":{"hash":"abc"}}"#.as_bytes(),
+            ),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO records (collection, id, collection_url, data) VALUES (?, ?, ?, ?)",
+            (
+                "a",
+                "2",
+                "https://example.com/api",
+                r#"{"id":"2"}"#.as_bytes(),
+            ),
+        )
+        .unwrap();
+
+        db_file.upgrade_to(6);
+
+        let hashes = conn
+            .prepare("SELECT id, attachment_hash FROM records ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(String, Option<String>)>>>()
+            .unwrap();
+        assert_eq!(
+            hashes,
+            vec![
+                ("1".to_string(), Some("abc".to_string())),
+                ("2".to_string(), None)
+            ]
+        );
+    }
 }
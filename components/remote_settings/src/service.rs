@@ -3,31 +3,54 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     sync::{Arc, Weak},
 };
 
 use camino::Utf8PathBuf;
-use error_support::trace;
+use error_support::{trace, warn};
 use parking_lot::Mutex;
 use serde::Deserialize;
 use url::Url;
-use viaduct::Request;
+use viaduct::{Request, Response};
 
 use crate::{
-    client::RemoteState, config::BaseUrl, error::Error, storage::Storage, RemoteSettingsClient,
-    RemoteSettingsConfig2, RemoteSettingsContext, RemoteSettingsServer, Result,
+    changes::{ChangeListeners, RemoteSettingsChangeListener, RemoteSettingsChanges},
+    client::{self, RemoteState},
+    config::BaseUrl,
+    error::Error,
+    reports::{RemoteSettingsSyncReportListener, SyncReportListeners},
+    storage::{Storage, StorageDb},
+    RemoteSettingsClient, RemoteSettingsConfig2, RemoteSettingsContext, RemoteSettingsServer,
+    Result,
 };
 
+/// The ID of the push broadcast that announces new Remote Settings data.
+///
+/// Its version is the quoted timestamp of the `monitor/changes` collection, like `"1700000000000"`.
+pub const BROADCAST_ID: &str = "remote-settings/monitor_changes";
+
 /// Internal Remote settings service API
 pub struct RemoteSettingsService {
     inner: Mutex<RemoteSettingsServiceInner>,
+    // This is outside the mutex, so that listeners can call back into the service.
+    listeners: ChangeListeners,
+    // These are shared with the clients, which pass them the report of each sync.
+    sync_report_listeners: Arc<SyncReportListeners>,
+    // Sends the async `monitor/changes` requests.  This is outside the mutex, so that the
+    // requests don't hold the lock.
+    http_client: viaduct::Client,
 }
 
 struct RemoteSettingsServiceInner {
     storage_dir: Utf8PathBuf,
+    /// The database that all clients store their records and attachments in.
+    db: Arc<StorageDb>,
     base_url: BaseUrl,
+    /// The bucket for clients that don't pick their own
     bucket_name: String,
+    /// Use the preview version of each client's bucket, like `main-preview` for `main`.
+    preview_mode: bool,
     app_context: Option<RemoteSettingsContext>,
     remote_state: RemoteState,
     /// Weakrefs for all clients that we've created.  Note: this stores the
@@ -35,7 +58,21 @@ struct RemoteSettingsServiceInner {
     /// The reason for this is that we return Arcs to the public struct to the foreign code, so we
     /// need to use the same type for our weakrefs.  The alternative would be to create 2 Arcs for
     /// each client, which is wasteful.
-    clients: Vec<Weak<RemoteSettingsClient>>,
+    clients: Vec<ServiceClient>,
+}
+
+/// The changes from a sync, keyed by bucket and collection name
+///
+/// Different buckets can have collections with the same name, like `main/intermediates` and
+/// `security-state/intermediates`, so a collection name alone doesn't identify the records.
+type SyncedCollections = HashMap<(String, String), RemoteSettingsChanges>;
+
+/// A client that we've created, and the bucket that it was created for
+struct ServiceClient {
+    client: Weak<RemoteSettingsClient>,
+    /// The bucket passed to [RemoteSettingsService::make_client_for_bucket], or `None` for the
+    /// service's bucket.
+    bucket_name: Option<String>,
 }
 
 impl RemoteSettingsService {
@@ -49,71 +86,223 @@ impl RemoteSettingsService {
             .unwrap_or(RemoteSettingsServer::Prod)
             .get_base_url_with_prod_fallback();
         let bucket_name = config.bucket_name.unwrap_or_else(|| String::from("main"));
+        // Allow using in-memory databases for testing of external crates.
+        let db_path = if storage_dir == ":memory:" {
+            storage_dir.clone()
+        } else {
+            storage_dir.join("remote-settings.sql")
+        };
 
         Self {
             inner: Mutex::new(RemoteSettingsServiceInner {
                 storage_dir,
+                db: Arc::new(StorageDb::new(db_path)),
                 base_url,
                 bucket_name,
+                preview_mode: false,
                 app_context: config.app_context,
                 remote_state: RemoteState::default(),
                 clients: vec![],
             }),
+            listeners: ChangeListeners::default(),
+            sync_report_listeners: Arc::default(),
+            http_client: viaduct::Client::new(viaduct::ClientSettings::default()),
         }
     }
 
     pub fn make_client(&self, collection_name: String) -> Arc<RemoteSettingsClient> {
+        self.make_client_with_bucket(collection_name, None)
+    }
+
+    /// Create a client for a collection in a different bucket than the service's
+    pub fn make_client_for_bucket(
+        &self,
+        collection_name: String,
+        bucket_name: String,
+    ) -> Arc<RemoteSettingsClient> {
+        self.make_client_with_bucket(collection_name, Some(bucket_name))
+    }
+
+    fn make_client_with_bucket(
+        &self,
+        collection_name: String,
+        bucket_name: Option<String>,
+    ) -> Arc<RemoteSettingsClient> {
         let mut inner = self.inner.lock();
-        // Allow using in-memory databases for testing of external crates.
-        let storage = if inner.storage_dir == ":memory:" {
-            Storage::new(inner.storage_dir.clone())
-        } else {
-            Storage::new(inner.storage_dir.join(format!("{collection_name}.sql")))
+        let storage = match &bucket_name {
+            // Collections in other buckets can have the same names as ones in the service's
+            // bucket, so store them separately.
+            Some(bucket_name) => Storage::for_collection(
+                inner.db.clone(),
+                format!("{bucket_name}/{collection_name}"),
+            ),
+            None => {
+                let storage = Storage::for_collection(inner.db.clone(), collection_name.clone());
+                if inner.storage_dir == ":memory:" {
+                    storage
+                } else {
+                    // Collections used to be stored in their own databases.  Delete them, since
+                    // the records will be synced again.
+                    storage
+                        .with_legacy_path(inner.storage_dir.join(format!("{collection_name}.sql")))
+                }
+            }
         };
 
         let client = Arc::new(RemoteSettingsClient::new(
             inner.base_url.clone(),
-            inner.bucket_name.clone(),
+            inner.client_bucket(bucket_name.as_deref()),
             collection_name.clone(),
             inner.app_context.clone(),
             storage,
+            self.sync_report_listeners.clone(),
         ));
-        inner.clients.push(Arc::downgrade(&client));
+        inner.clients.push(ServiceClient {
+            client: Arc::downgrade(&client),
+            bucket_name,
+        });
         client
     }
 
     /// Sync collections for all active clients
     pub fn sync(&self) -> Result<Vec<String>> {
+        self.sync_with_expected(0)
+    }
+
+    /// Sync collections for all active clients, if the `version` of a [BROADCAST_ID] broadcast is
+    /// newer than the data we last synced, or if any clients were created since then.
+    ///
+    /// If not, this returns an empty list without making any network requests.
+    pub fn sync_for_broadcast(&self, version: &str) -> Result<Vec<String>> {
+        let Some(timestamp) = parse_broadcast_version(version) else {
+            // We can't tell if the data changed, so sync to be safe.
+            warn!("invalid broadcast version: {version}");
+            return self.sync();
+        };
+        let synced = match self.inner.lock().synced_for_changes_timestamp(timestamp) {
+            Ok(synced) => synced,
+            Err(e) => {
+                warn!("failed to get the changes timestamp: {e}");
+                false
+            }
+        };
+        if synced {
+            trace!("skipping sync for broadcast version: {version}");
+            return Ok(vec![]);
+        }
+        self.sync_with_expected(timestamp)
+    }
+
+    /// Sync collections for all active clients
+    ///
+    /// `expected` is the `monitor/changes` timestamp that we expect the server to have, or `0` if
+    /// we don't know it.
+    fn sync_with_expected(&self, expected: u64) -> Result<Vec<String>> {
         // Make sure we only sync each collection once, even if there are multiple clients
-        let mut synced_collections = HashSet::new();
+        let mut synced_collections = SyncedCollections::new();
 
-        let mut inner = self.inner.lock();
-        let changes = inner.fetch_changes()?;
-        let change_map: HashMap<_, _> = changes
-            .changes
-            .iter()
-            .map(|c| ((c.collection.as_str(), &c.bucket), c.last_modified))
-            .collect();
-        let bucket_name = inner.bucket_name.clone();
+        let (clients, result) = {
+            let mut inner = self.inner.lock();
+            let clients = inner.active_clients();
+            let result = inner.sync_clients(&clients, expected, &mut synced_collections);
+            (clients, result)
+        };
+
+        // Notify listeners after releasing the lock, so that they can call back into the service.
+        // If a collection failed to sync, the ones that synced before it are still reported.
+        self.notify_changes(&clients, &synced_collections);
+        result?;
+        Ok(synced_collection_names(synced_collections))
+    }
+
+    /// Async version of [Self::sync]
+    ///
+    /// This doesn't hold the service lock while requests are in flight, so other calls don't
+    /// block on it.  Dropping the future cancels the sync.  Collections that finished syncing
+    /// before that keep their new records, but their listeners aren't notified.
+    pub async fn sync_async(&self) -> Result<Vec<String>> {
+        let mut synced_collections = SyncedCollections::new();
 
-        for client in inner.active_clients() {
+        let (clients, request) = {
+            let mut inner = self.inner.lock();
+            let clients = inner.active_clients();
+            let request = inner.changes_request(0)?;
+            (clients, request)
+        };
+        let result = self
+            .sync_clients_async(&clients, request, &mut synced_collections)
+            .await;
+
+        self.notify_changes(&clients, &synced_collections);
+        result?;
+        Ok(synced_collection_names(synced_collections))
+    }
+
+    /// Async version of [RemoteSettingsServiceInner::sync_clients]
+    async fn sync_clients_async(
+        &self,
+        clients: &[(Arc<RemoteSettingsClient>, String)],
+        request: Request,
+        synced_collections: &mut SyncedCollections,
+    ) -> Result<()> {
+        let response = self.http_client.send(request).await?;
+        let changes = self.inner.lock().handle_changes_response(response)?;
+
+        for (client, bucket_name) in clients {
             let client = &client.internal;
             let collection_name = client.collection_name();
-            if let Some(client_last_modified) = client.get_last_modified_timestamp()? {
-                if let Some(server_last_modified) = change_map.get(&(collection_name, &bucket_name))
+            if changes.is_up_to_date(client, bucket_name)? {
+                trace!("skipping up-to-date collection: {collection_name}");
+                continue;
+            }
+            let key = (bucket_name.clone(), collection_name.to_string());
+            if !synced_collections.contains_key(&key) {
+                trace!("syncing collection: {bucket_name}/{collection_name}");
+                let expected = changes.last_modified(bucket_name, collection_name);
+                synced_collections.insert(key, client.sync_async_with_expected(expected).await?);
+            }
+        }
+        self.inner
+            .lock()
+            .update_changes_timestamp(&changes, clients);
+        Ok(())
+    }
+
+    /// Pass the changes from a sync to the listeners for each collection
+    fn notify_changes(
+        &self,
+        clients: &[(Arc<RemoteSettingsClient>, String)],
+        synced_collections: &SyncedCollections,
+    ) {
+        for ((bucket_name, collection_name), changes) in synced_collections {
+            for (client, client_bucket_name) in clients {
+                if client_bucket_name == bucket_name
+                    && client.internal.collection_name() == collection_name
                 {
-                    if client_last_modified == *server_last_modified {
-                        trace!("skipping up-to-date collection: {collection_name}");
-                        continue;
-                    }
+                    client.notify_changes(changes);
                 }
             }
-            if synced_collections.insert(collection_name.to_string()) {
-                trace!("syncing collection: {collection_name}");
-                client.sync()?;
-            }
+            self.listeners.notify(collection_name, changes);
         }
-        Ok(synced_collections.into_iter().collect())
+    }
+
+    pub fn subscribe(&self, listener: Box<dyn RemoteSettingsChangeListener>) -> u64 {
+        self.listeners.add(listener)
+    }
+
+    pub fn unsubscribe(&self, subscription_id: u64) -> bool {
+        self.listeners.remove(subscription_id)
+    }
+
+    pub fn subscribe_sync_reports(
+        &self,
+        listener: Box<dyn RemoteSettingsSyncReportListener>,
+    ) -> u64 {
+        self.sync_report_listeners.add(listener)
+    }
+
+    pub fn unsubscribe_sync_reports(&self, subscription_id: u64) -> bool {
+        self.sync_report_listeners.remove(subscription_id)
     }
 
     /// Update the remote settings config
@@ -127,19 +316,52 @@ impl RemoteSettingsService {
             .get_base_url()?;
         let bucket_name = config.bucket_name.unwrap_or_else(|| String::from("main"));
         let mut inner = self.inner.lock();
-        for client in inner.active_clients() {
-            client.internal.update_config(
-                base_url.clone(),
-                bucket_name.clone(),
-                config.app_context.clone(),
-            );
-        }
         inner.base_url = base_url;
         inner.bucket_name = bucket_name;
         inner.app_context = config.app_context;
+        // The clients delete their stored records, so they need to sync again.
+        inner.db.clear_changes_timestamps()?;
+        for service_client in inner.clients.iter() {
+            if let Some(client) = service_client.client.upgrade() {
+                client.internal.update_config(
+                    inner.base_url.clone(),
+                    inner.client_bucket(service_client.bucket_name.as_deref()),
+                    inner.app_context.clone(),
+                );
+            }
+        }
         Ok(())
     }
 
+    /// Turn preview mode on or off
+    ///
+    /// In preview mode, clients use the preview version of their bucket, like `main-preview` for
+    /// `main`.  Clients whose bucket changes delete their stored records and attachments before
+    /// they're next used, like with [Self::update_config].
+    pub fn set_preview_mode(&self, enabled: bool) {
+        let mut inner = self.inner.lock();
+        if inner.preview_mode == enabled {
+            return;
+        }
+        inner.preview_mode = enabled;
+        if let Err(e) = inner.db.clear_changes_timestamps() {
+            warn!("failed to clear the changes timestamps: {e}");
+        }
+        for service_client in inner.clients.iter() {
+            if let Some(client) = service_client.client.upgrade() {
+                client.internal.update_config(
+                    inner.base_url.clone(),
+                    inner.client_bucket(service_client.bucket_name.as_deref()),
+                    inner.app_context.clone(),
+                );
+            }
+        }
+    }
+
+    pub fn preview_mode(&self) -> bool {
+        self.inner.lock().preview_mode
+    }
+
     pub fn client_url(&self) -> Url {
         let inner = self.inner.lock();
         let base_url = inner.base_url.clone();
@@ -148,23 +370,145 @@ impl RemoteSettingsService {
 }
 
 impl RemoteSettingsServiceInner {
-    // Find live clients in self.clients
+    // Find live clients in self.clients, and the buckets that they currently use
     //
     // Also, drop dead weakrefs from the vec
-    fn active_clients(&mut self) -> Vec<Arc<RemoteSettingsClient>> {
+    fn active_clients(&mut self) -> Vec<(Arc<RemoteSettingsClient>, String)> {
         let mut active_clients = vec![];
-        self.clients.retain(|weak| {
-            if let Some(client) = weak.upgrade() {
-                active_clients.push(client);
+        self.clients.retain(|service_client| {
+            if let Some(client) = service_client.client.upgrade() {
+                active_clients.push((client, service_client.bucket_name.clone()));
                 true
             } else {
                 false
             }
         });
         active_clients
+            .into_iter()
+            .map(|(client, bucket_name)| {
+                let bucket_name = self.client_bucket(bucket_name.as_deref());
+                (client, bucket_name)
+            })
+            .collect()
+    }
+
+    /// Get the bucket that a client should use
+    ///
+    /// `bucket_name` is the bucket that the client was created for, or `None` for the service's
+    /// bucket.
+    fn client_bucket(&self, bucket_name: Option<&str>) -> String {
+        let bucket_name = bucket_name.unwrap_or(&self.bucket_name);
+        if self.preview_mode {
+            preview_bucket(bucket_name)
+        } else {
+            bucket_name.to_string()
+        }
+    }
+
+    /// Sync the collections for `clients` that have changed on the server, adding the changes to
+    /// their records to `synced_collections`.
+    ///
+    /// The changes endpoint lists the collections in every bucket, so this checks each client
+    /// against the bucket that it uses.
+    fn sync_clients(
+        &mut self,
+        clients: &[(Arc<RemoteSettingsClient>, String)],
+        expected: u64,
+        synced_collections: &mut SyncedCollections,
+    ) -> Result<()> {
+        let request = self.changes_request(expected)?;
+        let changes = self.handle_changes_response(request.send()?)?;
+
+        for (client, bucket_name) in clients {
+            let client = &client.internal;
+            let collection_name = client.collection_name();
+            if changes.is_up_to_date(client, bucket_name)? {
+                trace!("skipping up-to-date collection: {collection_name}");
+                continue;
+            }
+            let key = (bucket_name.clone(), collection_name.to_string());
+            if !synced_collections.contains_key(&key) {
+                trace!("syncing collection: {bucket_name}/{collection_name}");
+                let expected = changes.last_modified(bucket_name, collection_name);
+                synced_collections.insert(key, client.sync_with_expected(expected)?);
+            }
+        }
+        self.update_changes_timestamp(&changes, clients);
+        Ok(())
+    }
+
+    /// Get the newest collection timestamp that we've seen on the `monitor/changes` endpoint and
+    /// synced all active clients for, with the current config
+    ///
+    /// This is stored in the database, so that broadcasts for data that we synced before a
+    /// restart don't sync again.
+    fn changes_timestamp(&self) -> Result<Option<u64>> {
+        self.db.get_changes_timestamp(
+            self.base_url.url().as_str(),
+            &self.bucket_name,
+            self.preview_mode,
+        )
+    }
+
+    /// Check if we've synced every active client for a `monitor/changes` timestamp, or a newer one
+    ///
+    /// Clients created since the last sync haven't been synced for it yet, even if the timestamp
+    /// is older.
+    fn synced_for_changes_timestamp(&mut self, timestamp: u64) -> Result<bool> {
+        if !self
+            .changes_timestamp()?
+            .is_some_and(|changes_timestamp| changes_timestamp >= timestamp)
+        {
+            return Ok(false);
+        }
+        let synced_collections = self.db.get_changes_collections(
+            self.base_url.url().as_str(),
+            &self.bucket_name,
+            self.preview_mode,
+        )?;
+        Ok(self
+            .active_clients()
+            .into_iter()
+            .all(|(client, bucket_name)| {
+                let collection_name = client.internal.collection_name().to_string();
+                synced_collections.contains(&(bucket_name, collection_name))
+            }))
+    }
+
+    /// Remember the newest collection timestamp from the changes endpoint, and the collections
+    /// that `clients` synced for it
+    ///
+    /// Only call this once every client has synced, so that a broadcast for the timestamp retries
+    /// any that failed.
+    fn update_changes_timestamp(
+        &mut self,
+        changes: &Changes,
+        clients: &[(Arc<RemoteSettingsClient>, String)],
+    ) {
+        if let Some(timestamp) = changes.changes.iter().map(|c| c.last_modified).max() {
+            let collections = clients
+                .iter()
+                .map(|(client, bucket_name)| {
+                    (
+                        bucket_name.clone(),
+                        client.internal.collection_name().to_string(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            if let Err(e) = self.db.set_changes_timestamp(
+                self.base_url.url().as_str(),
+                &self.bucket_name,
+                self.preview_mode,
+                timestamp,
+                &collections,
+            ) {
+                warn!("failed to store the changes timestamp: {e}");
+            }
+        }
     }
 
-    fn fetch_changes(&mut self) -> Result<Changes> {
+    /// Build a request for the changes endpoint, unless the server asked us to back off
+    fn changes_request(&mut self, expected: u64) -> Result<Request> {
         let mut url = self.base_url.clone();
         url.path_segments_mut()
             .push("buckets")
@@ -172,19 +516,18 @@ impl RemoteSettingsServiceInner {
             .push("collections")
             .push("changes")
             .push("changeset");
-        // For now, always use `0` for the expected value.  This means we'll get updates based on
-        // the default TTL of 1 hour.
-        //
-        // Eventually, we should add support for push notifications and use the timestamp from the
-        // notification.
-        url.query_pairs_mut().append_pair("_expected", "0");
+        // `0` means we'll get updates based on the default TTL of 1 hour.  The timestamp from a
+        // push broadcast busts the cache, so that we get the data that the broadcast announced.
+        url.query_pairs_mut()
+            .append_pair("_expected", &expected.to_string());
         let url = url.into_inner();
         trace!("make_request: {url}");
         self.remote_state.ensure_no_backoff()?;
+        Ok(Request::get(url))
+    }
 
-        let req = Request::get(url);
-        let resp = req.send()?;
-
+    /// Handle the response to a request from [Self::changes_request]
+    fn handle_changes_response(&mut self, resp: Response) -> Result<Changes> {
         self.remote_state.handle_backoff_hint(&resp)?;
 
         if resp.is_success() {
@@ -198,6 +541,30 @@ impl RemoteSettingsServiceInner {
     }
 }
 
+/// Get the preview version of a bucket, like `main-preview` for `main`
+fn preview_bucket(bucket_name: &str) -> String {
+    if bucket_name.ends_with("-preview") {
+        bucket_name.to_string()
+    } else {
+        format!("{bucket_name}-preview")
+    }
+}
+
+/// Get the names of the collections from a sync, without duplicates
+fn synced_collection_names(synced_collections: SyncedCollections) -> Vec<String> {
+    synced_collections
+        .into_keys()
+        .map(|(_, collection_name)| collection_name)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Parse the version of a [BROADCAST_ID] broadcast into a timestamp
+fn parse_broadcast_version(version: &str) -> Option<u64> {
+    version.trim_matches('"').parse().ok()
+}
+
 /// Data from the changes endpoint
 ///
 /// https://remote-settings.readthedocs.io/en/latest/client-specifications.html#endpoints
@@ -206,9 +573,641 @@ struct Changes {
     changes: Vec<ChangesCollection>,
 }
 
+impl Changes {
+    /// Check if a client's collection has the same timestamp as the server's
+    fn is_up_to_date(
+        &self,
+        client: &client::RemoteSettingsClient,
+        bucket_name: &str,
+    ) -> Result<bool> {
+        let Some(client_last_modified) = client.get_last_modified_timestamp()? else {
+            return Ok(false);
+        };
+        Ok(self.changes.iter().any(|c| {
+            c.collection == client.collection_name()
+                && c.bucket == bucket_name
+                && c.last_modified == client_last_modified
+        }))
+    }
+
+    /// Get the server's timestamp for a collection, or `0` if it's not listed
+    fn last_modified(&self, bucket_name: &str, collection_name: &str) -> u64 {
+        self.changes
+            .iter()
+            .find(|c| c.bucket == bucket_name && c.collection == collection_name)
+            .map_or(0, |c| c.last_modified)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct ChangesCollection {
     collection: String,
     bucket: String,
     last_modified: u64,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mockito::mock;
+
+    fn mock_changes_endpoint(expected: u64, last_modified: u64) -> mockito::Mock {
+        mock(
+            "GET",
+            format!("/v1/buckets/monitor/collections/changes/changeset?_expected={expected}")
+                .as_str(),
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{
+                    "collection": "broadcast-test",
+                    "bucket": "main",
+                    "last_modified": last_modified,
+                }],
+                "timestamp": last_modified,
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create()
+    }
+
+    #[test]
+    fn test_parse_broadcast_version() {
+        assert_eq!(
+            parse_broadcast_version("\"1700000000000\""),
+            Some(1700000000000)
+        );
+        assert_eq!(
+            parse_broadcast_version("1700000000000"),
+            Some(1700000000000)
+        );
+        assert_eq!(parse_broadcast_version("\"\""), None);
+        assert_eq!(parse_broadcast_version("abc"), None);
+    }
+
+    #[test]
+    fn test_preview_bucket() {
+        assert_eq!(preview_bucket("main"), "main-preview");
+        assert_eq!(preview_bucket("security-state"), "security-state-preview");
+        assert_eq!(preview_bucket("main-preview"), "main-preview");
+    }
+
+    #[test]
+    fn test_client_buckets() {
+        let service =
+            RemoteSettingsService::new(":memory:".into(), RemoteSettingsConfig2::default());
+        let _main_client = service.make_client("regions".into());
+        let _security_state_client =
+            service.make_client_for_bucket("intermediates".into(), "security-state".into());
+        let client_buckets = || {
+            service
+                .inner
+                .lock()
+                .active_clients()
+                .into_iter()
+                .map(|(client, bucket_name)| {
+                    (client.internal.collection_name().to_string(), bucket_name)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            client_buckets(),
+            vec![
+                ("regions".to_string(), "main".to_string()),
+                ("intermediates".to_string(), "security-state".to_string()),
+            ]
+        );
+
+        service.set_preview_mode(true);
+        assert!(service.preview_mode());
+        assert_eq!(
+            client_buckets(),
+            vec![
+                ("regions".to_string(), "main-preview".to_string()),
+                (
+                    "intermediates".to_string(),
+                    "security-state-preview".to_string()
+                ),
+            ]
+        );
+
+        // Changing the service's bucket doesn't change the buckets that clients picked
+        service
+            .update_config(RemoteSettingsConfig2 {
+                bucket_name: Some("other".into()),
+                ..RemoteSettingsConfig2::default()
+            })
+            .unwrap();
+        service.set_preview_mode(false);
+        assert_eq!(
+            client_buckets(),
+            vec![
+                ("regions".to_string(), "other".to_string()),
+                ("intermediates".to_string(), "security-state".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_client_buckets() {
+        viaduct_dev::init_backend_dev();
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+        let _client = service.make_client_for_bucket("bucket-test".into(), "security-state".into());
+
+        // The changes endpoint lists a collection with the same name in the service's bucket,
+        // which should be ignored.
+        let mock_changes = |expected: u64| {
+            mock(
+                "GET",
+                format!("/v1/buckets/monitor/collections/changes/changeset?_expected={expected}")
+                    .as_str(),
+            )
+            .with_body(
+                serde_json::json!({
+                    "changes": [
+                        {
+                            "collection": "bucket-test",
+                            "bucket": "main",
+                            "last_modified": 200,
+                        },
+                        {
+                            "collection": "bucket-test",
+                            "bucket": "security-state",
+                            "last_modified": 100,
+                        },
+                    ],
+                    "timestamp": 200,
+                })
+                .to_string(),
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .expect(1)
+            .create()
+        };
+        let m_changeset = mock(
+            "GET",
+            "/v1/buckets/security-state/collections/bucket-test/changeset?_expected=100",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [],
+                "timestamp": 100,
+                "metadata": {"bucket": "security-state", "signatures": []},
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+
+        let m = mock_changes(5000);
+        assert_eq!(
+            service.sync_for_broadcast("\"5000\"").unwrap(),
+            vec!["bucket-test"]
+        );
+        m.assert();
+
+        // The collection is up to date with the server's security-state bucket, so it shouldn't
+        // be synced again.
+        let m = mock_changes(6000);
+        assert!(service.sync_for_broadcast("\"6000\"").unwrap().is_empty());
+        m.assert();
+        m_changeset.assert();
+    }
+
+    #[test]
+    fn test_sync_same_collection_in_two_buckets() {
+        struct Recorder(Arc<Mutex<Vec<(String, RemoteSettingsChanges)>>>);
+
+        impl RemoteSettingsChangeListener for Recorder {
+            fn on_changes(&self, collection_name: String, changes: RemoteSettingsChanges) {
+                self.0.lock().push((collection_name, changes));
+            }
+        }
+
+        viaduct_dev::init_backend_dev();
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+        let main_client = service.make_client("two-buckets".into());
+        let security_state_client =
+            service.make_client_for_bucket("two-buckets".into(), "security-state".into());
+        let main_received = Arc::new(Mutex::new(vec![]));
+        main_client.subscribe(Box::new(Recorder(main_received.clone())));
+        let security_state_received = Arc::new(Mutex::new(vec![]));
+        security_state_client.subscribe(Box::new(Recorder(security_state_received.clone())));
+        let service_received = Arc::new(Mutex::new(vec![]));
+        service.subscribe(Box::new(Recorder(service_received.clone())));
+
+        let m_changes = mock(
+            "GET",
+            "/v1/buckets/monitor/collections/changes/changeset?_expected=7000",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [
+                    {
+                        "collection": "two-buckets",
+                        "bucket": "main",
+                        "last_modified": 7000,
+                    },
+                    {
+                        "collection": "two-buckets",
+                        "bucket": "security-state",
+                        "last_modified": 6000,
+                    },
+                ],
+                "timestamp": 7000,
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+        let mock_changeset = |bucket_name: &str, record_id: &str, last_modified: u64| {
+            mock(
+                "GET",
+                format!(
+                    "/v1/buckets/{bucket_name}/collections/two-buckets/changeset?_expected={last_modified}"
+                )
+                .as_str(),
+            )
+            .with_body(
+                serde_json::json!({
+                    "changes": [{"id": record_id, "last_modified": last_modified}],
+                    "timestamp": last_modified,
+                    "metadata": {"bucket": bucket_name, "signatures": []},
+                })
+                .to_string(),
+            )
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .expect(1)
+            .create()
+        };
+        let m_main = mock_changeset("main", "main-record", 7000);
+        let m_security_state = mock_changeset("security-state", "security-state-record", 6000);
+
+        assert_eq!(
+            service.sync_for_broadcast("\"7000\"").unwrap(),
+            vec!["two-buckets"]
+        );
+        m_changes.assert();
+        m_main.assert();
+        m_security_state.assert();
+
+        // Each client gets the records from its own bucket, and is only told about its own
+        // changes.
+        let record_ids = |client: &RemoteSettingsClient| {
+            client
+                .get_records(false)
+                .unwrap()
+                .into_iter()
+                .map(|record| record.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(record_ids(&main_client), vec!["main-record"]);
+        assert_eq!(
+            record_ids(&security_state_client),
+            vec!["security-state-record"]
+        );
+        let created = |record_id: &str| RemoteSettingsChanges {
+            created: vec![record_id.to_string()],
+            ..RemoteSettingsChanges::default()
+        };
+        assert_eq!(
+            *main_received.lock(),
+            vec![("two-buckets".to_string(), created("main-record"))]
+        );
+        assert_eq!(
+            *security_state_received.lock(),
+            vec![("two-buckets".to_string(), created("security-state-record"))]
+        );
+        // The service's listeners are told about both.
+        let mut service_received = service_received.lock().clone();
+        service_received.sort_by(|a, b| a.1.created.cmp(&b.1.created));
+        assert_eq!(
+            service_received,
+            vec![
+                ("two-buckets".to_string(), created("main-record")),
+                ("two-buckets".to_string(), created("security-state-record")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sync_async() {
+        viaduct_dev::init_backend_dev();
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+        let client = service.make_client("async-test".into());
+
+        let m_changes = mock(
+            "GET",
+            "/v1/buckets/monitor/collections/changes/changeset?_expected=0",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{
+                    "collection": "async-test",
+                    "bucket": "main",
+                    "last_modified": 300,
+                }],
+                "timestamp": 300,
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+        let m_changeset = mock(
+            "GET",
+            "/v1/buckets/main/collections/async-test/changeset?_expected=300",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{"id": "record-1", "last_modified": 300}],
+                "timestamp": 300,
+                "metadata": {"bucket": "main", "signatures": []},
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+
+        assert_eq!(
+            pollster::block_on(service.sync_async()).unwrap(),
+            vec!["async-test"]
+        );
+        m_changes.assert();
+        m_changeset.assert();
+        let records = client.get_records(false).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "record-1");
+        assert_eq!(service.inner.lock().changes_timestamp().unwrap(), Some(300));
+    }
+
+    #[test]
+    fn test_sync_with_test_server() {
+        use crate::reports::{testing::SyncReportRecorder, SignatureVerification, SyncRetryPath};
+
+        viaduct_dev::init_backend_dev();
+        // The test server's certificate chain ends at a test root, so verify its signatures
+        // against that.
+        #[cfg(feature = "signatures")]
+        {
+            nss::ensure_initialized();
+            client::mock_root_cert_sha256_hash(remote_settings_test_server::ROOT_CERT_SHA256_HASH);
+        }
+        let server = remote_settings_test_server::RemoteSettingsTestServer::start().unwrap();
+        server.set_records(
+            "main",
+            "regions",
+            vec![
+                serde_json::json!({"id": "fr", "name": "France"}),
+                serde_json::json!({"id": "de", "name": "Germany"}),
+            ],
+        );
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom { url: server.url() }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+        let client = service.make_client("regions".into());
+        let recorder = SyncReportRecorder::default();
+        service.subscribe_sync_reports(Box::new(recorder.clone()));
+
+        assert_eq!(service.sync().unwrap(), vec!["regions"]);
+        let reports = recorder.take();
+        assert_eq!(reports.len(), 1);
+        assert!(!reports[0].delta);
+        assert!(reports[0].bytes > 0);
+        assert_eq!(reports[0].changed_records, 2);
+        let mut ids = client
+            .get_records(false)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["de", "fr"]);
+
+        // Removing a record on the server deletes it on the next sync.
+        server.set_records(
+            "main",
+            "regions",
+            vec![serde_json::json!({"id": "fr", "name": "France"})],
+        );
+        assert_eq!(service.sync().unwrap(), vec!["regions"]);
+        let records = client.get_records(false).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, "fr");
+        // The second sync fetched a diff with a tombstone.
+        let reports = recorder.take();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].delta);
+        assert_eq!(reports[0].changed_records, 1);
+        let expected_signature = if cfg!(feature = "signatures") {
+            SignatureVerification::Valid
+        } else {
+            SignatureVerification::Skipped
+        };
+        assert_eq!(reports[0].signature, expected_signature);
+        assert_eq!(reports[0].retry, SyncRetryPath::NotRetried);
+
+        // Syncs started by a client are reported to the service's listeners too.
+        client.sync().unwrap();
+        assert_eq!(recorder.take().len(), 1);
+    }
+
+    #[test]
+    fn test_sync_for_broadcast() {
+        viaduct_dev::init_backend_dev();
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+
+        let m = mock_changes_endpoint(2000, 2000);
+        assert_eq!(
+            service.sync_for_broadcast("\"2000\"").unwrap(),
+            Vec::<String>::new()
+        );
+        m.assert();
+
+        // Broadcasts for data that we've already synced shouldn't fetch the changes again.
+        assert!(service.sync_for_broadcast("\"2000\"").unwrap().is_empty());
+        assert!(service.sync_for_broadcast("\"1000\"").unwrap().is_empty());
+
+        let m = mock_changes_endpoint(3000, 3000);
+        assert!(service.sync_for_broadcast("\"3000\"").unwrap().is_empty());
+        m.assert();
+    }
+
+    #[test]
+    fn test_sync_for_broadcast_new_client() {
+        viaduct_dev::init_backend_dev();
+        let service = RemoteSettingsService::new(
+            ":memory:".into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+
+        let m = mock_changes_endpoint(8000, 8000);
+        assert!(service.sync_for_broadcast("\"8000\"").unwrap().is_empty());
+        m.assert();
+
+        // A client created after the sync hasn't been synced for the broadcast yet.
+        let client = service.make_client("broadcast-test".into());
+        let m_changes = mock_changes_endpoint(8000, 8000);
+        let m_changeset = mock(
+            "GET",
+            "/v1/buckets/main/collections/broadcast-test/changeset?_expected=8000",
+        )
+        .with_body(
+            serde_json::json!({
+                "changes": [{"id": "record-1", "last_modified": 8000}],
+                "timestamp": 8000,
+                "metadata": {"bucket": "main", "signatures": []},
+            })
+            .to_string(),
+        )
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .expect(1)
+        .create();
+        assert_eq!(
+            service.sync_for_broadcast("\"8000\"").unwrap(),
+            vec!["broadcast-test"]
+        );
+        m_changes.assert();
+        m_changeset.assert();
+        assert_eq!(client.get_records(false).unwrap().len(), 1);
+
+        // Now that it has, the broadcast shouldn't fetch the changes again.
+        assert!(service.sync_for_broadcast("\"8000\"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sync_for_broadcast_after_restart() {
+        viaduct_dev::init_backend_dev();
+        let storage_dir = tempfile::tempdir().unwrap();
+        let make_service = || {
+            RemoteSettingsService::new(
+                storage_dir.path().to_str().unwrap().into(),
+                RemoteSettingsConfig2 {
+                    server: Some(RemoteSettingsServer::Custom {
+                        url: mockito::server_url(),
+                    }),
+                    bucket_name: None,
+                    app_context: None,
+                },
+            )
+        };
+
+        let m = mock_changes_endpoint(4000, 4000);
+        assert!(make_service()
+            .sync_for_broadcast("\"4000\"")
+            .unwrap()
+            .is_empty());
+        m.assert();
+
+        // The timestamp is stored, so a new service doesn't fetch the changes for a broadcast that
+        // we've already synced.
+        let service = make_service();
+        assert!(service.sync_for_broadcast("\"4000\"").unwrap().is_empty());
+
+        // ...unless the config changed since.
+        service
+            .update_config(RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: Some("other".into()),
+                app_context: None,
+            })
+            .unwrap();
+        let m = mock_changes_endpoint(4000, 4000);
+        assert!(service.sync_for_broadcast("\"4000\"").unwrap().is_empty());
+        m.assert();
+    }
+
+    #[test]
+    fn test_shutdown_releases_database() {
+        let storage_dir = tempfile::tempdir().unwrap();
+        let service = RemoteSettingsService::new(
+            storage_dir.path().to_str().unwrap().into(),
+            RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom {
+                    url: mockito::server_url(),
+                }),
+                bucket_name: None,
+                app_context: None,
+            },
+        );
+        // SQLite deletes the write-ahead log when the last connection to the database closes.
+        let wal_path = storage_dir.path().join("remote-settings.sql-wal");
+
+        let client_1 = service.make_client("collection-1".into());
+        let client_2 = service.make_client("collection-2".into());
+        assert!(client_1.get_records(false).is_none());
+        assert!(client_2.get_records(false).is_none());
+        assert!(wal_path.exists());
+
+        client_1.shutdown();
+        assert!(wal_path.exists());
+        client_2.shutdown();
+        assert!(!wal_path.exists());
+
+        // Reading the changes timestamp opens the database, but shouldn't leave it open.
+        assert_eq!(service.inner.lock().changes_timestamp().unwrap(), None);
+        assert!(!wal_path.exists());
+    }
+}
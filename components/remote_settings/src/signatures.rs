@@ -2,12 +2,44 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+//! Content signatures for collection data.
+//!
+//! These are public so that tools that handle raw changesets, like the dump CLI and the test
+//! server, serialize and verify them the same way that the client does.  Verifying signatures
+//! requires the `signatures` feature.
+
 use core::clone::Clone;
 
-use crate::{RemoteSettingsRecord, Result};
+use crate::Result;
+#[cfg(feature = "signatures")]
 use rc_crypto::contentsignature;
+use serde::Serialize;
 use serde_json::{json, Value};
 
+/// Hard-coded SHA256 of our root certificates. This is used by rc_crypto/pkixc to verify that the
+/// certificates chains used in content signatures verification were produced from our root certificate.
+/// See https://bugzilla.mozilla.org/show_bug.cgi?id=1940903 to align with desktop implementation.
+pub const ROOT_CERT_SHA256_HASH_PROD: &str = "C8:A8:0E:9A:FA:EF:4E:21:9B:6F:B5:D7:A7:1D:0F:10:12:23:BA:C5:00:1A:C2:8F:9B:0D:43:DC:59:A1:06:DB";
+/// Hard-coded SHA256 of the root certificate for the stage and dev servers.
+pub const ROOT_CERT_SHA256_HASH_NONPROD: &str = "3C:01:44:6A:BE:90:36:CE:A9:A0:9A:CA:A3:A5:20:AC:62:8F:20:A7:AE:32:CE:86:1C:B2:EF:B7:0F:A0:C7:45";
+
+/// Get the name that the leaf certificate of a bucket's signatures must be issued to.
+///
+/// The signer name is hard-coded. This would have to be modified in the very (very)
+/// unlikely situation where we would add a new collection signer.
+/// And clients code would have to be modified to handle this new collection anyway.
+/// https://searchfox.org/mozilla-central/rev/df850fa290fe962c2c5ae8b63d0943ce768e3cc4/services/settings/remote-settings.sys.mjs#40-48
+pub fn signer_name(bucket: &str) -> String {
+    format!(
+        "{}.content-signature.mozilla.org",
+        if bucket.contains("security-state") {
+            "onecrl"
+        } else {
+            "remote-settings"
+        }
+    )
+}
+
 /// Remove `deleted` and `attachment` fields if they are null.
 fn select_record_fields(value: &Value) -> Value {
     match value {
@@ -22,11 +54,16 @@ fn select_record_fields(value: &Value) -> Value {
 }
 
 /// Serialize collection data into canonical JSON. This must match the server implementation.
-fn serialize_data(timestamp: u64, records: &[RemoteSettingsRecord]) -> Result<Vec<u8>> {
-    let mut sorted_records = records.to_vec();
-    sorted_records.sort_by_cached_key(|r| r.id.clone());
+///
+/// `records` can be [crate::RemoteSettingsRecord]s, or raw JSON records from a changeset.
+pub fn serialize_data<T: Serialize>(timestamp: u64, records: &[T]) -> Result<Vec<u8>> {
+    let mut sorted_records = records
+        .iter()
+        .map(|r| Ok(select_record_fields(&serde_json::to_value(r)?)))
+        .collect::<Result<Vec<_>>>()?;
+    sorted_records.sort_by_cached_key(|r| r["id"].as_str().unwrap_or_default().to_string());
     let serialized = canonical_json::to_string(&json!({
-        "data": sorted_records.into_iter().map(|r| select_record_fields(&json!(r))).collect::<Vec<Value>>(),
+        "data": sorted_records,
         "last_modified": timestamp.to_string()
     }))?;
     let data = format!("Content-Signature:\x00{}", serialized);
@@ -34,9 +71,10 @@ fn serialize_data(timestamp: u64, records: &[RemoteSettingsRecord]) -> Result<Ve
 }
 
 /// Verify that the timestamp and records match the signature in the metadata.
-pub fn verify_signature(
+#[cfg(feature = "signatures")]
+pub fn verify_signature<T: Serialize>(
     timestamp: u64,
-    records: &[RemoteSettingsRecord],
+    records: &[T],
     signature: &[u8],
     cert_chain_bytes: &[u8],
     epoch_seconds: u64,
@@ -80,6 +118,21 @@ mod tests {
         assert_eq!(s, "Content-Signature:\u{0}{\"data\":[{\"id\":\"bonjour\",\"last_modified\":42,\"foo\":\"bar\"}],\"last_modified\":\"1337\"}");
     }
 
+    #[test]
+    fn test_json_records_canonicaljson_serialization() {
+        // Raw JSON records serialize like the equivalent `RemoteSettingsRecord`s.
+        let bytes = serialize_data(
+            1337,
+            &[
+                json!({"id": "salut", "last_modified": 43, "deleted": false, "attachment": null}),
+                json!({"id": "bonjour", "last_modified": 42, "foo": "bar"}),
+            ],
+        )
+        .unwrap();
+        let s = String::from_utf8(bytes).unwrap();
+        assert_eq!(s, "Content-Signature:\u{0}{\"data\":[{\"id\":\"bonjour\",\"last_modified\":42,\"foo\":\"bar\"},{\"id\":\"salut\",\"last_modified\":43}],\"last_modified\":\"1337\"}");
+    }
+
     #[test]
     fn test_records_canonicaljson_serialization_with_attachment() {
         let bytes = serialize_data(
@@ -3,62 +3,208 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{
-    client::CollectionMetadata, client::CollectionSignature,
-    schema::RemoteSettingsConnectionInitializer, Attachment, Error, RemoteSettingsRecord, Result,
+    changes::RemoteSettingsChanges, client::CollectionMetadata, client::CollectionSignature,
+    client::GetItemsOptions, schema::RemoteSettingsConnectionInitializer, Attachment, Error,
+    RemoteSettingsRecord, Result,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use error_support::warn;
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+use rusqlite::{
+    params, params_from_iter, types::Value, Connection, OpenFlags, OptionalExtension, Transaction,
 };
-use camino::Utf8PathBuf;
-use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Transaction};
 use serde_json;
 use sha2::{Digest, Sha256};
-use std::io;
+use std::{collections::HashSet, io, sync::Arc};
 
 use sql_support::{open_database::open_database_with_flags, ConnExt};
 
-/// Internal storage type
-///
-/// This will store downloaded records/attachments in a SQLite database.  Nothing is implemented
-/// yet other than the initial API.
-///
-/// Most methods input a `collection_url` parameter, is a URL that includes the remote settings
-/// server, bucket, and collection. If the `collection_url` for a get method does not match the one
-/// for a set method, then this means the application has switched their remote settings config and
-/// [Storage] should pretend like nothing is stored in the database.
+/// The most attachment data to cache, in bytes.  When the cache grows larger than this, the least
+/// recently used attachments are evicted.
+pub const DEFAULT_ATTACHMENT_CACHE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// A remote settings database, which can be shared by the [Storage] for several collections.
 ///
-/// The reason for this is the [crate::RemoteSettingsService::update_config] method.  If a consumer
-/// passes a new server or bucket to `update_config`, we don't want to be using cached data from
-/// the previous config.
-pub struct Storage {
+/// The SQLite connection is lazily opened on first use, and closed once every [Storage] that
+/// uses the database has been closed or dropped.
+pub struct StorageDb {
     path: Utf8PathBuf,
-    conn: ConnectionCell,
+    state: Mutex<StorageDbState>,
+    attachment_cache_size: u64,
 }
 
-impl Storage {
+struct StorageDbState {
+    conn: Option<Connection>,
+    /// The number of open [Storage]s that use the database
+    storages: usize,
+}
+
+impl StorageDb {
     pub fn new(path: Utf8PathBuf) -> Self {
         Self {
             path,
-            conn: ConnectionCell::Uninitialized,
+            state: Mutex::new(StorageDbState {
+                conn: None,
+                storages: 0,
+            }),
+            attachment_cache_size: DEFAULT_ATTACHMENT_CACHE_SIZE,
         }
     }
 
-    fn transaction(&mut self) -> Result<Transaction<'_>> {
-        match &self.conn {
-            ConnectionCell::Uninitialized => {
-                self.ensure_dir()?;
-                self.conn = ConnectionCell::Initialized(open_database_with_flags(
-                    &self.path,
-                    OpenFlags::default(),
-                    &RemoteSettingsConnectionInitializer,
-                )?);
-            }
-            ConnectionCell::Initialized(_) => (),
-            ConnectionCell::Closed => return Err(Error::DatabaseClosed),
+    /// Lock the database connection, opening it if needed
+    fn lock(&self) -> Result<MappedMutexGuard<'_, Connection>> {
+        let mut state = self.state.lock();
+        if state.conn.is_none() {
+            self.ensure_dir()?;
+            state.conn = Some(open_database_with_flags(
+                &self.path,
+                OpenFlags::default(),
+                &RemoteSettingsConnectionInitializer,
+            )?);
         }
-        match &mut self.conn {
-            ConnectionCell::Initialized(conn) => Ok(conn.transaction()?),
-            _ => unreachable!(),
+        Ok(MutexGuard::map(state, |state| {
+            state.conn.as_mut().expect("connection should be open")
+        }))
+    }
+
+    /// Run `op` with the database connection, for callers that aren't a [Storage]
+    ///
+    /// If no [Storage] is open, the connection is closed again afterwards.
+    fn with_conn<T>(&self, op: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let result = op(&self.lock()?);
+        self.close_if_unused(&mut self.state.lock());
+        result
+    }
+
+    fn add_storage(&self) {
+        self.state.lock().storages += 1;
+    }
+
+    fn remove_storage(&self) {
+        let mut state = self.state.lock();
+        state.storages -= 1;
+        self.close_if_unused(&mut state);
+    }
+
+    /// Close the connection if no [Storage] uses it, so that the database file is released
+    ///
+    /// In-memory databases are kept open, since closing them would lose their data.
+    fn close_if_unused(&self, state: &mut StorageDbState) {
+        if state.storages == 0 && self.path != ":memory:" {
+            state.conn = None;
         }
     }
 
+    /// Get the newest `monitor/changes` timestamp that the service synced all of its clients for,
+    /// with a server, bucket, and preview mode
+    pub fn get_changes_timestamp(
+        &self,
+        base_url: &str,
+        bucket: &str,
+        preview_mode: bool,
+    ) -> Result<Option<u64>> {
+        self.with_conn(|conn| {
+            Ok(conn.try_query_one(
+                "SELECT last_modified FROM changes_timestamps
+                WHERE base_url = ? AND bucket = ? AND preview_mode = ?",
+                params![base_url, bucket, preview_mode],
+                true,
+            )?)
+        })
+    }
+
+    /// Get the bucket and collection names that the stored `monitor/changes` timestamp was synced
+    /// for, with a server, bucket, and preview mode
+    pub fn get_changes_collections(
+        &self,
+        base_url: &str,
+        bucket: &str,
+        preview_mode: bool,
+    ) -> Result<HashSet<(String, String)>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT collection_bucket, collection FROM changes_collections
+                WHERE base_url = ? AND bucket = ? AND preview_mode = ?",
+            )?;
+            let collections = stmt
+                .query_map(params![base_url, bucket, preview_mode], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(collections)
+        })
+    }
+
+    /// Store the newest `monitor/changes` timestamp that the service synced all of its clients
+    /// for, and the bucket and collection names of those clients, unless a newer timestamp is
+    /// already stored
+    ///
+    /// Collections synced for the stored timestamp are added to the ones already stored for it.
+    pub fn set_changes_timestamp(
+        &self,
+        base_url: &str,
+        bucket: &str,
+        preview_mode: bool,
+        timestamp: u64,
+        collections: &[(String, String)],
+    ) -> Result<()> {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            let stored: Option<u64> = tx.try_query_one(
+                "SELECT last_modified FROM changes_timestamps
+                WHERE base_url = ? AND bucket = ? AND preview_mode = ?",
+                params![base_url, bucket, preview_mode],
+                true,
+            )?;
+            if stored.is_some_and(|stored| stored > timestamp) {
+                return Ok(());
+            }
+            if stored != Some(timestamp) {
+                tx.execute(
+                    "DELETE FROM changes_collections
+                    WHERE base_url = ? AND bucket = ? AND preview_mode = ?",
+                    params![base_url, bucket, preview_mode],
+                )?;
+                tx.execute(
+                    "INSERT OR REPLACE INTO changes_timestamps(
+                        base_url, bucket, preview_mode, last_modified
+                    )
+                    VALUES (?, ?, ?, ?)",
+                    params![base_url, bucket, preview_mode, timestamp],
+                )?;
+            }
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO changes_collections(
+                    base_url, bucket, preview_mode, collection_bucket, collection
+                )
+                VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for (collection_bucket, collection) in collections {
+                stmt.execute(params![
+                    base_url,
+                    bucket,
+                    preview_mode,
+                    collection_bucket,
+                    collection
+                ])?;
+            }
+            drop(stmt);
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    /// Forget the stored `monitor/changes` timestamps, because the service's config changed
+    pub fn clear_changes_timestamps(&self) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute_batch(
+                "DELETE FROM changes_timestamps;
+                DELETE FROM changes_collections;",
+            )?;
+            Ok(())
+        })
+    }
+
     pub fn ensure_dir(&self) -> Result<()> {
         if self.path == ":memory:" {
             return Ok(());
@@ -76,9 +222,86 @@ impl Storage {
         }
         Ok(())
     }
+}
 
+/// Internal storage type
+///
+/// This will store downloaded records/attachments in a SQLite database, which may be shared with
+/// the [Storage] for other collections.
+///
+/// Most methods input a `collection_url` parameter, is a URL that includes the remote settings
+/// server, bucket, and collection. If the `collection_url` for a get method does not match the one
+/// for a set method, then this means the application has switched their remote settings config and
+/// [Storage] should pretend like nothing is stored in the database.
+///
+/// The reason for this is the [crate::RemoteSettingsService::update_config] method.  If a consumer
+/// passes a new server or bucket to `update_config`, we don't want to be using cached data from
+/// the previous config.
+///
+/// Attachments are cached by their hash, and shared by all collections in the database.  They're
+/// evicted when the cache grows too large, starting with the least recently used, and deleted when
+/// no stored record refers to them anymore.
+pub struct Storage {
+    db: Arc<StorageDb>,
+    /// The collection that this storage is for.  Records from other collections in the
+    /// database are ignored.
+    collection: String,
+    /// A file from before collections shared a database, to delete on first use.
+    legacy_path: Option<Utf8PathBuf>,
+    closed: bool,
+}
+
+impl Storage {
+    /// Create storage with its own database, for a single collection
+    pub fn new(path: Utf8PathBuf) -> Self {
+        Self::for_collection(Arc::new(StorageDb::new(path)), String::new())
+    }
+
+    /// Create storage for a collection in a shared database
+    pub fn for_collection(db: Arc<StorageDb>, collection: String) -> Self {
+        db.add_storage();
+        Self {
+            db,
+            collection,
+            legacy_path: None,
+            closed: false,
+        }
+    }
+
+    /// Delete `path` on first use, if it exists
+    ///
+    /// This is used to clean up the database that this collection used before it shared one.  If
+    /// the collection's old database is the shared one, like for a collection named
+    /// `remote-settings`, it's kept: upgrading its schema already dropped the old records.
+    pub fn with_legacy_path(mut self, path: Utf8PathBuf) -> Self {
+        if path != self.db.path {
+            self.legacy_path = Some(path);
+        }
+        self
+    }
+
+    /// Lock the database connection, and return it with the collection name
+    ///
+    /// Callers should start a transaction on the returned connection.
+    fn lock_db(&mut self) -> Result<(MappedMutexGuard<'_, Connection>, &str)> {
+        if self.closed {
+            return Err(Error::DatabaseClosed);
+        }
+        if let Some(path) = self.legacy_path.take() {
+            remove_legacy_database(&path);
+        }
+        Ok((self.db.lock()?, &self.collection))
+    }
+
+    /// Close this storage
+    ///
+    /// The connection is closed once the storage for every collection that shares the database
+    /// has been closed or dropped.
     pub fn close(&mut self) {
-        self.conn = ConnectionCell::Closed;
+        if !self.closed {
+            self.closed = true;
+            self.db.remove_storage();
+        }
     }
 
     /// Get the last modified timestamp for the stored records
@@ -86,11 +309,14 @@ impl Storage {
     /// Returns None if no records are stored or if `collection_url` does not match the
     /// last `collection_url` passed to `insert_collection_content`
     pub fn get_last_modified_timestamp(&mut self, collection_url: &str) -> Result<Option<u64>> {
-        let tx = self.transaction()?;
-        let mut stmt =
-            tx.prepare("SELECT last_modified FROM collection_metadata WHERE collection_url = ?")?;
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
+        let mut stmt = tx.prepare(
+            "SELECT last_modified FROM collection_metadata
+            WHERE collection = ? AND collection_url = ?",
+        )?;
         let result: Option<u64> = stmt
-            .query_row((collection_url,), |row| row.get(0))
+            .query_row((collection, collection_url), |row| row.get(0))
             .optional()?;
         Ok(result)
     }
@@ -103,17 +329,20 @@ impl Storage {
         &mut self,
         collection_url: &str,
     ) -> Result<Option<Vec<RemoteSettingsRecord>>> {
-        let tx = self.transaction()?;
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
 
         let fetched = tx.exists(
-            "SELECT 1 FROM collection_metadata WHERE collection_url = ?",
-            (collection_url,),
+            "SELECT 1 FROM collection_metadata WHERE collection = ? AND collection_url = ?",
+            (collection, collection_url),
         )?;
         let result = if fetched {
             // If fetched before, get the records from the records table
             let records: Vec<RemoteSettingsRecord> = tx
-                .prepare("SELECT data FROM records WHERE collection_url = ?")?
-                .query_map(params![collection_url], |row| row.get::<_, Vec<u8>>(0))?
+                .prepare("SELECT data FROM records WHERE collection = ? AND collection_url = ?")?
+                .query_map(params![collection, collection_url], |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })?
                 .map(|data| serde_json::from_slice(&data.unwrap()).unwrap())
                 .collect();
 
@@ -126,6 +355,61 @@ impl Storage {
         result
     }
 
+    /// Get cached records for this collection that match `options`
+    ///
+    /// `filter` is called for each record that matches the options' filters, in order, and
+    /// records that it rejects don't count toward the options' limit.
+    ///
+    /// Returns None if no records are stored or if `collection_url` does not match the `collection_url` passed
+    /// to `insert_collection_content`.
+    pub fn query_records(
+        &mut self,
+        collection_url: &str,
+        options: &GetItemsOptions,
+        mut filter: impl FnMut(&RemoteSettingsRecord) -> bool,
+    ) -> Result<Option<Vec<RemoteSettingsRecord>>> {
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
+
+        let fetched = tx.exists(
+            "SELECT 1 FROM collection_metadata WHERE collection = ? AND collection_url = ?",
+            (collection, collection_url),
+        )?;
+        let result = if fetched {
+            let (options_sql, options_params) = options.records_sql();
+            let mut stmt = tx.prepare(&format!(
+                "SELECT data FROM records WHERE collection = ? AND collection_url = ?{options_sql}"
+            ))?;
+            let mut rows = stmt.query(params_from_iter(
+                [
+                    Value::Text(collection.to_owned()),
+                    Value::Text(collection_url.to_owned()),
+                ]
+                .into_iter()
+                .chain(options_params),
+            ))?;
+            let limit = options.record_limit().unwrap_or(usize::MAX);
+            let mut records = vec![];
+            while records.len() < limit {
+                let Some(row) = rows.next()? else {
+                    break;
+                };
+                let mut record: RemoteSettingsRecord =
+                    serde_json::from_slice(&row.get::<_, Vec<u8>>(0)?)?;
+                if filter(&record) {
+                    options.select_fields(&mut record);
+                    records.push(record);
+                }
+            }
+            Some(records)
+        } else {
+            None
+        };
+
+        tx.commit()?;
+        Ok(result)
+    }
+
     /// Get cached metadata for this collection
     ///
     /// Returns None if no data is stored or if `collection_url` does not match the `collection_url` passed
@@ -134,7 +418,8 @@ impl Storage {
         &mut self,
         collection_url: &str,
     ) -> Result<Option<CollectionMetadata>> {
-        let tx = self.transaction()?;
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
         // signatures is a JSON array of objects with "signature" and "x5u" fields,
         // so we need to iterate through the rows and construct the list of signatures.
         // we use LEFT JOIN to return a row even if list of signatures is empty.
@@ -146,11 +431,11 @@ impl Storage {
                 json_extract(sig.value, '$.signature') AS signature
             FROM collection_metadata AS cm
             LEFT JOIN json_each(cm.signatures) AS sig ON true
-            WHERE cm.collection_url = ?
+            WHERE cm.collection = ? AND cm.collection_url = ?
             ",
         )?;
 
-        let mut rows = stmt_metadata.query(params![collection_url])?;
+        let mut rows = stmt_metadata.query(params![collection, collection_url])?;
         let mut bucket: Option<String> = None;
         let mut signatures = Vec::new();
 
@@ -173,99 +458,126 @@ impl Storage {
 
     /// Get cached attachment data
     ///
-    /// This returns the last attachment data sent to [Self::set_attachment].
+    /// Attachments are looked up by their hash, so this returns data sent to
+    /// [Self::set_attachment] by the storage for any collection in the database.
     ///
-    /// Returns None if no attachment data is stored or if `collection_url` does not match the `collection_url`
-    /// passed to `set_attachment`.
-    pub fn get_attachment(
-        &mut self,
-        collection_url: &str,
-        metadata: Attachment,
-    ) -> Result<Option<Vec<u8>>> {
-        let tx = self.transaction()?;
-        let mut stmt =
-            tx.prepare("SELECT data FROM attachments WHERE id = ? AND collection_url = ?")?;
-
-        if let Some(data) = stmt
-            .query_row((metadata.location, collection_url), |row| {
-                row.get::<_, Vec<u8>>(0)
-            })
+    /// Returns None if no attachment data with the hash is stored, or if it doesn't match the
+    /// metadata.
+    pub fn get_attachment(&mut self, metadata: Attachment) -> Result<Option<Vec<u8>>> {
+        let (mut conn, _) = self.lock_db()?;
+        let tx = conn.transaction()?;
+        let Some(data) = tx
+            .query_row(
+                "SELECT data FROM attachments WHERE hash = ?",
+                (&metadata.hash,),
+                |row| row.get::<_, Vec<u8>>(0),
+            )
             .optional()?
-        {
-            // Return None if data doesn't match expected metadata
-            if data.len() as u64 != metadata.size {
-                return Ok(None);
-            }
-            let hash = format!("{:x}", Sha256::digest(&data));
-            if hash != metadata.hash {
-                return Ok(None);
-            }
-            Ok(Some(data))
-        } else {
-            Ok(None)
+        else {
+            return Ok(None);
+        };
+
+        // Return None if data doesn't match expected metadata
+        if data.len() as u64 != metadata.size {
+            return Ok(None);
+        }
+        let hash = format!("{:x}", Sha256::digest(&data));
+        if hash != metadata.hash {
+            return Ok(None);
         }
+
+        // Mark the attachment as the most recently used.
+        tx.execute(
+            "UPDATE attachments
+            SET last_used = (SELECT max(last_used) + 1 FROM attachments)
+            WHERE hash = ?",
+            (&metadata.hash,),
+        )?;
+        tx.commit()?;
+        Ok(Some(data))
     }
 
     /// Set cached content for this collection.
+    ///
+    /// Returns the IDs of the records that were created, updated, and deleted.
     pub fn insert_collection_content(
         &mut self,
         collection_url: &str,
         records: &[RemoteSettingsRecord],
         last_modified: u64,
         metadata: CollectionMetadata,
-    ) -> Result<()> {
-        let tx = self.transaction()?;
+    ) -> Result<RemoteSettingsChanges> {
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
+        let attachment_hashes = Self::attachment_hashes(&tx, collection)?;
 
-        // Delete ALL existing records and metadata for with different collection_urls.
+        // Delete ALL existing records and metadata for this collection with different
+        // collection_urls.
         //
         // This way, if a user (probably QA) switches the remote settings server in the middle of a
         // browser sessions, we'll delete the stale data from the previous server.
         tx.execute(
-            "DELETE FROM records where collection_url <> ?",
-            [collection_url],
+            "DELETE FROM records WHERE collection = ? AND collection_url <> ?",
+            (collection, collection_url),
         )?;
         tx.execute(
-            "DELETE FROM collection_metadata where collection_url <> ?",
-            [collection_url],
+            "DELETE FROM collection_metadata WHERE collection = ? AND collection_url <> ?",
+            (collection, collection_url),
         )?;
 
-        Self::update_record_rows(&tx, collection_url, records)?;
-        Self::update_collection_metadata(&tx, collection_url, last_modified, metadata)?;
+        let changes = Self::update_record_rows(&tx, collection, collection_url, records)?;
+        Self::update_collection_metadata(&tx, collection, collection_url, last_modified, metadata)?;
+        Self::delete_unused_attachments(&tx, &attachment_hashes)?;
         tx.commit()?;
-        Ok(())
+        Ok(changes)
     }
 
     /// Insert/remove/update rows in the records table based on a records list
     ///
-    /// Returns the max last modified record from the list
+    /// Returns the IDs of the records that were created, updated, and deleted.
+    /// Tombstones for records that aren't stored aren't counted as deletions.
     fn update_record_rows(
         tx: &Transaction<'_>,
+        collection: &str,
         collection_url: &str,
         records: &[RemoteSettingsRecord],
-    ) -> Result<u64> {
-        // Find the max last_modified time while inserting records
-        let mut max_last_modified = 0;
-        {
-            let mut insert_stmt = tx.prepare(
-                "INSERT OR REPLACE INTO records (id, collection_url, data) VALUES (?, ?, ?)",
-            )?;
-            let mut delete_stmt = tx.prepare("DELETE FROM records WHERE id=?")?;
-            for record in records {
-                if record.deleted {
-                    delete_stmt.execute(params![&record.id])?;
+    ) -> Result<RemoteSettingsChanges> {
+        let mut changes = RemoteSettingsChanges::default();
+        let mut exists_stmt =
+            tx.prepare("SELECT 1 FROM records WHERE collection = ? AND id = ?")?;
+        let mut insert_stmt = tx.prepare(
+            "INSERT OR REPLACE INTO records (collection, id, collection_url, data, attachment_hash)
+            VALUES (?, ?, ?, ?, ?)",
+        )?;
+        let mut delete_stmt = tx.prepare("DELETE FROM records WHERE collection = ? AND id = ?")?;
+        for record in records {
+            if record.deleted {
+                if delete_stmt.execute(params![collection, &record.id])? > 0 {
+                    changes.deleted.push(record.id.clone());
+                }
+            } else {
+                if exists_stmt.exists(params![collection, record.id])? {
+                    changes.updated.push(record.id.clone());
                 } else {
-                    max_last_modified = max_last_modified.max(record.last_modified);
-                    let data = serde_json::to_vec(&record)?;
-                    insert_stmt.execute(params![record.id, collection_url, data])?;
+                    changes.created.push(record.id.clone());
                 }
+                let data = serde_json::to_vec(&record)?;
+                insert_stmt.execute(params![
+                    collection,
+                    record.id,
+                    collection_url,
+                    data,
+                    record.attachment.as_ref().map(|a| &a.hash),
+                ])?;
             }
         }
-        Ok(max_last_modified)
+        Ok(changes)
     }
 
     /// Update the collection metadata after setting/merging records
     fn update_collection_metadata(
         tx: &Transaction<'_>,
+        collection: &str,
         collection_url: &str,
         last_modified: u64,
         metadata: CollectionMetadata,
@@ -275,11 +587,12 @@ impl Storage {
 
         let mut stmt = tx.prepare(
             "INSERT OR REPLACE INTO collection_metadata
-            (collection_url, last_modified, bucket, signatures)
-            VALUES (?, ?, ?, ?)",
+            (collection, collection_url, last_modified, bucket, signatures)
+            VALUES (?, ?, ?, ?, ?)",
         )?;
 
         stmt.execute((
+            collection,
             collection_url,
             last_modified,
             &metadata.bucket,
@@ -288,26 +601,56 @@ impl Storage {
         Ok(())
     }
 
-    /// Set the attachment data stored in the database, clearing out any previously stored data
-    pub fn set_attachment(
-        &mut self,
-        collection_url: &str,
-        location: &str,
-        attachment: &[u8],
-    ) -> Result<()> {
-        let tx = self.transaction()?;
+    /// Get the hashes of the attachments that a collection's records refer to
+    fn attachment_hashes(tx: &Transaction<'_>, collection: &str) -> Result<Vec<String>> {
+        Ok(tx
+            .prepare(
+                "SELECT DISTINCT attachment_hash FROM records
+                WHERE collection = ? AND attachment_hash IS NOT NULL",
+            )?
+            .query_map(params![collection], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?)
+    }
 
-        // Delete ALL existing attachments for every collection_url
-        tx.execute(
-            "DELETE FROM attachments WHERE collection_url != ?",
-            params![collection_url],
+    /// Delete the cached attachments in `hashes` that no stored record refers to anymore
+    ///
+    /// Callers pass the hashes from [Self::attachment_hashes] before changing a collection's
+    /// records, so that this only looks at the attachments that the collection used.
+    fn delete_unused_attachments(tx: &Transaction<'_>, hashes: &[String]) -> Result<()> {
+        let mut stmt = tx.prepare(
+            "DELETE FROM attachments
+            WHERE hash = ?1 AND NOT EXISTS (SELECT 1 FROM records WHERE attachment_hash = ?1)",
         )?;
+        for hash in hashes {
+            stmt.execute(params![hash])?;
+        }
+        Ok(())
+    }
+
+    /// Add attachment data to the cache
+    ///
+    /// The data is stored by its hash from `metadata`, which callers should have verified.  If the
+    /// cache grows larger than its size budget, the least recently used attachments are evicted,
+    /// except for this one.
+    pub fn set_attachment(&mut self, metadata: &Attachment, attachment: &[u8]) -> Result<()> {
+        let cache_size = self.db.attachment_cache_size;
+        let (mut conn, _) = self.lock_db()?;
+        let tx = conn.transaction()?;
 
         tx.execute(
-            "INSERT OR REPLACE INTO ATTACHMENTS \
-            (id, collection_url, data) \
-            VALUES (?, ?, ?)",
-            params![location, collection_url, attachment,],
+            "INSERT OR REPLACE INTO attachments (hash, size, last_used, data)
+            VALUES (?, ?, (SELECT coalesce(max(last_used), 0) + 1 FROM attachments), ?)",
+            params![metadata.hash, attachment.len() as u64, attachment],
+        )?;
+        tx.execute(
+            "DELETE FROM attachments WHERE hash <> ? AND hash IN (
+                SELECT hash FROM (
+                    SELECT hash, sum(size) OVER (ORDER BY last_used DESC) AS total_size
+                    FROM attachments
+                )
+                WHERE total_size > ?
+            )",
+            params![metadata.hash, cache_size],
         )?;
 
         tx.commit()?;
@@ -315,34 +658,203 @@ impl Storage {
         Ok(())
     }
 
-    /// Empty out all cached values and start from scratch.  This is called when
-    /// RemoteSettingsService::update_config() is called, since that could change the remote
+    /// Get the IDs of the cached records for this collection
+    pub fn get_record_ids(&mut self, collection_url: &str) -> Result<Vec<String>> {
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
+        let ids = tx
+            .prepare("SELECT id FROM records WHERE collection = ? AND collection_url = ?")?
+            .query_map(params![collection, collection_url], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    /// Empty out all cached values for this collection and start from scratch.  This is called
+    /// when RemoteSettingsService::update_config() is called, since that could change the remote
     /// settings server which would invalidate all cached data.
+    ///
+    /// Attachments that other collections' records still refer to are kept.
     pub fn empty(&mut self) -> Result<()> {
-        let tx = self.transaction()?;
-        tx.execute("DELETE FROM records", [])?;
-        tx.execute("DELETE FROM attachments", [])?;
-        tx.execute("DELETE FROM collection_metadata", [])?;
+        let (mut conn, collection) = self.lock_db()?;
+        let tx = conn.transaction()?;
+        let attachment_hashes = Self::attachment_hashes(&tx, collection)?;
+        tx.execute("DELETE FROM records WHERE collection = ?", (collection,))?;
+        tx.execute(
+            "DELETE FROM collection_metadata WHERE collection = ?",
+            (collection,),
+        )?;
+        Self::delete_unused_attachments(&tx, &attachment_hashes)?;
         tx.commit()?;
         Ok(())
     }
 }
 
-/// Stores the SQLite connection, which is lazily constructed and can be closed/shutdown.
-enum ConnectionCell {
-    Uninitialized,
-    Initialized(Connection),
-    Closed,
+impl Drop for Storage {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Delete a database file, and its journal files
+fn remove_legacy_database(path: &Utf8Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let path = format!("{path}{suffix}");
+        match std::fs::remove_file(&path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => warn!("failed to remove legacy database {path}: {e}"),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Storage;
+    use super::{Storage, StorageDb};
     use crate::{
-        client::CollectionMetadata, client::CollectionSignature, Attachment, RemoteSettingsRecord,
-        Result, RsJsonObject,
+        client::CollectionMetadata, client::CollectionSignature, client::GetItemsOptions,
+        client::SortOrder, Attachment, RemoteSettingsRecord, Result, RsJsonObject,
     };
+    use camino::Utf8Path;
     use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_storage_query_records() -> Result<()> {
+        let mut storage = Storage::new(":memory:".into());
+        let collection_url = "https://example.com/api";
+        let record = |id: &str, fields: serde_json::Value| RemoteSettingsRecord {
+            id: id.to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment: None,
+            fields: fields.as_object().unwrap().clone(),
+        };
+        let ids = |records: Option<Vec<RemoteSettingsRecord>>| {
+            records
+                .unwrap()
+                .into_iter()
+                .map(|r| r.id)
+                .collect::<Vec<_>>()
+        };
+
+        // Querying before anything is stored returns None, like `get_records`
+        assert_eq!(
+            storage.query_records(collection_url, &GetItemsOptions::new(), |_| true)?,
+            None
+        );
+
+        storage.insert_collection_content(
+            collection_url,
+            &[
+                record(
+                    "a",
+                    serde_json::json!({
+                        "name": "Alpha",
+                        "count": 3,
+                        "tags": ["x", "y"],
+                        "author": {"name": "Ben"},
+                    }),
+                ),
+                record(
+                    "b",
+                    serde_json::json!({
+                        "name": "Beta",
+                        "count": 1,
+                        "tags": ["y"],
+                        "author": {"name": "Cat"},
+                    }),
+                ),
+                record("c", serde_json::json!({"name": "50% off", "count": 2})),
+            ],
+            300,
+            CollectionMetadata::default(),
+        )?;
+
+        let mut query = |options: &GetItemsOptions| {
+            storage.query_records(collection_url, options, |record| record.id != "skipped")
+        };
+        assert_eq!(
+            ids(query(GetItemsOptions::new().filter_eq("count", "3"))?),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new().filter_eq("author.name", "Cat")
+            )?),
+            vec!["b"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new()
+                    .filter_not("name", "Beta")
+                    .sort("count", SortOrder::Ascending)
+            )?),
+            vec!["c", "a"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new()
+                    .filter_contains("tags", "y")
+                    .sort("name", SortOrder::Descending)
+            )?),
+            vec!["b", "a"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new().filter_contains("tags", r#"["x", "y"]"#)
+            )?),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new()
+                    .filter_min("count", "2")
+                    .filter_lt("count", "3")
+            )?),
+            vec!["c"]
+        );
+        assert_eq!(
+            ids(query(GetItemsOptions::new().filter_like("name", "a*h"))?),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(query(GetItemsOptions::new().filter_like("name", "%"))?),
+            vec!["c"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new()
+                    .filter_has_not("tags")
+                    .sort("id", SortOrder::Ascending)
+            )?),
+            vec!["c"]
+        );
+        assert_eq!(
+            ids(query(
+                GetItemsOptions::new()
+                    .sort("count", SortOrder::Descending)
+                    .limit(2)
+            )?),
+            vec!["a", "c"]
+        );
+
+        // Records that the filter rejects don't count toward the limit.
+        let records = storage.query_records(
+            collection_url,
+            GetItemsOptions::new()
+                .sort("id", SortOrder::Ascending)
+                .field("name")
+                .limit(1),
+            |record| record.id != "a",
+        )?;
+        assert_eq!(
+            records,
+            Some(vec![record("b", serde_json::json!({"name": "Beta"}))])
+        );
+
+        Ok(())
+    }
 
     #[test]
     fn test_storage_set_and_get_records() -> Result<()> {
@@ -453,10 +965,10 @@ mod tests {
         };
 
         // Store attachment
-        storage.set_attachment(collection_url, &attachment_metadata.location, attachment)?;
+        storage.set_attachment(&attachment_metadata, attachment)?;
 
         // Get attachment
-        let fetched_attachment = storage.get_attachment(collection_url, attachment_metadata)?;
+        let fetched_attachment = storage.get_attachment(attachment_metadata)?;
         assert!(fetched_attachment.is_some());
         let fetched_attachment = fetched_attachment.unwrap();
         assert_eq!(fetched_attachment, attachment);
@@ -464,102 +976,302 @@ mod tests {
         Ok(())
     }
 
+    fn test_attachment(data: &[u8]) -> Attachment {
+        Attachment {
+            filename: "abc".to_string(),
+            mimetype: "application/octet-stream".to_string(),
+            location: "tmp".to_string(),
+            hash: format!("{:x}", Sha256::digest(data)),
+            size: data.len() as u64,
+        }
+    }
+
+    fn test_record_with_attachment(id: &str, data: &[u8]) -> RemoteSettingsRecord {
+        RemoteSettingsRecord {
+            id: id.to_string(),
+            last_modified: 100,
+            deleted: false,
+            attachment: Some(test_attachment(data)),
+            fields: RsJsonObject::new(),
+        }
+    }
+
     #[test]
-    fn test_storage_set_and_replace_attachment() -> Result<()> {
+    fn test_storage_set_multiple_attachments() -> Result<()> {
         let mut storage = Storage::new(":memory:".into());
 
-        let collection_url = "https://example.com/api";
-
         let attachment_1 = &[0x18, 0x64];
         let attachment_2 = &[0x12, 0x48];
 
-        let attachment_metadata_1 = Attachment {
-            filename: "abc".to_string(),
-            mimetype: "application/json".to_string(),
-            location: "tmp".to_string(),
-            hash: format!("{:x}", Sha256::digest(attachment_1)),
-            size: attachment_1.len() as u64,
-        };
+        storage.set_attachment(&test_attachment(attachment_1), attachment_1)?;
+        storage.set_attachment(&test_attachment(attachment_2), attachment_2)?;
 
-        let attachment_metadata_2 = Attachment {
-            filename: "def".to_string(),
-            mimetype: "application/json".to_string(),
-            location: "tmp".to_string(),
-            hash: format!("{:x}", Sha256::digest(attachment_2)),
-            size: attachment_2.len() as u64,
-        };
+        // Attachments are cached by hash, so both are available
+        assert_eq!(
+            storage.get_attachment(test_attachment(attachment_1))?,
+            Some(attachment_1.to_vec())
+        );
+        assert_eq!(
+            storage.get_attachment(test_attachment(attachment_2))?,
+            Some(attachment_2.to_vec())
+        );
 
-        // Store first attachment
-        storage.set_attachment(
-            collection_url,
-            &attachment_metadata_1.location,
-            attachment_1,
-        )?;
+        Ok(())
+    }
 
-        // Replace attachment with new data
-        storage.set_attachment(
-            collection_url,
-            &attachment_metadata_2.location,
-            attachment_2,
-        )?;
+    #[test]
+    fn test_storage_attachment_size_mismatch() -> Result<()> {
+        let mut storage = Storage::new(":memory:".into());
 
-        // Get attachment
-        let fetched_attachment = storage.get_attachment(collection_url, attachment_metadata_2)?;
-        assert!(fetched_attachment.is_some());
-        let fetched_attachment = fetched_attachment.unwrap();
-        assert_eq!(fetched_attachment, attachment_2);
+        let attachment = &[0x18, 0x64];
+        storage.set_attachment(&test_attachment(attachment), attachment)?;
+
+        let metadata = Attachment {
+            size: 3,
+            ..test_attachment(attachment)
+        };
+        assert_eq!(storage.get_attachment(metadata)?, None);
 
         Ok(())
     }
 
     #[test]
-    fn test_storage_set_attachment_delete_others() -> Result<()> {
-        let mut storage = Storage::new(":memory:".into());
+    fn test_storage_attachment_eviction() -> Result<()> {
+        let db = Arc::new(StorageDb {
+            attachment_cache_size: 4,
+            ..StorageDb::new(":memory:".into())
+        });
+        let mut storage = Storage::for_collection(db, "test-collection".into());
+
+        let attachment_1 = &[0x01, 0x01];
+        let attachment_2 = &[0x02, 0x02];
+        let attachment_3 = &[0x03, 0x03];
+        let too_large = &[0x04, 0x04, 0x04, 0x04, 0x04];
+
+        storage.set_attachment(&test_attachment(attachment_1), attachment_1)?;
+        storage.set_attachment(&test_attachment(attachment_2), attachment_2)?;
+        // Use the first attachment, so that the second is the least recently used
+        assert!(storage
+            .get_attachment(test_attachment(attachment_1))?
+            .is_some());
+        storage.set_attachment(&test_attachment(attachment_3), attachment_3)?;
+
+        assert!(storage
+            .get_attachment(test_attachment(attachment_1))?
+            .is_some());
+        assert!(storage
+            .get_attachment(test_attachment(attachment_2))?
+            .is_none());
+        assert!(storage
+            .get_attachment(test_attachment(attachment_3))?
+            .is_some());
+
+        // An attachment that's larger than the whole cache evicts everything else, but is kept
+        // itself
+        storage.set_attachment(&test_attachment(too_large), too_large)?;
+        assert!(storage
+            .get_attachment(test_attachment(attachment_1))?
+            .is_none());
+        assert!(storage
+            .get_attachment(test_attachment(attachment_3))?
+            .is_none());
+        assert!(storage
+            .get_attachment(test_attachment(too_large))?
+            .is_some());
 
-        let collection_url_1 = "https://example.com/api1";
-        let collection_url_2 = "https://example.com/api2";
+        Ok(())
+    }
 
+    #[test]
+    fn test_storage_delete_unused_attachments() -> Result<()> {
+        let mut storage = Storage::new(":memory:".into());
+
+        let collection_url = "https://example.com/api";
         let attachment_1 = &[0x18, 0x64];
         let attachment_2 = &[0x12, 0x48];
 
-        let attachment_metadata_1 = Attachment {
-            filename: "abc".to_string(),
-            mimetype: "application/json".to_string(),
-            location: "first_tmp".to_string(),
-            hash: format!("{:x}", Sha256::digest(attachment_1)),
-            size: attachment_1.len() as u64,
-        };
+        storage.insert_collection_content(
+            collection_url,
+            &[
+                test_record_with_attachment("1", attachment_1),
+                test_record_with_attachment("2", attachment_2),
+            ],
+            100,
+            CollectionMetadata::default(),
+        )?;
+        storage.set_attachment(&test_attachment(attachment_1), attachment_1)?;
+        storage.set_attachment(&test_attachment(attachment_2), attachment_2)?;
 
-        let attachment_metadata_2 = Attachment {
-            filename: "def".to_string(),
-            mimetype: "application/json".to_string(),
-            location: "second_tmp".to_string(),
-            hash: format!("{:x}", Sha256::digest(attachment_2)),
-            size: attachment_2.len() as u64,
-        };
+        // Delete the first record.  Its attachment should be deleted, but the second record's
+        // attachment should be kept.
+        storage.insert_collection_content(
+            collection_url,
+            &[RemoteSettingsRecord {
+                id: "1".to_string(),
+                last_modified: 200,
+                deleted: true,
+                attachment: None,
+                fields: RsJsonObject::new(),
+            }],
+            200,
+            CollectionMetadata::default(),
+        )?;
+        assert!(storage
+            .get_attachment(test_attachment(attachment_1))?
+            .is_none());
+        assert!(storage
+            .get_attachment(test_attachment(attachment_2))?
+            .is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_shared_database() -> Result<()> {
+        let db = Arc::new(StorageDb::new(":memory:".into()));
+        let mut storage_1 = Storage::for_collection(db.clone(), "collection-1".into());
+        let mut storage_2 = Storage::for_collection(db, "collection-2".into());
+
+        let collection_url_1 = "https://example.com/api/collections/collection-1";
+        let collection_url_2 = "https://example.com/api/collections/collection-2";
+        let attachment = &[0x18, 0x64];
 
-        // Set attachments for two different collections
-        storage.set_attachment(
+        // Both collections have a record with the same ID and attachment
+        storage_1.insert_collection_content(
             collection_url_1,
-            &attachment_metadata_1.location,
-            attachment_1,
+            &[test_record_with_attachment("1", attachment)],
+            100,
+            CollectionMetadata::default(),
         )?;
-        storage.set_attachment(
+        storage_2.insert_collection_content(
             collection_url_2,
-            &attachment_metadata_2.location,
-            attachment_2,
+            &[test_record_with_attachment("1", attachment)],
+            200,
+            CollectionMetadata::default(),
         )?;
+        storage_1.set_attachment(&test_attachment(attachment), attachment)?;
+
+        // The attachment is shared
+        assert!(storage_2
+            .get_attachment(test_attachment(attachment))?
+            .is_some());
+
+        // Records and metadata are isolated
+        assert_eq!(
+            storage_1.get_last_modified_timestamp(collection_url_1)?,
+            Some(100)
+        );
+        assert_eq!(
+            storage_2.get_last_modified_timestamp(collection_url_2)?,
+            Some(200)
+        );
+        assert_eq!(
+            storage_1.get_last_modified_timestamp(collection_url_2)?,
+            None
+        );
+
+        // Emptying one collection keeps the other's records, and the attachment that they
+        // still refer to
+        storage_1.empty()?;
+        assert_eq!(storage_1.get_records(collection_url_1)?, None);
+        assert_eq!(storage_2.get_record_ids(collection_url_2)?, vec!["1"]);
+        assert!(storage_2
+            .get_attachment(test_attachment(attachment))?
+            .is_some());
+
+        storage_2.empty()?;
+        assert!(storage_2
+            .get_attachment(test_attachment(attachment))?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_remove_legacy_database() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let legacy_path = dir.join("test-collection.sql");
+        std::fs::write(&legacy_path, b"legacy").unwrap();
+        std::fs::write(dir.join("test-collection.sql-wal"), b"legacy").unwrap();
+
+        let mut storage = Storage::for_collection(
+            Arc::new(StorageDb::new(dir.join("remote-settings.sql"))),
+            "test-collection".into(),
+        )
+        .with_legacy_path(legacy_path.clone());
+        assert_eq!(storage.get_records("https://example.com/api")?, None);
+        assert!(!legacy_path.exists());
+        assert!(!dir.join("test-collection.sql-wal").exists());
+        assert!(dir.join("remote-settings.sql").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_legacy_path_is_shared_database() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let db = Arc::new(StorageDb::new(dir.join("remote-settings.sql")));
+        let collection_url = "https://example.com/api";
+
+        let mut storage = Storage::for_collection(db.clone(), "test-collection".into());
+        storage.insert_collection_content(
+            collection_url,
+            &[test_record_with_attachment("1", &[0x18, 0x64])],
+            100,
+            CollectionMetadata::default(),
+        )?;
+
+        // A collection named `remote-settings` used to be stored in the file that's now shared,
+        // so it shouldn't be deleted.
+        let mut storage_2 = Storage::for_collection(db, "remote-settings".into())
+            .with_legacy_path(dir.join("remote-settings.sql"));
+        assert_eq!(storage_2.get_records(collection_url)?, None);
+        assert!(dir.join("remote-settings.sql").exists());
+        assert_eq!(
+            storage.get_record_ids(collection_url)?,
+            vec!["1".to_string()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_close_releases_database() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let db = Arc::new(StorageDb::new(dir.join("remote-settings.sql")));
+        // SQLite deletes the write-ahead log when the last connection to the database closes.
+        let wal_path = dir.join("remote-settings.sql-wal");
+        let collection_url = "https://example.com/api";
+
+        let mut storage_1 = Storage::for_collection(db.clone(), "collection-1".into());
+        let mut storage_2 = Storage::for_collection(db.clone(), "collection-2".into());
+        for storage in [&mut storage_1, &mut storage_2] {
+            storage.insert_collection_content(
+                collection_url,
+                &[],
+                100,
+                CollectionMetadata::default(),
+            )?;
+        }
+        assert!(wal_path.exists());
+
+        storage_1.close();
+        assert!(wal_path.exists());
+        assert!(storage_2.get_records(collection_url)?.is_some());
+
+        // Closing the storage twice shouldn't close the database for the other collection.
+        storage_1.close();
+        assert!(storage_2.get_records(collection_url)?.is_some());
 
-        // Verify that only the attachment for the second collection remains
-        let fetched_attachment_1 =
-            storage.get_attachment(collection_url_1, attachment_metadata_1)?;
-        assert!(fetched_attachment_1.is_none());
+        drop(storage_2);
+        assert!(!wal_path.exists());
 
-        let fetched_attachment_2 =
-            storage.get_attachment(collection_url_2, attachment_metadata_2)?;
-        assert!(fetched_attachment_2.is_some());
-        let fetched_attachment_2 = fetched_attachment_2.unwrap();
-        assert_eq!(fetched_attachment_2, attachment_2);
+        // The database is reopened if it's used again.
+        let mut storage_3 = Storage::for_collection(db, "collection-1".into());
+        assert!(storage_3.get_records(collection_url)?.is_some());
 
         Ok(())
     }
@@ -572,7 +1284,7 @@ mod tests {
         let metadata = Attachment::default();
 
         // Get attachment that doesn't exist
-        let fetched_attachment = storage.get_attachment(collection_url, metadata)?;
+        let fetched_attachment = storage.get_attachment(metadata)?;
         assert!(fetched_attachment.is_none());
 
         Ok(())
@@ -626,12 +1338,12 @@ mod tests {
             42,
             CollectionMetadata::default(),
         )?;
-        storage.set_attachment(collection_url, &metadata.location, attachment)?;
+        storage.set_attachment(&metadata, attachment)?;
 
         // Verify they are stored
         let fetched_records = storage.get_records(collection_url)?;
         assert!(fetched_records.is_some());
-        let fetched_attachment = storage.get_attachment(collection_url, metadata.clone())?;
+        let fetched_attachment = storage.get_attachment(metadata.clone())?;
         assert!(fetched_attachment.is_some());
 
         // Empty the storage
@@ -640,7 +1352,7 @@ mod tests {
         // Verify they are deleted
         let fetched_records = storage.get_records(collection_url)?;
         assert!(fetched_records.is_none());
-        let fetched_attachment = storage.get_attachment(collection_url, metadata)?;
+        let fetched_attachment = storage.get_attachment(metadata)?;
         assert!(fetched_attachment.is_none());
 
         Ok(())
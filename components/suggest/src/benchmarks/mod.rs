@@ -28,6 +28,7 @@ pub mod client;
 pub mod geoname;
 pub mod ingest;
 pub mod query;
+pub mod typing;
 
 /// Trait for simple benchmarks
 ///
@@ -0,0 +1,81 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::sync::Arc;
+
+use crate::{
+    benchmarks::{new_store, BenchmarkWithInput},
+    SuggestStore, SuggestionProvider, SuggestionQuery,
+};
+
+/// Queries each prefix of a keyword in turn, like a user typing it, either
+/// with `SuggestStore::query` or with a query session.
+pub struct TypingBenchmark {
+    providers: Vec<SuggestionProvider>,
+    keyword: &'static str,
+    use_session: bool,
+}
+
+impl BenchmarkWithInput for TypingBenchmark {
+    type GlobalInput = Arc<SuggestStore>;
+    type IterationInput = Vec<SuggestionQuery>;
+
+    fn global_input(&self) -> Self::GlobalInput {
+        Arc::new(new_store())
+    }
+
+    fn iteration_input(&self) -> Self::IterationInput {
+        self.keyword
+            .char_indices()
+            .map(|(i, c)| SuggestionQuery {
+                providers: self.providers.clone(),
+                keyword: self.keyword[..i + c.len_utf8()].to_string(),
+                ..SuggestionQuery::default()
+            })
+            .collect()
+    }
+
+    fn benchmarked_code(&self, store: &Self::GlobalInput, queries: Self::IterationInput) {
+        if self.use_session {
+            let session = store.clone().new_query_session();
+            for query in queries {
+                session
+                    .query(query)
+                    .unwrap_or_else(|e| panic!("Error querying session: {e}"));
+            }
+        } else {
+            for query in queries {
+                store
+                    .query(query)
+                    .unwrap_or_else(|e| panic!("Error querying store: {e}"));
+            }
+        }
+    }
+}
+
+pub fn all_benchmarks() -> Vec<(&'static str, TypingBenchmark)> {
+    let typed = |keyword, use_session| TypingBenchmark {
+        providers: Vec::from(SuggestionProvider::all()),
+        keyword,
+        use_session,
+    };
+    vec![
+        (
+            "typing-weather-in-new-york",
+            typed("weather in new york", false),
+        ),
+        (
+            "typing-weather-in-new-york-session",
+            typed("weather in new york", true),
+        ),
+        (
+            "typing-firefox-dark-mode",
+            typed("firefox dark mode", false),
+        ),
+        (
+            "typing-firefox-dark-mode-session",
+            typed("firefox dark mode", true),
+        ),
+    ]
+}
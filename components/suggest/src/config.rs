@@ -5,7 +5,7 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::rs::DownloadedGlobalConfig;
+use crate::rs::{DownloadedEngagementConfig, DownloadedGlobalConfig};
 
 /// Global Suggest configuration data.
 #[derive(Clone, Default, Debug, Deserialize, Serialize, PartialEq, Eq, uniffi::Record)]
@@ -21,6 +21,54 @@ impl From<&DownloadedGlobalConfig> for SuggestGlobalConfig {
     }
 }
 
+/// A provider's "show less frequently" and impression caps.
+#[derive(Clone, Default, Debug, Deserialize, Serialize, PartialEq, Eq, uniffi::Record)]
+pub struct SuggestEngagementConfig {
+    /// The maximum number of "show less frequently" clicks for the provider
+    /// and its suggestions, or `None` to use the `show_less_frequently_cap`
+    /// in the global config. A cap of zero or less means there's no cap.
+    pub show_less_frequently_cap: Option<i32>,
+    /// Limits on how often the provider's suggestions can be shown.
+    /// [SuggestStore::query] doesn't return suggestions that have reached any
+    /// of these caps.
+    ///
+    /// [SuggestStore::query]: crate::SuggestStore::query
+    pub impression_caps: Vec<SuggestImpressionCap>,
+}
+
+impl From<&DownloadedEngagementConfig> for SuggestEngagementConfig {
+    fn from(config: &DownloadedEngagementConfig) -> Self {
+        Self {
+            show_less_frequently_cap: config.show_less_frequently_cap,
+            impression_caps: config
+                .impression_caps
+                .iter()
+                .map(|cap| SuggestImpressionCap {
+                    interval_secs: cap.interval_s,
+                    max_count: cap.max_count,
+                    per_suggestion: cap.per_suggestion,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A limit on the number of impressions of a provider's suggestions within a
+/// time window, recorded with [SuggestStore::record_impression].
+///
+/// [SuggestStore::record_impression]: crate::SuggestStore::record_impression
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, uniffi::Record)]
+pub struct SuggestImpressionCap {
+    /// The length of the window, in seconds.
+    pub interval_secs: u64,
+    /// The number of impressions allowed in the window.
+    pub max_count: u32,
+    /// Whether the cap applies to each suggestion separately, rather than to
+    /// all of the provider's suggestions together. Per-suggestion caps only
+    /// apply to suggestions with dismissal keys.
+    pub per_suggestion: bool,
+}
+
 /// Per-provider configuration data.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, uniffi::Enum)]
 pub enum SuggestProviderConfig {
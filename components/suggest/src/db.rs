@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.
  */
 
-use std::{cell::OnceCell, path::Path, sync::Arc};
+use std::{cell::OnceCell, collections::HashMap, path::Path, sync::Arc};
 
 use interrupt_support::{SqlInterruptHandle, SqlInterruptScope};
 use parking_lot::{Mutex, MutexGuard};
@@ -15,9 +15,10 @@ use rusqlite::{
 use sql_support::{open_database, repeat_sql_vars, ConnExt};
 
 use crate::{
-    config::{SuggestGlobalConfig, SuggestProviderConfig},
+    config::{SuggestEngagementConfig, SuggestGlobalConfig, SuggestProviderConfig},
     error::RusqliteResultExt,
     fakespot,
+    fuzzy::FuzzyKeywordInsertStatement,
     geoname::GeonameCache,
     provider::{AmpMatchingStrategy, SuggestionProvider},
     query::{full_keywords_to_fts_content, FtsQuery},
@@ -40,6 +41,11 @@ pub const GLOBAL_CONFIG_META_KEY: &str = "global_config";
 /// `SuggestProviderConfig`, which contains per-provider configuration data. The
 /// full key is this prefix plus the `SuggestionProvider` value as a u8.
 pub const PROVIDER_CONFIG_META_KEY_PREFIX: &str = "provider_config_";
+/// Prefix of metadata keys whose values are JSON strings encoding
+/// `SuggestEngagementConfig`, which contains a provider's "show less
+/// frequently" and impression caps. The full key is this prefix plus the
+/// `SuggestionProvider` value as a u8.
+pub const ENGAGEMENT_CONFIG_META_KEY_PREFIX: &str = "engagement_config_";
 
 // Default value when Suggestion does not have a value for score
 pub const DEFAULT_SUGGESTION_SCORE: f64 = 0.2;
@@ -68,6 +74,10 @@ pub(crate) struct SuggestDb {
     /// the database will be told to stop and release the `conn` lock as soon
     /// as possible.
     pub interrupt_handle: Arc<SqlInterruptHandle>,
+
+    /// The ID of the query session whose read is running on `conn`, if any.
+    /// See [Self::interrupt_session_read].
+    session_read: Mutex<Option<u64>>,
 }
 
 impl SuggestDb {
@@ -94,6 +104,7 @@ impl SuggestDb {
         Self {
             conn: Mutex::new(conn),
             interrupt_handle,
+            session_read: Mutex::new(None),
         }
     }
 
@@ -105,6 +116,55 @@ impl SuggestDb {
         op(&dao)
     }
 
+    /// Accesses the Suggest database for a query session's read, with caches
+    /// from the session's earlier reads.
+    ///
+    /// The DAO starts with `caches`, and hands them back when `op` returns, so
+    /// that the next read doesn't need to rebuild them. Callers are
+    /// responsible for resetting the caches when the data changes.
+    ///
+    /// The read fails with `Interrupted` if `interrupted` returns true when it
+    /// starts, or if [Self::interrupt_session_read] is called with the same
+    /// `session_id` while it's running.
+    pub fn session_read<T>(
+        &self,
+        session_id: u64,
+        interrupted: impl Fn() -> bool,
+        caches: &mut DaoCaches,
+        op: impl FnOnce(&SuggestDao) -> Result<T>,
+    ) -> Result<T> {
+        let conn = self.conn.lock();
+        let scope = self.interrupt_handle.begin_interrupt_scope()?;
+        {
+            // Check for an interrupt while holding the lock, so that
+            // `interrupt_session_read` either sees this read, or the read
+            // sees the interrupt.
+            let mut session_read = self.session_read.lock();
+            if interrupted() {
+                return Err(interrupt_support::Interrupted.into());
+            }
+            *session_read = Some(session_id);
+        }
+        let mut dao = SuggestDao::new(&conn, &scope);
+        dao.weather_cache = std::mem::take(&mut caches.weather);
+        dao.geoname_cache = std::mem::take(&mut caches.geoname);
+        let result = op(&dao);
+        caches.weather = dao.weather_cache;
+        caches.geoname = dao.geoname_cache;
+        *self.session_read.lock() = None;
+        result
+    }
+
+    /// Interrupts a query session's read, if it's running. Other reads on the
+    /// connection aren't interrupted, because they can only run once the
+    /// session's read releases the `conn` lock.
+    pub fn interrupt_session_read(&self, session_id: u64) {
+        let session_read = self.session_read.lock();
+        if *session_read == Some(session_id) {
+            self.interrupt_handle.interrupt();
+        }
+    }
+
     /// Accesses the Suggest database in a transaction for reading and writing.
     pub fn write<T>(&self, op: impl FnOnce(&mut SuggestDao) -> Result<T>) -> Result<T> {
         let mut conn = self.conn.lock();
@@ -160,6 +220,14 @@ impl WriteScope<'_> {
     }
 }
 
+/// Caches that a [SuggestDao] fills from the database as it's used, which can
+/// be kept between reads with [SuggestDb::session_read].
+#[derive(Default)]
+pub(crate) struct DaoCaches {
+    weather: OnceCell<WeatherCache>,
+    geoname: OnceCell<GeonameCache>,
+}
+
 /// A data access object (DAO) that wraps a connection to the Suggest database
 /// with methods for reading and writing suggestions, icons, and metadata.
 ///
@@ -225,6 +293,12 @@ impl<'a> SuggestDao<'a> {
         let mut insert_stmt = self.conn.prepare_cached(
             "INSERT OR REPLACE INTO ingested_records(id, collection, type, last_modified) VALUES(?, ?, ?, ?)",
         )?;
+        let mut insert_targeting_stmt = self.conn.prepare_cached(
+            "INSERT OR REPLACE INTO record_targeting(record_id, collection, targeting) VALUES(?, ?, ?)",
+        )?;
+        let mut delete_targeting_stmt = self.conn.prepare_cached(
+            "DELETE FROM record_targeting WHERE record_id = ? AND collection = ?",
+        )?;
         for record in new_records.iter().chain(updated_records) {
             insert_stmt.execute((
                 record.id.as_str(),
@@ -232,10 +306,29 @@ impl<'a> SuggestDao<'a> {
                 record.record_type().as_str(),
                 record.last_modified,
             ))?;
+            if record.targeting.is_empty() {
+                delete_targeting_stmt.execute((record.id.as_str(), collection))?;
+            } else {
+                insert_targeting_stmt.execute((
+                    record.id.as_str(),
+                    collection,
+                    serde_json::to_string(&record.targeting)?,
+                ))?;
+            }
         }
         Ok(())
     }
 
+    /// Returns the number of rows inserted, updated, or deleted on this
+    /// connection since it was opened.
+    pub fn total_changes(&self) -> Result<u64> {
+        Ok(self
+            .conn
+            .conn_ext_query_one::<i64>("SELECT total_changes()")?
+            .try_into()
+            .unwrap_or_default())
+    }
+
     /// Update the DB so that we re-ingest all records on the next ingestion.
     ///
     /// We hack this by setting the last_modified time to 1 so that the next time around we always
@@ -253,6 +346,24 @@ impl<'a> SuggestDao<'a> {
             .conn_ext_query_one::<bool>("SELECT NOT EXISTS (SELECT 1 FROM suggestions)")?)
     }
 
+    /// Fetches a provider's suggestions that match the given query
+    pub fn fetch_suggestions(
+        &self,
+        query: &SuggestionQuery,
+        provider: SuggestionProvider,
+    ) -> Result<Vec<Suggestion>> {
+        match provider {
+            SuggestionProvider::Amp => self.fetch_amp_suggestions(query),
+            SuggestionProvider::Wikipedia => self.fetch_wikipedia_suggestions(query),
+            SuggestionProvider::Amo => self.fetch_amo_suggestions(query),
+            SuggestionProvider::Yelp => self.fetch_yelp_suggestions(query),
+            SuggestionProvider::Mdn => self.fetch_mdn_suggestions(query),
+            SuggestionProvider::Weather => self.fetch_weather_suggestions(query),
+            SuggestionProvider::Fakespot => self.fetch_fakespot_suggestions(query),
+            SuggestionProvider::Dynamic => self.fetch_dynamic_suggestions(query),
+        }
+    }
+
     /// Fetches Suggestions of type Amp provider that match the given query
     pub fn fetch_amp_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
         let strategy = query
@@ -260,9 +371,13 @@ impl<'a> SuggestDao<'a> {
             .as_ref()
             .and_then(|c| c.amp_alternative_matching.as_ref());
         match strategy {
-            None => self.fetch_amp_suggestions_using_keywords(query, true),
+            None => self.fetch_with_fuzzy_fallback(query, SuggestionProvider::Amp, |query| {
+                self.fetch_amp_suggestions_using_keywords(query, true)
+            }),
             Some(AmpMatchingStrategy::NoKeywordExpansion) => {
-                self.fetch_amp_suggestions_using_keywords(query, false)
+                self.fetch_with_fuzzy_fallback(query, SuggestionProvider::Amp, |query| {
+                    self.fetch_amp_suggestions_using_keywords(query, false)
+                })
             }
             Some(AmpMatchingStrategy::FtsAgainstFullKeywords) => {
                 self.fetch_amp_suggestions_using_fts(query, "full_keywords")
@@ -369,6 +484,8 @@ impl<'a> SuggestDao<'a> {
                             raw_click_url,
                             score,
                             fts_match_info: None,
+                            fuzzy_match_info: None,
+                            personalization_info: None,
                         })
                     },
                 )
@@ -464,6 +581,8 @@ impl<'a> SuggestDao<'a> {
                             raw_click_url,
                             score,
                             fts_match_info: Some(match_info),
+                            fuzzy_match_info: None,
+                            personalization_info: None,
                         })
                     },
                 )
@@ -541,6 +660,15 @@ impl<'a> SuggestDao<'a> {
 
     /// Fetches Suggestions of type Wikipedia provider that match the given query
     pub fn fetch_wikipedia_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
+        self.fetch_with_fuzzy_fallback(query, SuggestionProvider::Wikipedia, |query| {
+            self.fetch_wikipedia_suggestions_using_keywords(query)
+        })
+    }
+
+    fn fetch_wikipedia_suggestions_using_keywords(
+        &self,
+        query: &SuggestionQuery,
+    ) -> Result<Vec<Suggestion>> {
         let keyword_lowercased = &query.keyword.to_lowercase();
         let suggestions = self.conn.query_rows_and_then_cached(
             r#"
@@ -605,6 +733,7 @@ impl<'a> SuggestDao<'a> {
                     full_keyword: full_keyword(keyword_lowercased, &keywords),
                     icon,
                     icon_mimetype,
+                    fuzzy_match_info: None,
                 })
             },
         )?;
@@ -659,6 +788,59 @@ impl<'a> SuggestDao<'a> {
         )
     }
 
+    /// Fetches the keyword suffixes and ranks that the provider's suggestions
+    /// can match for the query, by suggestion URL.
+    ///
+    /// Query sessions use these to narrow a keyword's candidates as the user
+    /// types more of it, without querying the database again. See
+    /// [Self::map_prefix_keywords] for how they're matched.
+    pub fn fetch_prefix_keyword_suffixes(
+        &self,
+        query: &SuggestionQuery,
+        provider: SuggestionProvider,
+    ) -> Result<HashMap<String, Vec<(String, i64)>>> {
+        let keyword_lowercased = &query.keyword.to_lowercase();
+        let (keyword_prefix, keyword_suffix) = split_keyword(keyword_lowercased);
+        let rows = self.conn.query_rows_and_then_cached(
+            r#"
+                SELECT
+                  s.url,
+                  k.keyword_suffix,
+                  k.rank
+                FROM
+                  suggestions s
+                JOIN
+                  prefix_keywords k
+                  ON k.suggestion_id = s.id
+                WHERE
+                  k.keyword_prefix = :keyword_prefix
+                  AND (k.keyword_suffix BETWEEN :keyword_suffix AND :keyword_suffix || x'FFFF')
+                  AND s.provider = :provider
+                  AND NOT EXISTS (SELECT 1 FROM dismissed_suggestions WHERE url=s.url)
+                "#,
+            named_params! {
+                ":keyword_prefix": keyword_prefix,
+                ":keyword_suffix": keyword_suffix,
+                ":provider": provider,
+            },
+            |row| -> Result<(String, String, i64)> {
+                Ok((
+                    row.get("url")?,
+                    row.get("keyword_suffix")?,
+                    row.get("rank")?,
+                ))
+            },
+        )?;
+        let mut suffixes = HashMap::<String, Vec<(String, i64)>>::new();
+        for (url, keyword_suffix, rank) in rows {
+            suffixes
+                .entry(url)
+                .or_default()
+                .push((keyword_suffix, rank));
+        }
+        Ok(suffixes)
+    }
+
     /// Fetches Suggestions of type Amo provider that match the given query
     pub fn fetch_amo_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
         let suggestions = self
@@ -716,6 +898,15 @@ impl<'a> SuggestDao<'a> {
 
     /// Fetches suggestions for MDN
     pub fn fetch_mdn_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
+        self.fetch_with_fuzzy_fallback(query, SuggestionProvider::Mdn, |query| {
+            self.fetch_mdn_suggestions_using_keywords(query)
+        })
+    }
+
+    fn fetch_mdn_suggestions_using_keywords(
+        &self,
+        query: &SuggestionQuery,
+    ) -> Result<Vec<Suggestion>> {
         let suggestions = self
             .map_prefix_keywords(
                 query,
@@ -748,6 +939,7 @@ impl<'a> SuggestDao<'a> {
                                         url: raw_url,
                                         description: row.get("description")?,
                                         score,
+                                        fuzzy_match_info: None,
                                     })
                                 },
                             )
@@ -1002,6 +1194,7 @@ impl<'a> SuggestDao<'a> {
         record_id: &SuggestRecordId,
         suggestions: &[DownloadedAmpSuggestion],
         enable_fts: bool,
+        enable_fuzzy: bool,
     ) -> Result<()> {
         // Prepare statements outside of the loop.  This results in a large performance
         // improvement on a fresh ingest, since there are so many rows.
@@ -1009,6 +1202,7 @@ impl<'a> SuggestDao<'a> {
         let mut amp_insert = AmpInsertStatement::new(self.conn)?;
         let mut keyword_insert = KeywordInsertStatement::new(self.conn)?;
         let mut fts_insert = AmpFtsInsertStatement::new(self.conn)?;
+        let mut fuzzy_insert = FuzzyKeywordInsertStatement::new(self.conn)?;
         let mut category_insert = CategoryInsertStatement::new(self.conn)?;
         for suggestion in suggestions {
             self.scope.err_if_interrupted()?;
@@ -1040,6 +1234,9 @@ impl<'a> SuggestDao<'a> {
                     full_keyword_id,
                     keyword.rank,
                 )?;
+                if enable_fuzzy {
+                    fuzzy_insert.execute(record_id, SuggestionProvider::Amp, keyword.keyword)?;
+                }
             }
 
             if let Some(categories) = &suggestion.serp_categories {
@@ -1056,12 +1253,14 @@ impl<'a> SuggestDao<'a> {
         &mut self,
         record_id: &SuggestRecordId,
         suggestions: &[DownloadedWikipediaSuggestion],
+        enable_fuzzy: bool,
     ) -> Result<()> {
         // Prepare statements outside of the loop.  This results in a large performance
         // improvement on a fresh ingest, since there are so many rows.
         let mut suggestion_insert = SuggestionInsertStatement::new(self.conn)?;
         let mut wiki_insert = WikipediaInsertStatement::new(self.conn)?;
         let mut keyword_insert = KeywordInsertStatement::new(self.conn)?;
+        let mut fuzzy_insert = FuzzyKeywordInsertStatement::new(self.conn)?;
         for suggestion in suggestions {
             self.scope.err_if_interrupted()?;
             let suggestion_id = suggestion_insert.execute(
@@ -1075,6 +1274,13 @@ impl<'a> SuggestDao<'a> {
             for keyword in suggestion.keywords() {
                 // Don't update `full_keywords`, see bug 1876217.
                 keyword_insert.execute(suggestion_id, keyword.keyword, None, keyword.rank)?;
+                if enable_fuzzy {
+                    fuzzy_insert.execute(
+                        record_id,
+                        SuggestionProvider::Wikipedia,
+                        keyword.keyword,
+                    )?;
+                }
             }
         }
         Ok(())
@@ -1086,10 +1292,12 @@ impl<'a> SuggestDao<'a> {
         &mut self,
         record_id: &SuggestRecordId,
         suggestions: &[DownloadedMdnSuggestion],
+        enable_fuzzy: bool,
     ) -> Result<()> {
         let mut suggestion_insert = SuggestionInsertStatement::new(self.conn)?;
         let mut mdn_insert = MdnInsertStatement::new(self.conn)?;
         let mut prefix_keyword_insert = PrefixKeywordInsertStatement::new(self.conn)?;
+        let mut fuzzy_insert = FuzzyKeywordInsertStatement::new(self.conn)?;
         for suggestion in suggestions {
             self.scope.err_if_interrupted()?;
             let suggestion_id = suggestion_insert.execute(
@@ -1109,6 +1317,9 @@ impl<'a> SuggestDao<'a> {
                     keyword_suffix,
                     index,
                 )?;
+                if enable_fuzzy {
+                    fuzzy_insert.execute(record_id, SuggestionProvider::Mdn, keyword)?;
+                }
             }
         }
         Ok(())
@@ -1290,6 +1501,16 @@ impl<'a> SuggestDao<'a> {
             named_params! { ":record_id": record_id.as_str() },
         )?;
         self.scope.err_if_interrupted()?;
+        self.conn.execute_cached(
+            "DELETE FROM fuzzy_keywords WHERE record_id = :record_id",
+            named_params! { ":record_id": record_id.as_str() },
+        )?;
+        self.scope.err_if_interrupted()?;
+        self.conn.execute_cached(
+            "DELETE FROM fuzzy_records WHERE record_id = :record_id",
+            named_params! { ":record_id": record_id.as_str() },
+        )?;
+        self.scope.err_if_interrupted()?;
         self.conn.execute_cached(
             "DELETE FROM keywords_metrics WHERE record_id = :record_id",
             named_params! { ":record_id": record_id.as_str() },
@@ -1303,6 +1524,14 @@ impl<'a> SuggestDao<'a> {
             named_params! { ":record_id": record_id.as_str() },
         )?;
         self.scope.err_if_interrupted()?;
+        self.conn.execute_cached(
+            "
+            DELETE FROM amp_fts
+            WHERE rowid IN (SELECT id from suggestions WHERE record_id = :record_id)
+            ",
+            named_params! { ":record_id": record_id.as_str() },
+        )?;
+        self.scope.err_if_interrupted()?;
         self.conn.execute_cached(
             "DELETE FROM suggestions WHERE record_id = :record_id",
             named_params! { ":record_id": record_id.as_str() },
@@ -1420,6 +1649,39 @@ impl<'a> SuggestDao<'a> {
             .map_or_else(|| Ok(None), |json| Ok(serde_json::from_str(&json)?))
     }
 
+    /// Replaces the stored "show less frequently" and impression caps for
+    /// every provider. Providers that aren't in `configs` get the default
+    /// config.
+    pub fn put_engagement_configs(
+        &mut self,
+        configs: &[(SuggestionProvider, SuggestEngagementConfig)],
+    ) -> Result<()> {
+        self.conn.execute_cached(
+            "DELETE FROM meta WHERE key LIKE :prefix || '%'",
+            named_params! { ":prefix": ENGAGEMENT_CONFIG_META_KEY_PREFIX },
+        )?;
+        for (provider, config) in configs {
+            self.put_meta(
+                &engagement_config_meta_key(*provider),
+                serde_json::to_string(config)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Gets the stored "show less frequently" and impression caps for a
+    /// provider, or a default config if none is stored.
+    pub fn get_engagement_config(
+        &self,
+        provider: SuggestionProvider,
+    ) -> Result<SuggestEngagementConfig> {
+        self.get_meta::<String>(&engagement_config_meta_key(provider))?
+            .map_or_else(
+                || Ok(SuggestEngagementConfig::default()),
+                |json| Ok(serde_json::from_str(&json)?),
+            )
+    }
+
     /// Gets keywords metrics for a record type.
     pub fn get_keywords_metrics(&self, record_type: SuggestRecordType) -> Result<KeywordsMetrics> {
         let data = self.conn.try_query_row(
@@ -1998,10 +2260,55 @@ fn provider_config_meta_key(provider: SuggestionProvider) -> String {
     format!("{}{}", PROVIDER_CONFIG_META_KEY_PREFIX, provider as u8)
 }
 
+fn engagement_config_meta_key(provider: SuggestionProvider) -> String {
+    format!("{}{}", ENGAGEMENT_CONFIG_META_KEY_PREFIX, provider as u8)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{store::tests::TestStore, testing::*, SuggestIngestionConstraints};
+    use crate::{error::Error, store::tests::TestStore, testing::*, SuggestIngestionConstraints};
+
+    /// Tests that interrupting a query session's read doesn't interrupt
+    /// other reads.
+    #[test]
+    fn interrupt_session_read() -> anyhow::Result<()> {
+        let db = SuggestDb::with_connection(Connection::open_in_memory()?);
+        let mut caches = DaoCaches::default();
+
+        // Interrupting another session doesn't affect this one.
+        db.session_read(
+            1,
+            || false,
+            &mut caches,
+            |dao| {
+                db.interrupt_session_read(2);
+                Ok(dao.scope.err_if_interrupted()?)
+            },
+        )?;
+
+        // Interrupting this session does.
+        let result = db.session_read(
+            1,
+            || false,
+            &mut caches,
+            |dao| {
+                db.interrupt_session_read(1);
+                Ok(dao.scope.err_if_interrupted()?)
+            },
+        );
+        assert!(matches!(result, Err(Error::Interrupted(_))));
+
+        // A session that's interrupted before its read starts doesn't run it.
+        let result = db.session_read(1, || true, &mut caches, |_| Ok(()));
+        assert!(matches!(result, Err(Error::Interrupted(_))));
+
+        // Reads that start after an interrupt aren't affected.
+        db.interrupt_session_read(1);
+        db.read(|dao| Ok(dao.scope.err_if_interrupted()?))?;
+
+        Ok(())
+    }
 
     #[test]
     fn keywords_metrics_updater() -> anyhow::Result<()> {
@@ -0,0 +1,304 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! "Show less frequently" counters and impression caps.
+//!
+//! Like dismissals, these are recorded by the consumer and stored alongside
+//! the suggestions, but they're not cleared when the suggestions are.  Queries
+//! honor them automatically:
+//!
+//! - Each "show less frequently" click for a keyword raises the minimum length
+//!   of the keywords that can match the suggestion (or all of the provider's
+//!   suggestions) to one more than that keyword's length.  Clicks beyond the
+//!   provider's `show_less_frequently_cap` aren't recorded.
+//! - Suggestions that have reached any of the impression caps in their
+//!   provider's `SuggestEngagementConfig` aren't returned until enough time
+//!   has passed.
+
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::named_params;
+use sql_support::ConnExt;
+
+use crate::{
+    config::SuggestEngagementConfig, db::SuggestDao, provider::SuggestionProvider,
+    suggestion::Suggestion, Result,
+};
+
+/// Impressions are kept for at least this long, in seconds, even if no caps
+/// need them, so that caps added in a later config apply right away.
+const MIN_IMPRESSION_RETENTION_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Returns the current time, in seconds since the epoch.
+pub(crate) fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Why a suggestion was removed by [`EngagementFilters`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EngagementFilter {
+    /// The keyword is shorter than the minimum length after "show less
+    /// frequently" clicks.
+    ShowLessFrequently { min_keyword_length: usize },
+    /// The suggestion has reached an impression cap.
+    ImpressionCap,
+}
+
+/// The "show less frequently" clicks, impressions, and caps for the providers
+/// of a query's suggestions, loaded together so that the suggestions can be
+/// filtered without a query for each one.
+#[derive(Default)]
+pub(crate) struct EngagementFilters {
+    configs: HashMap<SuggestionProvider, SuggestEngagementConfig>,
+    /// Minimum keyword lengths by provider and suggestion key.  The empty key
+    /// is for the whole provider.
+    min_keyword_lengths: HashMap<SuggestionProvider, HashMap<String, usize>>,
+    /// The keys and timestamps of the impressions that count toward a cap,
+    /// by provider.
+    impressions: HashMap<SuggestionProvider, Vec<(String, i64)>>,
+    now: i64,
+}
+
+impl EngagementFilters {
+    /// Returns why a suggestion should be removed for `keyword`, or `None` if
+    /// it should be kept.
+    pub fn filter(&self, keyword: &str, suggestion: &Suggestion) -> Option<EngagementFilter> {
+        let provider = suggestion.provider()?;
+        let suggestion_key = suggestion.dismissal_key();
+        let min_keyword_length = self
+            .min_keyword_lengths
+            .get(&provider)
+            .and_then(|lengths| {
+                [Some(""), suggestion_key]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|key| lengths.get(key).copied())
+                    .max()
+            })
+            .unwrap_or(0);
+        if keyword.trim().chars().count() < min_keyword_length {
+            return Some(EngagementFilter::ShowLessFrequently { min_keyword_length });
+        }
+        if self.is_impression_capped(provider, suggestion_key) {
+            return Some(EngagementFilter::ImpressionCap);
+        }
+        None
+    }
+
+    fn is_impression_capped(
+        &self,
+        provider: SuggestionProvider,
+        suggestion_key: Option<&str>,
+    ) -> bool {
+        let Some(config) = self.configs.get(&provider) else {
+            return false;
+        };
+        let impressions = self
+            .impressions
+            .get(&provider)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        config.impression_caps.iter().any(|cap| {
+            let suggestion_key = match (cap.per_suggestion, suggestion_key) {
+                (false, _) => None,
+                (true, Some(key)) => Some(key),
+                (true, None) => return false,
+            };
+            let since = self.now.saturating_sub(interval_secs(cap.interval_secs));
+            let count = impressions
+                .iter()
+                .filter(|(key, timestamp)| {
+                    *timestamp > since && suggestion_key.map_or(true, |k| k == key)
+                })
+                .count();
+            count >= usize::try_from(cap.max_count).unwrap_or(usize::MAX)
+        })
+    }
+}
+
+fn interval_secs(interval_secs: u64) -> i64 {
+    i64::try_from(interval_secs).unwrap_or(i64::MAX)
+}
+
+impl SuggestDao<'_> {
+    /// Records a "show less frequently" click, unless the provider has
+    /// already reached its `show_less_frequently_cap`. An empty
+    /// `suggestion_key` records it for the whole provider.
+    pub fn insert_show_less_frequently(
+        &mut self,
+        provider: SuggestionProvider,
+        suggestion_key: &str,
+        keyword: &str,
+    ) -> Result<()> {
+        if self.is_show_less_frequently_capped(provider)? {
+            return Ok(());
+        }
+        self.conn.execute_cached(
+            "INSERT INTO show_less_frequently(
+                 provider,
+                 suggestion_key,
+                 count,
+                 min_keyword_length
+             )
+             VALUES(:provider, :suggestion_key, 1, :min_keyword_length)
+             ON CONFLICT(provider, suggestion_key) DO UPDATE SET
+                 count = count + 1,
+                 min_keyword_length = MAX(min_keyword_length, excluded.min_keyword_length)",
+            named_params! {
+                ":provider": provider,
+                ":suggestion_key": suggestion_key,
+                ":min_keyword_length": keyword.trim().chars().count() + 1,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Returns the number of "show less frequently" clicks for a provider,
+    /// including the clicks for its individual suggestions.
+    pub fn show_less_frequently_count(&self, provider: SuggestionProvider) -> Result<u32> {
+        Ok(self.conn.query_row_and_then_cachable(
+            "SELECT IFNULL(SUM(count), 0) FROM show_less_frequently WHERE provider = :provider",
+            named_params! {
+                ":provider": provider,
+            },
+            |row| row.get(0),
+            true,
+        )?)
+    }
+
+    /// Returns whether a provider has reached its `show_less_frequently_cap`,
+    /// or the global one if its engagement config doesn't have one.
+    pub fn is_show_less_frequently_capped(&self, provider: SuggestionProvider) -> Result<bool> {
+        let cap = match self
+            .get_engagement_config(provider)?
+            .show_less_frequently_cap
+        {
+            Some(cap) => cap,
+            None => self.get_global_config()?.show_less_frequently_cap,
+        };
+        Ok(cap > 0 && i64::from(self.show_less_frequently_count(provider)?) >= i64::from(cap))
+    }
+
+    pub fn clear_show_less_frequently(&mut self) -> Result<()> {
+        self.conn.execute("DELETE FROM show_less_frequently", ())?;
+        Ok(())
+    }
+
+    /// Records an impression at time `now`, and removes the provider's
+    /// impressions that are too old to count toward any of its caps.
+    pub fn insert_impression(
+        &mut self,
+        provider: SuggestionProvider,
+        suggestion_key: &str,
+        now: i64,
+    ) -> Result<()> {
+        let retention_secs = self
+            .get_engagement_config(provider)?
+            .impression_caps
+            .iter()
+            .map(|cap| interval_secs(cap.interval_secs))
+            .fold(MIN_IMPRESSION_RETENTION_SECS, i64::max);
+        self.conn.execute_cached(
+            "DELETE FROM impressions WHERE provider = :provider AND timestamp < :cutoff",
+            named_params! {
+                ":provider": provider,
+                ":cutoff": now.saturating_sub(retention_secs),
+            },
+        )?;
+        self.conn.execute_cached(
+            "INSERT INTO impressions(provider, suggestion_key, timestamp)
+             VALUES(:provider, :suggestion_key, :timestamp)",
+            named_params! {
+                ":provider": provider,
+                ":suggestion_key": suggestion_key,
+                ":timestamp": now,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_impressions(&mut self) -> Result<()> {
+        self.conn.execute("DELETE FROM impressions", ())?;
+        Ok(())
+    }
+
+    /// Loads the [`EngagementFilters`] for suggestions from `providers` as of
+    /// `now`.
+    pub(crate) fn engagement_filters(
+        &self,
+        providers: impl IntoIterator<Item = SuggestionProvider>,
+        now: i64,
+    ) -> Result<EngagementFilters> {
+        let providers = providers.into_iter().collect::<HashSet<_>>();
+        if providers.is_empty() {
+            return Ok(EngagementFilters::default());
+        }
+        let mut filters = EngagementFilters {
+            now,
+            ..EngagementFilters::default()
+        };
+        for &provider in &providers {
+            filters
+                .configs
+                .insert(provider, self.get_engagement_config(provider)?);
+        }
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT provider, suggestion_key, min_keyword_length FROM show_less_frequently",
+        )?;
+        let rows = stmt.query_and_then((), |row| -> Result<_> {
+            Ok((
+                row.get::<_, SuggestionProvider>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, usize>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (provider, suggestion_key, min_keyword_length) = row?;
+            if providers.contains(&provider) {
+                filters
+                    .min_keyword_lengths
+                    .entry(provider)
+                    .or_default()
+                    .insert(suggestion_key, min_keyword_length);
+            }
+        }
+
+        // Only the impressions within the longest window count toward a cap.
+        let Some(longest_interval) = filters
+            .configs
+            .values()
+            .flat_map(|config| &config.impression_caps)
+            .map(|cap| interval_secs(cap.interval_secs))
+            .max()
+        else {
+            return Ok(filters);
+        };
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT provider, suggestion_key, timestamp FROM impressions WHERE timestamp > :since",
+        )?;
+        let rows = stmt.query_and_then(
+            named_params! { ":since": now.saturating_sub(longest_interval) },
+            |row| -> Result<_> {
+                Ok((
+                    row.get::<_, SuggestionProvider>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            },
+        )?;
+        for row in rows {
+            let (provider, suggestion_key, timestamp) = row?;
+            if providers.contains(&provider) {
+                filters
+                    .impressions
+                    .entry(provider)
+                    .or_default()
+                    .push((suggestion_key, timestamp));
+            }
+        }
+        Ok(filters)
+    }
+}
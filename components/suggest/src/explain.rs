@@ -0,0 +1,436 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Explaining why a query did or didn't return a suggestion.
+//!
+//! [crate::SuggestStore::explain_query] runs a query the same way as
+//! [crate::SuggestStore::query], but keeps track of the candidates that each
+//! provider considered along the way, and why each one was returned or
+//! dropped.  It's meant for debugging tools like `suggest-cli`, not for
+//! showing suggestions to the user, so it's slower than a regular query.
+//!
+//! The explanation is a side output of the query: each provider's
+//! suggestions are fetched the same way, then filtered, personalized, ranked,
+//! and truncated by the same [SuggestDao::rank_suggestions] step as
+//! [crate::SuggestStore::query], which reports what happened to each one.
+//!
+//! Providers that match keywords in the `keywords` or `prefix_keywords`
+//! tables also report the candidates that the query filtered out in SQL,
+//! like dismissed suggestions.  For the other providers, the candidates are
+//! the suggestions that the provider returned, and for weather, a keyword
+//! prefix that's shorter than the provider's `min_keyword_length`.
+
+use std::collections::HashSet;
+
+use rusqlite::named_params;
+use sql_support::ConnExt;
+
+use crate::{
+    db::SuggestDao,
+    engagement::EngagementFilter,
+    provider::{AmpMatchingStrategy, SuggestionProvider},
+    util::split_keyword,
+    Result, Suggestion, SuggestionQuery,
+};
+
+/// How a provider matched the query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum SuggestMatchStrategy {
+    /// The query matched a keyword exactly.
+    Exact,
+    /// Nothing matched the query exactly, so the provider fell back to
+    /// keywords one edit away.
+    Fuzzy,
+    /// The query matched the beginning of a keyword.
+    Prefix,
+    /// The query was matched with full-text search.
+    Fts,
+    /// The query was split into keywords and a location, and the location
+    /// was matched against geonames.
+    Geoname,
+}
+
+/// What happened to a candidate suggestion.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum SuggestCandidateOutcome {
+    /// The suggestion was returned at this position in the results.
+    Returned { position: u32 },
+    /// The user dismissed the suggestion.
+    Dismissed,
+    /// A keyword matched, but the provider's matching rules or the query's
+    /// provider constraints ruled the suggestion out.
+    NotMatched,
+    /// The query is shorter than the minimum keyword length after "show less
+    /// frequently" clicks.
+    ShowLessFrequently { min_keyword_length: u32 },
+    /// The query is shorter than the provider's minimum keyword length, like
+    /// the weather provider's `min_keyword_length`.
+    BelowThreshold { min_keyword_length: u32 },
+    /// The suggestion reached an impression cap.
+    ImpressionCapped,
+    /// The suggestion was ranked below the query's limit.
+    OverLimit,
+}
+
+/// The result of [crate::SuggestStore::explain_query].
+#[derive(Debug, uniffi::Record)]
+pub struct SuggestQueryExplanation {
+    /// The suggestions that the query returns.
+    pub suggestions: Vec<Suggestion>,
+    /// How each requested provider handled the query, in the order they
+    /// were requested.
+    pub providers: Vec<SuggestProviderExplanation>,
+}
+
+/// How a provider handled a query.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct SuggestProviderExplanation {
+    pub provider: SuggestionProvider,
+    pub strategy: SuggestMatchStrategy,
+    /// The keywords the provider looked up. For fuzzy matches, these are the
+    /// corrected keywords.
+    pub keywords: Vec<String>,
+    /// The Remote Settings records that the candidates came from.
+    pub record_ids: Vec<String>,
+    pub candidates: Vec<SuggestCandidateExplanation>,
+}
+
+/// A suggestion that a provider considered for a query.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct SuggestCandidateExplanation {
+    pub title: String,
+    pub url: Option<String>,
+    /// The keyword that matched the candidate, if known.
+    pub keyword: Option<String>,
+    /// The Remote Settings record that the candidate came from, if known.
+    pub record_id: Option<String>,
+    /// The candidate's score, including any personalization.
+    pub score: f64,
+    pub outcome: SuggestCandidateOutcome,
+}
+
+impl From<EngagementFilter> for SuggestCandidateOutcome {
+    fn from(filter: EngagementFilter) -> Self {
+        match filter {
+            EngagementFilter::ShowLessFrequently { min_keyword_length } => {
+                Self::ShowLessFrequently {
+                    min_keyword_length: u32::try_from(min_keyword_length).unwrap_or(u32::MAX),
+                }
+            }
+            EngagementFilter::ImpressionCap => Self::ImpressionCapped,
+        }
+    }
+}
+
+/// The keyword table that a provider matches against.
+#[derive(Clone, Copy)]
+enum KeywordLookup {
+    /// `keywords`, for exact matches.
+    Exact,
+    /// `prefix_keywords`, for prefix matches.
+    Prefix,
+}
+
+/// A row from a keyword table that matched the query.
+struct KeywordCandidate {
+    record_id: String,
+    title: String,
+    url: String,
+    score: f64,
+    keyword: String,
+    full_keyword: Option<String>,
+}
+
+/// Returns the strategy that `provider` uses for `query`, before any fuzzy
+/// fallback.
+fn match_strategy(query: &SuggestionQuery, provider: SuggestionProvider) -> SuggestMatchStrategy {
+    match provider {
+        SuggestionProvider::Amp => match query
+            .provider_constraints
+            .as_ref()
+            .and_then(|c| c.amp_alternative_matching.as_ref())
+        {
+            Some(
+                AmpMatchingStrategy::FtsAgainstFullKeywords | AmpMatchingStrategy::FtsAgainstTitle,
+            ) => SuggestMatchStrategy::Fts,
+            Some(AmpMatchingStrategy::NoKeywordExpansion) | None => SuggestMatchStrategy::Exact,
+        },
+        SuggestionProvider::Wikipedia | SuggestionProvider::Dynamic => SuggestMatchStrategy::Exact,
+        SuggestionProvider::Amo | SuggestionProvider::Mdn => SuggestMatchStrategy::Prefix,
+        SuggestionProvider::Fakespot => SuggestMatchStrategy::Fts,
+        SuggestionProvider::Yelp | SuggestionProvider::Weather => SuggestMatchStrategy::Geoname,
+    }
+}
+
+impl SuggestDao<'_> {
+    /// Runs a query like [crate::SuggestStore::query], and explains how each
+    /// provider handled it.  `personalize` re-scores the suggestions that
+    /// survive the engagement filters, before they're ranked.
+    pub(crate) fn explain_query(
+        &self,
+        query: &SuggestionQuery,
+        now: i64,
+        personalize: impl FnOnce(&mut [Suggestion]),
+    ) -> Result<SuggestQueryExplanation> {
+        let mut providers = vec![];
+        // The fetched suggestions, with the indexes of their provider and
+        // candidate in `providers`.
+        let mut fetched = vec![];
+        let mut seen_providers = HashSet::new();
+        for &provider in &query.providers {
+            if !seen_providers.insert(provider) {
+                continue;
+            }
+            let (explanation, suggestions) = self.explain_provider(query, provider)?;
+            fetched.extend(
+                suggestions
+                    .into_iter()
+                    .map(|(candidate_index, suggestion)| {
+                        ((providers.len(), candidate_index), suggestion)
+                    }),
+            );
+            providers.push(explanation);
+        }
+
+        let suggestions = self.rank_suggestions(
+            query,
+            fetched,
+            now,
+            personalize,
+            |(provider_index, candidate_index), suggestion, outcome| {
+                let candidate = &mut providers[provider_index].candidates[candidate_index];
+                candidate.score = suggestion.score();
+                candidate.outcome = outcome;
+            },
+        )?;
+        Ok(SuggestQueryExplanation {
+            suggestions,
+            providers,
+        })
+    }
+
+    /// Explains how one provider handled `query`, and returns its suggestions
+    /// with the indexes of their candidates.  The outcomes of those
+    /// candidates are left for [Self::explain_query] to fill in.
+    fn explain_provider(
+        &self,
+        query: &SuggestionQuery,
+        provider: SuggestionProvider,
+    ) -> Result<(SuggestProviderExplanation, Vec<(usize, Suggestion)>)> {
+        let fetched = self.fetch_suggestions(query, provider)?;
+        let (strategy, keywords) = if fetched.iter().any(|s| s.fuzzy_match_info().is_some()) {
+            let corrections = self
+                .fetch_fuzzy_corrections(provider, &query.keyword)?
+                .into_iter()
+                .map(|correction| correction.keyword)
+                .collect();
+            (SuggestMatchStrategy::Fuzzy, corrections)
+        } else {
+            (
+                match_strategy(query, provider),
+                vec![query.keyword.to_lowercase()],
+            )
+        };
+        let lookup = match (provider, match_strategy(query, provider)) {
+            (
+                SuggestionProvider::Amp | SuggestionProvider::Wikipedia,
+                SuggestMatchStrategy::Exact,
+            ) => Some(KeywordLookup::Exact),
+            (SuggestionProvider::Amo | SuggestionProvider::Mdn, _) => Some(KeywordLookup::Prefix),
+            _ => None,
+        };
+
+        let mut candidates = vec![];
+        if let Some(lookup) = lookup {
+            let mut seen_urls = HashSet::new();
+            for keyword in &keywords {
+                for candidate in self.fetch_keyword_candidates(lookup, provider, keyword)? {
+                    if !seen_urls.insert(candidate.url.clone()) {
+                        continue;
+                    }
+                    let dismissed = self.has_dismissal(&candidate.url)?
+                        || match &candidate.full_keyword {
+                            Some(full_keyword) => self.has_dismissal(full_keyword)?,
+                            None => false,
+                        };
+                    candidates.push(SuggestCandidateExplanation {
+                        title: candidate.title,
+                        url: Some(candidate.url),
+                        keyword: Some(candidate.keyword),
+                        record_id: Some(candidate.record_id),
+                        score: candidate.score,
+                        outcome: if dismissed {
+                            SuggestCandidateOutcome::Dismissed
+                        } else {
+                            SuggestCandidateOutcome::NotMatched
+                        },
+                    });
+                }
+            }
+        }
+
+        let mut suggestions = vec![];
+        for suggestion in fetched {
+            let raw_url = suggestion.raw_url();
+            let existing = raw_url.and_then(|raw_url| {
+                candidates
+                    .iter()
+                    .position(|c| c.url.as_deref() == Some(raw_url))
+            });
+            let candidate_index = match existing {
+                Some(index) => index,
+                None => {
+                    let record_id = match raw_url {
+                        Some(raw_url) => self.fetch_suggestion_record_id(provider, raw_url)?,
+                        None => None,
+                    };
+                    candidates.push(SuggestCandidateExplanation {
+                        title: suggestion.title().to_owned(),
+                        url: suggestion.url().map(str::to_owned),
+                        keyword: suggestion
+                            .fuzzy_match_info()
+                            .map(|info| info.corrected_keyword.clone()),
+                        record_id,
+                        score: suggestion.score(),
+                        // Filled in by `explain_query()`.
+                        outcome: SuggestCandidateOutcome::OverLimit,
+                    });
+                    candidates.len() - 1
+                }
+            };
+            suggestions.push((candidate_index, suggestion));
+        }
+
+        // Weather keywords only match once they're long enough, so explain
+        // why a keyword prefix didn't return a suggestion.
+        if provider == SuggestionProvider::Weather && suggestions.is_empty() {
+            if let Some((min_keyword_length, score)) =
+                self.weather_keyword_below_threshold(query)?
+            {
+                candidates.push(SuggestCandidateExplanation {
+                    title: "Weather".to_owned(),
+                    url: None,
+                    keyword: Some(query.keyword.trim().to_lowercase()),
+                    record_id: None,
+                    score,
+                    outcome: SuggestCandidateOutcome::BelowThreshold { min_keyword_length },
+                });
+            }
+        }
+
+        let mut record_ids = candidates
+            .iter()
+            .filter_map(|c| c.record_id.clone())
+            .collect::<Vec<_>>();
+        record_ids.sort();
+        record_ids.dedup();
+        Ok((
+            SuggestProviderExplanation {
+                provider,
+                strategy,
+                keywords,
+                record_ids,
+                candidates,
+            },
+            suggestions,
+        ))
+    }
+
+    /// Fetches the provider's suggestions that `keyword` matches in a
+    /// keyword table, including the ones the query would filter out.
+    fn fetch_keyword_candidates(
+        &self,
+        lookup: KeywordLookup,
+        provider: SuggestionProvider,
+        keyword: &str,
+    ) -> Result<Vec<KeywordCandidate>> {
+        let map_row = |row: &rusqlite::Row| -> Result<KeywordCandidate> {
+            Ok(KeywordCandidate {
+                record_id: row.get("record_id")?,
+                title: row.get("title")?,
+                url: row.get("url")?,
+                score: row.get("score")?,
+                keyword: row.get("keyword")?,
+                full_keyword: row.get("full_keyword")?,
+            })
+        };
+        match lookup {
+            KeywordLookup::Exact => self.conn.query_rows_and_then_cached(
+                "SELECT
+                   s.record_id,
+                   s.title,
+                   s.url,
+                   s.score,
+                   k.keyword,
+                   fk.full_keyword
+                 FROM
+                   suggestions s
+                 JOIN
+                   keywords k
+                   ON k.suggestion_id = s.id
+                 LEFT JOIN
+                   full_keywords fk
+                   ON k.full_keyword_id = fk.id
+                 WHERE
+                   s.provider = :provider
+                   AND k.keyword = :keyword
+                 ORDER BY
+                   s.score DESC, k.rank ASC",
+                named_params! {
+                    ":provider": provider,
+                    ":keyword": keyword,
+                },
+                map_row,
+            ),
+            KeywordLookup::Prefix => {
+                let (keyword_prefix, keyword_suffix) = split_keyword(keyword);
+                self.conn.query_rows_and_then_cached(
+                    "SELECT
+                       s.record_id,
+                       s.title,
+                       s.url,
+                       s.score,
+                       TRIM(k.keyword_prefix || ' ' || MIN(k.keyword_suffix)) AS keyword,
+                       NULL AS full_keyword
+                     FROM
+                       suggestions s
+                     JOIN
+                       prefix_keywords k
+                       ON k.suggestion_id = s.id
+                     WHERE
+                       s.provider = :provider
+                       AND k.keyword_prefix = :keyword_prefix
+                       AND (k.keyword_suffix BETWEEN :keyword_suffix AND :keyword_suffix || x'FFFF')
+                     GROUP BY
+                       s.id
+                     ORDER BY
+                       s.score DESC",
+                    named_params! {
+                        ":provider": provider,
+                        ":keyword_prefix": keyword_prefix,
+                        ":keyword_suffix": keyword_suffix,
+                    },
+                    map_row,
+                )
+            }
+        }
+    }
+
+    /// Returns the ID of the record that a provider's suggestion came from.
+    fn fetch_suggestion_record_id(
+        &self,
+        provider: SuggestionProvider,
+        raw_url: &str,
+    ) -> Result<Option<String>> {
+        Ok(self.conn.try_query_one(
+            "SELECT record_id FROM suggestions WHERE provider = :provider AND url = :url LIMIT 1",
+            named_params! {
+                ":provider": provider,
+                ":url": raw_url,
+            },
+            true,
+        )?)
+    }
+}
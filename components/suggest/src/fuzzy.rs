@@ -0,0 +1,297 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Typo-tolerant keyword matching.
+//!
+//! This uses the approach from SymSpell: at ingestion time, we store every
+//! keyword along with each variant of it that has a single character deleted.
+//! At query time, we generate the same variants of the query and look them up.
+//! Two strings within an edit distance of 1 always share a variant, so this
+//! finds all of the candidate keywords with a few index lookups rather than a
+//! scan of the keywords table.  Some candidates are further away than that
+//! (`xabc` and `abcx` share `abc`, but are 2 edits apart), so we check the
+//! real distance before using them.
+//!
+//! The index is only built when `SuggestionProviderConstraints::fuzzy_matching`
+//! is set in the ingestion constraints, since it's several times larger than
+//! the keywords it indexes.
+
+use std::collections::{BTreeSet, HashSet};
+
+use rusqlite::{named_params, Connection};
+use sql_support::{repeat_sql_vars, ConnExt};
+
+use crate::{
+    db::SuggestDao,
+    error::RusqliteResultExt,
+    provider::SuggestionProvider,
+    rs::SuggestRecordId,
+    suggestion::{FuzzyMatchInfo, Suggestion},
+    Result, SuggestionQuery,
+};
+
+/// Keywords and queries shorter than this are never fuzzy matched.  One typo
+/// in a short word changes too much of it for the correction to be useful.
+const MIN_FUZZY_KEYWORD_LEN: usize = 4;
+
+/// The largest edit distance we'll correct.  The index only supports 1.
+const MAX_FUZZY_EDIT_DISTANCE: usize = 1;
+
+/// The most corrections we'll try for a single query.
+const MAX_FUZZY_CORRECTIONS: usize = 5;
+
+/// A keyword that's within `MAX_FUZZY_EDIT_DISTANCE` of a query.
+#[derive(Debug, PartialEq)]
+pub(crate) struct FuzzyCorrection {
+    pub keyword: String,
+    pub edit_distance: usize,
+}
+
+impl FuzzyCorrection {
+    pub fn match_info(&self) -> FuzzyMatchInfo {
+        FuzzyMatchInfo {
+            corrected_keyword: self.keyword.clone(),
+            edit_distance: self.edit_distance as u32,
+        }
+    }
+}
+
+impl SuggestDao<'_> {
+    /// Checks if the fuzzy index has been built for a record.
+    pub fn is_fuzzy_data_ingested(&self, record_id: &SuggestRecordId) -> Result<bool> {
+        Ok(self.conn.exists(
+            "SELECT 1 FROM fuzzy_records WHERE record_id = :record_id",
+            named_params! {
+                ":record_id": record_id.as_str(),
+            },
+        )?)
+    }
+
+    /// Marks the fuzzy index as built for a record.
+    ///
+    /// This is separate from the keywords in the index, since a record whose
+    /// keywords are all too short to be fuzzy matched doesn't add any.
+    pub fn put_fuzzy_data_ingested(&mut self, record_id: &SuggestRecordId) -> Result<()> {
+        self.conn.execute_cached(
+            "INSERT OR IGNORE INTO fuzzy_records(record_id) VALUES(:record_id)",
+            named_params! {
+                ":record_id": record_id.as_str(),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Fetches the keywords for a provider that are a small, non-zero edit
+    /// distance from `keyword`, closest first.
+    pub(crate) fn fetch_fuzzy_corrections(
+        &self,
+        provider: SuggestionProvider,
+        keyword: &str,
+    ) -> Result<Vec<FuzzyCorrection>> {
+        let keyword = keyword.to_lowercase();
+        let variants = deletion_variants(&keyword);
+        if variants.is_empty() {
+            return Ok(vec![]);
+        }
+        let candidates: Vec<String> = self.conn.query_rows_and_then_cached(
+            &format!(
+                "SELECT DISTINCT keyword FROM fuzzy_keywords
+                 WHERE provider = ? AND variant IN ({})",
+                repeat_sql_vars(variants.len())
+            ),
+            rusqlite::params_from_iter(
+                std::iter::once(&provider as &dyn rusqlite::ToSql)
+                    .chain(variants.iter().map(|v| v as &dyn rusqlite::ToSql)),
+            ),
+            |row| row.get(0),
+        )?;
+        let mut corrections: Vec<_> = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let edit_distance = edit_distance(&keyword, &candidate.to_lowercase());
+                (1..=MAX_FUZZY_EDIT_DISTANCE)
+                    .contains(&edit_distance)
+                    .then_some(FuzzyCorrection {
+                        keyword: candidate,
+                        edit_distance,
+                    })
+            })
+            .collect();
+        // Prefer corrections that are closest in length to the query, so that
+        // a substituted character is corrected before a dropped one.
+        let len = keyword.chars().count();
+        corrections.sort_by_key(|c| {
+            (
+                c.edit_distance,
+                c.keyword.chars().count().abs_diff(len),
+                c.keyword.clone(),
+            )
+        });
+        corrections.truncate(MAX_FUZZY_CORRECTIONS);
+        Ok(corrections)
+    }
+
+    /// Fetches suggestions using `fetch`, falling back to fetching them for the
+    /// closest corrections of the query keyword if the query allows it and
+    /// there are no exact matches.
+    pub(crate) fn fetch_with_fuzzy_fallback(
+        &self,
+        query: &SuggestionQuery,
+        provider: SuggestionProvider,
+        fetch: impl Fn(&SuggestionQuery) -> Result<Vec<Suggestion>>,
+    ) -> Result<Vec<Suggestion>> {
+        let suggestions = fetch(query)?;
+        if !suggestions.is_empty() || !query.uses_fuzzy_matching() {
+            return Ok(suggestions);
+        }
+        // Several corrections can match the same suggestion, for example
+        // `amazn` is one edit from both the `amazo` and `amazon` keywords.
+        let mut seen_urls = HashSet::new();
+        let mut suggestions = vec![];
+        for correction in self.fetch_fuzzy_corrections(provider, &query.keyword)? {
+            self.scope.err_if_interrupted()?;
+            let corrected_query = SuggestionQuery {
+                keyword: correction.keyword.clone(),
+                ..query.clone()
+            };
+            for suggestion in fetch(&corrected_query)? {
+                if seen_urls.insert(suggestion.raw_url().map(str::to_owned)) {
+                    suggestions.push(suggestion.with_fuzzy_match_info(correction.match_info()));
+                }
+            }
+        }
+        Ok(suggestions)
+    }
+}
+
+/// Inserts keywords into the fuzzy index.
+pub(crate) struct FuzzyKeywordInsertStatement<'conn>(rusqlite::Statement<'conn>);
+
+impl<'conn> FuzzyKeywordInsertStatement<'conn> {
+    pub(crate) fn new(conn: &'conn Connection) -> Result<Self> {
+        Ok(Self(conn.prepare(
+            "INSERT OR IGNORE INTO fuzzy_keywords(
+                 variant,
+                 keyword,
+                 provider,
+                 record_id
+             )
+             VALUES(?, ?, ?, ?)",
+        )?))
+    }
+
+    pub(crate) fn execute(
+        &mut self,
+        record_id: &SuggestRecordId,
+        provider: SuggestionProvider,
+        keyword: &str,
+    ) -> Result<()> {
+        for variant in deletion_variants(&keyword.to_lowercase()) {
+            self.0
+                .execute((variant, keyword, provider, record_id.as_str()))
+                .with_context("fuzzy keyword insert")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `keyword` and each distinct string formed by deleting one character
+/// from it, or nothing if it's too short to be fuzzy matched.
+fn deletion_variants(keyword: &str) -> BTreeSet<String> {
+    let chars: Vec<char> = keyword.chars().collect();
+    if chars.len() < MIN_FUZZY_KEYWORD_LEN {
+        return BTreeSet::new();
+    }
+    std::iter::once(keyword.to_owned())
+        .chain((0..chars.len()).map(|i| {
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, c)| c)
+                .collect()
+        }))
+        .collect()
+}
+
+/// Returns the optimal string alignment distance between two strings: the
+/// number of single-character insertions, deletions, substitutions and
+/// transpositions of adjacent characters needed to turn one into the other.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // `d[i][j]` is the distance between the first `i` characters of `a` and
+    // the first `j` characters of `b`.
+    let mut d: Vec<Vec<usize>> = (0..=a.len())
+        .map(|i| (0..=b.len()).map(|j| if i == 0 { j } else { i }).collect())
+        .collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution_cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + substitution_cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        for (a, b, expected) in [
+            ("amazon", "amazon", 0),
+            ("amazn", "amazon", 1),
+            ("amazoon", "amazon", 1),
+            ("amazin", "amazon", 1),
+            ("amzaon", "amazon", 1),
+            ("wikipeda", "wikipedia", 1),
+            ("amzn", "amazon", 2),
+            ("abcx", "abxc", 1),
+            ("xabc", "abcx", 2),
+            ("", "abc", 3),
+            ("café", "cafe", 1),
+        ] {
+            assert_eq!(edit_distance(a, b), expected, "{a} -> {b}");
+            assert_eq!(edit_distance(b, a), expected, "{b} -> {a}");
+        }
+    }
+
+    #[test]
+    fn test_deletion_variants() {
+        assert_eq!(
+            deletion_variants("abcd"),
+            BTreeSet::from(["abcd", "bcd", "acd", "abd", "abc"].map(String::from))
+        );
+        // Repeated letters give the same variant.
+        assert_eq!(
+            deletion_variants("aabb"),
+            BTreeSet::from(["aabb", "abb", "aab"].map(String::from))
+        );
+        assert_eq!(deletion_variants("abc"), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_variants_share_distance_one() {
+        // Any two strings within an edit distance of 1 share a variant.
+        for (a, b) in [
+            ("amazn", "amazon"),
+            ("amazin", "amazon"),
+            ("amzaon", "amazon"),
+            ("wikipeda", "wikipedia"),
+        ] {
+            assert!(
+                !deletion_variants(a).is_disjoint(&deletion_variants(b)),
+                "{a} and {b} should share a variant"
+            );
+        }
+    }
+}
@@ -7,14 +7,25 @@
 pub mod benchmarks;
 mod config;
 mod db;
+mod engagement;
 mod error;
+mod explain;
 mod fakespot;
+mod fuzzy;
 mod geoname;
+mod market;
 mod metrics;
+#[cfg(feature = "merino")]
+mod online;
+#[cfg(feature = "relevancy")]
+mod personalization;
+mod progress;
 mod provider;
 mod query;
 mod rs;
 mod schema;
+mod session;
+mod storage;
 mod store;
 mod suggestion;
 #[cfg(test)]
@@ -23,14 +34,25 @@ pub mod util;
 mod weather;
 mod yelp;
 
-pub use config::{SuggestGlobalConfig, SuggestProviderConfig};
+pub use config::{
+    SuggestEngagementConfig, SuggestGlobalConfig, SuggestImpressionCap, SuggestProviderConfig,
+};
 pub use error::{Error, SuggestApiError};
+pub use explain::{
+    SuggestCandidateExplanation, SuggestCandidateOutcome, SuggestMatchStrategy,
+    SuggestProviderExplanation, SuggestQueryExplanation,
+};
 pub use geoname::{Geoname, GeonameMatch};
 pub use metrics::{LabeledTimingSample, SuggestIngestionMetrics};
+#[cfg(feature = "merino")]
+pub use online::{MerinoQueryOptions, MerinoQueryResult, OnlineStatus};
+pub use progress::{SuggestIngestionProgress, SuggestIngestionProgressListener};
 pub use provider::{AmpMatchingStrategy, SuggestionProvider, SuggestionProviderConstraints};
 pub use query::{QueryWithMetricsResult, SuggestionQuery};
+pub use session::SuggestQuerySession;
+pub use storage::{SuggestProviderStorageUsage, SuggestStorageBudget, SuggestStorageUsage};
 pub use store::{InterruptKind, SuggestIngestionConstraints, SuggestStore, SuggestStoreBuilder};
-pub use suggestion::{raw_suggestion_url_matches, Suggestion};
+pub use suggestion::{raw_suggestion_url_matches, PersonalizationInfo, Suggestion};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 pub type SuggestApiResult<T> = std::result::Result<T, SuggestApiError>;
@@ -0,0 +1,273 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Scopes ingestion and queries to the app's market.
+//!
+//! A Suggest record can be targeted to a market with a JEXL
+//! `filter_expression`, like other Remote Settings records, or with
+//! `locales`, `countries`, and `form_factors` fields. If the store has an app
+//! context, ingestion skips records for other markets without downloading
+//! their attachments, and records that were ingested for a different market
+//! are dropped when the store opens its database.
+
+use remote_settings::{JexlFilter, RemoteSettingsContext};
+use rusqlite::named_params;
+use serde::{Deserialize, Deserializer, Serialize};
+use sql_support::ConnExt;
+
+use crate::{
+    db::SuggestDao,
+    rs::{OneOrMany, SuggestRecordId},
+    Result,
+};
+
+/// The fields that target a record to a market.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct RecordTargeting {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter_expression: Option<String>,
+    #[serde(
+        default,
+        alias = "locale",
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub locales: Vec<String>,
+    #[serde(
+        default,
+        alias = "country",
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub countries: Vec<String>,
+    #[serde(
+        default,
+        alias = "form_factor",
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub form_factors: Vec<String>,
+}
+
+impl RecordTargeting {
+    /// Returns true if the record isn't targeted to a market.
+    pub fn is_empty(&self) -> bool {
+        self.filter_expression.is_none()
+            && self.locales.is_empty()
+            && self.countries.is_empty()
+            && self.form_factors.is_empty()
+    }
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(OneOrMany::deserialize(deserializer)?.into())
+}
+
+/// The market that a store ingests and returns suggestions for.
+pub(crate) struct SuggestMarket {
+    context: RemoteSettingsContext,
+}
+
+impl SuggestMarket {
+    pub fn new(context: RemoteSettingsContext) -> Self {
+        Self { context }
+    }
+
+    /// Returns a filter for records in this market.
+    ///
+    /// The filter holds a JEXL evaluator, so callers should make one for each
+    /// batch of records, rather than one per record.
+    pub fn filter(&self) -> MarketFilter<'_> {
+        MarketFilter {
+            context: &self.context,
+            jexl_filter: JexlFilter::new(Some(self.context.clone())),
+        }
+    }
+}
+
+pub(crate) struct MarketFilter<'a> {
+    context: &'a RemoteSettingsContext,
+    jexl_filter: JexlFilter,
+}
+
+impl MarketFilter<'_> {
+    /// Returns true if a record with this targeting should be ingested and
+    /// returned in this market.
+    pub fn matches(&self, targeting: &RecordTargeting) -> bool {
+        targeting
+            .filter_expression
+            .as_deref()
+            .is_none_or(|filter_expr| self.jexl_filter.matches(filter_expr))
+            && matches_any(
+                &targeting.locales,
+                self.context.locale.as_deref(),
+                locale_matches,
+            )
+            && matches_any(
+                &targeting.countries,
+                self.context.country.as_deref(),
+                str::eq_ignore_ascii_case,
+            )
+            && matches_any(
+                &targeting.form_factors,
+                self.context.form_factor.as_deref(),
+                str::eq_ignore_ascii_case,
+            )
+    }
+}
+
+impl SuggestDao<'_> {
+    /// Drops the data for ingested records that aren't for the market, and
+    /// returns their IDs. The records are no longer marked as ingested, so
+    /// they'll be ingested again if the market changes back.
+    pub fn drop_records_outside_market(
+        &mut self,
+        filter: &MarketFilter<'_>,
+    ) -> Result<Vec<SuggestRecordId>> {
+        let targeted_records = self.conn.query_rows_and_then(
+            "SELECT record_id, collection, targeting FROM record_targeting",
+            (),
+            |row| -> Result<_> {
+                let targeting: String = row.get("targeting")?;
+                Ok((
+                    SuggestRecordId::new(row.get("record_id")?),
+                    row.get::<_, String>("collection")?,
+                    serde_json::from_str::<RecordTargeting>(&targeting)?,
+                ))
+            },
+        )?;
+        let mut dropped = vec![];
+        for (record_id, collection, targeting) in targeted_records {
+            if filter.matches(&targeting) {
+                continue;
+            }
+            self.delete_record_data(&record_id)?;
+            self.conn.execute_cached(
+                "DELETE FROM ingested_records WHERE id = :id AND collection = :collection",
+                named_params! { ":id": record_id.as_str(), ":collection": collection },
+            )?;
+            dropped.push(record_id);
+        }
+        Ok(dropped)
+    }
+}
+
+/// Returns true if `market_value` is one of the record's `values`. Records
+/// that don't list any values, and markets without a value, match.
+fn matches_any(
+    values: &[String],
+    market_value: Option<&str>,
+    eq: impl Fn(&str, &str) -> bool,
+) -> bool {
+    match market_value {
+        Some(market_value) if !values.is_empty() => {
+            values.iter().any(|value| eq(value, market_value))
+        }
+        _ => true,
+    }
+}
+
+/// Returns true if a record's locale matches the app's locale. A record
+/// locale with a region, like `en-US`, only matches that locale; a record
+/// locale without one, like `en`, matches every locale for that language.
+fn locale_matches(record_locale: &str, locale: &str) -> bool {
+    let normalize = |locale: &str| locale.replace('_', "-");
+    let (record_locale, locale) = (normalize(record_locale), normalize(locale));
+    if record_locale.eq_ignore_ascii_case(&locale) {
+        return true;
+    }
+    !record_locale.contains('-')
+        && locale
+            .split('-')
+            .next()
+            .is_some_and(|language| language.eq_ignore_ascii_case(&record_locale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(locale: &str, country: &str, form_factor: &str) -> SuggestMarket {
+        SuggestMarket::new(RemoteSettingsContext {
+            locale: Some(locale.to_owned()),
+            country: Some(country.to_owned()),
+            form_factor: Some(form_factor.to_owned()),
+            ..RemoteSettingsContext::default()
+        })
+    }
+
+    fn targeting(value: serde_json::Value) -> RecordTargeting {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn deserialize() {
+        assert_eq!(
+            targeting(serde_json::json!({
+                "type": "amp",
+                "locale": "de",
+                "countries": ["DE", "AT"],
+            })),
+            RecordTargeting {
+                filter_expression: None,
+                locales: vec!["de".to_owned()],
+                countries: vec!["DE".to_owned(), "AT".to_owned()],
+                form_factors: vec![],
+            }
+        );
+        assert!(targeting(serde_json::json!({ "type": "amp" })).is_empty());
+    }
+
+    #[test]
+    fn matches() {
+        let market = market("de-AT", "AT", "phone");
+        let filter = market.filter();
+        for (value, expected) in [
+            (serde_json::json!({}), true),
+            (serde_json::json!({ "locales": ["de"] }), true),
+            (serde_json::json!({ "locales": ["de-AT", "fr"] }), true),
+            (serde_json::json!({ "locales": ["de_at"] }), true),
+            (serde_json::json!({ "locales": ["de-DE"] }), false),
+            (serde_json::json!({ "locales": ["en"] }), false),
+            (serde_json::json!({ "countries": ["at"] }), true),
+            (serde_json::json!({ "country": "DE" }), false),
+            (serde_json::json!({ "form_factor": "phone" }), true),
+            (serde_json::json!({ "form_factors": ["desktop"] }), false),
+            (
+                serde_json::json!({ "filter_expression": "env.country == \"AT\"" }),
+                true,
+            ),
+            (
+                serde_json::json!({ "filter_expression": "env.formFactor == \"desktop\"" }),
+                false,
+            ),
+            (
+                serde_json::json!({ "filter_expression": "env.country ==" }),
+                false,
+            ),
+            (
+                serde_json::json!({ "locales": ["de"], "countries": ["DE"] }),
+                false,
+            ),
+        ] {
+            assert_eq!(
+                filter.matches(&targeting(value.clone())),
+                expected,
+                "{value}"
+            );
+        }
+
+        // A market without a country matches records for every country.
+        let market = SuggestMarket::new(RemoteSettingsContext {
+            locale: Some("de-DE".to_owned()),
+            ..RemoteSettingsContext::default()
+        });
+        assert!(market
+            .filter()
+            .matches(&targeting(serde_json::json!({ "countries": ["AT"] }))));
+    }
+}
@@ -44,7 +44,7 @@ impl SuggestIngestionMetrics {
         let elapsed = timer.elapsed().as_micros() as u64;
         match context {
             MetricsContext::Uninstrumented => (),
-            MetricsContext::Instrumented { download_time } => {
+            MetricsContext::Instrumented { download_time, .. } => {
                 self.ingestion_times.push(LabeledTimingSample::new(
                     record_type.clone(),
                     elapsed - download_time,
@@ -67,7 +67,10 @@ pub enum MetricsContext {
     /// State after `measure_download()` is called.  We currently always download an attachment
     /// whenever we do any ingestion work, so we can use this a test for if we should record
     /// anything.
-    Instrumented { download_time: u64 },
+    Instrumented {
+        download_time: u64,
+        download_count: u32,
+    },
 }
 
 impl MetricsContext {
@@ -86,12 +89,27 @@ impl MetricsContext {
             Self::Uninstrumented => {
                 *self = Self::Instrumented {
                     download_time: elasped,
+                    download_count: 1,
                 }
             }
-            Self::Instrumented { download_time } => *download_time += elasped,
+            Self::Instrumented {
+                download_time,
+                download_count,
+            } => {
+                *download_time += elasped;
+                *download_count += 1;
+            }
         }
         result
     }
+
+    /// The number of times [Self::measure_download] was called
+    pub fn download_count(&self) -> u32 {
+        match self {
+            Self::Uninstrumented => 0,
+            Self::Instrumented { download_count, .. } => *download_count,
+        }
+    }
 }
 
 /// Query metrics
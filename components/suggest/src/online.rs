@@ -0,0 +1,216 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Merging online suggestions from Merino with offline suggestions.
+//!
+//! [crate::SuggestStore::query_with_merino] hands the query to the store's
+//! [MerinoWorker] while it queries the database, then waits for Merino's
+//! response until the latency budget runs out.  The worker sends one request
+//! at a time; if the user types faster than Merino responds, requests that
+//! are still waiting to be sent are replaced by newer ones.  Online
+//! suggestions are dropped if an offline suggestion already has the same URL,
+//! if an earlier online suggestion has the same URL, or if the user dismissed
+//! the URL.
+
+use std::{
+    collections::HashSet,
+    sync::{mpsc, Arc},
+    thread,
+};
+
+use merino::suggest::{
+    ApiResult as MerinoApiResult, MerinoSuggestClient, MerinoSuggestRequest, MerinoSuggestResponse,
+    MerinoSuggestion,
+};
+use parking_lot::{Condvar, Mutex};
+
+use crate::{db::SuggestDao, suggestion::raw_suggestion_url_matches, Result, Suggestion};
+
+/// The default latency budget for online suggestions, in milliseconds.
+pub const DEFAULT_MERINO_TIMEOUT_MS: u32 = 200;
+
+/// Options for the online part of [crate::SuggestStore::query_with_merino].
+#[derive(Clone, Debug, uniffi::Record)]
+pub struct MerinoQueryOptions {
+    /// How long to wait for Merino before returning offline suggestions only,
+    /// in milliseconds.  This includes the time spent querying the database.
+    #[uniffi(default = 200)]
+    pub timeout_ms: u32,
+    /// Merino providers to request suggestions from.  Merino uses its default
+    /// providers if this is empty.
+    #[uniffi(default)]
+    pub providers: Vec<String>,
+    /// The search session ID to send to Merino.
+    #[uniffi(default = None)]
+    pub session_id: Option<String>,
+    /// The position of this query in the search session.
+    #[uniffi(default = None)]
+    pub sequence_number: Option<u32>,
+}
+
+impl Default for MerinoQueryOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: DEFAULT_MERINO_TIMEOUT_MS,
+            providers: vec![],
+            session_id: None,
+            sequence_number: None,
+        }
+    }
+}
+
+/// What happened to the online part of a query.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Enum)]
+pub enum OnlineStatus {
+    /// Merino responded within the latency budget.
+    Success,
+    /// Merino didn't respond within the latency budget.
+    Timeout,
+    /// The request to Merino failed.
+    Error { reason: String },
+    /// A newer query replaced this one before its request was sent to Merino.
+    Superseded,
+    /// The store wasn't built with a Merino client.
+    NotConfigured,
+}
+
+/// The result of [crate::SuggestStore::query_with_merino].
+#[derive(Debug, uniffi::Record)]
+pub struct MerinoQueryResult {
+    /// Online and offline suggestions, sorted by score.
+    pub suggestions: Vec<Suggestion>,
+    pub online_status: OnlineStatus,
+}
+
+impl SuggestDao<'_> {
+    /// Adds online suggestions to the offline ones, skipping duplicate and
+    /// dismissed online suggestions.  The result isn't sorted.
+    pub(crate) fn merge_online_suggestions(
+        &self,
+        mut suggestions: Vec<Suggestion>,
+        online: Vec<MerinoSuggestion>,
+    ) -> Result<Vec<Suggestion>> {
+        let offline_len = suggestions.len();
+        let mut seen_urls = HashSet::new();
+        for suggestion in online {
+            let is_offline_duplicate = suggestions[..offline_len].iter().any(|offline| {
+                offline
+                    .raw_url()
+                    .is_some_and(|raw_url| raw_suggestion_url_matches(raw_url, &suggestion.url))
+                    || offline.url() == Some(suggestion.url.as_str())
+            });
+            if is_offline_duplicate
+                || !seen_urls.insert(suggestion.url.clone())
+                || self.has_dismissal(&suggestion.url)?
+            {
+                continue;
+            }
+            suggestions.push(Suggestion::Merino { suggestion });
+        }
+        Ok(suggestions)
+    }
+}
+
+/// A Merino response, sent from the worker thread to the waiting query.
+pub(crate) type MerinoReply = MerinoApiResult<MerinoSuggestResponse>;
+
+/// Sends requests to Merino from a single background thread.
+///
+/// The worker keeps at most one pending request. Queuing a new request drops
+/// the pending one, which disconnects its receiver, so a burst of queries
+/// makes at most one request in flight and one waiting. The thread exits when
+/// the worker is dropped.
+pub(crate) struct MerinoWorker {
+    shared: Arc<WorkerShared>,
+}
+
+#[derive(Default)]
+struct WorkerShared {
+    state: Mutex<WorkerState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct WorkerState {
+    pending: Option<(MerinoSuggestRequest, mpsc::Sender<MerinoReply>)>,
+    shutdown: bool,
+}
+
+impl MerinoWorker {
+    pub fn new(client: Arc<MerinoSuggestClient>) -> Self {
+        let shared = Arc::new(WorkerShared::default());
+        let worker_shared = Arc::clone(&shared);
+        thread::Builder::new()
+            .name("suggest-merino".into())
+            .spawn(move || run_worker(&client, &worker_shared))
+            .expect("Failed to spawn the Merino worker thread");
+        Self { shared }
+    }
+
+    /// Queues a request, replacing the pending request if the worker hasn't
+    /// started sending it yet. Returns a receiver for the response, which is
+    /// disconnected if this request is replaced in turn.
+    pub fn queue(&self, request: MerinoSuggestRequest) -> mpsc::Receiver<MerinoReply> {
+        let (tx, rx) = mpsc::channel();
+        self.shared.state.lock().pending = Some((request, tx));
+        self.shared.condvar.notify_one();
+        rx
+    }
+}
+
+impl Drop for MerinoWorker {
+    fn drop(&mut self) {
+        self.shared.state.lock().shutdown = true;
+        self.shared.condvar.notify_one();
+    }
+}
+
+fn run_worker(client: &MerinoSuggestClient, shared: &WorkerShared) {
+    loop {
+        let (request, reply) = {
+            let mut state = shared.state.lock();
+            loop {
+                if state.shutdown {
+                    return;
+                }
+                if let Some(pending) = state.pending.take() {
+                    break pending;
+                }
+                shared.condvar.wait(&mut state);
+            }
+        };
+        // The query might have timed out already, in which case nobody is
+        // listening for the response.
+        let _ = reply.send(client.fetch(&request));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_replaces_pending_request() {
+        // A worker without a thread, so that requests stay pending.
+        let worker = MerinoWorker {
+            shared: Arc::default(),
+        };
+        let first = worker.queue(MerinoSuggestRequest {
+            query: "lo".into(),
+            ..MerinoSuggestRequest::default()
+        });
+        let _second = worker.queue(MerinoSuggestRequest {
+            query: "los".into(),
+            ..MerinoSuggestRequest::default()
+        });
+        assert!(matches!(first.recv(), Err(mpsc::RecvError)));
+        let state = worker.shared.state.lock();
+        let (request, _) = state
+            .pending
+            .as_ref()
+            .expect("Should have a pending request");
+        assert_eq!(request.query, "los");
+    }
+}
@@ -0,0 +1,163 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Personalized ranking of suggestions using the user's interests from the
+//! `relevancy` component.
+//!
+//! AMP suggestions carry the SERP categories from their records, which use
+//! the same codes as relevancy's `Interest`.  We score how well those match
+//! the user's interest vector with `relevancy::score()`, and boost the
+//! suggestion's score by up to double for a perfect match.  Suggestions
+//! without categories, or with only unknown ones, keep their score.
+
+use std::sync::Arc;
+
+use error_support::warn;
+use parking_lot::Mutex;
+use relevancy::{Interest, InterestVector, RelevancyStore};
+
+use crate::suggestion::{PersonalizationInfo, Suggestion};
+
+/// The user's interests, either set directly or read from a relevancy store.
+#[derive(Default)]
+pub(crate) struct Personalizer {
+    interest_vector: Mutex<Option<InterestVector>>,
+    relevancy_store: Option<Arc<RelevancyStore>>,
+    /// The relevancy store's interest vector, cached until the next ingestion
+    /// so that each query doesn't have to read it from the relevancy database.
+    relevancy_interest_vector: Mutex<Option<InterestVector>>,
+}
+
+impl Personalizer {
+    /// Sets the interest vector to use instead of the relevancy store's, or
+    /// goes back to using the relevancy store's if `None`.
+    pub fn set_interest_vector(&self, interest_vector: Option<InterestVector>) {
+        *self.interest_vector.lock() = interest_vector;
+    }
+
+    pub fn set_relevancy_store(&mut self, relevancy_store: Arc<RelevancyStore>) {
+        self.relevancy_store = Some(relevancy_store);
+        self.clear_cache();
+    }
+
+    /// Forgets the cached interest vector from the relevancy store, so that
+    /// the next query reads it again.
+    pub fn clear_cache(&self) {
+        *self.relevancy_interest_vector.lock() = None;
+    }
+
+    /// Re-scores suggestions for the user's interests, if we know them.
+    pub fn personalize(&self, suggestions: &mut [Suggestion]) {
+        if let Some(interest_vector) = self.interest_vector() {
+            personalize(suggestions, &interest_vector);
+        }
+    }
+
+    fn interest_vector(&self) -> Option<InterestVector> {
+        if let Some(interest_vector) = self.interest_vector.lock().as_ref() {
+            return Some(interest_vector.clone());
+        }
+        let relevancy_store = self.relevancy_store.as_ref()?;
+        let mut cached = self.relevancy_interest_vector.lock();
+        if let Some(interest_vector) = cached.as_ref() {
+            return Some(interest_vector.clone());
+        }
+        // Personalization is best-effort, so we'd rather return unpersonalized
+        // suggestions than fail the query. Errors aren't cached, so the next
+        // query tries again.
+        match relevancy_store.user_interest_vector() {
+            Ok(interest_vector) => Some(cached.insert(interest_vector).clone()),
+            Err(e) => {
+                warn!("Error fetching interest vector: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Re-scores the suggestions that have interest categories.
+pub(crate) fn personalize(suggestions: &mut [Suggestion], interest_vector: &InterestVector) {
+    for suggestion in suggestions {
+        let Suggestion::Amp {
+            categories,
+            score,
+            personalization_info,
+            ..
+        } = suggestion
+        else {
+            continue;
+        };
+        let interests: Vec<Interest> = categories
+            .iter()
+            .filter_map(|&category| Interest::try_from(u32::try_from(category).ok()?).ok())
+            .filter(|&interest| interest != Interest::Inconclusive)
+            .collect();
+        if interests.is_empty() {
+            continue;
+        }
+        let interest_score = relevancy::score(interest_vector.clone(), interests);
+        let original_score = *score;
+        *score = original_score * (1.0 + interest_score);
+        *personalization_info = Some(PersonalizationInfo {
+            original_score,
+            interest_score,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn test_personalize() {
+        // `los_pollos_suggestion` has the food category; `good_place_eats_suggestion` has none.
+        let mut suggestions = vec![
+            good_place_eats_suggestion("lasagna", None),
+            los_pollos_suggestion("los pollos", None),
+        ];
+        personalize(
+            &mut suggestions,
+            &InterestVector {
+                food: 100,
+                ..InterestVector::default()
+            },
+        );
+        assert_eq!(suggestions[0], good_place_eats_suggestion("lasagna", None));
+        let info = suggestions[1].personalization_info().unwrap();
+        assert_eq!(info.original_score, 0.3);
+        assert_eq!(
+            info.interest_score,
+            relevancy::score(
+                InterestVector {
+                    food: 100,
+                    ..InterestVector::default()
+                },
+                vec![Interest::Food]
+            )
+        );
+        assert!(suggestions[1].score() > 0.3);
+        assert_eq!(suggestions[1].score(), 0.3 * (1.0 + info.interest_score));
+
+        // An interest vector without the food category leaves the score alone.
+        let mut suggestions = vec![los_pollos_suggestion("los pollos", None)];
+        personalize(
+            &mut suggestions,
+            &InterestVector {
+                sports: 100,
+                ..InterestVector::default()
+            },
+        );
+        assert_eq!(suggestions[0].score(), 0.3);
+        assert_eq!(
+            suggestions[0].personalization_info(),
+            Some(&PersonalizationInfo {
+                original_score: 0.3,
+                interest_score: 0.0,
+            })
+        );
+    }
+}
@@ -0,0 +1,86 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+/// Receives progress updates during [crate::SuggestStore::ingest_with_progress].
+///
+/// The listener is called on the ingestion thread, so it should return
+/// quickly. It's safe to call [crate::SuggestStore::interrupt] from it.
+#[uniffi::export(callback_interface)]
+pub trait SuggestIngestionProgressListener: Send + Sync {
+    fn on_progress(&self, progress: SuggestIngestionProgress);
+}
+
+/// Ingestion progress for one record type.
+///
+/// Each record is ingested in its own transaction, so the records counted in
+/// `records_ingested` stay ingested even if ingestion is interrupted. The next
+/// ingestion skips them and picks up with the remaining records.
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct SuggestIngestionProgress {
+    /// The record type, like `"amp"` or `"geonames-2"`.
+    pub record_type: String,
+    /// The number of records of this type fetched from Remote Settings.
+    pub records_fetched: u32,
+    /// The number of records that are new, changed, or need to be
+    /// re-ingested for other reasons.
+    pub records_to_ingest: u32,
+    /// The number of those records ingested so far.
+    pub records_ingested: u32,
+    /// The number of attachments downloaded so far.
+    pub attachments_downloaded: u32,
+    /// The number of database rows inserted, updated, or deleted so far.
+    pub rows_written: u64,
+}
+
+/// Tracks the progress for a record type and reports it to the listener,
+/// if there is one.
+pub(crate) struct ProgressReporter<'a> {
+    listener: Option<&'a dyn SuggestIngestionProgressListener>,
+    progress: SuggestIngestionProgress,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(
+        listener: Option<&'a dyn SuggestIngestionProgressListener>,
+        record_type: impl Into<String>,
+        records_fetched: usize,
+    ) -> Self {
+        Self {
+            listener,
+            progress: SuggestIngestionProgress {
+                record_type: record_type.into(),
+                records_fetched: u32::try_from(records_fetched).unwrap_or(u32::MAX),
+                ..SuggestIngestionProgress::default()
+            },
+        }
+    }
+
+    /// Reports the number of records to ingest, before ingesting them.
+    pub fn start(&mut self, records_to_ingest: usize) {
+        self.progress.records_to_ingest = u32::try_from(records_to_ingest).unwrap_or(u32::MAX);
+        self.report();
+    }
+
+    /// Reports that a record was ingested.
+    pub fn record_ingested(&mut self, attachments_downloaded: u32, rows_written: u64) {
+        self.progress.records_ingested += 1;
+        self.progress.attachments_downloaded += attachments_downloaded;
+        self.progress.rows_written += rows_written;
+        self.report();
+    }
+
+    /// Reports rows written outside of a record, like deletions.
+    pub fn rows_written(&mut self, rows_written: u64) {
+        if rows_written > 0 {
+            self.progress.rows_written += rows_written;
+            self.report();
+        }
+    }
+
+    fn report(&self) {
+        if let Some(listener) = self.listener {
+            listener.on_progress(self.progress.clone());
+        }
+    }
+}
@@ -13,6 +13,8 @@ use rusqlite::{
     Result as RusqliteResult,
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::rs::{Collection, SuggestRecordType};
 
 #[cfg(test)]
@@ -34,7 +36,7 @@ pub(crate) const DEFAULT_INGEST_PROVIDERS: [SuggestionProvider; 5] = [
 /// A provider is a source of search suggestions.
 /// Please preserve the integer values after removing or adding providers.
 /// Provider configs are associated with integer keys stored in the database.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, uniffi::Enum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Deserialize, Serialize, uniffi::Enum)]
 #[repr(u8)]
 pub enum SuggestionProvider {
     Amp = 1,
@@ -101,6 +103,13 @@ impl SuggestionProvider {
         }
     }
 
+    /// Looks up a provider by the name it's displayed as.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::all()
+            .into_iter()
+            .find(|provider| provider.to_string() == name)
+    }
+
     /// The collection that stores the provider's primary record.
     pub(crate) fn primary_collection(&self) -> Collection {
         match self {
@@ -215,7 +224,7 @@ impl SuggestionProvider {
 
 /// Some providers manage multiple suggestion subtypes. Queries, ingests, and
 /// other operations on those providers must be constrained to a desired subtype.
-#[derive(Clone, Default, Debug, uniffi::Record)]
+#[derive(Clone, Default, Debug, PartialEq, uniffi::Record)]
 pub struct SuggestionProviderConstraints {
     /// Which dynamic suggestions should we fetch or ingest? Corresponds to the
     /// `suggestion_type` value in dynamic suggestions remote settings records.
@@ -225,9 +234,17 @@ pub struct SuggestionProviderConstraints {
     /// Use None for the default strategy.
     #[uniffi(default = None)]
     pub amp_alternative_matching: Option<AmpMatchingStrategy>,
+    /// Should AMP, Wikipedia, Yelp and MDN queries that don't match a keyword
+    /// exactly fall back to keywords that are one typo away?
+    ///
+    /// This needs an index that's built at ingestion time, so it must be set
+    /// in the ingestion constraints as well as the query.  It's ignored for
+    /// the AMP FTS strategies.
+    #[uniffi(default = false)]
+    pub fuzzy_matching: bool,
 }
 
-#[derive(Clone, Debug, uniffi::Enum)]
+#[derive(Clone, Debug, PartialEq, uniffi::Enum)]
 pub enum AmpMatchingStrategy {
     /// Disable keywords added via keyword expansion.
     /// This eliminates keywords that for terms related to the "real" keywords, for example
@@ -4,7 +4,10 @@
 
 use std::collections::HashSet;
 
-use crate::{LabeledTimingSample, Suggestion, SuggestionProvider, SuggestionProviderConstraints};
+use crate::{
+    db::SuggestDao, explain::SuggestCandidateOutcome, LabeledTimingSample, Result, Suggestion,
+    SuggestionProvider, SuggestionProviderConstraints,
+};
 
 /// A query for suggestions to show in the address bar.
 #[derive(Clone, Debug, Default, uniffi::Record)]
@@ -130,12 +133,92 @@ impl SuggestionQuery {
         }
     }
 
+    pub fn fuzzy_matching(self) -> Self {
+        Self {
+            provider_constraints: Some(SuggestionProviderConstraints {
+                fuzzy_matching: true,
+                ..self.provider_constraints.unwrap_or_default()
+            }),
+            ..self
+        }
+    }
+
+    /// Should we fall back to fuzzy matching if the keyword doesn't match exactly?
+    pub(crate) fn uses_fuzzy_matching(&self) -> bool {
+        self.provider_constraints
+            .as_ref()
+            .is_some_and(|c| c.fuzzy_matching)
+    }
+
     /// Create an FTS query term for our keyword(s)
     pub(crate) fn fts_query(&self) -> FtsQuery<'_> {
         FtsQuery::new(&self.keyword)
     }
 }
 
+impl SuggestDao<'_> {
+    /// Filters, personalizes, ranks, and truncates the suggestions that a
+    /// query's providers fetched.
+    ///
+    /// Each fetched suggestion comes with a tag, which is passed back to
+    /// `on_outcome` with what happened to the suggestion. Regular queries
+    /// ignore the outcomes, and [crate::SuggestStore::explain_query] uses them
+    /// to explain each candidate.
+    pub(crate) fn rank_suggestions<T>(
+        &self,
+        query: &SuggestionQuery,
+        fetched: Vec<(T, Suggestion)>,
+        now: i64,
+        personalize: impl FnOnce(&mut [Suggestion]),
+        mut on_outcome: impl FnMut(T, &Suggestion, SuggestCandidateOutcome),
+    ) -> Result<Vec<Suggestion>> {
+        // Drop the suggestions the user asked to see less of, and the ones
+        // they've already seen too often.
+        let filters = self.engagement_filters(
+            fetched
+                .iter()
+                .filter_map(|(_, suggestion)| suggestion.provider()),
+            now,
+        )?;
+        let mut kept = vec![];
+        for (tag, suggestion) in fetched {
+            match filters.filter(&query.keyword, &suggestion) {
+                Some(filter) => on_outcome(tag, &suggestion, filter.into()),
+                None => kept.push((tag, suggestion)),
+            }
+        }
+        let (tags, mut suggestions): (Vec<_>, Vec<_>) = kept.into_iter().unzip();
+        personalize(&mut suggestions);
+
+        // Note: it's important that this is a stable sort to keep the intra-provider order stable.
+        // For example, we can return multiple fakespot-suggestions all with `score=0.245`.  In
+        // that case, they must be in the same order that `fetch_fakespot_suggestions` returned
+        // them in.
+        let mut ranked = tags.into_iter().zip(suggestions).collect::<Vec<_>>();
+        ranked.sort_by(|(_, a), (_, b)| a.cmp(b));
+        let limit = query
+            .limit
+            .and_then(|limit| usize::try_from(limit).ok())
+            .unwrap_or(usize::MAX);
+        let mut suggestions = vec![];
+        for (position, (tag, suggestion)) in ranked.into_iter().enumerate() {
+            if position < limit {
+                on_outcome(
+                    tag,
+                    &suggestion,
+                    SuggestCandidateOutcome::Returned {
+                        position: u32::try_from(position).unwrap_or(u32::MAX),
+                    },
+                );
+                suggestions.push(suggestion);
+            } else {
+                on_outcome(tag, &suggestion, SuggestCandidateOutcome::OverLimit);
+            }
+        }
+        Ok(suggestions)
+    }
+}
+
 pub struct FtsQuery<'a> {
     pub match_arg: String,
     pub match_arg_without_prefix_match: String,
@@ -31,7 +31,7 @@
 //!     the new suggestion in their results, and return `Suggestion::T` variants
 //!     as needed.
 
-use std::{fmt, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc};
 
 use remote_settings::{
     Attachment, RemoteSettingsClient, RemoteSettingsError, RemoteSettingsRecord,
@@ -40,7 +40,7 @@ use remote_settings::{
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use crate::{error::Error, query::full_keywords_to_fts_content, Result};
+use crate::{error::Error, market::RecordTargeting, query::full_keywords_to_fts_content, Result};
 use rusqlite::{types::ToSqlOutput, ToSql};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -141,15 +141,19 @@ pub(crate) struct Record {
     pub attachment: Option<Attachment>,
     pub payload: SuggestRecord,
     pub collection: Collection,
+    /// The market that the record is for, if any.
+    pub targeting: RecordTargeting,
 }
 
 impl Record {
     pub fn new(record: RemoteSettingsRecord, collection: Collection) -> Result<Self> {
+        let fields = serde_json::Value::Object(record.fields);
         Ok(Self {
             id: SuggestRecordId::new(record.id),
             last_modified: record.last_modified,
             attachment: record.attachment,
-            payload: serde_json::from_value(serde_json::Value::Object(record.fields))?,
+            targeting: RecordTargeting::deserialize(&fields)?,
+            payload: serde_json::from_value(fields)?,
             collection,
         })
     }
@@ -307,11 +311,20 @@ impl SuggestRecordType {
 /// deserialize downloaded attachments.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(untagged)]
-enum OneOrMany<T> {
+pub(crate) enum OneOrMany<T> {
     One(T),
     Many(Vec<T>),
 }
 
+impl<T> From<OneOrMany<T>> for Vec<T> {
+    fn from(value: OneOrMany<T>) -> Self {
+        match value {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
 /// A downloaded Remote Settings attachment that contains suggestions.
 #[derive(Clone, Debug, Deserialize)]
 #[serde(transparent)]
@@ -602,6 +615,30 @@ pub(crate) struct DownloadedGlobalConfigInner {
     /// The maximum number of times the user can click "Show less frequently"
     /// for a suggestion in the UI.
     pub show_less_frequently_cap: i32,
+    /// "Show less frequently" and impression caps for each provider, keyed by
+    /// the provider's name, as returned by `SuggestionProvider::to_string`.
+    /// Providers we don't know about are ignored.
+    #[serde(default)]
+    pub providers: HashMap<String, DownloadedEngagementConfig>,
+}
+
+/// A provider's caps in the global configuration record.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct DownloadedEngagementConfig {
+    /// Overrides the global `show_less_frequently_cap` for the provider.
+    #[serde(default)]
+    pub show_less_frequently_cap: Option<i32>,
+    #[serde(default)]
+    pub impression_caps: Vec<DownloadedImpressionCap>,
+}
+
+/// An impression cap in a provider's configuration.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct DownloadedImpressionCap {
+    pub interval_s: u64,
+    pub max_count: u32,
+    #[serde(default)]
+    pub per_suggestion: bool,
 }
 
 #[cfg(test)]
@@ -23,7 +23,7 @@ use sql_support::{
 ///     `clear_database()` by adding their names to `conditional_tables`, unless
 ///     they are cleared via a deletion trigger or there's some other good
 ///     reason not to do so.
-pub const VERSION: u32 = 44;
+pub const VERSION: u32 = 48;
 
 /// The current Suggest database schema.
 pub const SQL: &str = "
@@ -40,6 +40,20 @@ CREATE TABLE ingested_records(
     PRIMARY KEY (id, collection)
 ) WITHOUT ROWID;
 
+-- The markets that ingested records are for, as JSON. Only records that are
+-- targeted to a market have a row.
+CREATE TABLE record_targeting(
+    record_id TEXT NOT NULL,
+    collection TEXT NOT NULL,
+    targeting TEXT NOT NULL,
+    PRIMARY KEY (record_id, collection)
+) WITHOUT ROWID;
+
+CREATE TRIGGER record_targeting_delete AFTER DELETE ON ingested_records BEGIN
+    DELETE FROM record_targeting
+    WHERE record_id = old.id AND collection = old.collection;
+END;
+
 CREATE TABLE keywords(
     keyword TEXT NOT NULL,
     suggestion_id INTEGER NOT NULL,
@@ -85,6 +99,24 @@ CREATE TABLE prefix_keywords(
 
 CREATE UNIQUE INDEX keywords_suggestion_id_rank ON keywords(suggestion_id, rank);
 
+-- Index for fuzzy keyword matching, see `fuzzy.rs`. `variant` is the
+-- lowercased keyword or the keyword with a single character deleted.
+CREATE TABLE fuzzy_keywords(
+    variant TEXT NOT NULL,
+    keyword TEXT NOT NULL,
+    provider INTEGER NOT NULL,
+    record_id TEXT NOT NULL,
+    PRIMARY KEY (provider, variant, keyword, record_id)
+) WITHOUT ROWID;
+
+CREATE INDEX fuzzy_keywords_record_id ON fuzzy_keywords(record_id);
+
+-- Records whose keywords have been added to the fuzzy index. Records with only
+-- short keywords don't have any rows in `fuzzy_keywords`.
+CREATE TABLE fuzzy_records(
+    record_id TEXT PRIMARY KEY
+) WITHOUT ROWID;
+
 CREATE TABLE serp_categories(
     suggestion_id INTEGER NOT NULL,
     category INTEGER NOT NULL,
@@ -266,6 +298,26 @@ CREATE TABLE dismissed_dynamic_suggestions (
     dismissal_key TEXT NOT NULL,
     PRIMARY KEY(suggestion_type, dismissal_key)
 ) WITHOUT ROWID;
+
+-- 'Show less frequently' clicks.  An empty `suggestion_key` means the clicks
+-- apply to the whole provider.
+CREATE TABLE show_less_frequently(
+    provider INTEGER NOT NULL,
+    suggestion_key TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    min_keyword_length INTEGER NOT NULL,
+    PRIMARY KEY (provider, suggestion_key)
+) WITHOUT ROWID;
+
+-- Suggestion impressions, for enforcing impression caps.  `timestamp` is in
+-- seconds since the epoch.  An empty `suggestion_key` means the suggestion
+-- didn't have one.
+CREATE TABLE impressions(
+    provider INTEGER NOT NULL,
+    suggestion_key TEXT NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX impressions_provider ON impressions(provider, suggestion_key, timestamp);
 ";
 
 /// Initializes an SQLite connection to the Suggest database, performing
@@ -847,6 +899,81 @@ impl ConnectionInitializer for SuggestConnectionInitializer<'_> {
                 )?;
                 Ok(())
             }
+            44 => {
+                // The fuzzy index is only built for consumers that ask for it,
+                // which they'll do on their next ingestion, so there's no need
+                // to clear the database.
+                tx.execute_batch(
+                    r#"
+                    CREATE TABLE fuzzy_keywords(
+                        variant TEXT NOT NULL,
+                        keyword TEXT NOT NULL,
+                        provider INTEGER NOT NULL,
+                        record_id TEXT NOT NULL,
+                        PRIMARY KEY (provider, variant, keyword, record_id)
+                    ) WITHOUT ROWID;
+                    CREATE INDEX fuzzy_keywords_record_id ON fuzzy_keywords(record_id);
+                    "#,
+                )?;
+                Ok(())
+            }
+            45 => {
+                // Like dismissals, these are user data, so they're not
+                // cleared along with the suggestions.
+                tx.execute_batch(
+                    r#"
+                    CREATE TABLE show_less_frequently(
+                        provider INTEGER NOT NULL,
+                        suggestion_key TEXT NOT NULL,
+                        count INTEGER NOT NULL,
+                        min_keyword_length INTEGER NOT NULL,
+                        PRIMARY KEY (provider, suggestion_key)
+                    ) WITHOUT ROWID;
+                    CREATE TABLE impressions(
+                        provider INTEGER NOT NULL,
+                        suggestion_key TEXT NOT NULL,
+                        timestamp INTEGER NOT NULL
+                    );
+                    CREATE INDEX impressions_provider ON impressions(provider, suggestion_key, timestamp);
+                    "#,
+                )?;
+                Ok(())
+            }
+            46 => {
+                // Records that were ingested before this version are treated
+                // as untargeted until they change, so there's no need to
+                // clear the database.
+                tx.execute_batch(
+                    r#"
+                    CREATE TABLE record_targeting(
+                        record_id TEXT NOT NULL,
+                        collection TEXT NOT NULL,
+                        targeting TEXT NOT NULL,
+                        PRIMARY KEY (record_id, collection)
+                    ) WITHOUT ROWID;
+                    CREATE TRIGGER record_targeting_delete AFTER DELETE ON ingested_records BEGIN
+                        DELETE FROM record_targeting
+                        WHERE record_id = old.id AND collection = old.collection;
+                    END;
+                    "#,
+                )?;
+                Ok(())
+            }
+            47 => {
+                // Records that already have fuzzy keywords don't need to be
+                // reingested. Ones with only short keywords are reingested
+                // once more, and then marked.
+                tx.execute_batch(
+                    r#"
+                    CREATE TABLE fuzzy_records(
+                        record_id TEXT PRIMARY KEY
+                    ) WITHOUT ROWID;
+                    INSERT INTO fuzzy_records(record_id)
+                    SELECT DISTINCT record_id FROM fuzzy_keywords;
+                    "#,
+                )?;
+                Ok(())
+            }
 
             _ => Err(open_database::Error::IncompatibleVersion(version)),
         }
@@ -873,6 +1000,8 @@ pub fn clear_database(db: &Connection) -> rusqlite::Result<()> {
     )?;
     let conditional_tables = [
         "fakespot_fts",
+        "fuzzy_keywords",
+        "fuzzy_records",
         "geonames",
         "geonames_metrics",
         "ingested_records",
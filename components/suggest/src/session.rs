@@ -0,0 +1,335 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! As-you-type query sessions.
+//!
+//! The address bar queries Suggest on every keystroke, and each query usually
+//! extends the one before it. A [SuggestQuerySession] keeps state between
+//! those queries, so that it doesn't redo the same work:
+//!
+//! * It caches each provider's candidates by keyword, so backspacing and
+//!   retyping doesn't query the database again.
+//! * Providers like AMO only match keywords that start with the query, so
+//!   a keyword that extends another one can only match the other keyword's
+//!   candidates. The session narrows those candidates instead of querying
+//!   the database again.
+//! * It keeps the DAO caches, like the geoname cache, warm between queries.
+//!
+//! Cached candidates are dropped when the store's data changes, or when the
+//! query's provider constraints or limit change. Candidates are still filtered
+//! by engagement and ranked on every query, so impressions and "show less
+//! frequently" take effect right away.
+
+use std::{
+    cmp::Ordering as CmpOrdering,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use error_support::handle_error;
+use parking_lot::Mutex;
+
+use crate::{
+    db::DaoCaches,
+    error::Error,
+    metrics::SuggestQueryMetrics,
+    provider::{SuggestionProvider, SuggestionProviderConstraints},
+    store::{SuggestStore, SuggestStoreInner},
+    util::split_keyword,
+    QueryWithMetricsResult, Result, SuggestApiResult, Suggestion, SuggestionQuery,
+};
+
+/// The most keywords that a session caches candidates for. The cache starts
+/// over when it's full.
+const MAX_CACHED_KEYWORDS: usize = 128;
+
+/// The ID of the next query session.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A query session for a single search, like one address bar interaction.
+///
+/// Create one with [SuggestStore::new_query_session].
+#[derive(uniffi::Object)]
+pub struct SuggestQuerySession {
+    store: Arc<SuggestStore>,
+    session: QuerySession,
+}
+
+impl SuggestQuerySession {
+    pub(crate) fn new(store: Arc<SuggestStore>) -> Self {
+        Self {
+            store,
+            session: QuerySession::default(),
+        }
+    }
+}
+
+#[uniffi::export]
+impl SuggestQuerySession {
+    /// Queries for suggestions, reusing work from this session's earlier
+    /// queries.
+    #[handle_error(Error)]
+    pub fn query(&self, query: SuggestionQuery) -> SuggestApiResult<Vec<Suggestion>> {
+        Ok(self.session.query(&self.store.inner, query)?.suggestions)
+    }
+
+    /// Queries for suggestions, reusing work from this session's earlier
+    /// queries.
+    #[handle_error(Error)]
+    pub fn query_with_metrics(
+        &self,
+        query: SuggestionQuery,
+    ) -> SuggestApiResult<QueryWithMetricsResult> {
+        self.session.query(&self.store.inner, query)
+    }
+
+    /// Interrupts this session's in-flight query, which fails with
+    /// `SuggestApiError::Interrupted`. Queries that start after this call,
+    /// and queries from other sessions and the store, aren't affected.
+    pub fn interrupt(&self) {
+        self.session.interrupt(&self.store.inner)
+    }
+}
+
+/// The implementation of a query session. This is split out from
+/// [SuggestQuerySession] so that it can be tested with a mock store.
+pub(crate) struct QuerySession {
+    /// Identifies this session's reads, so that it can interrupt them
+    /// without interrupting other reads on the store.
+    id: u64,
+    state: Mutex<QuerySessionState>,
+    /// Incremented by [QuerySession::interrupt]. A query fails if this changes
+    /// while it's running.
+    interrupt_count: AtomicU64,
+}
+
+impl Default for QuerySession {
+    fn default() -> Self {
+        Self {
+            id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+            state: Mutex::default(),
+            interrupt_count: AtomicU64::default(),
+        }
+    }
+}
+
+impl QuerySession {
+    pub fn query<S>(
+        &self,
+        store: &SuggestStoreInner<S>,
+        query: SuggestionQuery,
+    ) -> Result<QueryWithMetricsResult> {
+        let interrupt_count = self.interrupt_count.load(Ordering::Acquire);
+        let mut state = self.state.lock();
+        state.reset_if_stale(store.data_generation(), &query);
+        let interrupted = || self.interrupt_count.load(Ordering::Acquire) != interrupt_count;
+        store.query_providers(query, |query, provider, metrics| {
+            if interrupted() {
+                return Err(interrupt_support::Interrupted.into());
+            }
+            state.fetch(store, self.id, &interrupted, query, provider, metrics)
+        })
+    }
+
+    pub fn interrupt<S>(&self, store: &SuggestStoreInner<S>) {
+        self.interrupt_count.fetch_add(1, Ordering::AcqRel);
+        store.interrupt_session_read(self.id);
+    }
+}
+
+#[derive(Default)]
+struct QuerySessionState {
+    /// The store's data generation when the candidates were fetched.
+    data_generation: u64,
+    /// The provider constraints and limit of the queries that the candidates
+    /// were fetched for.
+    provider_constraints: Option<SuggestionProviderConstraints>,
+    limit: Option<i32>,
+    /// Each provider's candidates, by query keyword.
+    candidates: HashMap<(SuggestionProvider, String), Candidates>,
+    caches: DaoCaches,
+}
+
+/// A provider's candidates for a keyword.
+struct Candidates {
+    suggestions: Vec<Suggestion>,
+    /// For providers that match by prefix, the keyword suffixes and ranks that
+    /// each suggestion can match, by URL. This is `None` if the candidates
+    /// can't be narrowed.
+    suffixes: Option<HashMap<String, Vec<(String, i64)>>>,
+}
+
+impl QuerySessionState {
+    /// Drops the cached candidates if the data changed, or they were fetched
+    /// for a different kind of query.
+    fn reset_if_stale(&mut self, data_generation: u64, query: &SuggestionQuery) {
+        if self.data_generation != data_generation {
+            // The DAO caches are built from the data, too.
+            self.data_generation = data_generation;
+            self.candidates.clear();
+            self.caches = DaoCaches::default();
+        }
+        if self.provider_constraints != query.provider_constraints || self.limit != query.limit {
+            self.provider_constraints = query.provider_constraints.clone();
+            self.limit = query.limit;
+            self.candidates.clear();
+        }
+    }
+
+    fn fetch<S>(
+        &mut self,
+        store: &SuggestStoreInner<S>,
+        session_id: u64,
+        interrupted: &dyn Fn() -> bool,
+        query: &SuggestionQuery,
+        provider: SuggestionProvider,
+        metrics: &mut SuggestQueryMetrics,
+    ) -> Result<Vec<Suggestion>> {
+        metrics.measure_query(provider.to_string(), || {
+            let key = (provider, query.keyword.clone());
+            if let Some(candidates) = self.candidates.get(&key) {
+                return Ok(candidates.suggestions.clone());
+            }
+            let candidates = match self.narrow(query, provider) {
+                Some(candidates) => candidates,
+                None => {
+                    let result =
+                        store.session_read(session_id, interrupted, &mut self.caches, |dao| {
+                            let suggestions = dao.fetch_suggestions(query, provider)?;
+                            let suffixes = if !can_narrow(query, provider, &suggestions) {
+                                None
+                            } else if suggestions.is_empty() {
+                                Some(HashMap::new())
+                            } else {
+                                Some(dao.fetch_prefix_keyword_suffixes(query, provider)?)
+                            };
+                            Ok(Candidates {
+                                suggestions,
+                                suffixes,
+                            })
+                        });
+                    match result {
+                        Ok(candidates) => candidates,
+                        Err(e) => {
+                            // An interrupted read can leave the caches
+                            // half-built, so start them over.
+                            self.caches = DaoCaches::default();
+                            return Err(e);
+                        }
+                    }
+                }
+            };
+            let suggestions = candidates.suggestions.clone();
+            if self.candidates.len() >= MAX_CACHED_KEYWORDS {
+                self.candidates.clear();
+            }
+            self.candidates.insert(key, candidates);
+            Ok(suggestions)
+        })
+    }
+
+    /// Narrows the candidates for the longest cached keyword that the query's
+    /// keyword extends, if the provider matches by prefix. Returns `None` if
+    /// there aren't any candidates to narrow.
+    fn narrow(&self, query: &SuggestionQuery, provider: SuggestionProvider) -> Option<Candidates> {
+        if !matches_by_prefix(query, provider) {
+            return None;
+        }
+        let keyword = query.keyword.to_lowercase();
+        let (keyword_prefix, keyword_suffix) = split_keyword(&keyword);
+        let (_, candidates, suffixes) = self
+            .candidates
+            .iter()
+            .filter_map(|((candidate_provider, candidate_keyword), candidates)| {
+                let candidate_keyword = candidate_keyword.to_lowercase();
+                let suffixes = candidates.suffixes.as_ref()?;
+                (*candidate_provider == provider
+                    && keyword.starts_with(&candidate_keyword)
+                    && split_keyword(&candidate_keyword).0 == keyword_prefix)
+                    .then_some((candidate_keyword.len(), candidates, suffixes))
+            })
+            .max_by_key(|(len, _, _)| *len)?;
+
+        // Keep the suffixes that still match, and the suggestions with at
+        // least one of them.
+        let suffixes: HashMap<String, Vec<(String, i64)>> = suffixes
+            .iter()
+            .filter_map(|(url, suffixes)| {
+                let suffixes: Vec<_> = suffixes
+                    .iter()
+                    .filter(|(suffix, _)| suffix.starts_with(keyword_suffix))
+                    .cloned()
+                    .collect();
+                (!suffixes.is_empty()).then(|| (url.clone(), suffixes))
+            })
+            .collect();
+        let mut ranked: Vec<_> = candidates
+            .suggestions
+            .iter()
+            .filter_map(|suggestion| {
+                let rank = suffixes
+                    .get(suggestion.raw_url()?)?
+                    .iter()
+                    .map(|(_, rank)| *rank)
+                    .max()?;
+                Some((suggestion, rank))
+            })
+            .collect();
+        // Order them like `SuggestDao::map_prefix_keywords`, by score and then
+        // by the rank of their best matching keyword.
+        ranked.sort_by(|(a, a_rank), (b, b_rank)| {
+            b.score()
+                .partial_cmp(&a.score())
+                .unwrap_or(CmpOrdering::Equal)
+                .then(b_rank.cmp(a_rank))
+        });
+        Some(Candidates {
+            suggestions: ranked
+                .into_iter()
+                .map(|(suggestion, _)| suggestion.clone())
+                .collect(),
+            suffixes: Some(suffixes),
+        })
+    }
+}
+
+/// Returns true if the candidates for a query can be narrowed for keywords
+/// that extend the query's keyword.
+///
+/// The provider has to match by prefix, and the candidates have to be complete:
+/// if the query's limit cut them off, a longer keyword might match suggestions
+/// that aren't among them. Candidates are matched to their keywords by URL, so
+/// they also need different URLs.
+fn can_narrow(
+    query: &SuggestionQuery,
+    provider: SuggestionProvider,
+    suggestions: &[Suggestion],
+) -> bool {
+    let is_complete = query
+        .limit
+        .is_none_or(|limit| (suggestions.len() as i64) < i64::from(limit));
+    let mut urls = HashSet::new();
+    matches_by_prefix(query, provider)
+        && is_complete
+        && suggestions
+            .iter()
+            .all(|suggestion| suggestion.raw_url().is_some_and(|url| urls.insert(url)))
+}
+
+/// Returns true if the provider only matches keywords whose first word is the
+/// query's first word, and whose rest starts with the rest of the query. See
+/// `SuggestDao::map_prefix_keywords`.
+fn matches_by_prefix(query: &SuggestionQuery, provider: SuggestionProvider) -> bool {
+    match provider {
+        SuggestionProvider::Amo => true,
+        // Fuzzy matching can find candidates that the keyword's prefix
+        // doesn't match.
+        SuggestionProvider::Mdn => !query.uses_fuzzy_matching(),
+        _ => false,
+    }
+}
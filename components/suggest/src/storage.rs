@@ -0,0 +1,378 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Storage budgets for the Suggest database.
+//!
+//! Consumers can give the store a maximum database size and a priority order
+//! for providers, with [crate::SuggestStoreBuilder::storage_budget]. After
+//! each ingestion, if the database is over budget, the store evicts the data
+//! for the lowest-priority providers, then VACUUMs the database to give the
+//! freed pages back to the OS. Evicted providers aren't ingested again until
+//! the database is cleared, or the budget grows.
+//!
+//! SQLite can't cheaply tell us how much space each provider uses, so we
+//! estimate it from what each provider's records add to the database.  Icon
+//! blobs are counted by their length, and the rest of the database is shared
+//! out by the number of rows.  A provider's usage only includes record types
+//! that no other provider depends on, like AMP icons; shared record types
+//! like geonames stay resident as long as any provider that uses them does.
+
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::named_params;
+use serde::{Deserialize, Serialize};
+use sql_support::ConnExt;
+
+use crate::{db::SuggestDao, provider::SuggestionProvider, rs::SuggestRecordId, Result};
+
+/// The metadata key whose value is a JSON string encoding an
+/// [`EvictionState`].
+const EVICTION_STATE_META_KEY: &str = "storage_eviction";
+
+/// Full-text search tables whose `rowid` is a suggestion ID. Other tables
+/// are found by their `suggestion_id` and `record_id` columns.
+const SUGGESTION_FTS_TABLES: [&str; 2] = ["amp_fts", "fakespot_fts"];
+
+/// A disk budget for the Suggest database.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct SuggestStorageBudget {
+    /// The maximum size of the database, in bytes.
+    pub max_bytes: u64,
+    /// Providers in priority order, highest first. When the database is over
+    /// budget, providers that aren't in this list are evicted first, followed
+    /// by the providers at the end of the list.
+    #[uniffi(default)]
+    pub provider_priority: Vec<SuggestionProvider>,
+}
+
+impl SuggestStorageBudget {
+    /// Returns the providers in the order they should be evicted.
+    fn eviction_order(&self) -> Vec<SuggestionProvider> {
+        SuggestionProvider::all()
+            .into_iter()
+            .rev()
+            .filter(|provider| !self.provider_priority.contains(provider))
+            .chain(self.provider_priority.iter().rev().copied())
+            .collect()
+    }
+}
+
+/// How much space the Suggest database uses.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct SuggestStorageUsage {
+    /// The size of the database, in bytes, not counting free pages.
+    pub db_size_bytes: u64,
+    /// Estimated usage for each provider with data in the database.
+    pub providers: Vec<SuggestProviderStorageUsage>,
+    /// Providers that were evicted to stay within the storage budget.
+    pub evicted_providers: Vec<SuggestionProvider>,
+}
+
+/// How much space a provider uses in the Suggest database.
+#[derive(Clone, Debug, PartialEq, uniffi::Record)]
+pub struct SuggestProviderStorageUsage {
+    pub provider: SuggestionProvider,
+    /// The number of rows for the provider's records.
+    pub row_count: u64,
+    /// The estimated size of those rows, in bytes.
+    pub estimated_bytes: u64,
+}
+
+/// The providers evicted under a budget.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EvictionState {
+    max_bytes: u64,
+    providers: Vec<SuggestionProvider>,
+}
+
+/// A record type in a collection, as stored in `ingested_records`.
+type CollectionRecordType = (String, String);
+
+/// The rows that one or more record types added to the database.
+#[derive(Clone, Copy, Debug, Default)]
+struct RowUsage {
+    count: u64,
+    /// The total length of the icon blobs in those rows.
+    blob_bytes: u64,
+}
+
+impl std::iter::Sum<RowUsage> for RowUsage {
+    fn sum<I: Iterator<Item = RowUsage>>(iter: I) -> Self {
+        iter.fold(RowUsage::default(), |total, usage| RowUsage {
+            count: total.count + usage.count,
+            blob_bytes: total.blob_bytes + usage.blob_bytes,
+        })
+    }
+}
+
+impl<'a> std::iter::Sum<&'a RowUsage> for RowUsage {
+    fn sum<I: Iterator<Item = &'a RowUsage>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// Estimates how many bytes rows take up in the database.
+struct SizeEstimator {
+    db_size: u64,
+    total: RowUsage,
+}
+
+impl SizeEstimator {
+    fn new(db_size: u64, row_usage: &HashMap<CollectionRecordType, RowUsage>) -> Self {
+        Self {
+            db_size,
+            total: row_usage.values().sum(),
+        }
+    }
+
+    /// Blobs are counted by their length, and the rest of the database is
+    /// shared out by the number of rows.
+    fn estimate(&self, rows: RowUsage) -> u64 {
+        if self.total.count == 0 {
+            return 0;
+        }
+        let rest = self.db_size.saturating_sub(self.total.blob_bytes);
+        rows.blob_bytes + ((rows.count as f64 / self.total.count as f64) * rest as f64) as u64
+    }
+}
+
+/// Returns the record types that only the `evicted` providers use.
+fn evictable_record_types(evicted: &HashSet<SuggestionProvider>) -> HashSet<CollectionRecordType> {
+    let record_types = |provider: SuggestionProvider| {
+        provider
+            .record_types_by_collection()
+            .into_iter()
+            .flat_map(|(collection, record_types)| {
+                record_types
+                    .into_iter()
+                    .map(move |record_type| (collection.name().to_owned(), record_type.to_string()))
+            })
+            .collect::<HashSet<_>>()
+    };
+    let resident = SuggestionProvider::all()
+        .into_iter()
+        .filter(|provider| !evicted.contains(provider))
+        .flat_map(&record_types)
+        .collect::<HashSet<_>>();
+    evicted
+        .iter()
+        .flat_map(|provider| record_types(*provider))
+        .filter(|record_type| !resident.contains(record_type))
+        .collect()
+}
+
+impl SuggestDao<'_> {
+    /// Returns the size of the database, in bytes, not counting free pages.
+    pub(crate) fn db_size(&self) -> Result<u64> {
+        Ok(self.conn.conn_ext_query_one::<i64>(
+            "SELECT page_size * (page_count - freelist_count)
+             FROM pragma_page_count(), pragma_page_size(), pragma_freelist_count()",
+        )? as u64)
+    }
+
+    /// Returns the names of the tables with a column named `column`.
+    fn tables_with_column(&self, column: &str) -> Result<Vec<String>> {
+        self.conn.query_rows_and_then(
+            "SELECT m.name FROM sqlite_schema m
+             JOIN pragma_table_info(m.name) p
+             WHERE m.type = 'table' AND p.name = :column
+             ORDER BY m.name",
+            named_params! { ":column": column },
+            |row| -> Result<_> { Ok(row.get(0)?) },
+        )
+    }
+
+    /// Returns the rows that the records of each record type added to the
+    /// database.
+    fn record_type_row_usage(&self) -> Result<HashMap<CollectionRecordType, RowUsage>> {
+        let suggestion_tables = self
+            .tables_with_column("suggestion_id")?
+            .into_iter()
+            .map(|table| (table, "suggestion_id"))
+            .chain(
+                SUGGESTION_FTS_TABLES
+                    .iter()
+                    .map(|table| (table.to_string(), "rowid")),
+            )
+            .map(|(table, column)| {
+                format!(
+                    "SELECT s.record_id, count(*) AS n, 0 AS bytes FROM {table} t
+                     JOIN suggestions s ON s.id = t.{column}
+                     GROUP BY s.record_id"
+                )
+            })
+            .collect::<Vec<_>>();
+        let record_tables = self
+            .tables_with_column("record_id")?
+            .into_iter()
+            .map(|table| {
+                format!(
+                    "SELECT record_id, count(*) AS n, 0 AS bytes FROM {table} GROUP BY record_id"
+                )
+            })
+            .collect::<Vec<_>>();
+        let row_usage_by_record = suggestion_tables
+            .into_iter()
+            .chain(record_tables)
+            .chain(std::iter::once(
+                "SELECT 'icon-' || id, 1 AS n, length(data) AS bytes FROM icons".to_owned(),
+            ))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        self.conn
+            .query_rows_and_then(
+                &format!(
+                    "SELECT i.collection, i.type, sum(r.n), sum(r.bytes)
+                     FROM ({row_usage_by_record}) r
+                     JOIN ingested_records i ON i.id = r.record_id
+                     GROUP BY i.collection, i.type"
+                ),
+                (),
+                |row| -> Result<_> {
+                    Ok((
+                        (row.get(0)?, row.get(1)?),
+                        RowUsage {
+                            count: row.get::<_, i64>(2)? as u64,
+                            blob_bytes: row.get::<_, i64>(3)? as u64,
+                        },
+                    ))
+                },
+            )
+            .map(|rows: Vec<_>| rows.into_iter().collect())
+    }
+
+    /// Returns the providers evicted under `budget`.
+    ///
+    /// Evictions are forgotten when the budget grows, so that the providers
+    /// can be ingested again.
+    pub(crate) fn evicted_providers(
+        &self,
+        budget: &SuggestStorageBudget,
+    ) -> Result<Vec<SuggestionProvider>> {
+        Ok(self
+            .get_eviction_state()?
+            .filter(|state| state.max_bytes >= budget.max_bytes)
+            .map(|state| state.providers)
+            .unwrap_or_default())
+    }
+
+    fn get_eviction_state(&self) -> Result<Option<EvictionState>> {
+        self.get_meta::<String>(EVICTION_STATE_META_KEY)?
+            .map(|json| Ok(serde_json::from_str(&json)?))
+            .transpose()
+    }
+
+    /// Estimates how much space each provider uses.
+    pub(crate) fn storage_usage(
+        &self,
+        budget: Option<&SuggestStorageBudget>,
+    ) -> Result<SuggestStorageUsage> {
+        let db_size_bytes = self.db_size()?;
+        let row_usage = self.record_type_row_usage()?;
+        let estimator = SizeEstimator::new(db_size_bytes, &row_usage);
+        let mut providers = vec![];
+        for provider in SuggestionProvider::all() {
+            let rows = evictable_record_types(&HashSet::from([provider]))
+                .iter()
+                .filter_map(|record_type| row_usage.get(record_type))
+                .sum::<RowUsage>();
+            if rows.count > 0 {
+                providers.push(SuggestProviderStorageUsage {
+                    provider,
+                    row_count: rows.count,
+                    estimated_bytes: estimator.estimate(rows),
+                });
+            }
+        }
+        let evicted_providers = match budget {
+            Some(budget) => self.evicted_providers(budget)?,
+            None => vec![],
+        };
+        Ok(SuggestStorageUsage {
+            db_size_bytes,
+            providers,
+            evicted_providers,
+        })
+    }
+
+    /// Evicts data for the lowest-priority providers until the database is
+    /// estimated to fit in the budget, and returns the newly evicted
+    /// providers.  The database should be VACUUMed afterward to actually
+    /// shrink it.
+    pub(crate) fn evict_for_storage_budget(
+        &mut self,
+        budget: &SuggestStorageBudget,
+    ) -> Result<Vec<SuggestionProvider>> {
+        let mut evicted = self
+            .evicted_providers(budget)?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let db_size = self.db_size()?;
+        let row_usage = self.record_type_row_usage()?;
+        let estimator = SizeEstimator::new(db_size, &row_usage);
+        let mut estimated_size = db_size;
+        let mut newly_evicted = vec![];
+        for provider in budget.eviction_order() {
+            if estimated_size <= budget.max_bytes {
+                break;
+            }
+            if evicted.contains(&provider) {
+                continue;
+            }
+            let already_evictable = evictable_record_types(&evicted);
+            let mut candidate = evicted.clone();
+            candidate.insert(provider);
+            let record_types = evictable_record_types(&candidate)
+                .into_iter()
+                .filter(|record_type| !already_evictable.contains(record_type))
+                .collect::<Vec<_>>();
+            let freed_rows = record_types
+                .iter()
+                .filter_map(|record_type| row_usage.get(record_type))
+                .sum::<RowUsage>();
+            if freed_rows.count == 0 {
+                // Evicting a provider without data wouldn't help, and would
+                // stop us from ingesting it later.
+                continue;
+            }
+            for (collection, record_type) in &record_types {
+                self.delete_record_type_data(collection, record_type)?;
+            }
+            estimated_size = estimated_size.saturating_sub(estimator.estimate(freed_rows));
+            evicted = candidate;
+            newly_evicted.push(provider);
+        }
+        if !newly_evicted.is_empty() {
+            let mut providers = evicted.into_iter().collect::<Vec<_>>();
+            providers.sort_by_key(|provider| *provider as u8);
+            self.put_meta(
+                EVICTION_STATE_META_KEY,
+                serde_json::to_string(&EvictionState {
+                    max_bytes: budget.max_bytes,
+                    providers,
+                })?,
+            )?;
+        }
+        Ok(newly_evicted)
+    }
+
+    /// Deletes the data for all ingested records of a record type, so that
+    /// they'll be ingested again if the record type is needed later.
+    fn delete_record_type_data(&mut self, collection: &str, record_type: &str) -> Result<()> {
+        let record_ids = self.conn.query_rows_and_then(
+            "SELECT id FROM ingested_records WHERE collection = :collection AND type = :type",
+            named_params! { ":collection": collection, ":type": record_type },
+            |row| -> Result<_> { Ok(SuggestRecordId::new(row.get(0)?)) },
+        )?;
+        for record_id in &record_ids {
+            self.delete_record_data(record_id)?;
+        }
+        self.conn.execute_cached(
+            "DELETE FROM ingested_records WHERE collection = :collection AND type = :type",
+            named_params! { ":collection": collection, ":type": record_type },
+        )?;
+        Ok(())
+    }
+}
@@ -6,27 +6,50 @@
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+#[cfg(feature = "merino")]
+use std::{
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
 };
 
 use error_support::{breadcrumb, handle_error, trace};
+#[cfg(feature = "merino")]
+use merino::suggest::{MerinoSuggestClient, MerinoSuggestRequest};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use remote_settings::{self, RemoteSettingsError, RemoteSettingsServer, RemoteSettingsService};
+use remote_settings::{
+    self, RemoteSettingsContext, RemoteSettingsError, RemoteSettingsServer, RemoteSettingsService,
+};
 
 use serde::de::DeserializeOwned;
 
+#[cfg(feature = "merino")]
+use crate::online::{MerinoQueryOptions, MerinoQueryResult, MerinoWorker, OnlineStatus};
 use crate::{
-    config::{SuggestGlobalConfig, SuggestProviderConfig},
-    db::{ConnectionType, IngestedRecord, Sqlite3Extension, SuggestDao, SuggestDb},
+    config::{SuggestEngagementConfig, SuggestGlobalConfig, SuggestProviderConfig},
+    db::{
+        ConnectionType, DaoCaches, IngestedRecord, Sqlite3Extension, SuggestDao, SuggestDb,
+        WriteScope,
+    },
+    engagement::now_secs,
     error::Error,
+    explain::SuggestQueryExplanation,
     geoname::{Geoname, GeonameAlternates, GeonameMatch},
+    market::SuggestMarket,
     metrics::{MetricsContext, SuggestIngestionMetrics, SuggestQueryMetrics},
+    progress::{ProgressReporter, SuggestIngestionProgressListener},
     provider::{SuggestionProvider, SuggestionProviderConstraints, DEFAULT_INGEST_PROVIDERS},
     rs::{
         Client, Collection, DownloadedDynamicRecord, Record, SuggestAttachment, SuggestRecord,
         SuggestRecordId, SuggestRecordType, SuggestRemoteSettingsClient,
     },
+    session::SuggestQuerySession,
+    storage::{SuggestStorageBudget, SuggestStorageUsage},
     QueryWithMetricsResult, Result, SuggestApiResult, Suggestion, SuggestionQuery,
 };
 
@@ -44,6 +67,12 @@ struct SuggestStoreBuilderInner {
     remote_settings_service: Option<Arc<RemoteSettingsService>>,
     remote_settings_bucket_name: Option<String>,
     extensions_to_load: Vec<Sqlite3Extension>,
+    #[cfg(feature = "merino")]
+    merino_client: Option<Arc<MerinoSuggestClient>>,
+    storage_budget: Option<SuggestStorageBudget>,
+    app_context: Option<RemoteSettingsContext>,
+    #[cfg(feature = "relevancy")]
+    relevancy_store: Option<Arc<relevancy::RelevancyStore>>,
 }
 
 impl Default for SuggestStoreBuilder {
@@ -105,6 +134,29 @@ impl SuggestStoreBuilder {
         self
     }
 
+    /// Limit the size of the database.
+    ///
+    /// After each ingestion, if the database is larger than the budget, the
+    /// store evicts data for the lowest-priority providers and stops
+    /// ingesting them. See [SuggestStorageBudget] for details.
+    pub fn storage_budget(self: Arc<Self>, budget: SuggestStorageBudget) -> Arc<Self> {
+        self.0.lock().storage_budget = Some(budget);
+        self
+    }
+
+    /// Only ingest and return suggestions for the app's market.
+    ///
+    /// Records for other locales, countries, or form factors aren't
+    /// downloaded. A record can be targeted with a JEXL `filter_expression`,
+    /// which is evaluated against `context`, or with `locales`, `countries`,
+    /// and `form_factors` fields, which are matched against the context's
+    /// `locale`, `country`, and `form_factor`. Suggestions that were ingested
+    /// for a different market are dropped when the store opens its database.
+    pub fn app_context(self: Arc<Self>, context: RemoteSettingsContext) -> Arc<Self> {
+        self.0.lock().app_context = Some(context);
+        self
+    }
+
     #[handle_error(Error)]
     pub fn build(&self) -> SuggestApiResult<Arc<SuggestStore>> {
         let inner = self.0.lock();
@@ -118,13 +170,46 @@ impl SuggestStoreBuilder {
                 reason: "remote_settings_service_not_specified".to_string(),
             })
         })?;
-        Ok(Arc::new(SuggestStore {
-            inner: SuggestStoreInner::new(
-                data_path,
-                extensions_to_load,
-                SuggestRemoteSettingsClient::new(&rs_service),
-            ),
-        }))
+        let mut store = SuggestStoreInner::new(
+            data_path,
+            extensions_to_load,
+            SuggestRemoteSettingsClient::new(&rs_service),
+        );
+        #[cfg(feature = "merino")]
+        if let Some(merino_client) = inner.merino_client.clone() {
+            store.merino = Some(MerinoWorker::new(merino_client));
+        }
+        store.storage_budget = inner.storage_budget.clone();
+        store.market = inner.app_context.clone().map(SuggestMarket::new);
+        #[cfg(feature = "relevancy")]
+        if let Some(relevancy_store) = inner.relevancy_store.clone() {
+            store.personalizer.set_relevancy_store(relevancy_store);
+        }
+        Ok(Arc::new(SuggestStore { inner: store }))
+    }
+}
+
+#[cfg(feature = "merino")]
+#[uniffi::export]
+impl SuggestStoreBuilder {
+    /// Fetch online suggestions from Merino in [SuggestStore::query_with_merino].
+    pub fn merino_client(self: Arc<Self>, client: Arc<MerinoSuggestClient>) -> Arc<Self> {
+        self.0.lock().merino_client = Some(client);
+        self
+    }
+}
+
+#[cfg(feature = "relevancy")]
+#[uniffi::export]
+impl SuggestStoreBuilder {
+    /// Personalize the ranking of suggestions using the user's interest vector
+    /// from a relevancy store.
+    pub fn relevancy_store(
+        self: Arc<Self>,
+        relevancy_store: Arc<relevancy::RelevancyStore>,
+    ) -> Arc<Self> {
+        self.0.lock().relevancy_store = Some(relevancy_store);
+        self
     }
 }
 
@@ -170,7 +255,7 @@ pub enum InterruptKind {
 ///    on the first launch.
 #[derive(uniffi::Object)]
 pub struct SuggestStore {
-    inner: SuggestStoreInner<SuggestRemoteSettingsClient>,
+    pub(crate) inner: SuggestStoreInner<SuggestRemoteSettingsClient>,
 }
 
 #[uniffi::export]
@@ -199,6 +284,31 @@ impl SuggestStore {
         self.inner.query(query)
     }
 
+    /// Starts a query session for a single search, like one address bar
+    /// interaction.
+    ///
+    /// A session is meant to be queried with each keystroke as the user
+    /// types. It reuses work from its earlier queries, so it's faster than
+    /// calling `query()` each time.
+    pub fn new_query_session(self: Arc<Self>) -> Arc<SuggestQuerySession> {
+        Arc::new(SuggestQuerySession::new(self))
+    }
+
+    /// Queries the database for suggestions, and explains how each provider
+    /// handled the query.
+    ///
+    /// The explanation includes the keywords and records that each provider
+    /// considered, the matching strategy it used, and why each candidate was
+    /// returned or dropped. This is slower than `query()`, and is meant for
+    /// debugging.
+    #[handle_error(Error)]
+    pub fn explain_query(
+        &self,
+        query: SuggestionQuery,
+    ) -> SuggestApiResult<SuggestQueryExplanation> {
+        self.inner.explain_query(query)
+    }
+
     /// Dismiss a suggestion.
     ///
     /// Dismissed suggestions cannot be fetched again.
@@ -263,6 +373,84 @@ impl SuggestStore {
         self.inner.any_dismissed_suggestions()
     }
 
+    /// Records a "show less frequently" click on a suggestion that was shown
+    /// for `keyword`.
+    ///
+    /// [SuggestStore::query] won't return the suggestion again for keywords
+    /// that are the same length as `keyword` or shorter. Clicks on suggestions
+    /// without dismissal keys, and online Merino suggestions, aren't recorded;
+    /// use [SuggestStore::record_show_less_frequently_for_provider] for those.
+    /// Clicks beyond the provider's `show_less_frequently_cap` aren't recorded
+    /// either.
+    #[handle_error(Error)]
+    pub fn record_show_less_frequently(
+        &self,
+        suggestion: &Suggestion,
+        keyword: &str,
+    ) -> SuggestApiResult<()> {
+        self.inner.record_show_less_frequently(suggestion, keyword)
+    }
+
+    /// Records a "show less frequently" click for all of a provider's
+    /// suggestions that were shown for `keyword`.
+    ///
+    /// [SuggestStore::query] won't return the provider's suggestions again
+    /// for keywords that are the same length as `keyword` or shorter.
+    #[handle_error(Error)]
+    pub fn record_show_less_frequently_for_provider(
+        &self,
+        provider: SuggestionProvider,
+        keyword: &str,
+    ) -> SuggestApiResult<()> {
+        self.inner
+            .record_show_less_frequently_for_provider(provider, keyword)
+    }
+
+    /// Returns the number of "show less frequently" clicks for a provider and
+    /// its suggestions.
+    #[handle_error(Error)]
+    pub fn show_less_frequently_count(
+        &self,
+        provider: SuggestionProvider,
+    ) -> SuggestApiResult<u32> {
+        self.inner.show_less_frequently_count(provider)
+    }
+
+    /// Returns whether a provider has reached its `show_less_frequently_cap`,
+    /// after which the UI shouldn't offer the command for its suggestions.
+    /// The cap is in the provider's [SuggestEngagementConfig], or the global
+    /// config if that doesn't have one. A cap of zero or less means there's no
+    /// cap.
+    #[handle_error(Error)]
+    pub fn is_show_less_frequently_capped(
+        &self,
+        provider: SuggestionProvider,
+    ) -> SuggestApiResult<bool> {
+        self.inner.is_show_less_frequently_capped(provider)
+    }
+
+    /// Clear "show less frequently" clicks for all providers.
+    #[handle_error(Error)]
+    pub fn clear_show_less_frequently(&self) -> SuggestApiResult<()> {
+        self.inner.clear_show_less_frequently()
+    }
+
+    /// Records an impression of a suggestion.
+    ///
+    /// [SuggestStore::query] won't return suggestions that have reached one
+    /// of the impression caps in their provider's [SuggestEngagementConfig].
+    /// Online Merino suggestions aren't recorded.
+    #[handle_error(Error)]
+    pub fn record_impression(&self, suggestion: &Suggestion) -> SuggestApiResult<()> {
+        self.inner.record_impression(suggestion)
+    }
+
+    /// Clear recorded impressions for all providers.
+    #[handle_error(Error)]
+    pub fn clear_impressions(&self) -> SuggestApiResult<()> {
+        self.inner.clear_impressions()
+    }
+
     /// Interrupts any ongoing queries.
     ///
     /// This should be called when the user types new input into the address
@@ -282,12 +470,45 @@ impl SuggestStore {
         self.inner.ingest(constraints)
     }
 
+    /// Ingests new suggestions from Remote Settings, reporting progress for
+    /// each record type to `listener`.
+    ///
+    /// Records are ingested one at a time, so if ingestion is interrupted,
+    /// the next call picks up where this one left off. This lets large
+    /// datasets be ingested in short background windows.
+    #[handle_error(Error)]
+    pub fn ingest_with_progress(
+        &self,
+        constraints: SuggestIngestionConstraints,
+        listener: Box<dyn SuggestIngestionProgressListener>,
+    ) -> SuggestApiResult<SuggestIngestionMetrics> {
+        self.inner
+            .ingest_with_progress(constraints, Some(listener.as_ref()))
+    }
+
     /// Removes all content from the database.
     #[handle_error(Error)]
     pub fn clear(&self) -> SuggestApiResult<()> {
         self.inner.clear()
     }
 
+    /// Returns the size of the database, and estimates how much of it each
+    /// provider uses.
+    #[handle_error(Error)]
+    pub fn storage_usage(&self) -> SuggestApiResult<SuggestStorageUsage> {
+        self.inner.storage_usage()
+    }
+
+    /// Evicts data for the lowest-priority providers if the database is over
+    /// the storage budget, and returns the newly evicted providers.
+    ///
+    /// [SuggestStore::ingest] does this automatically, so this is only needed
+    /// if the budget shrinks between ingestions.
+    #[handle_error(Error)]
+    pub fn enforce_storage_budget(&self) -> SuggestApiResult<Vec<SuggestionProvider>> {
+        self.inner.enforce_storage_budget()
+    }
+
     /// Returns global Suggest configuration data.
     #[handle_error(Error)]
     pub fn fetch_global_config(&self) -> SuggestApiResult<SuggestGlobalConfig> {
@@ -303,6 +524,15 @@ impl SuggestStore {
         self.inner.fetch_provider_config(provider)
     }
 
+    /// Returns a provider's "show less frequently" and impression caps.
+    #[handle_error(Error)]
+    pub fn fetch_engagement_config(
+        &self,
+        provider: SuggestionProvider,
+    ) -> SuggestApiResult<SuggestEngagementConfig> {
+        self.inner.fetch_engagement_config(provider)
+    }
+
     /// Fetches geonames stored in the database. A geoname represents a
     /// geographic place.
     ///
@@ -329,6 +559,39 @@ impl SuggestStore {
     }
 }
 
+#[cfg(feature = "merino")]
+#[uniffi::export]
+impl SuggestStore {
+    /// Queries the database and Merino for suggestions, and merges them.
+    ///
+    /// Online suggestions that duplicate offline ones or were dismissed are
+    /// dropped. If Merino doesn't respond within `options.timeout_ms`, or the
+    /// request fails, this returns offline suggestions only; the result's
+    /// `online_status` says what happened.
+    #[handle_error(Error)]
+    pub fn query_with_merino(
+        &self,
+        query: SuggestionQuery,
+        options: MerinoQueryOptions,
+    ) -> SuggestApiResult<MerinoQueryResult> {
+        self.inner.query_with_merino(query, options)
+    }
+}
+
+#[cfg(feature = "relevancy")]
+#[uniffi::export]
+impl SuggestStore {
+    /// Sets the user's interest vector for personalizing the ranking of
+    /// suggestions, for example one from `RelevancyStore::user_interest_vector()`.
+    ///
+    /// This takes precedence over the store passed to
+    /// [SuggestStoreBuilder::relevancy_store]. Pass `None` to go back to using
+    /// that store, if any.
+    pub fn set_interest_vector(&self, interest_vector: Option<relevancy::InterestVector>) {
+        self.inner.personalizer.set_interest_vector(interest_vector)
+    }
+}
+
 impl SuggestStore {
     pub fn force_reingest(&self) {
         self.inner.force_reingest()
@@ -398,6 +661,12 @@ impl SuggestIngestionConstraints {
             .map(|constraints| constraints.uses_fts())
             .unwrap_or(false)
     }
+
+    fn uses_fuzzy_matching(&self) -> bool {
+        self.provider_constraints
+            .as_ref()
+            .is_some_and(|c| c.fuzzy_matching)
+    }
 }
 
 /// The implementation of the store. This is generic over the Remote Settings
@@ -413,6 +682,17 @@ pub(crate) struct SuggestStoreInner<S> {
     dbs: OnceCell<SuggestStoreDbs>,
     extensions_to_load: Vec<Sqlite3Extension>,
     settings_client: S,
+    /// Sends requests to Merino, if the store was built with a Merino client.
+    #[cfg(feature = "merino")]
+    merino: Option<MerinoWorker>,
+    storage_budget: Option<SuggestStorageBudget>,
+    market: Option<SuggestMarket>,
+    /// Incremented whenever ingestion, eviction, clearing, or a dismissal
+    /// changes which suggestions a query can return. Query sessions use this
+    /// to tell when their cached results are stale.
+    data_generation: AtomicU64,
+    #[cfg(feature = "relevancy")]
+    personalizer: crate::personalization::Personalizer,
 }
 
 impl<S> SuggestStoreInner<S> {
@@ -426,49 +706,191 @@ impl<S> SuggestStoreInner<S> {
             extensions_to_load,
             dbs: OnceCell::new(),
             settings_client,
+            #[cfg(feature = "merino")]
+            merino: None,
+            storage_budget: None,
+            market: None,
+            data_generation: AtomicU64::new(0),
+            #[cfg(feature = "relevancy")]
+            personalizer: Default::default(),
         }
     }
 
     /// Returns this store's database connections, initializing them if
     /// they're not already open.
+    ///
+    /// If the store has a market, opening the database drops the data for
+    /// records that were ingested for a different one.
     fn dbs(&self) -> Result<&SuggestStoreDbs> {
-        self.dbs
-            .get_or_try_init(|| SuggestStoreDbs::open(&self.data_path, &self.extensions_to_load))
+        self.dbs.get_or_try_init(|| {
+            let dbs = SuggestStoreDbs::open(&self.data_path, &self.extensions_to_load)?;
+            if let Some(market) = &self.market {
+                let dropped = dbs
+                    .writer
+                    .write(|dao| dao.drop_records_outside_market(&market.filter()))?;
+                if !dropped.is_empty() {
+                    breadcrumb!("Dropped {} records for another market", dropped.len());
+                }
+            }
+            Ok(dbs)
+        })
+    }
+
+    /// Returns the current data generation. See `data_generation`.
+    pub(crate) fn data_generation(&self) -> u64 {
+        self.data_generation.load(Ordering::Acquire)
+    }
+
+    /// Notes that the suggestions a query can return may have changed.
+    fn data_changed(&self) {
+        self.data_generation.fetch_add(1, Ordering::AcqRel);
     }
 
     fn query(&self, query: SuggestionQuery) -> Result<QueryWithMetricsResult> {
+        let reader = &self.dbs()?.reader;
+        self.query_providers(query, |query, provider, metrics| {
+            metrics.measure_query(provider.to_string(), || {
+                reader.read(|dao| dao.fetch_suggestions(query, provider))
+            })
+        })
+    }
+
+    /// Fetches suggestions for each of the query's providers with `fetch`,
+    /// then filters, ranks, and truncates them.
+    pub(crate) fn query_providers(
+        &self,
+        query: SuggestionQuery,
+        mut fetch: impl FnMut(
+            &SuggestionQuery,
+            SuggestionProvider,
+            &mut SuggestQueryMetrics,
+        ) -> Result<Vec<Suggestion>>,
+    ) -> Result<QueryWithMetricsResult> {
         let mut metrics = SuggestQueryMetrics::default();
-        let mut suggestions = vec![];
+        let mut fetched = vec![];
 
         let unique_providers = query.providers.iter().collect::<HashSet<_>>();
         let reader = &self.dbs()?.reader;
         for provider in unique_providers {
-            let new_suggestions = metrics.measure_query(provider.to_string(), || {
-                reader.read(|dao| match provider {
-                    SuggestionProvider::Amp => dao.fetch_amp_suggestions(&query),
-                    SuggestionProvider::Wikipedia => dao.fetch_wikipedia_suggestions(&query),
-                    SuggestionProvider::Amo => dao.fetch_amo_suggestions(&query),
-                    SuggestionProvider::Yelp => dao.fetch_yelp_suggestions(&query),
-                    SuggestionProvider::Mdn => dao.fetch_mdn_suggestions(&query),
-                    SuggestionProvider::Weather => dao.fetch_weather_suggestions(&query),
-                    SuggestionProvider::Fakespot => dao.fetch_fakespot_suggestions(&query),
-                    SuggestionProvider::Dynamic => dao.fetch_dynamic_suggestions(&query),
-                })
-            })?;
-            suggestions.extend(new_suggestions);
+            fetched.extend(
+                fetch(&query, *provider, &mut metrics)?
+                    .into_iter()
+                    .map(|suggestion| ((), suggestion)),
+            );
+        }
+        let suggestions = reader.read(|dao| {
+            dao.rank_suggestions(
+                &query,
+                fetched,
+                now_secs(),
+                |suggestions| self.personalize(suggestions),
+                |_, _, _| {},
+            )
+        })?;
+        Ok(QueryWithMetricsResult {
+            suggestions,
+            query_times: metrics.times,
+        })
+    }
+
+    /// Runs a query session's read. See [SuggestDb::session_read].
+    pub(crate) fn session_read<T>(
+        &self,
+        session_id: u64,
+        interrupted: impl Fn() -> bool,
+        caches: &mut DaoCaches,
+        op: impl FnOnce(&SuggestDao) -> Result<T>,
+    ) -> Result<T> {
+        self.dbs()?
+            .reader
+            .session_read(session_id, interrupted, caches, op)
+    }
+
+    /// Interrupts a query session's read, if it's running.
+    pub(crate) fn interrupt_session_read(&self, session_id: u64) {
+        if let Some(dbs) = self.dbs.get() {
+            dbs.reader.interrupt_session_read(session_id);
         }
+    }
+
+    /// Re-scores suggestions using the user's interests, if personalization
+    /// is enabled.
+    fn personalize(&self, _suggestions: &mut [Suggestion]) {
+        #[cfg(feature = "relevancy")]
+        self.personalizer.personalize(_suggestions);
+    }
+
+    fn explain_query(&self, query: SuggestionQuery) -> Result<SuggestQueryExplanation> {
+        self.dbs()?.reader.read(|dao| {
+            dao.explain_query(&query, now_secs(), |suggestions| {
+                self.personalize(suggestions)
+            })
+        })
+    }
 
-        // Note: it's important that this is a stable sort to keep the intra-provider order stable.
-        // For example, we can return multiple fakespot-suggestions all with `score=0.245`.  In
-        // that case, they must be in the same order that `fetch_fakespot_suggestions` returned
-        // them in.
+    #[cfg(feature = "merino")]
+    fn query_with_merino(
+        &self,
+        query: SuggestionQuery,
+        options: MerinoQueryOptions,
+    ) -> Result<MerinoQueryResult> {
+        let Some(merino) = &self.merino else {
+            return Ok(MerinoQueryResult {
+                suggestions: self.query(query)?.suggestions,
+                online_status: OnlineStatus::NotConfigured,
+            });
+        };
+        let deadline = Instant::now() + Duration::from_millis(options.timeout_ms.into());
+
+        // Queue the online request first, so that it runs while we query the
+        // database. If it misses the deadline, the worker finishes it on its
+        // own and its result is dropped.
+        let request = MerinoSuggestRequest {
+            query: query.keyword.clone(),
+            providers: options.providers,
+            session_id: options.session_id,
+            sequence_number: options.sequence_number,
+            ..MerinoSuggestRequest::default()
+        };
+        let rx = merino.queue(request);
+
+        // Truncate after merging, so that online suggestions can outrank
+        // offline ones.
+        let limit = query.limit;
+        let offline = self
+            .query(SuggestionQuery {
+                limit: None,
+                ..query
+            })?
+            .suggestions;
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let (online, online_status) = match rx.recv_timeout(timeout) {
+            Ok(Ok(response)) => (response.suggestions, OnlineStatus::Success),
+            Ok(Err(e)) => {
+                error_support::warn!("Error fetching suggestions from Merino: {e}");
+                (
+                    vec![],
+                    OnlineStatus::Error {
+                        reason: e.to_string(),
+                    },
+                )
+            }
+            Err(RecvTimeoutError::Timeout) => (vec![], OnlineStatus::Timeout),
+            // The worker drops a pending request when a newer one is queued.
+            Err(RecvTimeoutError::Disconnected) => (vec![], OnlineStatus::Superseded),
+        };
+        let mut suggestions = self
+            .dbs()?
+            .reader
+            .read(|dao| dao.merge_online_suggestions(offline, online))?;
         suggestions.sort();
-        if let Some(limit) = query.limit.and_then(|limit| usize::try_from(limit).ok()) {
+        if let Some(limit) = limit.and_then(|limit| usize::try_from(limit).ok()) {
             suggestions.truncate(limit);
         }
-        Ok(QueryWithMetricsResult {
+        Ok(MerinoQueryResult {
             suggestions,
-            query_times: metrics.times,
+            online_status,
         })
     }
 
@@ -477,10 +899,12 @@ impl<S> SuggestStoreInner<S> {
             match suggestion {
                 Suggestion::Dynamic {
                     suggestion_type, ..
-                } => self
-                    .dbs()?
-                    .writer
-                    .write(|dao| dao.insert_dynamic_dismissal(suggestion_type, key))?,
+                } => {
+                    self.dbs()?
+                        .writer
+                        .write(|dao| dao.insert_dynamic_dismissal(suggestion_type, key))?;
+                    self.data_changed();
+                }
                 _ => self.dismiss_by_key(key)?,
             }
         }
@@ -488,17 +912,22 @@ impl<S> SuggestStoreInner<S> {
     }
 
     fn dismiss_by_key(&self, key: &str) -> Result<()> {
-        self.dbs()?.writer.write(|dao| dao.insert_dismissal(key))
+        self.dbs()?.writer.write(|dao| dao.insert_dismissal(key))?;
+        self.data_changed();
+        Ok(())
     }
 
     fn dismiss_suggestion(&self, suggestion_url: String) -> Result<()> {
         self.dbs()?
             .writer
-            .write(|dao| dao.insert_dismissal(&suggestion_url))
+            .write(|dao| dao.insert_dismissal(&suggestion_url))?;
+        self.data_changed();
+        Ok(())
     }
 
     fn clear_dismissed_suggestions(&self) -> Result<()> {
         self.dbs()?.writer.write(|dao| dao.clear_dismissals())?;
+        self.data_changed();
         Ok(())
     }
 
@@ -526,7 +955,62 @@ impl<S> SuggestStoreInner<S> {
         self.dbs()?.reader.read(|dao| dao.any_dismissals())
     }
 
-    fn interrupt(&self, kind: Option<InterruptKind>) {
+    fn record_show_less_frequently(&self, suggestion: &Suggestion, keyword: &str) -> Result<()> {
+        let Some(provider) = suggestion.provider() else {
+            return Ok(());
+        };
+        // Without a key, the click can't be recorded for just this suggestion.
+        let Some(suggestion_key) = suggestion.dismissal_key() else {
+            return Ok(());
+        };
+        self.dbs()?
+            .writer
+            .write(|dao| dao.insert_show_less_frequently(provider, suggestion_key, keyword))
+    }
+
+    fn record_show_less_frequently_for_provider(
+        &self,
+        provider: SuggestionProvider,
+        keyword: &str,
+    ) -> Result<()> {
+        self.dbs()?
+            .writer
+            .write(|dao| dao.insert_show_less_frequently(provider, "", keyword))
+    }
+
+    fn show_less_frequently_count(&self, provider: SuggestionProvider) -> Result<u32> {
+        self.dbs()?
+            .reader
+            .read(|dao| dao.show_less_frequently_count(provider))
+    }
+
+    fn is_show_less_frequently_capped(&self, provider: SuggestionProvider) -> Result<bool> {
+        self.dbs()?
+            .reader
+            .read(|dao| dao.is_show_less_frequently_capped(provider))
+    }
+
+    fn clear_show_less_frequently(&self) -> Result<()> {
+        self.dbs()?
+            .writer
+            .write(|dao| dao.clear_show_less_frequently())
+    }
+
+    fn record_impression(&self, suggestion: &Suggestion) -> Result<()> {
+        let Some(provider) = suggestion.provider() else {
+            return Ok(());
+        };
+        let suggestion_key = suggestion.dismissal_key().unwrap_or_default();
+        self.dbs()?
+            .writer
+            .write(|dao| dao.insert_impression(provider, suggestion_key, now_secs()))
+    }
+
+    fn clear_impressions(&self) -> Result<()> {
+        self.dbs()?.writer.write(|dao| dao.clear_impressions())
+    }
+
+    pub(crate) fn interrupt(&self, kind: Option<InterruptKind>) {
         if let Some(dbs) = self.dbs.get() {
             // Only interrupt if the databases are already open.
             match kind.unwrap_or(InterruptKind::Read) {
@@ -545,7 +1029,38 @@ impl<S> SuggestStoreInner<S> {
     }
 
     fn clear(&self) -> Result<()> {
-        self.dbs()?.writer.write(|dao| dao.clear())
+        self.dbs()?.writer.write(|dao| dao.clear())?;
+        self.data_changed();
+        Ok(())
+    }
+
+    fn storage_usage(&self) -> Result<SuggestStorageUsage> {
+        self.dbs()?
+            .reader
+            .read(|dao| dao.storage_usage(self.storage_budget.as_ref()))
+    }
+
+    fn enforce_storage_budget(&self) -> Result<Vec<SuggestionProvider>> {
+        self.evict_over_storage_budget(&mut self.dbs()?.writer.write_scope()?)
+    }
+
+    /// Evicts the lowest-priority providers if we're over budget, and
+    /// VACUUMs the database if we evicted any.
+    fn evict_over_storage_budget(
+        &self,
+        write_scope: &mut WriteScope<'_>,
+    ) -> Result<Vec<SuggestionProvider>> {
+        let Some(budget) = &self.storage_budget else {
+            return Ok(vec![]);
+        };
+        let evicted = write_scope.write(|dao| dao.evict_for_storage_budget(budget))?;
+        if !evicted.is_empty() {
+            self.data_changed();
+            breadcrumb!("Evicted providers over storage budget: {evicted:?}");
+            write_scope.err_if_interrupted()?;
+            write_scope.conn.execute_batch("VACUUM")?;
+        }
+        Ok(evicted)
     }
 
     pub fn fetch_global_config(&self) -> Result<SuggestGlobalConfig> {
@@ -561,6 +1076,15 @@ impl<S> SuggestStoreInner<S> {
             .read(|dao| dao.get_provider_config(provider))
     }
 
+    pub fn fetch_engagement_config(
+        &self,
+        provider: SuggestionProvider,
+    ) -> Result<SuggestEngagementConfig> {
+        self.dbs()?
+            .reader
+            .read(|dao| dao.get_engagement_config(provider))
+    }
+
     // Cause the next ingestion to re-ingest all data
     pub fn force_reingest(&self) {
         let writer = &self.dbs().unwrap().writer;
@@ -596,14 +1120,31 @@ where
     pub fn ingest(
         &self,
         constraints: SuggestIngestionConstraints,
+    ) -> Result<SuggestIngestionMetrics> {
+        self.ingest_with_progress(constraints, None)
+    }
+
+    pub fn ingest_with_progress(
+        &self,
+        constraints: SuggestIngestionConstraints,
+        listener: Option<&dyn SuggestIngestionProgressListener>,
     ) -> Result<SuggestIngestionMetrics> {
         breadcrumb!("Ingestion starting");
+        // Ingestion is when we pick up changes to the user's interests, too.
+        #[cfg(feature = "relevancy")]
+        self.personalizer.clear_cache();
         let writer = &self.dbs()?.writer;
         let mut metrics = SuggestIngestionMetrics::default();
         if constraints.empty_only && !writer.read(|dao| dao.suggestions_table_empty())? {
             return Ok(metrics);
         }
 
+        // Skip providers that were evicted to stay within the storage budget.
+        let evicted_providers = match &self.storage_budget {
+            Some(budget) => writer.read(|dao| dao.evicted_providers(budget))?,
+            None => vec![],
+        };
+
         // Figure out which record types we're ingesting and group them by
         // collection. A record type may be used by multiple providers, but we
         // want to ingest each one at most once. We always ingest some types
@@ -617,6 +1158,7 @@ where
             .as_ref()
             .unwrap_or(&DEFAULT_INGEST_PROVIDERS.to_vec())
             .iter()
+            .filter(|provider| !evicted_providers.contains(provider))
         {
             for (collection, provider_rts) in provider.record_types_by_collection() {
                 record_types_by_collection
@@ -638,28 +1180,53 @@ where
         // For each collection, fetch all records
         for (collection, record_types) in record_types_by_collection {
             breadcrumb!("Ingesting collection {}", collection.name());
-            let records = self.settings_client.get_records(collection)?;
+            let mut records = self.settings_client.get_records(collection)?;
+            // Skip records for other markets. Records that we ingested for a
+            // different market are treated as deleted.
+            if let Some(market) = &self.market {
+                let filter = market.filter();
+                records.retain(|record| {
+                    let matches = filter.matches(&record.targeting);
+                    if !matches {
+                        trace!("Skipping record for another market: {}", record.id);
+                    }
+                    matches
+                });
+            }
 
             // For each record type in that collection, calculate the changes and pass them to
-            // [Self::ingest_records]
+            // [Self::process_changes]
             for record_type in record_types {
                 breadcrumb!("Ingesting record_type: {record_type}");
+                let records_of_type = records
+                    .iter()
+                    .filter(|r| r.record_type() == record_type)
+                    .collect::<Vec<_>>();
+                let mut progress =
+                    ProgressReporter::new(listener, record_type.as_str(), records_of_type.len());
                 let changes = RecordChanges::new(
-                    records.iter().filter(|r| r.record_type() == record_type),
+                    records_of_type.into_iter(),
                     ingested_records.iter().filter(|i| {
                         i.record_type == record_type.as_str() && i.collection == collection.name()
                     }),
                 );
                 has_changes |= changes.has_changes();
                 metrics.measure_ingest(record_type.to_string(), |context| {
-                    write_scope.write(|dao| {
-                        self.process_changes(dao, collection, changes, &constraints, context)
-                    })
+                    self.process_changes(
+                        &mut write_scope,
+                        collection,
+                        changes,
+                        &constraints,
+                        context,
+                        &mut progress,
+                    )
                 })?;
                 write_scope.err_if_interrupted()?;
             }
         }
 
+        has_changes |= !self.evict_over_storage_budget(&mut write_scope)?.is_empty();
+
         // Truncate the WAL if the DB is updated without interruption.
         // This avoids the overhead iccurred by handling a large SQLite WAL.
         // See https://bugzilla.mozilla.org/show_bug.cgi?id=2005613
@@ -676,17 +1243,43 @@ where
         Ok(metrics)
     }
 
+    /// Ingests the changes for one record type.
+    ///
+    /// Each record is ingested and marked as ingested in its own transaction,
+    /// so that an interrupted ingestion keeps the records it finished.
     fn process_changes(
         &self,
-        dao: &mut SuggestDao,
+        write_scope: &mut WriteScope<'_>,
         collection: Collection,
         changes: RecordChanges<'_>,
         constraints: &SuggestIngestionConstraints,
         context: &mut MetricsContext,
+        progress: &mut ProgressReporter<'_>,
     ) -> Result<()> {
+        let reprocessed = write_scope.read(|dao| {
+            let mut reprocessed = vec![];
+            for record in &changes.unchanged {
+                if self.should_reprocess_record(dao, record, constraints)? {
+                    reprocessed.push(*record);
+                } else {
+                    trace!("Skipping unchanged record ID: {}", record.id.as_str());
+                }
+            }
+            Ok(reprocessed)
+        })?;
+        progress.start(changes.new.len() + changes.updated.len() + reprocessed.len());
+
         for record in &changes.new {
             trace!("Ingesting record ID: {}", record.id.as_str());
-            self.process_record(dao, record, constraints, context)?;
+            self.process_record_in_transaction(
+                write_scope,
+                collection,
+                record,
+                false,
+                constraints,
+                context,
+                progress,
+            )?;
         }
         for record in &changes.updated {
             // Drop any data that we previously ingested from this record.
@@ -694,50 +1287,98 @@ where
             // determining which suggestions in the record actually changed is
             // more complicated than dropping and re-ingesting all of them.
             trace!("Reingesting updated record ID: {}", record.id.as_str());
-            dao.delete_record_data(&record.id)?;
-            self.process_record(dao, record, constraints, context)?;
+            self.process_record_in_transaction(
+                write_scope,
+                collection,
+                record,
+                true,
+                constraints,
+                context,
+                progress,
+            )?;
         }
-        for record in &changes.unchanged {
-            if self.should_reprocess_record(dao, record, constraints)? {
-                trace!("Reingesting unchanged record ID: {}", record.id.as_str());
-                self.process_record(dao, record, constraints, context)?;
-            } else {
-                trace!("Skipping unchanged record ID: {}", record.id.as_str());
-            }
+        for record in &reprocessed {
+            // Drop the data we ingested last time so we don't duplicate it.
+            trace!("Reingesting unchanged record ID: {}", record.id.as_str());
+            self.process_record_in_transaction(
+                write_scope,
+                collection,
+                record,
+                true,
+                constraints,
+                context,
+                progress,
+            )?;
         }
-        for record in &changes.deleted {
-            trace!("Deleting record ID: {:?}", record.id);
-            dao.delete_record_data(&record.id)?;
+        if !changes.deleted.is_empty() {
+            let rows_written = write_scope.write(|dao| {
+                let changes_before = dao.total_changes()?;
+                for record in &changes.deleted {
+                    trace!("Deleting record ID: {:?}", record.id);
+                    dao.delete_record_data(&record.id)?;
+                }
+                dao.update_ingested_records(collection.name(), &[], &[], &changes.deleted)?;
+                Ok(dao.total_changes()? - changes_before)
+            })?;
+            self.data_changed();
+            progress.rows_written(rows_written);
         }
-        dao.update_ingested_records(
-            collection.name(),
-            &changes.new,
-            &changes.updated,
-            &changes.deleted,
-        )?;
         Ok(())
     }
 
-    fn process_record(
+    /// Ingests a single record and marks it as ingested, in one transaction.
+    #[allow(clippy::too_many_arguments)]
+    fn process_record_in_transaction(
         &self,
-        dao: &mut SuggestDao,
+        write_scope: &mut WriteScope<'_>,
+        collection: Collection,
         record: &Record,
+        delete_existing_data: bool,
         constraints: &SuggestIngestionConstraints,
         context: &mut MetricsContext,
+        progress: &mut ProgressReporter<'_>,
     ) -> Result<()> {
-        match &record.payload {
-            SuggestRecord::Amp => {
-                self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
+        write_scope.err_if_interrupted()?;
+        let downloads_before = context.download_count();
+        let rows_written = write_scope.write(|dao| {
+            let changes_before = dao.total_changes()?;
+            if delete_existing_data {
+                dao.delete_record_data(&record.id)?;
+            }
+            self.process_record(dao, record, constraints, context)?;
+            dao.update_ingested_records(collection.name(), &[record], &[], &[])?;
+            Ok(dao.total_changes()? - changes_before)
+        })?;
+        self.data_changed();
+        progress.record_ingested(context.download_count() - downloads_before, rows_written);
+        Ok(())
+    }
+
+    fn process_record(
+        &self,
+        dao: &mut SuggestDao,
+        record: &Record,
+        constraints: &SuggestIngestionConstraints,
+        context: &mut MetricsContext,
+    ) -> Result<()> {
+        match &record.payload {
+            SuggestRecord::Amp => {
+                self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
                     dao.insert_amp_suggestions(
                         record_id,
                         suggestions,
                         constraints.amp_matching_uses_fts(),
+                        constraints.uses_fuzzy_matching(),
                     )
                 })?;
             }
             SuggestRecord::Wikipedia => {
                 self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
-                    dao.insert_wikipedia_suggestions(record_id, suggestions)
+                    dao.insert_wikipedia_suggestions(
+                        record_id,
+                        suggestions,
+                        constraints.uses_fuzzy_matching(),
+                    )
                 })?;
             }
             SuggestRecord::Icon => {
@@ -761,19 +1402,39 @@ where
             SuggestRecord::Yelp => {
                 self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
                     match suggestions.first() {
-                        Some(suggestion) => dao.insert_yelp_suggestions(record_id, suggestion),
+                        Some(suggestion) => dao.insert_yelp_suggestions(
+                            record_id,
+                            suggestion,
+                            constraints.uses_fuzzy_matching(),
+                        ),
                         None => Ok(()),
                     }
                 })?;
             }
             SuggestRecord::Mdn => {
                 self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
-                    dao.insert_mdn_suggestions(record_id, suggestions)
+                    dao.insert_mdn_suggestions(
+                        record_id,
+                        suggestions,
+                        constraints.uses_fuzzy_matching(),
+                    )
                 })?;
             }
             SuggestRecord::Weather => self.process_weather_record(dao, record, context)?,
             SuggestRecord::GlobalConfig(config) => {
-                dao.put_global_config(&SuggestGlobalConfig::from(config))?
+                dao.put_global_config(&SuggestGlobalConfig::from(config))?;
+                dao.put_engagement_configs(
+                    &config
+                        .configuration
+                        .providers
+                        .iter()
+                        .filter_map(|(name, config)| {
+                            // Skip providers we don't know about, which might
+                            // have been added in a newer version.
+                            Some((SuggestionProvider::from_name(name)?, config.into()))
+                        })
+                        .collect::<Vec<_>>(),
+                )?;
             }
             SuggestRecord::Fakespot => {
                 self.download_attachment(dao, record, context, |dao, record_id, suggestions| {
@@ -797,6 +1458,17 @@ where
                 self.process_geonames_alternates_record(dao, record, context)?
             }
         }
+        if constraints.uses_fuzzy_matching()
+            && matches!(
+                record.payload,
+                SuggestRecord::Amp
+                    | SuggestRecord::Wikipedia
+                    | SuggestRecord::Yelp
+                    | SuggestRecord::Mdn
+            )
+        {
+            dao.put_fuzzy_data_ingested(&record.id)?;
+        }
         Ok(())
     }
 
@@ -827,7 +1499,7 @@ where
 
     fn should_reprocess_record(
         &self,
-        dao: &mut SuggestDao,
+        dao: &SuggestDao,
         record: &Record,
         constraints: &SuggestIngestionConstraints,
     ) -> Result<bool> {
@@ -836,8 +1508,14 @@ where
                 .are_suggestions_ingested_for_record(&record.id)?
                 && constraints.matches_dynamic_record(r)),
             SuggestRecord::Amp => {
-                Ok(constraints.amp_matching_uses_fts()
-                    && !dao.is_amp_fts_data_ingested(&record.id)?)
+                let needs_fts_data = constraints.amp_matching_uses_fts()
+                    && !dao.is_amp_fts_data_ingested(&record.id)?;
+                let needs_fuzzy_data =
+                    constraints.uses_fuzzy_matching() && !dao.is_fuzzy_data_ingested(&record.id)?;
+                Ok(needs_fts_data || needs_fuzzy_data)
+            }
+            SuggestRecord::Wikipedia | SuggestRecord::Yelp | SuggestRecord::Mdn => {
+                Ok(constraints.uses_fuzzy_matching() && !dao.is_fuzzy_data_ingested(&record.id)?)
             }
             _ => Ok(false),
         }
@@ -928,17 +1606,16 @@ where
                 .iter()
                 .filter(|i| i.record_type == ingest_record_type.as_str()),
         );
-        writer
-            .write(|dao| {
-                self.process_changes(
-                    dao,
-                    collection,
-                    changes,
-                    &SuggestIngestionConstraints::default(),
-                    &mut context,
-                )
-            })
-            .unwrap();
+        let mut progress = ProgressReporter::new(None, ingest_record_type.as_str(), records.len());
+        self.process_changes(
+            &mut writer.write_scope().unwrap(),
+            collection,
+            changes,
+            &SuggestIngestionConstraints::default(),
+            &mut context,
+            &mut progress,
+        )
+        .unwrap();
     }
 
     pub fn table_row_counts(&self) -> Vec<(String, u32)> {
@@ -1005,8 +1682,18 @@ pub(crate) mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
 
     use crate::{
-        db::DEFAULT_SUGGESTION_SCORE, provider::AmpMatchingStrategy, suggestion::FtsMatchInfo,
-        testing::*, SuggestionProvider,
+        config::SuggestImpressionCap,
+        db::DEFAULT_SUGGESTION_SCORE,
+        explain::{
+            SuggestCandidateExplanation, SuggestCandidateOutcome, SuggestMatchStrategy,
+            SuggestProviderExplanation,
+        },
+        provider::AmpMatchingStrategy,
+        session::QuerySession,
+        storage::SuggestProviderStorageUsage,
+        suggestion::{FtsMatchInfo, FuzzyMatchInfo},
+        testing::*,
+        SuggestionProvider,
     };
 
     // Extra methods for the tests
@@ -1027,6 +1714,15 @@ pub(crate) mod tests {
                 ..Self::default()
             }
         }
+        fn all_providers_with_fuzzy_matching() -> Self {
+            Self {
+                provider_constraints: Some(SuggestionProviderConstraints {
+                    fuzzy_matching: true,
+                    ..SuggestionProviderConstraints::default()
+                }),
+                ..Self::all_providers()
+            }
+        }
     }
 
     /// In-memory Suggest store for testing
@@ -1575,6 +2271,237 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn fuzzy_matching() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(
+                    SuggestionProvider::Wikipedia.record("wiki-1", json!([california_wiki()])),
+                )
+                .with_record(SuggestionProvider::Yelp.record("yelp-1", json!([ramen_yelp()])))
+                .with_record(SuggestionProvider::Mdn.record("mdn-1", json!([array_mdn()])))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Wikipedia.icon(california_icon()))
+                .with_record(SuggestionProvider::Yelp.icon(yelp_favicon())),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers_with_fuzzy_matching());
+
+        let fuzzy_match_info = |corrected_keyword: &str| FuzzyMatchInfo {
+            corrected_keyword: corrected_keyword.into(),
+            edit_distance: 1,
+        };
+
+        // Typos shouldn't match unless the query asks for fuzzy matching.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los polos")),
+            vec![]
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los polos").fuzzy_matching()),
+            vec![los_pollos_suggestion("los pollos", None)
+                .with_fuzzy_match_info(fuzzy_match_info("los pollos"))],
+        );
+        // Transpositions count as a single edit.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los plolos").fuzzy_matching()),
+            vec![los_pollos_suggestion("los pollos", None)
+                .with_fuzzy_match_info(fuzzy_match_info("los pollos"))],
+        );
+        // Exact matches don't have fuzzy match info.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los pollos").fuzzy_matching()),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        // Short queries and queries with more than one typo don't match.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lso").fuzzy_matching()),
+            vec![]
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los plos").fuzzy_matching()),
+            vec![]
+        );
+
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::wikipedia("califrnia").fuzzy_matching()),
+            vec![california_suggestion("california")
+                .with_fuzzy_match_info(fuzzy_match_info("california"))],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("javascript aray").fuzzy_matching()),
+            vec![array_suggestion().with_fuzzy_match_info(fuzzy_match_info("javascript array"))],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::yelp("ramn in tokyo").fuzzy_matching()),
+            vec![ramen_suggestion(
+                "ramen in tokyo",
+                "https://www.yelp.com/search?find_desc=ramen&find_loc=tokyo"
+            )
+            .with_fuzzy_match_info(fuzzy_match_info("ramen"))],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reingest_after_fuzzy_matching_constraint_changes() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon())),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        // The fuzzy index wasn't built, so there's nothing to fall back to.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los polos").fuzzy_matching()),
+            vec![]
+        );
+
+        store.ingest(SuggestIngestionConstraints::all_providers_with_fuzzy_matching());
+        // Reingesting the record shouldn't duplicate its suggestions.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los pollos")),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los polos").fuzzy_matching()),
+            vec![
+                los_pollos_suggestion("los pollos", None).with_fuzzy_match_info(FuzzyMatchInfo {
+                    corrected_keyword: "los pollos".into(),
+                    edit_distance: 1,
+                })
+            ],
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a record whose keywords are all too short to be fuzzy
+    /// matched isn't reingested every time.
+    #[test]
+    fn fuzzy_matching_short_keywords() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            SuggestionProvider::Wikipedia.record(
+                "wiki-1",
+                json!([california_wiki().merge(json!({"keywords": ["ca", "cal"]}))]),
+            ),
+        ));
+        let listener = TestProgressListener::default();
+        let updates = listener.updates.clone();
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers_with_fuzzy_matching(),
+            Some(&listener),
+        )?;
+        let wikipedia = TestProgressListener::last(&updates, "wikipedia").unwrap();
+        assert_eq!(wikipedia.records_ingested, 1);
+        assert_eq!(store.count_rows("fuzzy_keywords"), 0);
+
+        // The record didn't add any fuzzy keywords, but it's still marked as
+        // ingested, so the second ingestion is a no-op.
+        updates.lock().clear();
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers_with_fuzzy_matching(),
+            Some(&listener),
+        )?;
+        let wikipedia = TestProgressListener::last(&updates, "wikipedia").unwrap();
+        assert_eq!(wikipedia.records_to_ingest, 0);
+        assert_eq!(wikipedia.attachments_downloaded, 0);
+        assert_eq!(wikipedia.rows_written, 0);
+
+        Ok(())
+    }
+
+    /// Tests that reingesting unchanged records, after the constraints change,
+    /// replaces their data rather than duplicating or losing it.
+    #[test]
+    fn reingest_unchanged_records() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(
+                    SuggestionProvider::Wikipedia.record("wiki-1", json!([california_wiki()])),
+                )
+                .with_record(SuggestionProvider::Yelp.record("yelp-1", json!([ramen_yelp()])))
+                .with_record(SuggestionProvider::Mdn.record("mdn-1", json!([array_mdn()])))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Wikipedia.icon(california_icon()))
+                .with_record(SuggestionProvider::Yelp.icon(yelp_favicon())),
+        );
+        let constraints = |fuzzy_matching| SuggestIngestionConstraints {
+            provider_constraints: Some(SuggestionProviderConstraints {
+                amp_alternative_matching: Some(AmpMatchingStrategy::FtsAgainstFullKeywords),
+                fuzzy_matching,
+                ..SuggestionProviderConstraints::default()
+            }),
+            ..SuggestIngestionConstraints::all_providers()
+        };
+        let tables = [
+            "suggestions",
+            "keywords",
+            "full_keywords",
+            "prefix_keywords",
+            "keywords_metrics",
+            "amp_fts",
+            "yelp_subjects",
+            "yelp_modifiers",
+            "icons",
+        ];
+        let count_all_rows = || {
+            tables
+                .iter()
+                .map(|table| (*table, store.count_rows(table)))
+                .collect::<Vec<_>>()
+        };
+
+        store.ingest(constraints(false));
+        let counts = count_all_rows();
+        assert_eq!(store.count_rows("fuzzy_keywords"), 0);
+
+        // Turning on fuzzy matching reingests every unchanged suggestion
+        // record, to build the fuzzy index.
+        store.ingest(constraints(true));
+        assert_eq!(count_all_rows(), counts);
+        assert!(store.count_rows("fuzzy_keywords") > 0);
+
+        // Ingesting again doesn't reingest anything.
+        let fuzzy_keywords = store.count_rows("fuzzy_keywords");
+        store.ingest(constraints(true));
+        assert_eq!(count_all_rows(), counts);
+        assert_eq!(store.count_rows("fuzzy_keywords"), fuzzy_keywords);
+
+        // The reingested suggestions are still returned, once each.
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los pollos")),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::wikipedia("california")),
+            vec![california_suggestion("california")],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("javascript array")),
+            vec![array_suggestion()],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::yelp("ramen in tokyo")),
+            vec![ramen_suggestion(
+                "ramen in tokyo",
+                "https://www.yelp.com/search?find_desc=ramen&find_loc=tokyo"
+            )],
+        );
+
+        Ok(())
+    }
+
     /// Tests re-ingesting icons from an updated attachment.
     #[test]
     fn reingest_icons() -> anyhow::Result<()> {
@@ -1692,6 +2619,59 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    /// Tests ingesting suggestions from a Remote Settings server through the
+    /// real Remote Settings client, including downloading and verifying the
+    /// attachment.
+    #[test]
+    fn ingest_from_remote_settings_test_server() -> anyhow::Result<()> {
+        before_each();
+        viaduct_dev::init_backend_dev();
+        rc_crypto::ensure_initialized();
+
+        let server = remote_settings_test_server::RemoteSettingsTestServer::start()?;
+        let location = "main-workspace/quicksuggest-other/data-1.json";
+        let attachment = serde_json::to_vec(&json!([relay_amo()]))?;
+        let hash = rc_crypto::digest::digest(&rc_crypto::digest::SHA256, &attachment)?;
+        server.set_attachment(location, attachment.clone());
+        server.set_records(
+            "main",
+            Collection::Other.name(),
+            vec![json!({
+                "id": "data-1",
+                "type": "amo-suggestions",
+                "attachment": {
+                    "filename": "data-1.json",
+                    "mimetype": "application/json",
+                    "location": location,
+                    "hash": hex::encode(hash),
+                    "size": attachment.len(),
+                },
+            })],
+        );
+
+        let rs_service = Arc::new(RemoteSettingsService::new(
+            ":memory:".into(),
+            remote_settings::RemoteSettingsConfig2 {
+                server: Some(RemoteSettingsServer::Custom { url: server.url() }),
+                bucket_name: None,
+                app_context: None,
+            },
+        ));
+        let store = SuggestStore::new(
+            "file:test_store_data_rs_server?mode=memory&cache=shared",
+            rs_service,
+        );
+        store.ingest(SuggestIngestionConstraints {
+            providers: Some(vec![SuggestionProvider::Amo]),
+            ..SuggestIngestionConstraints::default()
+        })?;
+        assert_eq!(
+            store.query(SuggestionQuery::amo("masking e"))?,
+            vec![relay_suggestion()],
+        );
+        Ok(())
+    }
+
     /// Tests ingestion when previously-ingested suggestions/icons have been deleted.
     #[test]
     fn ingest_with_deletions() -> anyhow::Result<()> {
@@ -2402,153 +3382,1257 @@ pub(crate) mod tests {
         };
         store.ingest(constraints);
 
-        // This should have been ingested
+        // This should have been ingested
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos_suggestion("los pollos", None)]
+        );
+        // This should not have been ingested, since it wasn't in the providers list
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::yelp("best ramen")),
+            vec![]
+        );
+
+        Ok(())
+    }
+
+    /// Tests that records with invalid attachments are ignored
+    #[test]
+    fn skip_over_invalid_records() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                // valid record
+                .with_record(
+                    SuggestionProvider::Amp.record("data-1", json!([good_place_eats_amp()])),
+                )
+                // This attachment is missing the `title` field and is invalid
+                .with_record(SuggestionProvider::Amp.record(
+                    "data-2",
+                    json!([{
+                            "id": 1,
+                            "advertiser": "Los Pollos Hermanos",
+                            "iab_category": "8 - Food & Drink",
+                            "keywords": ["lo", "los", "los pollos"],
+                            "url": "https://www.lph-nm.biz",
+                            "icon": "5678",
+                            "impression_url": "https://example.com/impression_url",
+                            "click_url": "https://example.com/click_url",
+                            "score": 0.3
+                    }]),
+                ))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon())),
+        );
+
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        // Test that the valid record was read
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("la")),
+            vec![good_place_eats_suggestion("lasagna", None)]
+        );
+        // Test that the invalid record was skipped
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_mdn() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Mdn.record("mdn-1", json!([array_mdn()]))),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        // prefix
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("array")),
+            vec![array_suggestion(),]
+        );
+        // prefix + partial suffix
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("array java")),
+            vec![array_suggestion(),]
+        );
+        // prefix + entire suffix
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("javascript array")),
+            vec![array_suggestion(),]
+        );
+        // partial prefix word
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("wild")),
+            vec![]
+        );
+        // single word
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::mdn("wildcard")),
+            vec![array_suggestion()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn query_no_yelp_icon_data() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            SuggestionProvider::Yelp.record("yelp-1", json!([ramen_yelp()])), // Note: yelp_favicon() is missing
+        ));
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert!(matches!(
+            store.fetch_suggestions(SuggestionQuery::yelp("ramen")).as_slice(),
+            [Suggestion::Yelp { icon, icon_mimetype, .. }] if icon.is_none() && icon_mimetype.is_none()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_global_config() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(MockRecord {
+            collection: Collection::Other,
+            record_type: SuggestRecordType::GlobalConfig,
+            id: "configuration-1".to_string(),
+            inline_data: Some(json!({
+                "configuration": {
+                    "show_less_frequently_cap": 3,
+                },
+            })),
+            attachment: None,
+        }));
+
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(
+            store.fetch_global_config(),
+            SuggestGlobalConfig {
+                show_less_frequently_cap: 3,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_global_config_default() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default());
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(
+            store.fetch_global_config(),
+            SuggestGlobalConfig {
+                show_less_frequently_cap: 0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain_query() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(
+                    SuggestionProvider::Amp
+                        .record("data-1", json!([los_pollos_amp(), good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon()))
+                .with_record(
+                    SuggestionProvider::Wikipedia.record("wikipedia-1", json!([california_wiki()])),
+                )
+                .with_record(SuggestionProvider::Wikipedia.icon(california_icon())),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let los_pollos_candidate = |outcome| SuggestCandidateExplanation {
+            title: "Los Pollos Hermanos - Albuquerque".into(),
+            url: Some("https://www.lph-nm.biz".into()),
+            keyword: Some("lo".into()),
+            record_id: Some("data-1".into()),
+            score: 0.3,
+            outcome,
+        };
+        let amp_explanation =
+            |candidates: Vec<SuggestCandidateExplanation>| SuggestProviderExplanation {
+                provider: SuggestionProvider::Amp,
+                strategy: SuggestMatchStrategy::Exact,
+                keywords: vec!["lo".into()],
+                record_ids: if candidates.is_empty() {
+                    vec![]
+                } else {
+                    vec!["data-1".into()]
+                },
+                candidates,
+            };
+
+        let explanation = store.inner.explain_query(SuggestionQuery::amp("lo"))?;
+        assert_eq!(
+            explanation.suggestions,
+            store.fetch_suggestions(SuggestionQuery::amp("lo"))
+        );
+        assert_eq!(
+            explanation.providers,
+            vec![amp_explanation(vec![los_pollos_candidate(
+                SuggestCandidateOutcome::Returned { position: 0 }
+            )])]
+        );
+
+        // Each provider should only be explained once, in the order they
+        // were requested.
+        let explanation = store.inner.explain_query(SuggestionQuery::with_providers(
+            "cal",
+            vec![
+                SuggestionProvider::Wikipedia,
+                SuggestionProvider::Amp,
+                SuggestionProvider::Wikipedia,
+            ],
+        ))?;
+        assert_eq!(
+            explanation.suggestions,
+            vec![california_suggestion("california")]
+        );
+        assert_eq!(
+            explanation.providers,
+            vec![
+                SuggestProviderExplanation {
+                    provider: SuggestionProvider::Wikipedia,
+                    strategy: SuggestMatchStrategy::Exact,
+                    keywords: vec!["cal".into()],
+                    record_ids: vec!["wikipedia-1".into()],
+                    candidates: vec![SuggestCandidateExplanation {
+                        title: "California".into(),
+                        url: Some("https://wikipedia.org/California".into()),
+                        keyword: Some("cal".into()),
+                        record_id: Some("wikipedia-1".into()),
+                        score: DEFAULT_SUGGESTION_SCORE,
+                        outcome: SuggestCandidateOutcome::Returned { position: 0 },
+                    }],
+                },
+                SuggestProviderExplanation {
+                    keywords: vec!["cal".into()],
+                    ..amp_explanation(vec![])
+                },
+            ]
+        );
+
+        // Candidates ranked below the limit.
+        let explanation = store.inner.explain_query(SuggestionQuery {
+            limit: Some(0),
+            ..SuggestionQuery::amp("lo")
+        })?;
+        assert_eq!(explanation.suggestions, vec![]);
+        assert_eq!(
+            explanation.providers,
+            vec![amp_explanation(vec![los_pollos_candidate(
+                SuggestCandidateOutcome::OverLimit
+            )])]
+        );
+
+        // Candidates filtered by "show less frequently" clicks.
+        store
+            .inner
+            .record_show_less_frequently(&los_pollos_suggestion("los pollos", None), "lo")?;
+        let explanation = store.inner.explain_query(SuggestionQuery::amp("lo"))?;
+        assert_eq!(explanation.suggestions, vec![]);
+        assert_eq!(
+            explanation.providers,
+            vec![amp_explanation(vec![los_pollos_candidate(
+                SuggestCandidateOutcome::ShowLessFrequently {
+                    min_keyword_length: 3
+                }
+            )])]
+        );
+        store.inner.clear_show_less_frequently()?;
+
+        // Dismissed candidates, which the query filters out in SQL.
+        store
+            .inner
+            .dismiss_by_suggestion(&los_pollos_suggestion("los pollos", None))?;
+        let explanation = store.inner.explain_query(SuggestionQuery::amp("lo"))?;
+        assert_eq!(explanation.suggestions, vec![]);
+        assert_eq!(
+            explanation.providers,
+            vec![amp_explanation(vec![los_pollos_candidate(
+                SuggestCandidateOutcome::Dismissed
+            )])]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain_query_below_threshold() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            SuggestionProvider::Weather.record(
+                "weather-1",
+                json!({
+                    "min_keyword_length": 5,
+                    "keywords": ["weather"],
+                    "score": 0.24
+                }),
+            ),
+        ));
+        store.ingest(SuggestIngestionConstraints {
+            providers: Some(vec![SuggestionProvider::Weather]),
+            ..SuggestIngestionConstraints::all_providers()
+        });
+
+        // A keyword prefix that's shorter than `min_keyword_length`.
+        let explanation = store
+            .inner
+            .explain_query(SuggestionQuery::weather("weat"))?;
+        assert_eq!(explanation.suggestions, vec![]);
+        assert_eq!(
+            explanation.providers,
+            vec![SuggestProviderExplanation {
+                provider: SuggestionProvider::Weather,
+                strategy: SuggestMatchStrategy::Geoname,
+                keywords: vec!["weat".into()],
+                record_ids: vec![],
+                candidates: vec![SuggestCandidateExplanation {
+                    title: "Weather".into(),
+                    url: None,
+                    keyword: Some("weat".into()),
+                    record_id: None,
+                    score: 0.24,
+                    outcome: SuggestCandidateOutcome::BelowThreshold {
+                        min_keyword_length: 5
+                    },
+                }],
+            }]
+        );
+
+        // Long enough prefixes, and strings that aren't keyword prefixes,
+        // shouldn't be reported.
+        for keyword in ["weath", "xyz"] {
+            let explanation = store
+                .inner
+                .explain_query(SuggestionQuery::weather(keyword))?;
+            assert!(
+                explanation.providers[0]
+                    .candidates
+                    .iter()
+                    .all(|c| !matches!(c.outcome, SuggestCandidateOutcome::BelowThreshold { .. })),
+                "keyword: {keyword}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn show_less_frequently() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(
+                    SuggestionProvider::Amp
+                        .record("data-1", json!([los_pollos_amp(), good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon()))
+                .with_record(MockRecord {
+                    collection: Collection::Other,
+                    record_type: SuggestRecordType::GlobalConfig,
+                    id: "configuration-1".to_string(),
+                    inline_data: Some(json!({
+                        "configuration": {
+                            "show_less_frequently_cap": 2,
+                        },
+                    })),
+                    attachment: None,
+                }),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let suggestions = store.fetch_suggestions(SuggestionQuery::amp("lo"));
+        assert_eq!(suggestions, vec![los_pollos_suggestion("los pollos", None)]);
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Amp)?,
+            0
+        );
+
+        // A click for a suggestion only applies to that suggestion, and only
+        // to keywords that aren't longer than the one it was shown for.
+        store
+            .inner
+            .record_show_less_frequently(&suggestions[0], "lo")?;
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("los")),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("la")),
+            vec![good_place_eats_suggestion("lasagna", None)],
+        );
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Amp)?,
+            1
+        );
+        assert!(!store
+            .inner
+            .is_show_less_frequently_capped(SuggestionProvider::Amp)?);
+
+        // A click for the provider applies to all of its suggestions.
+        store
+            .inner
+            .record_show_less_frequently_for_provider(SuggestionProvider::Amp, "las")?;
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("los")), vec![]);
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("las")), vec![]);
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lasa")),
+            vec![good_place_eats_suggestion("lasagna", None)],
+        );
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Amp)?,
+            2
+        );
+        assert!(store
+            .inner
+            .is_show_less_frequently_capped(SuggestionProvider::Amp)?);
+        assert!(!store
+            .inner
+            .is_show_less_frequently_capped(SuggestionProvider::Wikipedia)?);
+
+        // Clicks beyond the cap aren't recorded.
+        store
+            .inner
+            .record_show_less_frequently_for_provider(SuggestionProvider::Amp, "lasa")?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lasa")),
+            vec![good_place_eats_suggestion("lasagna", None)],
+        );
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Amp)?,
+            2
+        );
+
+        // A click for a suggestion without a dismissal key isn't recorded,
+        // rather than applying to all of the provider's suggestions.
+        store.inner.record_show_less_frequently(
+            &Suggestion::Dynamic {
+                suggestion_type: "test-type".into(),
+                data: None,
+                dismissal_key: None,
+                score: DEFAULT_SUGGESTION_SCORE,
+            },
+            "a",
+        )?;
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Dynamic)?,
+            0
+        );
+
+        // The clicks aren't cleared along with the suggestions.
+        store.inner.clear()?;
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+
+        store.inner.clear_show_less_frequently()?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos_suggestion("los pollos", None)],
+        );
+        assert_eq!(
+            store
+                .inner
+                .show_less_frequently_count(SuggestionProvider::Amp)?,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn impression_caps() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(
+                    SuggestionProvider::Amp
+                        .record("data-1", json!([los_pollos_amp(), good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon()))
+                .with_record(
+                    SuggestionProvider::Wikipedia.record("wikipedia-1", json!([california_wiki()])),
+                )
+                .with_record(SuggestionProvider::Wikipedia.icon(california_icon()))
+                .with_record(MockRecord {
+                    collection: Collection::Other,
+                    record_type: SuggestRecordType::GlobalConfig,
+                    id: "configuration-1".to_string(),
+                    inline_data: Some(json!({
+                        "configuration": {
+                            "show_less_frequently_cap": 3,
+                            "providers": {
+                                "amp": {
+                                    "show_less_frequently_cap": 1,
+                                    "impression_caps": [
+                                        {
+                                            "interval_s": 3600,
+                                            "max_count": 3,
+                                        },
+                                        {
+                                            "interval_s": 86400,
+                                            "max_count": 1,
+                                            "per_suggestion": true,
+                                        },
+                                    ],
+                                },
+                                // Caps for unknown providers should be ignored.
+                                "not-a-provider": {
+                                    "impression_caps": [
+                                        {
+                                            "interval_s": 60,
+                                            "max_count": 1,
+                                        },
+                                    ],
+                                },
+                            },
+                        },
+                    })),
+                    attachment: None,
+                }),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(
+            store
+                .inner
+                .fetch_engagement_config(SuggestionProvider::Amp)?,
+            SuggestEngagementConfig {
+                show_less_frequently_cap: Some(1),
+                impression_caps: vec![
+                    SuggestImpressionCap {
+                        interval_secs: 3600,
+                        max_count: 3,
+                        per_suggestion: false,
+                    },
+                    SuggestImpressionCap {
+                        interval_secs: 86400,
+                        max_count: 1,
+                        per_suggestion: true,
+                    },
+                ],
+            }
+        );
+        // Providers without a config get the default one, which uses the
+        // global "show less frequently" cap.
+        assert_eq!(
+            store
+                .inner
+                .fetch_engagement_config(SuggestionProvider::Wikipedia)?,
+            SuggestEngagementConfig::default(),
+        );
+
+        // The per-suggestion cap only applies to the suggestion that was shown.
+        let los_pollos = los_pollos_suggestion("los pollos", None);
+        store.inner.record_impression(&los_pollos)?;
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("la")),
+            vec![good_place_eats_suggestion("lasagna", None)],
+        );
+
+        // The provider cap applies to all of its suggestions, but not to other
+        // providers.
+        let lasagna = good_place_eats_suggestion("lasagna", None);
+        store
+            .inner
+            .record_impression(&california_suggestion("california"))?;
+        store.inner.record_impression(&los_pollos)?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("la")),
+            vec![lasagna.clone()],
+        );
+        store.inner.record_impression(&los_pollos)?;
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("la")), vec![]);
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::wikipedia("cal")),
+            vec![california_suggestion("california")],
+        );
+
+        // The caps stop applying once their windows have passed.
+        store.inner.clear_impressions()?;
+        let now = now_secs();
+        store.write(|dao| {
+            for _ in 0..3 {
+                dao.insert_impression(SuggestionProvider::Amp, "los pollos", now)?;
+            }
+            Ok(())
+        })?;
+        let candidates = vec![los_pollos.clone(), lasagna.clone()];
+        for (later, expected) in [
+            (now + 3599, vec![]),
+            (now + 3600, vec![lasagna.clone()]),
+            (now + 86400, candidates.clone()),
+        ] {
+            assert_eq!(
+                store.read(|dao| {
+                    let filters = dao.engagement_filters([SuggestionProvider::Amp], later)?;
+                    Ok(candidates
+                        .iter()
+                        .filter(|suggestion| filters.filter("lo", suggestion).is_none())
+                        .cloned()
+                        .collect::<Vec<_>>())
+                })?,
+                expected,
+                "filtered {} seconds later",
+                later - now
+            );
+        }
+
+        store.inner.clear_impressions()?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos],
+        );
+
+        // The provider's "show less frequently" cap overrides the global one.
+        store
+            .inner
+            .record_show_less_frequently_for_provider(SuggestionProvider::Amp, "x")?;
+        assert!(store
+            .inner
+            .is_show_less_frequently_capped(SuggestionProvider::Amp)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "relevancy")]
+    #[test]
+    fn personalized_ranking() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record(
+                    "data-1",
+                    json!([
+                        los_pollos_amp(),
+                        good_place_eats_amp().merge(json!({
+                            "keywords": ["lo"],
+                            "full_keywords": [("lasagna", 1)],
+                            "score": 0.35,
+                        })),
+                    ]),
+                ))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon())),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        let titles = |suggestions: &[Suggestion]| {
+            suggestions
+                .iter()
+                .map(|s| s.title().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let suggestions = store.fetch_suggestions(SuggestionQuery::amp("lo"));
+        assert_eq!(
+            titles(&suggestions),
+            [
+                "Lasagna Come Out Tomorrow",
+                "Los Pollos Hermanos - Albuquerque"
+            ]
+        );
+        assert!(suggestions
+            .iter()
+            .all(|s| s.personalization_info().is_none()));
+
+        // Los Pollos Hermanos is in the food category, so it should be ranked
+        // first for a user who's interested in food.
+        store
+            .inner
+            .personalizer
+            .set_interest_vector(Some(relevancy::InterestVector {
+                food: 100,
+                ..relevancy::InterestVector::default()
+            }));
+        let suggestions = store.fetch_suggestions(SuggestionQuery::amp("lo"));
+        assert_eq!(
+            titles(&suggestions),
+            [
+                "Los Pollos Hermanos - Albuquerque",
+                "Lasagna Come Out Tomorrow"
+            ]
+        );
+        let info = suggestions[0].personalization_info().unwrap();
+        assert_eq!(info.original_score, 0.3);
+        assert!(info.interest_score > 0.0);
+        assert_eq!(suggestions[1].personalization_info(), None);
+
+        store.inner.personalizer.set_interest_vector(None);
+        assert_eq!(
+            titles(&store.fetch_suggestions(SuggestionQuery::amp("lo"))),
+            [
+                "Lasagna Come Out Tomorrow",
+                "Los Pollos Hermanos - Albuquerque"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "merino")]
+    #[test]
+    fn query_with_merino() -> anyhow::Result<()> {
+        use std::io::Write;
+
+        use merino::suggest::MerinoSuggestConfig;
+        use mockito::{mock, Matcher};
+
+        before_each();
+        viaduct_dev::init_backend_dev();
+
+        let mut store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon())),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        let titles = |suggestions: &[Suggestion]| {
+            suggestions
+                .iter()
+                .map(|s| s.title().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // Without a Merino client, we should only return offline suggestions.
+        let result = store
+            .inner
+            .query_with_merino(SuggestionQuery::amp("lo"), MerinoQueryOptions::default())?;
+        assert_eq!(result.online_status, OnlineStatus::NotConfigured);
+        assert_eq!(
+            titles(&result.suggestions),
+            ["Los Pollos Hermanos - Albuquerque"]
+        );
+
+        store.inner.merino = Some(MerinoWorker::new(Arc::new(MerinoSuggestClient::new(
+            MerinoSuggestConfig {
+                base_host: Some(mockito::server_url()),
+                user_agent_header: "suggest-test/1.0".to_string(),
+                ohttp_channel: None,
+            },
+        )?)));
+        // Each request in the session has its own mock, with a different
+        // sequence number.
+        let options = |sequence_number, timeout_ms| MerinoQueryOptions {
+            timeout_ms,
+            session_id: Some("session-1".to_string()),
+            sequence_number: Some(sequence_number),
+            ..MerinoQueryOptions::default()
+        };
+        let session_query = |sequence_number: u32| {
+            Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".into(), "lo".into()),
+                Matcher::UrlEncoded("sid".into(), "session-1".into()),
+                Matcher::UrlEncoded("seq".into(), sequence_number.to_string()),
+            ])
+        };
+
+        // Online suggestions should be merged with offline ones by score,
+        // without duplicates or dismissed suggestions.
+        let m = mock("GET", "/api/v1/suggest")
+            .match_query(session_query(0))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "suggestions": [
+                        {
+                            "provider": "adm",
+                            "title": "Los Pollos Hermanos (online)",
+                            "url": "https://www.lph-nm.biz",
+                            "score": 0.9,
+                        },
+                        {
+                            "provider": "wikipedia",
+                            "title": "Lord of the Rings",
+                            "url": "https://en.wikipedia.org/wiki/The_Lord_of_the_Rings",
+                            "score": 0.5,
+                        },
+                        {
+                            "provider": "wikipedia",
+                            "title": "Lord of the Rings (duplicate)",
+                            "url": "https://en.wikipedia.org/wiki/The_Lord_of_the_Rings",
+                            "score": 0.4,
+                        },
+                        {
+                            "provider": "wikipedia",
+                            "title": "Dismissed",
+                            "url": "https://example.com/dismissed",
+                            "score": 0.6,
+                        },
+                        {
+                            "provider": "adm",
+                            "title": "Lots of Lobsters",
+                            "url": "https://example.com/lobsters",
+                            "score": 0.1,
+                        },
+                    ],
+                })
+                .to_string(),
+            )
+            .create();
+        store
+            .inner
+            .dismiss_suggestion("https://example.com/dismissed".to_string())?;
+        let result = store
+            .inner
+            .query_with_merino(SuggestionQuery::amp("lo"), options(0, 5000))?;
+        m.expect(1).assert();
+        assert_eq!(result.online_status, OnlineStatus::Success);
+        assert_eq!(
+            titles(&result.suggestions),
+            [
+                "Lord of the Rings",
+                "Los Pollos Hermanos - Albuquerque",
+                "Lots of Lobsters"
+            ]
+        );
+        assert!(matches!(result.suggestions[0], Suggestion::Merino { .. }));
+
+        // The limit should apply to the merged suggestions.
+        let result = store
+            .inner
+            .query_with_merino(SuggestionQuery::amp("lo").limit(2), options(0, 5000))?;
+        assert_eq!(
+            titles(&result.suggestions),
+            ["Lord of the Rings", "Los Pollos Hermanos - Albuquerque"]
+        );
+
+        // If Merino fails, we should fall back to offline suggestions.
+        let m = mock("GET", "/api/v1/suggest")
+            .match_query(session_query(1))
+            .with_status(500)
+            .create();
+        let result = store
+            .inner
+            .query_with_merino(SuggestionQuery::amp("lo"), options(1, 5000))?;
+        m.expect(1).assert();
+        assert!(matches!(result.online_status, OnlineStatus::Error { .. }));
+        assert_eq!(
+            titles(&result.suggestions),
+            ["Los Pollos Hermanos - Albuquerque"]
+        );
+
+        // Same if Merino doesn't respond within the latency budget.
+        let _m = mock("GET", "/api/v1/suggest")
+            .match_query(session_query(2))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_fn(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(br#"{"suggestions": []}"#)
+            })
+            .create();
+        let result = store
+            .inner
+            .query_with_merino(SuggestionQuery::amp("lo"), options(2, 50))?;
+        assert_eq!(result.online_status, OnlineStatus::Timeout);
+        assert_eq!(
+            titles(&result.suggestions),
+            ["Los Pollos Hermanos - Albuquerque"]
+        );
+
+        Ok(())
+    }
+
+    /// Collects ingestion progress, optionally calling a function for each
+    /// update.
+    #[derive(Default)]
+    struct TestProgressListener {
+        updates: Arc<Mutex<Vec<SuggestIngestionProgress>>>,
+        on_progress: Option<Box<dyn Fn(&SuggestIngestionProgress) + Send + Sync>>,
+    }
+
+    impl TestProgressListener {
+        /// Returns the last update for a record type.
+        fn last(
+            updates: &Mutex<Vec<SuggestIngestionProgress>>,
+            record_type: &str,
+        ) -> Option<SuggestIngestionProgress> {
+            updates
+                .lock()
+                .iter()
+                .rev()
+                .find(|p| p.record_type == record_type)
+                .cloned()
+        }
+    }
+
+    impl SuggestIngestionProgressListener for TestProgressListener {
+        fn on_progress(&self, progress: SuggestIngestionProgress) {
+            if let Some(on_progress) = &self.on_progress {
+                on_progress(&progress);
+            }
+            self.updates.lock().push(progress);
+        }
+    }
+
+    #[test]
+    fn ingest_with_progress() -> anyhow::Result<()> {
+        before_each();
+
+        let mut store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(
+                    SuggestionProvider::Amp.record("data-2", json!([good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon())),
+        );
+        let listener = TestProgressListener::default();
+        let updates = listener.updates.clone();
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers(),
+            Some(&listener),
+        )?;
+
+        let amp = TestProgressListener::last(&updates, "amp").unwrap();
+        assert_eq!(amp.records_fetched, 2);
+        assert_eq!(amp.records_to_ingest, 2);
+        assert_eq!(amp.records_ingested, 2);
+        assert_eq!(amp.attachments_downloaded, 2);
+        assert!(amp.rows_written > 0);
+        let icons = TestProgressListener::last(&updates, "icon").unwrap();
+        assert_eq!(icons.records_ingested, 2);
+        assert_eq!(icons.attachments_downloaded, 2);
+        // Progress should be reported before each record type starts, and
+        // after each record.
+        assert_eq!(
+            updates
+                .lock()
+                .iter()
+                .filter(|p| p.record_type == "amp")
+                .map(|p| p.records_ingested)
+                .collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+
+        // Nothing changed, so there's nothing to ingest.
+        updates.lock().clear();
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers(),
+            Some(&listener),
+        )?;
+        let amp = TestProgressListener::last(&updates, "amp").unwrap();
+        assert_eq!(amp.records_fetched, 2);
+        assert_eq!(amp.records_to_ingest, 0);
+        assert_eq!(amp.rows_written, 0);
+
+        // Deleting a record should count the deleted rows.
+        updates.lock().clear();
+        store
+            .client_mut()
+            .delete_record(SuggestionProvider::Amp.empty_record("data-2"));
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers(),
+            Some(&listener),
+        )?;
+        let amp = TestProgressListener::last(&updates, "amp").unwrap();
+        assert_eq!(amp.records_fetched, 1);
+        assert_eq!(amp.records_to_ingest, 0);
+        assert!(amp.rows_written > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn resume_interrupted_ingestion() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.record("data-1", json!([los_pollos_amp()])))
+                .with_record(
+                    SuggestionProvider::Amp.record("data-2", json!([good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon())),
+        );
+
+        // Interrupt the ingestion after the first AMP record.
+        let interrupt_handle = store.inner.dbs()?.writer.interrupt_handle.clone();
+        let listener = TestProgressListener {
+            on_progress: Some(Box::new(move |progress| {
+                if progress.record_type == "amp" && progress.records_ingested == 1 {
+                    interrupt_handle.interrupt();
+                }
+            })),
+            ..TestProgressListener::default()
+        };
+        let updates = listener.updates.clone();
+        assert!(matches!(
+            store.inner.ingest_with_progress(
+                SuggestIngestionConstraints::all_providers(),
+                Some(&listener),
+            ),
+            Err(Error::Interrupted(_))
+        ));
+        assert_eq!(
+            store.read(|dao| Ok(dao
+                .get_ingested_records()?
+                .into_iter()
+                .filter(|r| r.record_type == "amp")
+                .count()))?,
+            1
+        );
+        let amp = TestProgressListener::last(&updates, "amp").unwrap();
+        assert_eq!(amp.records_to_ingest, 2);
+        assert_eq!(amp.records_ingested, 1);
+
+        // The next ingestion should only ingest the other record.
+        updates.lock().clear();
+        let listener = TestProgressListener {
+            updates: updates.clone(),
+            on_progress: None,
+        };
+        store.inner.ingest_with_progress(
+            SuggestIngestionConstraints::all_providers(),
+            Some(&listener),
+        )?;
+        let amp = TestProgressListener::last(&updates, "amp").unwrap();
+        assert_eq!(amp.records_fetched, 2);
+        assert_eq!(amp.records_to_ingest, 1);
+        assert_eq!(amp.records_ingested, 1);
+        assert_eq!(amp.attachments_downloaded, 1);
+
         assert_eq!(
             store.fetch_suggestions(SuggestionQuery::amp("lo")),
             vec![los_pollos_suggestion("los pollos", None)]
         );
-        // This should not have been ingested, since it wasn't in the providers list
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::yelp("best ramen")),
-            vec![]
+            store.fetch_suggestions(SuggestionQuery::amp("la")),
+            vec![good_place_eats_suggestion("lasagna", None)]
         );
 
         Ok(())
     }
 
-    /// Tests that records with invalid attachments are ignored
     #[test]
-    fn skip_over_invalid_records() -> anyhow::Result<()> {
+    fn storage_usage_counts_fts_rows_and_icon_bytes() -> anyhow::Result<()> {
         before_each();
 
         let store = TestStore::new(
             MockRemoteSettingsClient::default()
-                // valid record
                 .with_record(
-                    SuggestionProvider::Amp.record("data-1", json!([good_place_eats_amp()])),
+                    SuggestionProvider::Amp
+                        .record("data-1", json!([los_pollos_amp(), good_place_eats_amp()])),
                 )
-                // This attachment is missing the `title` field and is invalid
-                .with_record(SuggestionProvider::Amp.record(
-                    "data-2",
-                    json!([{
-                            "id": 1,
-                            "advertiser": "Los Pollos Hermanos",
-                            "iab_category": "8 - Food & Drink",
-                            "keywords": ["lo", "los", "los pollos"],
-                            "url": "https://www.lph-nm.biz",
-                            "icon": "5678",
-                            "impression_url": "https://example.com/impression_url",
-                            "click_url": "https://example.com/click_url",
-                            "score": 0.3
-                    }]),
-                ))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
                 .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon())),
         );
+        let amp_usage = || -> anyhow::Result<SuggestProviderStorageUsage> {
+            Ok(store
+                .inner
+                .storage_usage()?
+                .providers
+                .into_iter()
+                .find(|p| p.provider == SuggestionProvider::Amp)
+                .unwrap())
+        };
 
         store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(store.count_rows("amp_fts"), 0);
+        let without_fts = amp_usage()?;
 
-        // Test that the valid record was read
-        assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::amp("la")),
-            vec![good_place_eats_suggestion("lasagna", None)]
-        );
-        // Test that the invalid record was skipped
-        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+        // Icons should be counted by the length of their data.
+        let icon_bytes = (los_pollos_icon().data.len() + good_place_eats_icon().data.len()) as u64;
+        assert!(without_fts.estimated_bytes >= icon_bytes);
+
+        // Ingesting the FTS data should count the FTS rows too.
+        store.ingest(SuggestIngestionConstraints::amp_with_fts());
+        let fts_rows = store.count_rows("amp_fts");
+        assert!(fts_rows > 0);
+        assert_eq!(amp_usage()?.row_count, without_fts.row_count + fts_rows);
 
         Ok(())
     }
 
     #[test]
-    fn query_mdn() -> anyhow::Result<()> {
+    fn storage_budget() -> anyhow::Result<()> {
         before_each();
 
-        let store = TestStore::new(
+        let mut store = TestStore::new(
             MockRemoteSettingsClient::default()
-                .with_record(SuggestionProvider::Mdn.record("mdn-1", json!([array_mdn()]))),
+                .with_record(
+                    SuggestionProvider::Amp
+                        .record("data-1", json!([los_pollos_amp(), good_place_eats_amp()])),
+                )
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon()))
+                .with_record(
+                    SuggestionProvider::Wikipedia.record("wikipedia-1", json!([california_wiki()])),
+                )
+                .with_record(SuggestionProvider::Wikipedia.icon(california_icon())),
         );
         store.ingest(SuggestIngestionConstraints::all_providers());
-        // prefix
+
+        let usage = store.inner.storage_usage()?;
+        assert!(usage.db_size_bytes > 0);
+        assert!(usage.evicted_providers.is_empty());
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::mdn("array")),
-            vec![array_suggestion(),]
+            usage
+                .providers
+                .iter()
+                .map(|p| p.provider)
+                .collect::<Vec<_>>(),
+            [SuggestionProvider::Amp, SuggestionProvider::Wikipedia]
         );
-        // prefix + partial suffix
+        let amp_usage = usage
+            .providers
+            .iter()
+            .find(|p| p.provider == SuggestionProvider::Amp)
+            .unwrap();
+        assert!(amp_usage.row_count > 0);
+        assert!(amp_usage.estimated_bytes > 0);
+
+        // Without a budget, nothing should be evicted.
+        assert_eq!(store.inner.enforce_storage_budget()?, vec![]);
+
+        // Set a budget that requires evicting AMP, but not Wikipedia.
+        let budget = usage.db_size_bytes - amp_usage.estimated_bytes / 2;
+        store.inner.storage_budget = Some(SuggestStorageBudget {
+            max_bytes: budget,
+            provider_priority: vec![SuggestionProvider::Wikipedia, SuggestionProvider::Amp],
+        });
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::mdn("array java")),
-            vec![array_suggestion(),]
+            store.inner.enforce_storage_budget()?,
+            vec![SuggestionProvider::Amp]
         );
-        // prefix + entire suffix
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::mdn("javascript array")),
-            vec![array_suggestion(),]
+            store.fetch_suggestions(SuggestionQuery::wikipedia("cal")),
+            vec![california_suggestion("california")],
         );
-        // partial prefix word
+        let usage = store.inner.storage_usage()?;
+        assert_eq!(usage.evicted_providers, vec![SuggestionProvider::Amp]);
+        assert!(usage
+            .providers
+            .iter()
+            .all(|p| p.provider != SuggestionProvider::Amp));
+
+        // Evicted providers shouldn't be ingested again...
+        store.ingest(SuggestIngestionConstraints::all_providers());
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("lo")), vec![]);
+
+        // ...until the budget grows.
+        store.inner.storage_budget = Some(SuggestStorageBudget {
+            max_bytes: u64::MAX,
+            provider_priority: vec![],
+        });
+        store.ingest(SuggestIngestionConstraints::all_providers());
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::mdn("wild")),
-            vec![]
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos_suggestion("los pollos", None)]
         );
-        // single word
+        assert!(store.inner.storage_usage()?.evicted_providers.is_empty());
+
+        // Clearing the store should forget evictions too.
+        store.inner.storage_budget = Some(SuggestStorageBudget {
+            max_bytes: budget,
+            provider_priority: vec![SuggestionProvider::Wikipedia, SuggestionProvider::Amp],
+        });
         assert_eq!(
-            store.fetch_suggestions(SuggestionQuery::mdn("wildcard")),
-            vec![array_suggestion()]
+            store.inner.enforce_storage_budget()?,
+            vec![SuggestionProvider::Amp]
         );
-        Ok(())
-    }
-
-    #[test]
-    fn query_no_yelp_icon_data() -> anyhow::Result<()> {
-        before_each();
-
-        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
-            SuggestionProvider::Yelp.record("yelp-1", json!([ramen_yelp()])), // Note: yelp_favicon() is missing
-        ));
-        store.ingest(SuggestIngestionConstraints::all_providers());
-        assert!(matches!(
-            store.fetch_suggestions(SuggestionQuery::yelp("ramen")).as_slice(),
-            [Suggestion::Yelp { icon, icon_mimetype, .. }] if icon.is_none() && icon_mimetype.is_none()
-        ));
+        store.inner.clear()?;
+        assert!(store.inner.storage_usage()?.evicted_providers.is_empty());
 
         Ok(())
     }
 
+    /// Tests that a store with an app context only ingests and returns
+    /// suggestions for its market.
     #[test]
-    fn fetch_global_config() -> anyhow::Result<()> {
+    fn ingest_for_market() -> anyhow::Result<()> {
         before_each();
 
-        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(MockRecord {
-            collection: Collection::Other,
-            record_type: SuggestRecordType::GlobalConfig,
-            id: "configuration-1".to_string(),
-            inline_data: Some(json!({
-                "configuration": {
-                    "show_less_frequently_cap": 3,
-                },
-            })),
-            attachment: None,
-        }));
+        fn client() -> MockRemoteSettingsClient {
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amp.full_record(
+                    "data-de",
+                    Some(json!({ "locales": ["de"], "countries": ["DE", "AT"] })),
+                    Some(MockAttachment::Json(json!([los_pollos_amp()]))),
+                ))
+                .with_record(SuggestionProvider::Amp.full_record(
+                    "data-fr",
+                    Some(json!({ "filter_expression": "env.locale == \"fr-FR\"" })),
+                    Some(MockAttachment::Json(json!([good_place_eats_amp()]))),
+                ))
+                .with_record(SuggestionProvider::Amp.icon(los_pollos_icon()))
+                .with_record(SuggestionProvider::Amp.icon(good_place_eats_icon()))
+        }
+        fn market(locale: &str, country: &str) -> Option<SuggestMarket> {
+            Some(SuggestMarket::new(RemoteSettingsContext {
+                locale: Some(locale.to_owned()),
+                country: Some(country.to_owned()),
+                ..RemoteSettingsContext::default()
+            }))
+        }
 
+        let mut store = TestStore::new(client());
+        store.inner.market = market("de-DE", "DE");
         store.ingest(SuggestIngestionConstraints::all_providers());
         assert_eq!(
-            store.fetch_global_config(),
-            SuggestGlobalConfig {
-                show_less_frequently_cap: 3,
-            }
+            store.fetch_suggestions(SuggestionQuery::amp("lo")),
+            vec![los_pollos_suggestion("los pollos", None)],
         );
+        assert_eq!(store.fetch_suggestions(SuggestionQuery::amp("la")), vec![]);
+        let ingested_ids = store.read(|dao| {
+            Ok(dao
+                .get_ingested_records()?
+                .into_iter()
+                .map(|record| record.id.as_str().to_owned())
+                .collect::<HashSet<_>>())
+        })?;
+        assert!(ingested_ids.contains("data-de"));
+        assert!(!ingested_ids.contains("data-fr"));
 
-        Ok(())
-    }
-
-    #[test]
-    fn fetch_global_config_default() -> anyhow::Result<()> {
-        before_each();
-
-        let store = TestStore::new(MockRemoteSettingsClient::default());
-        store.ingest(SuggestIngestionConstraints::all_providers());
+        // Opening the same database for a different market should drop the
+        // suggestions for the old one, and ingest the ones for the new one.
+        let mut fr_store = SuggestStoreInner::new(store.inner.data_path.clone(), vec![], client());
+        fr_store.market = market("fr-FR", "FR");
         assert_eq!(
-            store.fetch_global_config(),
-            SuggestGlobalConfig {
-                show_less_frequently_cap: 0,
-            }
+            fr_store.query(SuggestionQuery::amp("lo"))?.suggestions,
+            vec![]
+        );
+        fr_store.ingest(SuggestIngestionConstraints::all_providers())?;
+        assert_eq!(
+            fr_store.query(SuggestionQuery::amp("la"))?.suggestions,
+            vec![good_place_eats_suggestion("lasagna", None)],
+        );
+        assert_eq!(
+            fr_store.query(SuggestionQuery::amp("lo"))?.suggestions,
+            vec![]
         );
 
         Ok(())
@@ -4031,10 +6115,223 @@ pub(crate) mod tests {
             attachment: None,
             payload: SuggestRecord::Icon,
             collection: Collection::Other,
+            targeting: Default::default(),
         };
         rc = RecordChanges::new(std::iter::once(&record), std::iter::empty());
         assert!(rc.has_changes(), "Has changes");
 
         Ok(())
     }
+
+    /// Tests that a query session caches candidates until the data changes.
+    #[test]
+    fn query_session_caches_candidates() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amo.record("data-1", json!([dark_mode_amo()]))),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let session = QuerySession::default();
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark m"))?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+
+        // Delete the suggestion behind the store's back. The session should
+        // keep returning its cached candidate.
+        store.write(|dao| dao.delete_record_data(&SuggestRecordId::new("data-1".into())))?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amo("dark m")),
+            vec![]
+        );
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark m"))?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+
+        // Changes through the store invalidate the cache.
+        store.inner.clear_dismissed_suggestions()?;
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark m"))?
+                .suggestions,
+            vec![],
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a query session doesn't query prefix-matching providers
+    /// for keywords that extend a keyword without candidates.
+    #[test]
+    fn query_session_narrows_prefix_matches() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amo.record("data-1", json!([dark_mode_amo()]))),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let session = QuerySession::default();
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark x"))?
+                .suggestions,
+            vec![],
+        );
+
+        // Add a keyword behind the store's back, so that we can tell whether
+        // the session queried the database.
+        store.write(|dao| {
+            dao.conn.execute(
+                "INSERT INTO prefix_keywords(keyword_prefix, keyword_suffix, rank, suggestion_id)
+                 SELECT 'dark', 'xylophone', 0, id FROM suggestions",
+                (),
+            )?;
+            Ok(())
+        })?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amo("dark xy")),
+            vec![dark_mode_suggestion()],
+        );
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark xy"))?
+                .suggestions,
+            vec![],
+        );
+
+        // Keywords with a different first word aren't narrowed.
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("night m"))?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+
+        // Neither are queries with a different limit.
+        assert_eq!(
+            session
+                .query(
+                    &store.inner,
+                    SuggestionQuery {
+                        limit: Some(1),
+                        ..SuggestionQuery::amo("dark xy")
+                    },
+                )?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a query session narrows the candidates of a keyword that
+    /// the query's keyword extends, instead of querying the database.
+    #[test]
+    fn query_session_narrows_candidates() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(MockRemoteSettingsClient::default().with_record(
+            SuggestionProvider::Amo.record(
+                "data-1",
+                json!([
+                    dark_mode_amo(),
+                    {
+                        "title": "Dark Reader",
+                        "description": "Dark mode for every website",
+                        "url": "https://example.org/amo-suggestion-dark-reader",
+                        "guid": "{0b5e9a6b-2a52-4a8b-9d5b-4b1c0f4d8e10}",
+                        "keywords": ["dark reader"],
+                        "icon": "https://example.org/amo-suggestion-dark-reader/icon.png",
+                        "rating": "4.7",
+                        "number_of_ratings": 1000,
+                        "score": 0.5
+                    },
+                ]),
+            ),
+        ));
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let session = QuerySession::default();
+        let titles = |keyword: &str| -> anyhow::Result<Vec<String>> {
+            Ok(session
+                .query(&store.inner, SuggestionQuery::amo(keyword))?
+                .suggestions
+                .iter()
+                .map(|suggestion| suggestion.title().to_owned())
+                .collect())
+        };
+        assert_eq!(titles("dark ")?, vec!["Dark Reader", "Dark Mode"]);
+
+        // Candidates that the limit cut off can't be narrowed: "Dark Mode"
+        // isn't among them.
+        let limited_session = QuerySession::default();
+        let limited = |keyword: &str| -> anyhow::Result<Vec<Suggestion>> {
+            Ok(limited_session
+                .query(
+                    &store.inner,
+                    SuggestionQuery {
+                        limit: Some(1),
+                        ..SuggestionQuery::amo(keyword)
+                    },
+                )?
+                .suggestions)
+        };
+        assert_eq!(limited("dark ")?.len(), 1);
+        assert_eq!(limited("dark m")?, vec![dark_mode_suggestion()]);
+
+        // Delete the keywords behind the store's back, so that we can tell
+        // whether the session queried the database.
+        store.write(|dao| {
+            dao.conn.execute("DELETE FROM prefix_keywords", ())?;
+            Ok(())
+        })?;
+        assert_eq!(
+            store.fetch_suggestions(SuggestionQuery::amo("dark m")),
+            vec![]
+        );
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark m"))?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+        assert_eq!(titles("dark the")?, vec!["Dark Mode"]);
+        assert_eq!(titles("dark r")?, vec!["Dark Reader"]);
+        assert_eq!(titles("dark rx")?, Vec::<String>::new());
+
+        Ok(())
+    }
+
+    /// Tests that interrupting a query session doesn't affect later queries.
+    #[test]
+    fn query_session_interrupt() -> anyhow::Result<()> {
+        before_each();
+
+        let store = TestStore::new(
+            MockRemoteSettingsClient::default()
+                .with_record(SuggestionProvider::Amo.record("data-1", json!([dark_mode_amo()]))),
+        );
+        store.ingest(SuggestIngestionConstraints::all_providers());
+
+        let session = QuerySession::default();
+        session.interrupt(&store.inner);
+        assert_eq!(
+            session
+                .query(&store.inner, SuggestionQuery::amo("dark m"))?
+                .suggestions,
+            vec![dark_mode_suggestion()],
+        );
+
+        Ok(())
+    }
 }
@@ -5,7 +5,7 @@
 
 use chrono::Local;
 
-use crate::{db::DEFAULT_SUGGESTION_SCORE, geoname::Geoname};
+use crate::{db::DEFAULT_SUGGESTION_SCORE, geoname::Geoname, provider::SuggestionProvider};
 
 /// The template parameter for a timestamp in a "raw" sponsored suggestion URL.
 const TIMESTAMP_TEMPLATE: &str = "%YYYYMMDDHH%";
@@ -45,6 +45,9 @@ pub enum Suggestion {
         raw_click_url: String,
         score: f64,
         fts_match_info: Option<FtsMatchInfo>,
+        fuzzy_match_info: Option<FuzzyMatchInfo>,
+        /// Set if `score` was adjusted for the user's interests.
+        personalization_info: Option<PersonalizationInfo>,
     },
     Wikipedia {
         title: String,
@@ -52,6 +55,7 @@ pub enum Suggestion {
         icon: Option<Vec<u8>>,
         icon_mimetype: Option<String>,
         full_keyword: String,
+        fuzzy_match_info: Option<FuzzyMatchInfo>,
     },
     Amo {
         title: String,
@@ -73,12 +77,14 @@ pub enum Suggestion {
         subject_exact_match: bool,
         subject_type: YelpSubjectType,
         location_param: String,
+        fuzzy_match_info: Option<FuzzyMatchInfo>,
     },
     Mdn {
         title: String,
         url: String,
         description: String,
         score: f64,
+        fuzzy_match_info: Option<FuzzyMatchInfo>,
     },
     Weather {
         city: Option<Geoname>,
@@ -109,6 +115,12 @@ pub enum Suggestion {
         dismissal_key: Option<String>,
         score: f64,
     },
+    /// An online suggestion from Merino, returned by
+    /// [crate::SuggestStore::query_with_merino].
+    #[cfg(feature = "merino")]
+    Merino {
+        suggestion: merino::suggest::MerinoSuggestion,
+    },
 }
 
 /// Additional data about how an FTS match was made
@@ -120,6 +132,32 @@ pub struct FtsMatchInfo {
     pub stemming: bool,
 }
 
+/// Additional data about how a fuzzy match was made
+///
+/// Fuzzy matches are only made when `SuggestionProviderConstraints::fuzzy_matching` is set and
+/// the query doesn't match any keywords exactly.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct FuzzyMatchInfo {
+    /// The keyword the query was corrected to (`amazn` matched against `amazon`).  For Yelp, this
+    /// is the corrected word of the subject.
+    pub corrected_keyword: String,
+    /// The number of single-character insertions, deletions, substitutions and transpositions
+    /// needed to turn the query into `corrected_keyword`.
+    pub edit_distance: u32,
+}
+
+/// Additional data about how a suggestion's score was personalized
+///
+/// Suggestions are only personalized when the store has the user's interests and the suggestion
+/// has interest categories.  The suggestion's `score` is the personalized score.
+#[derive(Debug, Clone, PartialEq, uniffi::Record)]
+pub struct PersonalizationInfo {
+    /// The score from the suggestion's record, before personalization.
+    pub original_score: f64,
+    /// How closely the suggestion's categories match the user's interests, from 0 to 1.
+    pub interest_score: f64,
+}
+
 impl PartialOrd for Suggestion {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -155,9 +193,28 @@ impl Suggestion {
             | Self::Mdn { .. }
             | Self::Weather { .. }
             | Self::Fakespot { .. } => self.raw_url(),
+            #[cfg(feature = "merino")]
+            Self::Merino { .. } => self.raw_url(),
         }
     }
 
+    /// Get the provider this suggestion came from, or `None` for online
+    /// suggestions from Merino.
+    pub fn provider(&self) -> Option<SuggestionProvider> {
+        Some(match self {
+            Self::Amp { .. } => SuggestionProvider::Amp,
+            Self::Wikipedia { .. } => SuggestionProvider::Wikipedia,
+            Self::Amo { .. } => SuggestionProvider::Amo,
+            Self::Yelp { .. } => SuggestionProvider::Yelp,
+            Self::Mdn { .. } => SuggestionProvider::Mdn,
+            Self::Weather { .. } => SuggestionProvider::Weather,
+            Self::Fakespot { .. } => SuggestionProvider::Fakespot,
+            Self::Dynamic { .. } => SuggestionProvider::Dynamic,
+            #[cfg(feature = "merino")]
+            Self::Merino { .. } => return None,
+        })
+    }
+
     /// Get the URL for this suggestion, if present
     pub fn url(&self) -> Option<&str> {
         match self {
@@ -167,6 +224,8 @@ impl Suggestion {
             | Self::Yelp { url, .. }
             | Self::Mdn { url, .. }
             | Self::Fakespot { url, .. } => Some(url),
+            #[cfg(feature = "merino")]
+            Self::Merino { suggestion } => Some(&suggestion.url),
             Self::Weather { .. } | Self::Dynamic { .. } => None,
         }
     }
@@ -185,6 +244,8 @@ impl Suggestion {
             | Self::Weather { .. }
             | Self::Fakespot { .. }
             | Self::Dynamic { .. } => self.url(),
+            #[cfg(feature = "merino")]
+            Self::Merino { .. } => self.url(),
         }
     }
 
@@ -196,6 +257,8 @@ impl Suggestion {
             | Self::Yelp { title, .. }
             | Self::Mdn { title, .. }
             | Self::Fakespot { title, .. } => title,
+            #[cfg(feature = "merino")]
+            Self::Merino { suggestion } => &suggestion.title,
             _ => "untitled",
         }
     }
@@ -219,6 +282,8 @@ impl Suggestion {
             | Self::Weather { score, .. }
             | Self::Fakespot { score, .. }
             | Self::Dynamic { score, .. } => *score,
+            #[cfg(feature = "merino")]
+            Self::Merino { suggestion } => suggestion.score,
             Self::Wikipedia { .. } => DEFAULT_SUGGESTION_SCORE,
         }
     }
@@ -229,6 +294,55 @@ impl Suggestion {
             _ => None,
         }
     }
+
+    pub fn fuzzy_match_info(&self) -> Option<&FuzzyMatchInfo> {
+        match self {
+            Self::Amp {
+                fuzzy_match_info, ..
+            }
+            | Self::Wikipedia {
+                fuzzy_match_info, ..
+            }
+            | Self::Yelp {
+                fuzzy_match_info, ..
+            }
+            | Self::Mdn {
+                fuzzy_match_info, ..
+            } => fuzzy_match_info.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn personalization_info(&self) -> Option<&PersonalizationInfo> {
+        match self {
+            Self::Amp {
+                personalization_info,
+                ..
+            } => personalization_info.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Marks the suggestion as a fuzzy match.  This does nothing for suggestion types that don't
+    /// support fuzzy matching.
+    pub(crate) fn with_fuzzy_match_info(mut self, info: FuzzyMatchInfo) -> Self {
+        match &mut self {
+            Self::Amp {
+                fuzzy_match_info, ..
+            }
+            | Self::Wikipedia {
+                fuzzy_match_info, ..
+            }
+            | Self::Yelp {
+                fuzzy_match_info, ..
+            }
+            | Self::Mdn {
+                fuzzy_match_info, ..
+            } => *fuzzy_match_info = Some(info),
+            _ => (),
+        }
+        self
+    }
 }
 
 #[cfg(test)]
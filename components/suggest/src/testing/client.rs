@@ -163,17 +163,16 @@ impl From<MockRecord> for Record {
             },
         });
 
+        let fields = json!({
+            "type": mock_record.record_type.as_str(),
+        })
+        .merge(mock_record.inline_data.unwrap_or(json!({})));
         Self {
             id: SuggestRecordId::new(mock_record.id),
             collection: mock_record.collection,
             last_modified: 0,
-            payload: serde_json::from_value(
-                json!({
-                    "type": mock_record.record_type.as_str(),
-                })
-                .merge(mock_record.inline_data.unwrap_or(json!({}))),
-            )
-            .unwrap(),
+            targeting: serde_json::from_value(fields.clone()).unwrap(),
+            payload: serde_json::from_value(fields).unwrap(),
             attachment,
         }
     }
@@ -53,6 +53,8 @@ pub fn los_pollos_suggestion(
         score: 0.3,
         full_keyword: full_keyword.to_string(),
         fts_match_info,
+        fuzzy_match_info: None,
+        personalization_info: None,
     }
 }
 
@@ -99,6 +101,8 @@ pub fn good_place_eats_suggestion(
         raw_click_url: "https://example.com/click_url".into(),
         score: 0.2,
         fts_match_info,
+        fuzzy_match_info: None,
+        personalization_info: None,
     }
 }
 
@@ -129,6 +133,7 @@ pub fn california_suggestion(full_keyword: &str) -> Suggestion {
         icon: Some("california-icon-data".as_bytes().to_vec()),
         icon_mimetype: Some("image/png".into()),
         full_keyword: full_keyword.into(),
+        fuzzy_match_info: None,
     }
 }
 
@@ -159,6 +164,7 @@ pub fn caltech_suggestion(full_keyword: &str) -> Suggestion {
         icon: Some("caltech-icon-data".as_bytes().to_vec()),
         icon_mimetype: Some("image/png".into()),
         full_keyword: full_keyword.into(),
+        fuzzy_match_info: None,
     }
 }
 
@@ -303,6 +309,7 @@ pub fn ramen_suggestion(title: &str, url: &str) -> Suggestion {
         subject_exact_match: true,
         subject_type: YelpSubjectType::Service,
         location_param: "find_loc".into(),
+        fuzzy_match_info: None,
     }
 }
 
@@ -332,6 +339,7 @@ pub fn array_suggestion() -> Suggestion {
                 .into(),
         description: "Javascript Array".into(),
         score: 0.24,
+        fuzzy_match_info: None,
     }
 }
 
@@ -389,6 +397,7 @@ pub fn multimatch_wiki_suggestion() -> Suggestion {
         icon: Some("multimatch-wiki-icon-data".as_bytes().to_vec()),
         icon_mimetype: Some("image/png".into()),
         full_keyword: "multimatch".into(),
+        fuzzy_match_info: None,
     }
 }
 
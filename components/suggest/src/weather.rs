@@ -289,6 +289,31 @@ impl SuggestDao<'_> {
         }
     }
 
+    /// Returns the weather provider's `min_keyword_length` and score if the
+    /// query is only a weather keyword or keyword prefix that's too short to
+    /// match, for [crate::SuggestStore::explain_query].
+    pub(crate) fn weather_keyword_below_threshold(
+        &self,
+        query: &SuggestionQuery,
+    ) -> Result<Option<(u32, f64)>> {
+        let keyword = query.keyword.trim().to_lowercase();
+        let cache = self.weather_cache();
+        if keyword.is_empty()
+            || keyword.len() >= cache.min_keyword_length
+            || keyword.contains(|c: char| c.is_whitespace() || c == ',')
+        {
+            return Ok(None);
+        }
+        Ok(
+            (!self.match_weather_keywords(&keyword, true)?.is_empty()).then(|| {
+                (
+                    u32::try_from(cache.min_keyword_length).unwrap_or(u32::MAX),
+                    cache.score,
+                )
+            }),
+        )
+    }
+
     fn match_weather_keywords(
         &self,
         candidate: &str,
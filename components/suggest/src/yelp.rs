@@ -10,6 +10,7 @@ use url::form_urlencoded;
 
 use crate::{
     db::SuggestDao,
+    fuzzy::FuzzyKeywordInsertStatement,
     provider::SuggestionProvider,
     rs::{DownloadedYelpSuggestion, SuggestRecordId},
     suggestion::Suggestion,
@@ -92,6 +93,7 @@ impl SuggestDao<'_> {
         &mut self,
         record_id: &SuggestRecordId,
         suggestion: &DownloadedYelpSuggestion,
+        enable_fuzzy: bool,
     ) -> Result<()> {
         for keyword in &suggestion.subjects {
             self.scope.err_if_interrupted()?;
@@ -117,6 +119,21 @@ impl SuggestDao<'_> {
             )?;
         }
 
+        if enable_fuzzy {
+            // Subjects are fuzzy matched a word at a time, since the query
+            // usually has modifiers and a location around the subject.
+            let mut fuzzy_insert = FuzzyKeywordInsertStatement::new(self.conn)?;
+            let words = suggestion
+                .subjects
+                .iter()
+                .chain(suggestion.business_subjects.iter().flatten())
+                .flat_map(|subject| subject.split_whitespace());
+            for word in words {
+                self.scope.err_if_interrupted()?;
+                fuzzy_insert.execute(record_id, SuggestionProvider::Yelp, word)?;
+            }
+        }
+
         for keyword in &suggestion.pre_modifiers {
             self.scope.err_if_interrupted()?;
             self.conn.execute_cached(
@@ -191,6 +208,42 @@ impl SuggestDao<'_> {
             return Ok(vec![]);
         }
 
+        let suggestions = self.fetch_yelp_suggestions_using_keywords(query)?;
+        if !suggestions.is_empty() || !query.uses_fuzzy_matching() {
+            return Ok(suggestions);
+        }
+        self.fetch_fuzzy_yelp_suggestions(query)
+    }
+
+    /// Fetch Yelp suggestion for the query with one of its words corrected to
+    /// a word of a subject. The first correction that gives a suggestion wins.
+    fn fetch_fuzzy_yelp_suggestions(&self, query: &SuggestionQuery) -> Result<Vec<Suggestion>> {
+        let words: Vec<_> = query.keyword.split_whitespace().collect();
+        for (i, word) in words.iter().enumerate() {
+            for correction in self.fetch_fuzzy_corrections(SuggestionProvider::Yelp, word)? {
+                self.scope.err_if_interrupted()?;
+                let mut corrected_words = words.clone();
+                corrected_words[i] = &correction.keyword;
+                let corrected_query = SuggestionQuery {
+                    keyword: corrected_words.join(" "),
+                    ..query.clone()
+                };
+                let suggestions = self.fetch_yelp_suggestions_using_keywords(&corrected_query)?;
+                if !suggestions.is_empty() {
+                    return Ok(suggestions
+                        .into_iter()
+                        .map(|s| s.with_fuzzy_match_info(correction.match_info()))
+                        .collect());
+                }
+            }
+        }
+        Ok(vec![])
+    }
+
+    fn fetch_yelp_suggestions_using_keywords(
+        &self,
+        query: &SuggestionQuery,
+    ) -> Result<Vec<Suggestion>> {
         let query_vec: Vec<_> = query.keyword.split_whitespace().collect();
         let mut query_words: &[&str] = &query_vec;
 
@@ -481,6 +534,7 @@ impl<'a> From<SuggestionBuilder<'a>> for Suggestion {
             subject_exact_match: builder.subject_exact_match,
             subject_type: builder.subject_type,
             location_param: "find_loc".to_string(),
+            fuzzy_match_info: None,
         }
     }
 }
@@ -4,7 +4,7 @@
 
 use criterion::{criterion_group, measurement::Measurement, BatchSize, BenchmarkGroup, Criterion};
 use std::sync::Once;
-use suggest::benchmarks::{cleanup, geoname, ingest, query, BenchmarkWithInput};
+use suggest::benchmarks::{cleanup, geoname, ingest, query, typing, BenchmarkWithInput};
 
 pub fn geoname(c: &mut Criterion) {
     setup_viaduct();
@@ -27,6 +27,12 @@ pub fn query(c: &mut Criterion) {
     run_benchmarks(group, query::all_benchmarks())
 }
 
+pub fn typing(c: &mut Criterion) {
+    setup_viaduct();
+    let group = c.benchmark_group("typing");
+    run_benchmarks(group, typing::all_benchmarks())
+}
+
 fn run_benchmarks<B: BenchmarkWithInput, M: Measurement>(
     mut group: BenchmarkGroup<M>,
     benchmarks: Vec<(&'static str, B)>,
@@ -54,7 +60,7 @@ fn setup_viaduct() {
     });
 }
 
-criterion_group!(benches, geoname, ingest, query);
+criterion_group!(benches, geoname, ingest, query, typing);
 
 fn main() {
     benches();
@@ -175,6 +175,40 @@ impl PrivateKey {
         self.curve
     }
 
+    /// ECDSA sign operation
+    ///
+    /// Returns the signature as the concatenated `r` and `s` values.  This is only available with
+    /// the `test-signing` feature, since only test servers sign data.
+    #[cfg(feature = "test-signing")]
+    pub fn sign(&self, message: &[u8], hash_algorithm: HashAlgorithm) -> Result<Vec<u8>> {
+        // The following code is adapted from:
+        // https://searchfox.org/mozilla-central/rev/b2716c233e9b4398fc5923cbe150e7f83c7c6c5b/dom/crypto/WebCryptoTask.cpp#1096
+        let hash = pk11::context::hash_buf(&hash_algorithm, message)?;
+        let hash = nss_sys::SECItem {
+            len: u32::try_from(hash.len())?,
+            data: hash.as_ptr() as *mut u8,
+            type_: 0,
+        };
+        let signature_len = unsafe { nss_sys::PK11_SignatureLen(self.as_mut_ptr()) };
+        let mut signature_buf = vec![0u8; usize::try_from(signature_len)?];
+        let mut signature = nss_sys::SECItem {
+            len: u32::try_from(signature_buf.len())?,
+            data: signature_buf.as_mut_ptr(),
+            type_: 0,
+        };
+        map_nss_secstatus(|| unsafe {
+            nss_sys::PK11_SignWithMechanism(
+                self.as_mut_ptr(),
+                nss_sys::PK11_MapSignKeyType((*self.wrapped.as_ptr()).keyType),
+                ptr::null(),
+                &mut signature,
+                &hash,
+            )
+        })?;
+        signature_buf.truncate(usize::try_from(signature.len)?);
+        Ok(signature_buf)
+    }
+
     pub fn private_value(&self) -> Result<Vec<u8>> {
         let mut private_value = self.read_raw_attribute(nss_sys::CKA_VALUE.into()).unwrap();
         let private_key = unsafe { sec_item_as_slice(private_value.as_mut_ref())?.to_vec() };
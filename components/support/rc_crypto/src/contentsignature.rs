@@ -154,6 +154,22 @@ pub fn verify(
     }
 }
 
+/// Sign data like the Autograph service signs content, with the ECDSA P384 curve and SHA-384
+/// hashing.
+///
+/// This is only meant for test servers, which sign data with the key of a test certificate, so it
+/// requires the `test-signing` feature.  The data must be prefixed like for [verify].  The
+/// signature is returned as base 64 url-safe encoded.
+#[cfg(feature = "test-signing")]
+pub fn sign(input: &[u8], private_key: &nss::ec::EcKey) -> Result<String> {
+    if private_key.curve() != nss::ec::Curve::P384 {
+        return Err(ErrorKind::InternalError.into());
+    }
+    let private_key = nss::ec::PrivateKey::import(private_key)?;
+    let signature = private_key.sign(input, nss::pbkdf2::HashAlgorithm::SHA384)?;
+    Ok(URL_SAFE.encode(signature))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -414,6 +430,22 @@ fdfeff
         .is_err());
     }
 
+    #[cfg(feature = "test-signing")]
+    #[test]
+    fn test_sign() {
+        nss::ensure_initialized();
+        let (private_key, public_key) = nss::ec::generate_keypair(nss::ec::Curve::P384).unwrap();
+        let signature = sign(VALID_INPUT, &private_key.export().unwrap()).unwrap();
+        let signature_bytes = URL_SAFE.decode(signature).unwrap();
+        assert_eq!(signature_bytes.len(), 96);
+        signature::UnparsedPublicKey::new(
+            &signature::ECDSA_P384_SHA384,
+            &public_key.to_bytes().unwrap(),
+        )
+        .verify(VALID_INPUT, &signature_bytes)
+        .unwrap();
+    }
+
     #[test]
     fn test_verify_succeeds_if_valid() {
         nss::ensure_initialized();
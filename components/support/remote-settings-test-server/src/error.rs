@@ -0,0 +1,19 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] rc_crypto::Error),
+    #[error("Remote Settings error: {0}")]
+    RemoteSettings(#[from] remote_settings::error::Error),
+    #[error("Invalid fixture: {0}")]
+    Fixture(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
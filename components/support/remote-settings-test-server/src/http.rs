@@ -0,0 +1,125 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A minimal HTTP/1.1 server, which handles one `GET` request per connection.
+//!
+//! This is all that the viaduct backends need to talk to the test server, and it keeps the crate
+//! free of server dependencies.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+use serde_json::Value;
+use url::Url;
+
+use crate::error::{Error, Result};
+
+pub(crate) struct HttpRequest {
+    /// The request target, like `/v1/buckets/main/collections/regions/changeset?_expected=0`
+    pub target: String,
+    pub path_segments: Vec<String>,
+    pub query: HashMap<String, String>,
+}
+
+impl HttpRequest {
+    /// Read the request head from a stream.  Request bodies are ignored.
+    pub fn read(stream: &TcpStream) -> Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let target = request_line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| Error::Fixture(format!("Invalid request line: {request_line}")))?
+            .to_string();
+        let url = Url::parse(&format!("http://localhost{target}"))
+            .map_err(|e| Error::Fixture(format!("Invalid request target {target}: {e}")))?;
+        let path_segments = url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|segment| !segment.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let query = url.query_pairs().into_owned().collect();
+        Ok(Self {
+            target,
+            path_segments,
+            query,
+        })
+    }
+}
+
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn json(value: &Value) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", "application/json".into())],
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    pub fn bytes(body: Vec<u8>, content_type: &str) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", content_type.into())],
+            body,
+        }
+    }
+
+    /// A Kinto-style 404 error
+    pub fn not_found() -> Self {
+        Self {
+            status: 404,
+            ..Self::json(&serde_json::json!({
+                "code": 404,
+                "errno": 111,
+                "error": "Not Found",
+            }))
+        }
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn write(&self, mut stream: &TcpStream) -> Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        ));
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&self.body)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
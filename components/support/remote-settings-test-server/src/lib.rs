@@ -0,0 +1,584 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! In-process fake Remote Settings server, for tests
+//!
+//! [RemoteSettingsTestServer] serves the Kinto endpoints that the Remote Settings clients use, on
+//! a local port, so that component tests can run a realistic sync through viaduct instead of
+//! mocking the client:
+//!
+//!   - `/v1/`, with the attachments capability
+//!   - `/v1/buckets/monitor/collections/changes/changeset` and `.../records`
+//!   - `/v1/buckets/{bucket}/collections/{collection}/changeset`, including `_since` diffs with
+//!     tombstones, signed with a test certificate
+//!   - `/v1/buckets/{bucket}/collections/{collection}/records`
+//!   - `/attachments/{location}`
+//!
+//! The data comes from fixture directories, which are laid out like the packaged dumps in
+//! `components/remote_settings/dumps`:
+//!
+//!   - `{bucket}/{collection}.json`, with the collection's `data` and `timestamp`
+//!   - `{bucket}/attachments/{collection}/{record_id}`, with a record's attachment data
+//!
+//! Tests can also change the data while the server is running, with
+//! [RemoteSettingsTestServer::set_records] and [RemoteSettingsTestServer::set_attachment].
+//!
+//! Changeset signatures are valid, but for a certificate chain that ends at a test root instead of
+//! the Remote Settings root.  Its hash is [ROOT_CERT_SHA256_HASH].  Make sure to call
+//! `viaduct_dev::init_backend_dev()` or initialize another viaduct backend before syncing.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use error_support::warn;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+
+mod error;
+mod http;
+mod signing;
+
+pub use error::{Error, Result};
+use http::{HttpRequest, HttpResponse};
+
+/// SHA-256 hash of the test root certificate, in the format that
+/// `rc_crypto::contentsignature::verify` expects.
+pub const ROOT_CERT_SHA256_HASH: &str =
+    "15:31:A1:26:3F:93:9B:0F:57:70:16:42:F3:2B:DE:F7:07:CC:D7:D5:3A:41:7F:53:87:AE:F6:85:49:73:35:DE";
+
+/// A fake Remote Settings server, running on a background thread
+///
+/// The server stops when this is dropped.
+pub struct RemoteSettingsTestServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    collections: BTreeMap<(String, String), Collection>,
+    /// Attachment data, by location
+    attachments: HashMap<String, Vec<u8>>,
+    /// The request targets that the server has received, in order
+    requests: Vec<String>,
+}
+
+#[derive(Default)]
+struct Collection {
+    /// All records, including tombstones
+    records: Vec<Value>,
+    timestamp: u64,
+}
+
+impl RemoteSettingsTestServer {
+    /// Start a server with no collections
+    pub fn start() -> Result<Self> {
+        nss::ensure_initialized();
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            move || serve(listener, addr, state, shutdown)
+        });
+        Ok(Self {
+            addr,
+            state,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Start a server with the collections from a fixture directory
+    pub fn with_fixtures(dir: impl AsRef<Path>) -> Result<Self> {
+        let server = Self::start()?;
+        server.load_fixtures(dir)?;
+        Ok(server)
+    }
+
+    /// The server URL, to use for `RemoteSettingsServer::Custom`
+    ///
+    /// Like for other custom servers, this doesn't include the `/v1` path.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Load the collections and attachments from a fixture directory
+    ///
+    /// This replaces any collections that the server already has with the same bucket and name.
+    pub fn load_fixtures(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let mut state = self.state.lock();
+        for bucket_entry in std::fs::read_dir(dir)? {
+            let bucket_path = bucket_entry?.path();
+            if !bucket_path.is_dir() {
+                continue;
+            }
+            let bucket = file_name(&bucket_path)?;
+            for collection_entry in std::fs::read_dir(&bucket_path)? {
+                let collection_path = collection_entry?.path();
+                if collection_path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let collection_name = collection_path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or_else(|| {
+                        Error::Fixture(format!("Invalid file name: {collection_path:?}"))
+                    })?
+                    .to_string();
+                let fixture: Value =
+                    serde_json::from_str(&std::fs::read_to_string(&collection_path)?)?;
+                let records = fixture["data"]
+                    .as_array()
+                    .ok_or_else(|| Error::Fixture(format!("No data in {collection_path:?}")))?
+                    .clone();
+                let timestamp = fixture["timestamp"].as_u64().ok_or_else(|| {
+                    Error::Fixture(format!("No timestamp in {collection_path:?}"))
+                })?;
+
+                let attachments_dir = bucket_path.join("attachments").join(&collection_name);
+                for record in &records {
+                    let (Some(id), Some(location)) = (
+                        record["id"].as_str(),
+                        record["attachment"]["location"].as_str(),
+                    ) else {
+                        continue;
+                    };
+                    let attachment_path = attachments_dir.join(id);
+                    if attachment_path.exists() {
+                        state
+                            .attachments
+                            .insert(location.to_string(), std::fs::read(attachment_path)?);
+                    }
+                }
+                state.collections.insert(
+                    (bucket.clone(), collection_name),
+                    Collection { records, timestamp },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the records of a collection, as if they had been published on the server
+    ///
+    /// The collection gets a new timestamp, and records that were removed get tombstones, so that
+    /// clients that already synced get a diff.  Records without a `last_modified` field are
+    /// stamped with the new timestamp.  Returns the new timestamp.
+    pub fn set_records(&self, bucket: &str, collection: &str, records: Vec<Value>) -> u64 {
+        let mut state = self.state.lock();
+        let collection = state
+            .collections
+            .entry((bucket.to_string(), collection.to_string()))
+            .or_default();
+        let timestamp = records
+            .iter()
+            .filter_map(|record| record["last_modified"].as_u64())
+            .fold(collection.timestamp + 1, u64::max);
+
+        let mut new_records = records
+            .into_iter()
+            .map(|mut record| {
+                if record["last_modified"].is_null() {
+                    record["last_modified"] = timestamp.into();
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+        let new_ids = new_records
+            .iter()
+            .filter_map(|record| record["id"].as_str().map(str::to_string))
+            .collect::<HashSet<_>>();
+        for record in &collection.records {
+            let Some(id) = record["id"].as_str() else {
+                continue;
+            };
+            if new_ids.contains(id) {
+                continue;
+            }
+            if is_tombstone(record) {
+                new_records.push(record.clone());
+            } else {
+                new_records.push(json!({
+                    "id": id,
+                    "last_modified": timestamp,
+                    "deleted": true,
+                }));
+            }
+        }
+        collection.records = new_records;
+        collection.timestamp = timestamp;
+        timestamp
+    }
+
+    /// Serve attachment data at a location, like the `location` of a record's attachment
+    pub fn set_attachment(&self, location: &str, data: Vec<u8>) {
+        self.state
+            .lock()
+            .attachments
+            .insert(location.to_string(), data);
+    }
+
+    /// The request targets that the server has received, like
+    /// `/v1/buckets/main/collections/regions/changeset?_expected=0`
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().requests.clone()
+    }
+
+    /// Forget the requests that the server has received
+    pub fn clear_requests(&self) {
+        self.state.lock().requests.clear();
+    }
+}
+
+impl Drop for RemoteSettingsTestServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the server thread, so that it sees the shutdown flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(
+    listener: TcpListener,
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    shutdown: Arc<AtomicBool>,
+) {
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Error accepting connection: {e}");
+                continue;
+            }
+        };
+        let result = HttpRequest::read(&stream).and_then(|request| {
+            let response = state.lock().handle_request(&request, addr);
+            response.write(&stream)
+        });
+        if let Err(e) = result {
+            warn!("Error handling request: {e}");
+        }
+    }
+}
+
+impl ServerState {
+    fn handle_request(&mut self, request: &HttpRequest, addr: SocketAddr) -> HttpResponse {
+        self.requests.push(request.target.clone());
+        match self.route(request, addr) {
+            Ok(Some(response)) => response,
+            Ok(None) => HttpResponse::not_found(),
+            Err(e) => HttpResponse {
+                status: 500,
+                ..HttpResponse::json(&json!({
+                    "code": 500,
+                    "errno": 999,
+                    "error": e.to_string(),
+                }))
+            },
+        }
+    }
+
+    fn route(&self, request: &HttpRequest, addr: SocketAddr) -> Result<Option<HttpResponse>> {
+        let segments = request
+            .path_segments
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let since = request
+            .query
+            .get("_since")
+            .and_then(|since| since.trim_matches('"').parse::<u64>().ok());
+        Ok(match segments.as_slice() {
+            ["v1"] => Some(HttpResponse::json(&json!({
+                "project_name": "Remote Settings Test Server",
+                "capabilities": {
+                    "attachments": {
+                        "base_url": format!("http://{addr}/attachments/"),
+                    },
+                },
+            }))),
+            ["v1", "buckets", "monitor", "collections", "changes", "changeset"] => {
+                Some(HttpResponse::json(&json!({
+                    "changes": self.monitor_changes(since),
+                    "timestamp": self.monitor_timestamp(),
+                    "metadata": {"bucket": "monitor"},
+                })))
+            }
+            ["v1", "buckets", "monitor", "collections", "changes", "records"] => Some(
+                HttpResponse::json(&json!({ "data": self.monitor_changes(since) }))
+                    .with_header("ETag", format!("\"{}\"", self.monitor_timestamp())),
+            ),
+            ["v1", "buckets", bucket, "collections", collection, "changeset"] => {
+                match self.collection(bucket, collection) {
+                    Some(c) => Some(HttpResponse::json(&json!({
+                        "changes": c.changes_since(since),
+                        "timestamp": c.timestamp,
+                        "metadata": {
+                            "bucket": bucket,
+                            "signatures": [{
+                                "signature": signing::sign_collection(c.timestamp, &c.live_records())?,
+                                "x5u": format!("http://{addr}/x5u/chain.pem"),
+                            }],
+                        },
+                    }))),
+                    None => None,
+                }
+            }
+            ["v1", "buckets", bucket, "collections", collection, "records"] => {
+                self.collection(bucket, collection).map(|c| {
+                    HttpResponse::json(&json!({ "data": c.live_records() }))
+                        .with_header("ETag", format!("\"{}\"", c.timestamp))
+                })
+            }
+            ["attachments", location @ ..] => self
+                .attachments
+                .get(&location.join("/"))
+                .map(|data| HttpResponse::bytes(data.clone(), "application/octet-stream")),
+            ["x5u", "chain.pem"] => Some(HttpResponse::bytes(
+                signing::CERT_CHAIN.as_bytes().to_vec(),
+                "application/x-pem-file",
+            )),
+            _ => None,
+        })
+    }
+
+    fn collection(&self, bucket: &str, collection: &str) -> Option<&Collection> {
+        self.collections
+            .get(&(bucket.to_string(), collection.to_string()))
+    }
+
+    /// The entries for the `monitor/changes` collection, one per collection
+    fn monitor_changes(&self, since: Option<u64>) -> Vec<Value> {
+        self.collections
+            .iter()
+            .filter(|(_, c)| since.is_none_or(|since| c.timestamp > since))
+            .map(|((bucket, collection), c)| {
+                json!({
+                    "id": format!("{bucket}/{collection}"),
+                    "bucket": bucket,
+                    "collection": collection,
+                    "last_modified": c.timestamp,
+                })
+            })
+            .collect()
+    }
+
+    fn monitor_timestamp(&self) -> u64 {
+        self.collections
+            .values()
+            .map(|c| c.timestamp)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl Collection {
+    /// The records that haven't been deleted
+    fn live_records(&self) -> Vec<Value> {
+        self.records
+            .iter()
+            .filter(|record| !is_tombstone(record))
+            .cloned()
+            .collect()
+    }
+
+    /// The records to send for a changeset
+    ///
+    /// Full changesets only have the live records.  Diffs have the records that changed after
+    /// `since`, including tombstones.
+    fn changes_since(&self, since: Option<u64>) -> Vec<Value> {
+        match since {
+            None => self.live_records(),
+            Some(since) => self
+                .records
+                .iter()
+                .filter(|record| record["last_modified"].as_u64().unwrap_or_default() > since)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+fn is_tombstone(record: &Value) -> bool {
+    record["deleted"].as_bool().unwrap_or_default()
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| Error::Fixture(format!("Invalid file name: {path:?}")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use url::Url;
+
+    fn get(server: &RemoteSettingsTestServer, path: &str) -> viaduct::Response {
+        viaduct_dev::init_backend_dev();
+        let url = Url::parse(&format!("{}{path}", server.url())).unwrap();
+        viaduct::Request::get(url).send().unwrap()
+    }
+
+    fn write_fixtures(dir: &Path) {
+        let bucket_dir = dir.join("main");
+        std::fs::create_dir_all(bucket_dir.join("attachments").join("regions")).unwrap();
+        std::fs::write(
+            bucket_dir.join("regions.json"),
+            json!({
+                "data": [
+                    {
+                        "id": "fr",
+                        "last_modified": 100,
+                        "attachment": {"location": "main/regions/fr.json"},
+                    },
+                    {"id": "de", "last_modified": 90},
+                ],
+                "timestamp": 100,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            bucket_dir.join("attachments").join("regions").join("fr"),
+            b"bonjour",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_changeset_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        write_fixtures(dir.path());
+        let server = RemoteSettingsTestServer::with_fixtures(dir.path()).unwrap();
+
+        let changeset: Value = get(&server, "/v1/buckets/main/collections/regions/changeset")
+            .json()
+            .unwrap();
+        assert_eq!(changeset["timestamp"], 100);
+        let records = changeset["changes"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+
+        let signature = &changeset["metadata"]["signatures"][0];
+        let x5u = signature["x5u"].as_str().unwrap();
+        let chain = get(&server, &x5u[server.url().len()..]).body;
+        let epoch_seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        rc_crypto::contentsignature::verify(
+            &remote_settings::signatures::serialize_data(100, records).unwrap(),
+            signature["signature"].as_str().unwrap().as_bytes(),
+            &chain,
+            epoch_seconds,
+            ROOT_CERT_SHA256_HASH,
+            "remote-settings.content-signature.mozilla.org",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_changeset_since() {
+        let server = RemoteSettingsTestServer::start().unwrap();
+        let timestamp = server.set_records(
+            "main",
+            "regions",
+            vec![json!({"id": "fr"}), json!({"id": "de"})],
+        );
+        let new_timestamp = server.set_records(
+            "main",
+            "regions",
+            vec![
+                json!({"id": "fr", "last_modified": timestamp}),
+                json!({"id": "it"}),
+            ],
+        );
+        assert_eq!(new_timestamp, timestamp + 1);
+
+        let changeset: Value = get(
+            &server,
+            &format!(
+                "/v1/buckets/main/collections/regions/changeset?_expected=0&_since=\"{timestamp}\""
+            ),
+        )
+        .json()
+        .unwrap();
+        assert_eq!(changeset["timestamp"], new_timestamp);
+        assert_eq!(
+            changeset["changes"],
+            json!([
+                {"id": "it", "last_modified": new_timestamp},
+                {"id": "de", "last_modified": new_timestamp, "deleted": true},
+            ])
+        );
+
+        let changes: Value = get(&server, "/v1/buckets/monitor/collections/changes/changeset")
+            .json()
+            .unwrap();
+        assert_eq!(
+            changes["changes"],
+            json!([{
+                "id": "main/regions",
+                "bucket": "main",
+                "collection": "regions",
+                "last_modified": new_timestamp,
+            }])
+        );
+        assert_eq!(
+            server.requests(),
+            vec![
+                format!("/v1/buckets/main/collections/regions/changeset?_expected=0&_since=%22{timestamp}%22"),
+                "/v1/buckets/monitor/collections/changes/changeset".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        write_fixtures(dir.path());
+        let server = RemoteSettingsTestServer::with_fixtures(dir.path()).unwrap();
+
+        let server_info: Value = get(&server, "/v1/").json().unwrap();
+        assert_eq!(
+            server_info["capabilities"]["attachments"]["base_url"],
+            format!("{}/attachments/", server.url())
+        );
+        assert_eq!(
+            get(&server, "/attachments/main/regions/fr.json").body,
+            b"bonjour"
+        );
+        server.set_attachment("main/regions/de.json", b"hallo".to_vec());
+        assert_eq!(
+            get(&server, "/attachments/main/regions/de.json").body,
+            b"hallo"
+        );
+        assert_eq!(
+            get(&server, "/attachments/main/regions/it.json").status,
+            404
+        );
+    }
+}
@@ -0,0 +1,39 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Content signatures for the test server's collections.
+//!
+//! The server signs changesets like the Autograph service does, but with the key of a test
+//! certificate.  `certs/chain.pem` has that certificate, valid for both the `remote-settings` and
+//! `onecrl` signer names until 2125, followed by the test root that issued it.
+
+use nss::ec::{Curve, EcKey};
+use rc_crypto::contentsignature;
+use remote_settings::signatures::serialize_data;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// The certificate chain that the server returns for the `x5u` URL of its signatures
+pub(crate) const CERT_CHAIN: &str = include_str!("../certs/chain.pem");
+
+/// Private value (`d`) of the key for the leaf certificate in [CERT_CHAIN]
+const SIGNER_PRIVATE_KEY: &str = "0e9c94ce548d17c4f544eb063e23bd4a2cc4daca1252c42b199594593ab5e65a2ef14ef981de504788c62d4de71a3625";
+/// Uncompressed public point of the key for the leaf certificate in [CERT_CHAIN]
+const SIGNER_PUBLIC_KEY: &str = "042fc5455f5d99605f030977b76b79542384a0eb28b4ab7546d1f33d835e38d833a68d6aef49683b815969c2f0bd3c0cce5fd05e851307b9076bd686b05ce58ba3537e574ca31744a7aaa46f58a17de1f995839a65d44614783f22adc627364109";
+
+/// Sign the records of a collection at `timestamp`.
+///
+/// `records` shouldn't include tombstones.
+pub(crate) fn sign_collection(timestamp: u64, records: &[Value]) -> Result<String> {
+    let key = EcKey::new(
+        Curve::P384,
+        &hex::decode(SIGNER_PRIVATE_KEY).expect("Invalid signer private key"),
+        &hex::decode(SIGNER_PUBLIC_KEY).expect("Invalid signer public key"),
+    );
+    Ok(contentsignature::sign(
+        &serialize_data(timestamp, records)?,
+        &key,
+    )?)
+}
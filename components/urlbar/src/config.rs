@@ -0,0 +1,118 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use suggest::SuggestionProvider;
+
+/// The default maximum number of results.
+pub const DEFAULT_MAX_RESULTS: u32 = 10;
+
+/// A group of results. Each result belongs to exactly one group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, uniffi::Enum)]
+pub enum UrlbarResultGroup {
+    /// The result for what the user typed: an autofilled URL, the URL they
+    /// typed, or a search for the typed string.
+    Heuristic,
+    /// Search suggestions from the search engine.
+    SearchSuggestion,
+    /// Open tabs that the user can switch to.
+    OpenTab,
+    Bookmark,
+    History,
+    /// Suggestions from the Suggest component.
+    Suggest,
+}
+
+/// How many results of a group to show, and where.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct UrlbarGroupConfig {
+    pub group: UrlbarResultGroup,
+    /// The maximum number of results from this group, or `None` for no
+    /// limit other than `UrlbarMuxerConfig::max_results`.
+    #[uniffi(default = None)]
+    pub max_results: Option<u32>,
+}
+
+/// Configures how the muxer picks, orders and deduplicates results.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct UrlbarMuxerConfig {
+    /// The maximum number of results, including the heuristic result.
+    #[uniffi(default = 10)]
+    pub max_results: u32,
+    /// The groups to show, in order. Groups that aren't listed aren't
+    /// shown. If this is empty, the muxer uses [`default_groups`].
+    #[uniffi(default)]
+    pub groups: Vec<UrlbarGroupConfig>,
+    /// Always show the heuristic result first, even if `groups` puts it
+    /// somewhere else or leaves it out.
+    #[uniffi(default = true)]
+    pub heuristic_first: bool,
+    /// Use an autofilled URL from history as the heuristic result, if the
+    /// typed string is the beginning of one.
+    #[uniffi(default = true)]
+    pub autofill: bool,
+    /// The Suggest providers to query. If this is empty, Suggest isn't
+    /// queried.
+    #[uniffi(default)]
+    pub suggest_providers: Vec<SuggestionProvider>,
+    /// Treat `http` and `https` URLs as the same when deduplicating.
+    #[uniffi(default = true)]
+    pub dedupe_ignore_scheme: bool,
+    /// Treat URLs with and without a `www.` prefix as the same when
+    /// deduplicating.
+    #[uniffi(default = true)]
+    pub dedupe_ignore_www: bool,
+    /// Treat URLs with and without a trailing slash as the same when
+    /// deduplicating.
+    #[uniffi(default = true)]
+    pub dedupe_ignore_trailing_slash: bool,
+    /// Ignore the fragment (`#...`) when deduplicating.
+    #[uniffi(default = true)]
+    pub dedupe_ignore_fragment: bool,
+}
+
+impl Default for UrlbarMuxerConfig {
+    fn default() -> Self {
+        Self {
+            max_results: DEFAULT_MAX_RESULTS,
+            groups: vec![],
+            heuristic_first: true,
+            autofill: true,
+            suggest_providers: vec![],
+            dedupe_ignore_scheme: true,
+            dedupe_ignore_www: true,
+            dedupe_ignore_trailing_slash: true,
+            dedupe_ignore_fragment: true,
+        }
+    }
+}
+
+impl UrlbarMuxerConfig {
+    /// Returns the groups to show, in order.
+    pub(crate) fn groups(&self) -> Vec<UrlbarGroupConfig> {
+        if self.groups.is_empty() {
+            default_groups()
+        } else {
+            self.groups.clone()
+        }
+    }
+}
+
+/// Returns the groups that the muxer shows if `UrlbarMuxerConfig::groups` is
+/// empty. This is the same order as Firefox Desktop: the heuristic result,
+/// a few search suggestions, the user's own tabs, bookmarks and history, and
+/// then Suggest results.
+#[uniffi::export]
+pub fn default_groups() -> Vec<UrlbarGroupConfig> {
+    [
+        (UrlbarResultGroup::Heuristic, Some(1)),
+        (UrlbarResultGroup::SearchSuggestion, Some(4)),
+        (UrlbarResultGroup::OpenTab, None),
+        (UrlbarResultGroup::Bookmark, None),
+        (UrlbarResultGroup::History, None),
+        (UrlbarResultGroup::Suggest, Some(2)),
+    ]
+    .into_iter()
+    .map(|(group, max_results)| UrlbarGroupConfig { group, max_results })
+    .collect()
+}
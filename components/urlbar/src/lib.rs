@@ -0,0 +1,23 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A result muxer for address bars.
+//!
+//! [`UrlbarMuxer`] takes the string the user typed, queries history and
+//! bookmarks from Places and suggestions from Suggest, adds the open tabs and
+//! search suggestions that the app passes in, and returns a single ordered
+//! and deduplicated list of results. The grouping, per-group limits,
+//! heuristic result and URL deduplication rules are all set in
+//! [`UrlbarMuxerConfig`], so that every app's address bar can share them.
+
+mod config;
+mod muxer;
+mod result;
+mod urls;
+
+uniffi::setup_scaffolding!("urlbar");
+
+pub use config::{default_groups, UrlbarGroupConfig, UrlbarMuxerConfig, UrlbarResultGroup};
+pub use muxer::{UrlbarMuxer, UrlbarOpenTab, UrlbarQuery};
+pub use result::UrlbarResult;
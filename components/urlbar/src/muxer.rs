@@ -0,0 +1,648 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use error_support::warn;
+use places::PlacesConnection;
+use suggest::{SuggestStore, SuggestionQuery};
+
+use crate::{
+    config::{UrlbarMuxerConfig, UrlbarResultGroup},
+    result::UrlbarResult,
+    urls::{dedupe_key, fixup_url},
+};
+
+/// An open tab that the app passes to [`UrlbarMuxer::query`].
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct UrlbarOpenTab {
+    pub url: String,
+    pub title: String,
+    /// An app-specific ID for switching to the tab.
+    #[uniffi(default = None)]
+    pub tab_id: Option<String>,
+}
+
+/// What the user typed, and the results that the app provides itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, uniffi::Record)]
+pub struct UrlbarQuery {
+    pub search_string: String,
+    /// The user's open tabs. The muxer returns the ones whose URL or title
+    /// matches the search string.
+    #[uniffi(default)]
+    pub open_tabs: Vec<UrlbarOpenTab>,
+    /// Suggestions from the search engine's suggest endpoint, in the order
+    /// the engine returned them.
+    #[uniffi(default)]
+    pub search_suggestions: Vec<String>,
+    /// The name of the search engine to use for search results.
+    #[uniffi(default = None)]
+    pub search_engine_name: Option<String>,
+}
+
+/// Returns an ordered, deduplicated list of address bar results for what the
+/// user typed.
+#[derive(uniffi::Object)]
+pub struct UrlbarMuxer {
+    config: UrlbarMuxerConfig,
+    places: Option<Arc<PlacesConnection>>,
+    suggest: Option<Arc<SuggestStore>>,
+}
+
+#[uniffi::export]
+impl UrlbarMuxer {
+    /// Creates a muxer. History, bookmarks and autofill come from `places`,
+    /// and Suggest results from `suggest`; if either is `None`, those
+    /// results are left out.
+    #[uniffi::constructor]
+    pub fn new(
+        config: UrlbarMuxerConfig,
+        places: Option<Arc<PlacesConnection>>,
+        suggest: Option<Arc<SuggestStore>>,
+    ) -> Self {
+        Self {
+            config,
+            places,
+            suggest,
+        }
+    }
+
+    /// Returns the results for a query.
+    ///
+    /// Results are deduplicated by URL, keeping the result from the first of
+    /// these sources: the heuristic result, open tabs, Suggest, and then
+    /// bookmarks and history. Search suggestions that repeat the typed string
+    /// are dropped, since the heuristic result already searches for it. A
+    /// result only hides its duplicates if it's shown.
+    ///
+    /// The muxer is best-effort: if Places or Suggest fail, their errors are
+    /// logged and the results from the other sources are still returned.
+    /// This blocks on database queries, so it should be called from a worker
+    /// thread.
+    pub fn query(&self, query: UrlbarQuery) -> Vec<UrlbarResult> {
+        let search_string = query.search_string.trim();
+        if search_string.is_empty() {
+            return vec![];
+        }
+        let mut candidates = vec![self.heuristic_result(search_string, &query)];
+        candidates.extend(open_tab_results(search_string, &query.open_tabs));
+        candidates.extend(self.suggest_results(search_string));
+        candidates.extend(self.places_results(search_string));
+        candidates.extend(
+            query
+                .search_suggestions
+                .iter()
+                .map(|suggestion| UrlbarResult::Search {
+                    query: suggestion.clone(),
+                    engine_name: query.search_engine_name.clone(),
+                    heuristic: false,
+                }),
+        );
+        mux(&self.config, candidates)
+    }
+}
+
+impl UrlbarMuxer {
+    /// Returns an autofilled URL, the URL that the user typed, or a search
+    /// for the typed string, in that order of preference.
+    fn heuristic_result(&self, search_string: &str, query: &UrlbarQuery) -> UrlbarResult {
+        if let (true, Some(places)) = (self.config.autofill, &self.places) {
+            match places.match_url(search_string.to_owned()) {
+                Ok(Some(url)) => return UrlbarResult::Autofill { url: url.into() },
+                Ok(None) => (),
+                Err(e) => warn!("Error autofilling URL: {e}"),
+            }
+        }
+        match fixup_url(search_string) {
+            Some(url) => UrlbarResult::VisitUrl { url },
+            None => UrlbarResult::Search {
+                query: search_string.to_owned(),
+                engine_name: query.search_engine_name.clone(),
+                heuristic: true,
+            },
+        }
+    }
+
+    fn suggest_results(&self, search_string: &str) -> Vec<UrlbarResult> {
+        let Some(suggest) = &self.suggest else {
+            return vec![];
+        };
+        if self.config.suggest_providers.is_empty() {
+            return vec![];
+        }
+        let query = SuggestionQuery {
+            keyword: search_string.to_owned(),
+            providers: self.config.suggest_providers.clone(),
+            ..SuggestionQuery::default()
+        };
+        match suggest.query(query) {
+            Ok(suggestions) => suggestions
+                .into_iter()
+                .map(|suggestion| UrlbarResult::Suggest { suggestion })
+                .collect(),
+            Err(e) => {
+                warn!("Error querying Suggest: {e}");
+                vec![]
+            }
+        }
+    }
+
+    fn places_results(&self, search_string: &str) -> Vec<UrlbarResult> {
+        let Some(places) = &self.places else {
+            return vec![];
+        };
+        let limit = i32::try_from(self.config.max_results).unwrap_or(i32::MAX);
+        let results = match places.query_autocomplete(search_string.to_owned(), limit) {
+            Ok(results) => results,
+            Err(e) => {
+                warn!("Error querying history: {e}");
+                return vec![];
+            }
+        };
+        let urls = results
+            .iter()
+            .map(|result| result.url.to_string())
+            .collect::<Vec<_>>();
+        let bookmarked_urls = places
+            .bookmarks_get_bookmarked_urls(urls)
+            .map(|urls| urls.into_iter().collect::<HashSet<_>>())
+            .unwrap_or_else(|e| {
+                warn!("Error fetching bookmarks: {e}");
+                HashSet::new()
+            });
+        results
+            .into_iter()
+            .map(|result| {
+                let url = String::from(result.url);
+                if bookmarked_urls.contains(&url) {
+                    UrlbarResult::Bookmark {
+                        url,
+                        title: result.title,
+                        frecency: result.frecency,
+                    }
+                } else {
+                    UrlbarResult::History {
+                        url,
+                        title: result.title,
+                        frecency: result.frecency,
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the open tabs whose URL or title contains every word of the
+/// search string, ignoring case.
+fn open_tab_results(search_string: &str, open_tabs: &[UrlbarOpenTab]) -> Vec<UrlbarResult> {
+    let search_string = search_string.to_lowercase();
+    let words = search_string.split_whitespace().collect::<Vec<_>>();
+    open_tabs
+        .iter()
+        .filter(|tab| {
+            let url = tab.url.to_lowercase();
+            let title = tab.title.to_lowercase();
+            words
+                .iter()
+                .all(|word| url.contains(word) || title.contains(word))
+        })
+        .map(|tab| UrlbarResult::OpenTab {
+            url: tab.url.clone(),
+            title: tab.title.clone(),
+            tab_id: tab.tab_id.clone(),
+        })
+        .collect()
+}
+
+/// The key that two results must share to be duplicates.
+#[derive(PartialEq, Eq, Hash)]
+enum DedupeKey {
+    Url(String),
+    Search(String),
+}
+
+/// Returns the key that a result shares with its duplicates, if any.
+fn result_dedupe_key(result: &UrlbarResult, config: &UrlbarMuxerConfig) -> Option<DedupeKey> {
+    match result {
+        UrlbarResult::Search { query, .. } => Some(DedupeKey::Search(query.to_lowercase())),
+        result => result
+            .url()
+            .map(|url| DedupeKey::Url(dedupe_key(url, config))),
+    }
+}
+
+/// Returns the groups to show, in order, with the maximum number of results
+/// for each.
+fn layout(config: &UrlbarMuxerConfig) -> Vec<(UrlbarResultGroup, usize)> {
+    let mut layout = vec![];
+    if config.heuristic_first {
+        layout.push((UrlbarResultGroup::Heuristic, 1));
+    }
+    for group_config in config.groups() {
+        if layout.iter().any(|(group, _)| *group == group_config.group) {
+            continue;
+        }
+        let max_results = group_config
+            .max_results
+            .map_or(usize::MAX, |max_results| max_results as usize);
+        layout.push((group_config.group, max_results));
+    }
+    layout
+}
+
+/// Deduplicates, groups and limits results. `candidates` are in order of
+/// precedence: when results are duplicates, the first one that's shown is
+/// kept.
+fn mux(config: &UrlbarMuxerConfig, candidates: Vec<UrlbarResult>) -> Vec<UrlbarResult> {
+    let layout = layout(config);
+    let groups = candidates
+        .iter()
+        .map(UrlbarResult::group)
+        .collect::<Vec<_>>();
+    let keys = candidates
+        .iter()
+        .map(|result| result_dedupe_key(result, config))
+        .collect::<Vec<_>>();
+
+    // A result that's cut by `max_results` isn't shown, so it shouldn't hide
+    // its duplicates. Pick the results again without it until that doesn't
+    // happen. This ends because `hidden` grows each time.
+    let mut hidden = HashSet::new();
+    let shown = loop {
+        let (shown, cut) = pick_results(config, &layout, &groups, &keys, &hidden);
+        if cut.is_empty() {
+            break shown;
+        }
+        hidden.extend(cut);
+    };
+
+    let mut candidates = candidates.into_iter().map(Some).collect::<Vec<_>>();
+    shown
+        .into_iter()
+        .filter_map(|index| candidates[index].take())
+        .collect()
+}
+
+/// Picks the indexes of the results to show, in order, skipping the
+/// `hidden` ones. Also returns the indexes of the results that were cut by
+/// `max_results` after hiding a duplicate.
+fn pick_results(
+    config: &UrlbarMuxerConfig,
+    layout: &[(UrlbarResultGroup, usize)],
+    groups: &[UrlbarResultGroup],
+    keys: &[Option<DedupeKey>],
+    hidden: &HashSet<usize>,
+) -> (Vec<usize>, Vec<usize>) {
+    // The result that each key was first shown for.
+    let mut seen: HashMap<&DedupeKey, usize> = HashMap::new();
+    let mut hid_duplicates = HashSet::new();
+    let mut indexes_by_group: HashMap<UrlbarResultGroup, Vec<usize>> = HashMap::new();
+    for (index, (group, key)) in groups.iter().zip(keys).enumerate() {
+        if hidden.contains(&index) {
+            continue;
+        }
+        let Some(&(_, max_results)) = layout.iter().find(|(g, _)| g == group) else {
+            // Results in groups that aren't shown don't hide their
+            // duplicates.
+            continue;
+        };
+        if let Some(&kept) = key.as_ref().and_then(|key| seen.get(key)) {
+            hid_duplicates.insert(kept);
+            continue;
+        }
+        let group_indexes = indexes_by_group.entry(*group).or_default();
+        if group_indexes.len() >= max_results {
+            continue;
+        }
+        group_indexes.push(index);
+        if let Some(key) = key {
+            seen.insert(key, index);
+        }
+    }
+
+    let mut shown = layout
+        .iter()
+        .flat_map(|(group, _)| indexes_by_group.remove(group).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let cut = shown.split_off(shown.len().min(config.max_results as usize));
+    let cut = cut
+        .into_iter()
+        .filter(|index| hid_duplicates.contains(index))
+        .collect();
+    (shown, cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use places::{
+        storage::bookmarks::BookmarkRootGuid, BookmarkPosition, ConnectionType, InsertableBookmark,
+        InsertableBookmarkItem, PlacesApi, VisitObservation, VisitType,
+    };
+    use url::Url;
+
+    use crate::config::UrlbarGroupConfig;
+
+    fn history(url: &str, title: &str, frecency: i64) -> UrlbarResult {
+        UrlbarResult::History {
+            url: url.into(),
+            title: title.into(),
+            frecency,
+        }
+    }
+
+    fn search(query: &str, heuristic: bool) -> UrlbarResult {
+        UrlbarResult::Search {
+            query: query.into(),
+            engine_name: None,
+            heuristic,
+        }
+    }
+
+    fn open_tab(url: &str, title: &str) -> UrlbarOpenTab {
+        UrlbarOpenTab {
+            url: url.into(),
+            title: title.into(),
+            tab_id: None,
+        }
+    }
+
+    #[test]
+    fn mux_groups_and_limits() {
+        let candidates = vec![
+            search("pizza", true),
+            history("https://pizza.example.com/", "Pizza", 200),
+            history("https://www.pizza.example.com", "Pizza again", 100),
+            history("https://pizza.example.org/", "More pizza", 50),
+            search("pizza near me", false),
+            search("Pizza", false),
+            search("pizza recipes", false),
+        ];
+
+        // By default, the heuristic result comes first, then search
+        // suggestions, then history. Duplicate URLs and search suggestions
+        // for the typed string are dropped.
+        assert_eq!(
+            mux(&UrlbarMuxerConfig::default(), candidates.clone()),
+            vec![
+                search("pizza", true),
+                search("pizza near me", false),
+                search("pizza recipes", false),
+                history("https://pizza.example.com/", "Pizza", 200),
+                history("https://pizza.example.org/", "More pizza", 50),
+            ]
+        );
+
+        // Groups can be reordered and limited.
+        let config = UrlbarMuxerConfig {
+            max_results: 3,
+            groups: vec![
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::History,
+                    max_results: None,
+                },
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::SearchSuggestion,
+                    max_results: Some(1),
+                },
+            ],
+            ..UrlbarMuxerConfig::default()
+        };
+        assert_eq!(
+            mux(&config, candidates.clone()),
+            vec![
+                search("pizza", true),
+                history("https://pizza.example.com/", "Pizza", 200),
+                history("https://pizza.example.org/", "More pizza", 50),
+            ]
+        );
+
+        // Without `heuristic_first`, the heuristic result only shows up where
+        // the groups put it.
+        let config = UrlbarMuxerConfig {
+            heuristic_first: false,
+            dedupe_ignore_www: false,
+            ..config
+        };
+        assert_eq!(
+            mux(&config, candidates),
+            vec![
+                history("https://pizza.example.com/", "Pizza", 200),
+                history("https://www.pizza.example.com", "Pizza again", 100),
+                history("https://pizza.example.org/", "More pizza", 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn mux_only_dedupes_shown_results() {
+        let open_tab_result = |url: &str| UrlbarResult::OpenTab {
+            url: url.into(),
+            title: "Open tab".into(),
+            tab_id: None,
+        };
+
+        // Without `heuristic_first` or a heuristic group, the heuristic
+        // result isn't shown, so it shouldn't hide its duplicates.
+        let config = UrlbarMuxerConfig {
+            heuristic_first: false,
+            groups: vec![UrlbarGroupConfig {
+                group: UrlbarResultGroup::History,
+                max_results: None,
+            }],
+            ..UrlbarMuxerConfig::default()
+        };
+        assert_eq!(
+            mux(
+                &config,
+                vec![
+                    UrlbarResult::VisitUrl {
+                        url: "https://example.com/".into()
+                    },
+                    history("https://example.com/", "Example", 100),
+                ]
+            ),
+            vec![history("https://example.com/", "Example", 100)]
+        );
+
+        // Results that are cut by `max_results` shouldn't hide their
+        // duplicates either.
+        let config = UrlbarMuxerConfig {
+            max_results: 2,
+            groups: vec![
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::History,
+                    max_results: None,
+                },
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::OpenTab,
+                    max_results: None,
+                },
+            ],
+            ..config
+        };
+        assert_eq!(
+            mux(
+                &config,
+                vec![
+                    open_tab_result("https://example.com/x"),
+                    history("https://example.com/x", "X", 300),
+                    history("https://example.com/y", "Y", 200),
+                    history("https://example.com/z", "Z", 100),
+                ]
+            ),
+            vec![
+                history("https://example.com/x", "X", 300),
+                history("https://example.com/y", "Y", 200),
+            ]
+        );
+
+        // Results over their group's limit shouldn't hide their duplicates.
+        let config = UrlbarMuxerConfig {
+            max_results: 10,
+            groups: vec![
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::OpenTab,
+                    max_results: Some(1),
+                },
+                UrlbarGroupConfig {
+                    group: UrlbarResultGroup::History,
+                    max_results: None,
+                },
+            ],
+            ..config
+        };
+        assert_eq!(
+            mux(
+                &config,
+                vec![
+                    open_tab_result("https://example.com/x"),
+                    open_tab_result("https://example.com/y"),
+                    history("https://example.com/y", "Y", 200),
+                ]
+            ),
+            vec![
+                open_tab_result("https://example.com/x"),
+                history("https://example.com/y", "Y", 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn query_without_sources() {
+        let muxer = UrlbarMuxer::new(UrlbarMuxerConfig::default(), None, None);
+        assert_eq!(
+            muxer.query(UrlbarQuery {
+                search_string: "  ".into(),
+                ..UrlbarQuery::default()
+            }),
+            vec![]
+        );
+        assert_eq!(
+            muxer.query(UrlbarQuery {
+                search_string: "example.com".into(),
+                open_tabs: vec![
+                    open_tab("https://example.com/", "Example Domain"),
+                    open_tab("https://example.org/", "Another Example"),
+                ],
+                ..UrlbarQuery::default()
+            }),
+            vec![UrlbarResult::VisitUrl {
+                url: "https://example.com/".into()
+            }]
+        );
+        assert_eq!(
+            muxer.query(UrlbarQuery {
+                search_string: "example domain".into(),
+                open_tabs: vec![
+                    open_tab("https://example.com/", "Example Domain"),
+                    open_tab("https://example.org/", "Another Example"),
+                ],
+                search_suggestions: vec!["example domain names".into()],
+                search_engine_name: Some("Engine".into()),
+            }),
+            vec![
+                UrlbarResult::Search {
+                    query: "example domain".into(),
+                    engine_name: Some("Engine".into()),
+                    heuristic: true,
+                },
+                UrlbarResult::Search {
+                    query: "example domain names".into(),
+                    engine_name: Some("Engine".into()),
+                    heuristic: false,
+                },
+                UrlbarResult::OpenTab {
+                    url: "https://example.com/".into(),
+                    title: "Example Domain".into(),
+                    tab_id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn query_places() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = PlacesApi::new(dir.path().join("places.sqlite")).unwrap();
+        let conn = api.new_connection(ConnectionType::ReadWrite).unwrap();
+        for (url, title) in [
+            ("https://www.mozilla.org/", "Mozilla"),
+            ("https://developer.mozilla.org/", "MDN Web Docs"),
+            ("https://example.com/mozilla", "Example Mozilla page"),
+        ] {
+            conn.apply_observation(
+                VisitObservation::new(Url::parse(url).unwrap())
+                    .with_title(Some(title.to_string()))
+                    .with_visit_type(VisitType::Link),
+            )
+            .unwrap();
+        }
+        conn.bookmarks_insert(InsertableBookmarkItem::Bookmark {
+            b: InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://developer.mozilla.org/").unwrap(),
+                title: Some("MDN Web Docs".into()),
+            },
+        })
+        .unwrap();
+
+        let muxer = UrlbarMuxer::new(UrlbarMuxerConfig::default(), Some(conn), None);
+        let results = muxer.query(UrlbarQuery {
+            search_string: "mozilla".into(),
+            open_tabs: vec![open_tab("http://example.com/mozilla#top", "Open tab")],
+            ..UrlbarQuery::default()
+        });
+
+        // "mozilla" autofills to the origin, which dedupes the history
+        // result for it; the open tab dedupes the other history result.
+        assert_eq!(
+            results[0],
+            UrlbarResult::Autofill {
+                url: "https://www.mozilla.org/".into()
+            }
+        );
+        assert_eq!(
+            results[1],
+            UrlbarResult::OpenTab {
+                url: "http://example.com/mozilla#top".into(),
+                title: "Open tab".into(),
+                tab_id: None,
+            }
+        );
+        assert!(matches!(
+            &results[2],
+            UrlbarResult::Bookmark { url, .. } if url == "https://developer.mozilla.org/"
+        ));
+        assert_eq!(results.len(), 3);
+    }
+}
@@ -0,0 +1,79 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use suggest::Suggestion;
+
+use crate::config::UrlbarResultGroup;
+
+/// A result to show in the address bar.
+#[derive(Clone, Debug, PartialEq, uniffi::Enum)]
+pub enum UrlbarResult {
+    /// A URL from history that the typed string is the beginning of.
+    Autofill {
+        url: String,
+    },
+    /// The URL that the user typed.
+    VisitUrl {
+        url: String,
+    },
+    /// A search for the typed string, if `heuristic` is set, or for a search
+    /// suggestion.
+    Search {
+        query: String,
+        engine_name: Option<String>,
+        heuristic: bool,
+    },
+    /// An open tab to switch to.
+    OpenTab {
+        url: String,
+        title: String,
+        tab_id: Option<String>,
+    },
+    Bookmark {
+        url: String,
+        title: String,
+        frecency: i64,
+    },
+    History {
+        url: String,
+        title: String,
+        frecency: i64,
+    },
+    Suggest {
+        suggestion: Suggestion,
+    },
+}
+
+impl UrlbarResult {
+    /// Returns the group this result belongs to.
+    pub fn group(&self) -> UrlbarResultGroup {
+        match self {
+            Self::Autofill { .. } | Self::VisitUrl { .. } => UrlbarResultGroup::Heuristic,
+            Self::Search { heuristic, .. } => {
+                if *heuristic {
+                    UrlbarResultGroup::Heuristic
+                } else {
+                    UrlbarResultGroup::SearchSuggestion
+                }
+            }
+            Self::OpenTab { .. } => UrlbarResultGroup::OpenTab,
+            Self::Bookmark { .. } => UrlbarResultGroup::Bookmark,
+            Self::History { .. } => UrlbarResultGroup::History,
+            Self::Suggest { .. } => UrlbarResultGroup::Suggest,
+        }
+    }
+
+    /// Returns the URL this result opens, if it's not a search.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Self::Autofill { url }
+            | Self::VisitUrl { url }
+            | Self::OpenTab { url, .. }
+            | Self::Bookmark { url, .. }
+            | Self::History { url, .. } => Some(url),
+            Self::Suggest { suggestion } => suggestion.url(),
+            Self::Search { .. } => None,
+        }
+    }
+}
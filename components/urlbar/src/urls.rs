@@ -0,0 +1,159 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! URL helpers for the heuristic result and deduplication.
+
+use url::{Host, Position, Url};
+
+use crate::config::UrlbarMuxerConfig;
+
+/// Schemes that the user can type to visit a URL.
+const VISITABLE_SCHEMES: [&str; 4] = ["http", "https", "ftp", "file"];
+
+/// Returns the URL to visit if the typed string looks like one, like
+/// `https://example.com/a`, `example.com` or `localhost:8080`.
+pub(crate) fn fixup_url(search_string: &str) -> Option<String> {
+    if search_string.contains(char::is_whitespace) {
+        return None;
+    }
+    if let Ok(url) = Url::parse(search_string) {
+        if VISITABLE_SCHEMES.contains(&url.scheme()) {
+            return Some(url.into());
+        }
+        // `Url` parses `localhost:8080` as a URL with a `localhost` scheme,
+        // so only go on if what follows the "scheme" is a port.
+        let is_port = url
+            .path()
+            .split('/')
+            .next()
+            .is_some_and(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()));
+        if !is_port {
+            return None;
+        }
+    }
+    // Strings without a scheme need a host that looks like a domain name or
+    // an IP address, so that single words and numbers are searched for.
+    let url = Url::parse(&format!("https://{search_string}")).ok()?;
+    let looks_like_host = match url.host()? {
+        Host::Domain(domain) => {
+            domain == "localhost"
+                || domain.rsplit_once('.').is_some_and(|(name, tld)| {
+                    !name.is_empty()
+                        && tld.len() >= 2
+                        && (tld.chars().all(|c| c.is_ascii_alphabetic()) || tld.starts_with("xn--"))
+                })
+        }
+        // `Url` parses shorthand like `1.5` as an IPv4 address, so only
+        // accept addresses that were typed with all four parts.
+        Host::Ipv4(_) => search_string
+            .split(['/', ':', '?', '#'])
+            .next()
+            .is_some_and(|host| host.split('.').count() == 4),
+        Host::Ipv6(_) => true,
+    };
+    looks_like_host.then(|| url.into())
+}
+
+/// Returns the URL in the form used to deduplicate results. URLs that differ
+/// only in the ways that `config` ignores have the same key.
+pub(crate) fn dedupe_key(url: &str, config: &UrlbarMuxerConfig) -> String {
+    let Ok(mut url) = Url::parse(url) else {
+        return url.to_owned();
+    };
+    if url.host_str().is_none() {
+        return url.into();
+    }
+    if config.dedupe_ignore_fragment {
+        url.set_fragment(None);
+    }
+    let host = url.host_str().unwrap_or_default();
+    let scheme = match url.scheme() {
+        "http" | "https" if config.dedupe_ignore_scheme => "http(s)",
+        scheme => scheme,
+    };
+    let host = match host.strip_prefix("www.") {
+        Some(host) if config.dedupe_ignore_www => host,
+        _ => host,
+    };
+    let mut rest = &url[Position::BeforePort..];
+    if config.dedupe_ignore_trailing_slash {
+        rest = rest.strip_suffix('/').unwrap_or(rest);
+    }
+    format!(
+        "{scheme}://{}{host}{rest}",
+        &url[Position::BeforeUsername..Position::BeforeHost]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixup() {
+        for (search_string, expected) in [
+            ("https://example.com/a", Some("https://example.com/a")),
+            ("example.com", Some("https://example.com/")),
+            (
+                "www.example.co.uk/path?q",
+                Some("https://www.example.co.uk/path?q"),
+            ),
+            ("localhost:8080", Some("https://localhost:8080/")),
+            ("192.168.0.1", Some("https://192.168.0.1/")),
+            ("xn--80ak6aa92e.com", Some("https://xn--80ak6aa92e.com/")),
+            ("example", None),
+            ("example.", None),
+            ("1.5", None),
+            ("example.com is great", None),
+            ("about:config", None),
+            ("mailto:someone@example.com", None),
+        ] {
+            assert_eq!(
+                fixup_url(search_string).as_deref(),
+                expected,
+                "fixup_url({search_string:?})"
+            );
+        }
+    }
+
+    #[test]
+    fn dedupe() {
+        let config = UrlbarMuxerConfig::default();
+        for url in [
+            "https://www.example.com/",
+            "http://example.com",
+            "https://example.com/#top",
+        ] {
+            assert_eq!(dedupe_key(url, &config), "http(s)://example.com", "{url}");
+        }
+        assert_ne!(
+            dedupe_key("https://example.com/a", &config),
+            dedupe_key("https://example.com/b", &config)
+        );
+        assert_ne!(
+            dedupe_key("https://example.com/?a", &config),
+            dedupe_key("https://example.com/", &config)
+        );
+        assert_ne!(
+            dedupe_key("https://example.com:8080/", &config),
+            dedupe_key("https://example.com/", &config)
+        );
+
+        let strict = UrlbarMuxerConfig {
+            dedupe_ignore_scheme: false,
+            dedupe_ignore_www: false,
+            dedupe_ignore_trailing_slash: false,
+            dedupe_ignore_fragment: false,
+            ..UrlbarMuxerConfig::default()
+        };
+        assert_eq!(
+            dedupe_key("https://www.example.com/#top", &strict),
+            "https://www.example.com/#top"
+        );
+        assert_ne!(
+            dedupe_key("http://example.com/", &strict),
+            dedupe_key("https://example.com/", &strict)
+        );
+    }
+}
@@ -5,6 +5,10 @@
 #![warn(rust_2018_idioms)]
 
 use anyhow::Result;
+use autofill::credit_card::{
+    credit_card_last_4, credit_card_network, is_valid_credit_card_number,
+    normalize_credit_card_number,
+};
 use autofill::db::{
     models::{address, credit_card},
     store::Store,
@@ -235,35 +239,26 @@ fn run_delete_address(store: &Store, guid: String) -> Result<()> {
 
 fn run_add_credit_card(store: &Store, key: &str) -> Result<()> {
     let encdec = EncryptorDecryptor::new(key)?;
-    let cc_number = prompt_string("cc_number").unwrap_or_default();
+    let cc_number = normalize_credit_card_number(prompt_string("cc_number").unwrap_or_default());
+    if !is_valid_credit_card_number(cc_number.clone()) {
+        println!("***** - that doesn't look like a valid credit-card number");
+    }
     let cc_number_enc = encdec.encrypt(&cc_number)?;
-    let cc_number_last_4 = cc_number_enc.chars().rev().take(4).collect();
     let cc_fields = credit_card::UpdatableCreditCardFields {
         cc_name: prompt_string("cc_name").unwrap_or_default(),
         cc_number_enc,
-        cc_number_last_4,
+        cc_number_last_4: credit_card_last_4(cc_number.clone()),
         cc_exp_month: prompt_usize("cc_exp_month").unwrap_or_default() as i64,
         cc_exp_year: prompt_usize("cc_exp_year").unwrap_or_default() as i64,
-        cc_type: prompt_string("cc_type").unwrap_or_default(),
+        cc_type: credit_card_network(cc_number),
     };
     println!("Making `add_credit_card` api call");
-    let credit_card = Store::add_credit_card(store, cc_fields)?;
+    let credit_card = Store::add_credit_card(store, cc_fields, Some(key.to_string()))?;
 
     println!("Created credit card: {:#?}", credit_card);
     Ok(())
 }
 
-// copied from the impl.
-fn get_last_4(v: &str) -> String {
-    v.chars()
-        .rev()
-        .take(4)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .collect::<String>()
-}
-
 fn run_get_credit_card(store: &Store, guid: String, key: &str) -> Result<()> {
     println!("Getting credit card for guid `{}`", guid);
 
@@ -273,7 +268,7 @@ fn run_get_credit_card(store: &Store, guid: String, key: &str) -> Result<()> {
     let encdec = EncryptorDecryptor::new(key)?;
     let card_number = encdec.decrypt(&credit_card.cc_number_enc)?;
     println!("credit-card number decrypts as: {}", card_number);
-    if get_last_4(&card_number) != credit_card.cc_number_last_4 {
+    if credit_card_last_4(card_number) != credit_card.cc_number_last_4 {
         println!("***** - last 4 digits are wrong!!!");
     }
     Ok(())
@@ -290,7 +285,7 @@ fn run_get_all_credit_cards(store: &Store, key: &str) -> Result<()> {
         println!("{:#?}", card);
         let card_number = encdec.decrypt(&card.cc_number_enc)?;
         println!("credit-card number decrypts as: {}", card_number);
-        if get_last_4(&card_number) != card.cc_number_last_4 {
+        if credit_card_last_4(card_number) != card.cc_number_last_4 {
             println!("***** - last 4 digits are wrong!!!");
         }
     }
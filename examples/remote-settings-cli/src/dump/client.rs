@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::error::*;
+use crate::signatures::{collection_signatures, verify_signature};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use remote_settings::RemoteSettingsServer;
 use serde::de::Error;
@@ -41,6 +42,14 @@ pub struct UpdateResult {
     not_found: Vec<String>,
 }
 
+pub struct CheckResult {
+    stale: Vec<String>,
+    up_to_date: Vec<String>,
+    not_found: Vec<String>,
+    /// Collections whose dump doesn't match its signature or attachments, with the reasons why
+    invalid: Vec<(String, Vec<String>)>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AttachmentMetadata {
     pub location: String,
@@ -219,19 +228,47 @@ impl CollectionDownloader {
         let timestamp = changeset["timestamp"].as_u64().ok_or_else(|| {
             RemoteSettingsError::Json(serde_json::Error::custom("No timestamp in changeset"))
         })?;
+        let data = CollectionData {
+            data: changeset["changes"]
+                .as_array()
+                .unwrap_or(&Vec::new())
+                .to_vec(),
+            timestamp,
+        };
+
+        // Don't package data that the client would reject
+        pb.set_message(format!("Verifying signature for {}", name));
+        self.verify_collection_signature(bucket, &data, &changeset["metadata"])?;
 
         pb.finish_with_message(format!("Downloaded {}", name));
 
-        Ok((
-            collection_name,
-            CollectionData {
-                data: changeset["changes"]
-                    .as_array()
-                    .unwrap_or(&Vec::new())
-                    .to_vec(),
-                timestamp,
-            },
-        ))
+        Ok((collection_name, data))
+    }
+
+    /// Verify collection data against the signatures in a changeset's metadata.
+    ///
+    /// Only one of the signatures needs to be valid, to allow for key rotation.
+    fn verify_collection_signature(
+        &self,
+        bucket: &str,
+        data: &CollectionData,
+        metadata: &Value,
+    ) -> Result<()> {
+        let mut result = Err(RemoteSettingsError::Signature("No signatures found".into()).into());
+        for signature in collection_signatures(metadata)? {
+            let cert_chain_bytes = self.get(&signature.x5u)?.body;
+            result = verify_signature(
+                bucket,
+                data.timestamp,
+                &data.data,
+                &signature,
+                &cert_chain_bytes,
+            );
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
     }
 
     fn get_attachments_base_url(&self) -> Result<String> {
@@ -251,29 +288,7 @@ impl CollectionDownloader {
 
         let response = self.get(&url)?;
         let data = response.body;
-
-        // Verify size
-        if data.len() as u64 != attachment.size {
-            return Err(RemoteSettingsError::Attachment(format!(
-                "Size mismatch for attachment {}: expected {}, got {}",
-                record_id,
-                attachment.size,
-                data.len()
-            ))
-            .into());
-        }
-
-        // Verify hash
-        let mut hasher = Sha256::new();
-        hasher.update(&data);
-        let hash = format!("{:x}", hasher.finalize());
-        if hash != attachment.hash {
-            return Err(RemoteSettingsError::Attachment(format!(
-                "Hash mismatch for attachment {}: expected {}, got {}",
-                record_id, attachment.hash, hash
-            ))
-            .into());
-        }
+        verify_attachment_data(record_id, attachment, &data)?;
 
         pb.set_message(format!("Verified attachment for record {}", record_id));
         Ok(data)
@@ -335,9 +350,71 @@ impl CollectionDownloader {
             return Ok(false);
         }
 
+        // Compare the data itself, in case it was changed or only partially written
+        let data = std::fs::read(&bin_path)?;
+        if let Err(e) = verify_attachment_data(record_id, remote_attachment, &data) {
+            log::debug!(
+                "Attachment data mismatch for {}/{}: {}",
+                bucket,
+                collection,
+                e
+            );
+            return Ok(false);
+        }
+
         Ok(true)
     }
 
+    /// Verify the packaged attachments for a collection's records.
+    ///
+    /// Attachments are only packaged for some records, so this only checks the ones whose data
+    /// file exists.  Returns a description of each problem that it finds.
+    fn verify_local_attachments(
+        &self,
+        bucket: &str,
+        collection: &str,
+        records: &[Value],
+    ) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+        for record in records {
+            let Some(attachment) = record.get("attachment") else {
+                continue;
+            };
+            let record_id = record["id"].as_str().ok_or_else(|| {
+                RemoteSettingsError::Json(serde_json::Error::custom("No record id"))
+            })?;
+            let attachment: AttachmentMetadata = serde_json::from_value(attachment.clone())?;
+            let (bin_path, meta_path) = self.get_attachment_paths(bucket, collection, record_id);
+            if !bin_path.exists() {
+                continue;
+            }
+            if !meta_path.exists() {
+                problems.push(format!("Missing metadata for attachment {}", record_id));
+                continue;
+            }
+            let local_attachment: AttachmentMetadata =
+                serde_json::from_str(&std::fs::read_to_string(&meta_path)?)?;
+            if local_attachment.hash != attachment.hash || local_attachment.size != attachment.size
+            {
+                problems.push(format!(
+                    "Metadata mismatch for attachment {}: record has hash={}, size={}, metadata file has hash={}, size={}",
+                    record_id,
+                    attachment.hash,
+                    attachment.size,
+                    local_attachment.hash,
+                    local_attachment.size
+                ));
+                continue;
+            }
+            if let Err(e) =
+                verify_attachment_data(record_id, &attachment, &std::fs::read(&bin_path)?)
+            {
+                problems.push(e.to_string());
+            }
+        }
+        Ok(problems)
+    }
+
     fn download_attachments_bundle(
         &self,
         bucket: &str,
@@ -547,6 +624,108 @@ impl CollectionDownloader {
         })
     }
 
+    /// Check every local dump against the server, without changing any files.
+    ///
+    /// Returns an error if any dump is stale or invalid, so that this can be used in CI.
+    pub fn check(&self) -> Result<()> {
+        let result = self.check_all()?;
+
+        println!("\nCheck summary:");
+        if !result.up_to_date.is_empty() {
+            println!("Collections up to date:");
+            for collection in &result.up_to_date {
+                println!("  - {}", collection);
+            }
+        }
+
+        if !result.stale.is_empty() {
+            println!("Stale collections:");
+            for collection in &result.stale {
+                println!("  - {}", collection);
+            }
+        }
+
+        if !result.not_found.is_empty() {
+            println!("Collections not found on remote:");
+            for collection in &result.not_found {
+                println!("  - {}", collection);
+            }
+        }
+
+        if !result.invalid.is_empty() {
+            println!("Invalid collections:");
+            for (collection, problems) in &result.invalid {
+                println!("  - {}", collection);
+                for problem in problems {
+                    println!("    - {}", problem);
+                }
+            }
+        }
+
+        if !result.stale.is_empty() || !result.invalid.is_empty() {
+            anyhow::bail!(
+                "{} stale and {} invalid collections, run `cargo remote-settings dump-sync` to update them",
+                result.stale.len(),
+                result.invalid.len()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn check_all(&self) -> Result<CheckResult> {
+        let local_collections = self.scan_local_dumps()?;
+        let remote_timestamps = self.fetch_timestamps()?;
+        let mut result = CheckResult {
+            stale: vec![],
+            up_to_date: vec![],
+            not_found: vec![],
+            invalid: vec![],
+        };
+
+        let mut collection_keys = local_collections.into_iter().collect::<Vec<_>>();
+        collection_keys.sort();
+        for (collection_key, (bucket, local_timestamp)) in collection_keys {
+            let Some(&remote_timestamp) = remote_timestamps.get(&collection_key) else {
+                result.not_found.push(collection_key);
+                continue;
+            };
+            if local_timestamp < remote_timestamp {
+                result.stale.push(collection_key);
+                continue;
+            }
+
+            let name = collection_key
+                .split_once('/')
+                .map(|(_, name)| name)
+                .ok_or_else(|| RemoteSettingsError::Path("Invalid collection path".into()))?;
+            let dump_path = self
+                .output_dir
+                .join(DUMPS_DIR)
+                .join(&bucket)
+                .join(format!("{}.json", name));
+            let data: CollectionData = serde_json::from_str(&std::fs::read_to_string(&dump_path)?)?;
+
+            let mut problems = self.verify_local_attachments(&bucket, name, &data.data)?;
+            // The server only has signatures for its current data, which is the same as ours.
+            let url = format!(
+                "{}/buckets/{}/collections/{}/changeset?_expected={}",
+                self.url, bucket, name, remote_timestamp
+            );
+            let changeset: Value = self.get(&url)?.json()?;
+            if let Err(e) = self.verify_collection_signature(&bucket, &data, &changeset["metadata"])
+            {
+                problems.push(format!("Invalid signature: {}", e));
+            }
+
+            if problems.is_empty() {
+                result.up_to_date.push(collection_key);
+            } else {
+                result.invalid.push((collection_key, problems));
+            }
+        }
+        Ok(result)
+    }
+
     pub fn download_single(&self, bucket: &str, collection_name: &str) -> Result<()> {
         std::fs::create_dir_all(self.output_dir.join(DUMPS_DIR))?;
 
@@ -646,3 +825,32 @@ impl CollectionDownloader {
         Ok(Request::get(url).send()?)
     }
 }
+
+/// Verify attachment data against the size and hash from its metadata
+fn verify_attachment_data(
+    record_id: &str,
+    attachment: &AttachmentMetadata,
+    data: &[u8],
+) -> Result<()> {
+    if data.len() as u64 != attachment.size {
+        return Err(RemoteSettingsError::Attachment(format!(
+            "Size mismatch for attachment {}: expected {}, got {}",
+            record_id,
+            attachment.size,
+            data.len()
+        ))
+        .into());
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let hash = format!("{:x}", hasher.finalize());
+    if hash != attachment.hash {
+        return Err(RemoteSettingsError::Attachment(format!(
+            "Hash mismatch for attachment {}: expected {}, got {}",
+            record_id, attachment.hash, hash
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -18,4 +18,6 @@ pub enum RemoteSettingsError {
     Path(String),
     #[error("Attachment error: {0}")]
     Attachment(String),
+    #[error("Signature error: {0}")]
+    Signature(String),
 }
@@ -4,3 +4,4 @@
 
 pub mod client;
 pub(crate) mod error;
+pub(crate) mod signatures;
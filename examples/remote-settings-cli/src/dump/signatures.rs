@@ -0,0 +1,64 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use remote_settings::signatures::{self, ROOT_CERT_SHA256_HASH_PROD};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A collection signature, from the `metadata.signatures` list of a changeset
+pub struct CollectionSignature {
+    pub signature: String,
+    pub x5u: String,
+}
+
+/// Get the signatures from the metadata of a changeset
+pub fn collection_signatures(metadata: &Value) -> Result<Vec<CollectionSignature>> {
+    let signatures = metadata["signatures"]
+        .as_array()
+        .ok_or_else(|| RemoteSettingsError::Signature("No signatures in metadata".into()))?;
+    signatures
+        .iter()
+        .map(
+            |signature| match (signature["signature"].as_str(), signature["x5u"].as_str()) {
+                (Some(signature), Some(x5u)) => Ok(CollectionSignature {
+                    signature: signature.to_string(),
+                    x5u: x5u.to_string(),
+                }),
+                _ => {
+                    Err(RemoteSettingsError::Signature("Invalid signature metadata".into()).into())
+                }
+            },
+        )
+        .collect()
+}
+
+/// Verify the records of a collection against one of its signatures.
+///
+/// This uses the signature verification from `remote_settings`, on the raw JSON records from a
+/// dump.  Dumps are only used with the production server, so the certificate chain must end at
+/// its root.
+pub fn verify_signature(
+    bucket: &str,
+    timestamp: u64,
+    records: &[Value],
+    signature: &CollectionSignature,
+    cert_chain_bytes: &[u8],
+) -> Result<()> {
+    let epoch_seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Current date before unix epoch.")
+        .as_secs();
+    signatures::verify_signature(
+        timestamp,
+        records,
+        signature.signature.as_bytes(),
+        cert_chain_bytes,
+        epoch_seconds,
+        ROOT_CERT_SHA256_HASH_PROD,
+        &signatures::signer_name(bucket),
+    )
+    .map_err(|e| RemoteSettingsError::Signature(e.to_string()))?;
+    Ok(())
+}
@@ -48,6 +48,8 @@ enum Commands {
         sync_if_empty: bool,
     },
     /// Download and combine all remote settings collections
+    ///
+    /// Collection signatures and attachment hashes are verified before any files are written.
     DumpSync {
         /// Root path of the repository
         #[arg(short, long, default_value = ".")]
@@ -57,6 +59,16 @@ enum Commands {
         #[arg(long, default_value_t = false)]
         dry_run: bool,
     },
+    /// Check that all remote settings collections are up to date and valid, without writing any
+    /// files
+    ///
+    /// This fails if any dump is older than the server's data, doesn't match the collection
+    /// signature, or has attachments that don't match their hash and size.
+    DumpCheck {
+        /// Root path of the repository
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+    },
     /// Download a single collection to the dumps directory
     DumpGet {
         /// Bucket name
@@ -96,6 +108,10 @@ fn main() -> Result<()> {
             let downloader = CollectionDownloader::new(path);
             downloader.run(dry_run)
         }
+        Commands::DumpCheck { path } => {
+            let downloader = CollectionDownloader::new(path);
+            downloader.check()
+        }
         Commands::DumpGet {
             bucket,
             collection_name,
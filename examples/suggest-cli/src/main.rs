@@ -9,8 +9,8 @@ use clap::{Parser, Subcommand, ValueEnum};
 
 use remote_settings::{RemoteSettingsConfig2, RemoteSettingsServer, RemoteSettingsService};
 use suggest::{
-    AmpMatchingStrategy, SuggestIngestionConstraints, SuggestStore, SuggestStoreBuilder,
-    SuggestionProvider, SuggestionProviderConstraints, SuggestionQuery,
+    AmpMatchingStrategy, SuggestCandidateOutcome, SuggestIngestionConstraints, SuggestStore,
+    SuggestStoreBuilder, SuggestionProvider, SuggestionProviderConstraints, SuggestionQuery,
 };
 
 static DB_FILENAME: &str = "suggest.db";
@@ -59,6 +59,18 @@ enum Commands {
         #[clap(long, short)]
         amp_matching_strategy: Option<AmpMatchingStrategyArg>,
     },
+    /// Explain why a query does or doesn't return suggestions
+    Explain {
+        #[clap(long, short)]
+        provider: Option<SuggestionProviderArg>,
+        /// Input to search
+        input: String,
+        #[clap(long, short)]
+        amp_matching_strategy: Option<AmpMatchingStrategyArg>,
+        /// Maximum number of suggestions to return
+        #[clap(long, short)]
+        limit: Option<i32>,
+    },
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -134,6 +146,12 @@ fn main() -> Result<()> {
             amp_matching_strategy,
             cli.verbose,
         ),
+        Commands::Explain {
+            provider,
+            input,
+            amp_matching_strategy,
+            limit,
+        } => explain(&store, provider, input, amp_matching_strategy, limit),
     };
     Ok(())
 }
@@ -215,18 +233,7 @@ fn query(
     amp_matching_strategy: Option<AmpMatchingStrategyArg>,
     verbose: bool,
 ) {
-    let query = SuggestionQuery {
-        providers: match provider {
-            Some(provider) => vec![provider.into()],
-            None => SuggestionProvider::all().to_vec(),
-        },
-        keyword: input,
-        provider_constraints: Some(SuggestionProviderConstraints {
-            amp_alternative_matching: amp_matching_strategy.map(Into::into),
-            ..SuggestionProviderConstraints::default()
-        }),
-        ..SuggestionQuery::default()
-    };
+    let query = build_query(provider, input, amp_matching_strategy);
     let mut results = store
         .query_with_metrics(query)
         .unwrap_or_else(|e| panic!("Error querying store: {e}"));
@@ -265,6 +272,89 @@ fn query(
     }
 }
 
+fn explain(
+    store: &SuggestStore,
+    provider: Option<SuggestionProviderArg>,
+    input: String,
+    amp_matching_strategy: Option<AmpMatchingStrategyArg>,
+    limit: Option<i32>,
+) {
+    let query = SuggestionQuery {
+        limit,
+        ..build_query(provider, input, amp_matching_strategy)
+    };
+    let explanation = store
+        .explain_query(query)
+        .unwrap_or_else(|e| panic!("Error explaining query: {e}"));
+    for provider in explanation.providers {
+        print_header(format!("{} ({:?})", provider.provider, provider.strategy));
+        println!("Keywords: {}", provider.keywords.join(", "));
+        println!("Records:  {}", provider.record_ids.join(", "));
+        if provider.candidates.is_empty() {
+            println!("No candidates");
+        }
+        for candidate in provider.candidates {
+            let url = candidate.url.as_deref().unwrap_or("[no-url]");
+            println!("* {} ({url})", candidate.title);
+            println!(
+                "    keyword: {}  record: {}  score: {:.4}",
+                candidate.keyword.as_deref().unwrap_or("[unknown]"),
+                candidate.record_id.as_deref().unwrap_or("[unknown]"),
+                candidate.score,
+            );
+            println!("    {}", describe_outcome(&candidate.outcome));
+        }
+    }
+    print_header(format!("{} Results", explanation.suggestions.len()));
+    for suggestion in explanation.suggestions {
+        let url = suggestion.url().unwrap_or("[no-url]");
+        println!(
+            "* {} ({url}) (score: {:.4})",
+            suggestion.title(),
+            suggestion.score()
+        );
+    }
+}
+
+fn describe_outcome(outcome: &SuggestCandidateOutcome) -> String {
+    match outcome {
+        SuggestCandidateOutcome::Returned { position } => {
+            format!("returned at position {position}")
+        }
+        SuggestCandidateOutcome::Dismissed => "dropped: dismissed".to_string(),
+        SuggestCandidateOutcome::NotMatched => {
+            "dropped: ruled out by matching rules or constraints".to_string()
+        }
+        SuggestCandidateOutcome::ShowLessFrequently { min_keyword_length } => {
+            format!("dropped: shown less frequently (needs {min_keyword_length}+ characters)")
+        }
+        SuggestCandidateOutcome::BelowThreshold { min_keyword_length } => {
+            format!("dropped: below the keyword length threshold ({min_keyword_length} characters)")
+        }
+        SuggestCandidateOutcome::ImpressionCapped => "dropped: impression cap reached".to_string(),
+        SuggestCandidateOutcome::OverLimit => "dropped: over the limit".to_string(),
+    }
+}
+
+fn build_query(
+    provider: Option<SuggestionProviderArg>,
+    input: String,
+    amp_matching_strategy: Option<AmpMatchingStrategyArg>,
+) -> SuggestionQuery {
+    SuggestionQuery {
+        providers: match provider {
+            Some(provider) => vec![provider.into()],
+            None => SuggestionProvider::all().to_vec(),
+        },
+        keyword: input,
+        provider_constraints: Some(SuggestionProviderConstraints {
+            amp_alternative_matching: amp_matching_strategy.map(Into::into),
+            ..SuggestionProviderConstraints::default()
+        }),
+        ..SuggestionQuery::default()
+    }
+}
+
 fn print_header(msg: impl Into<String>) {
     let mut msg = msg.into();
     if msg.len() % 2 == 1 {
@@ -21,6 +21,7 @@ extern int MOZ_EXPORT ffi_nimbus_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_places_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_push_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_relay_uniffi_contract_version();
+extern int MOZ_EXPORT ffi_relevancy_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_remote_settings_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_rust_log_forwarder_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_search_uniffi_contract_version();
@@ -28,6 +29,7 @@ extern int MOZ_EXPORT ffi_suggest_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_sync15_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_sync_manager_uniffi_contract_version();
 extern int MOZ_EXPORT ffi_tabs_uniffi_contract_version();
+extern int MOZ_EXPORT ffi_urlbar_uniffi_contract_version();
 
 // far out, this is crazy - without this, only the search _NAMESPACE meta comes in,
 // meaning uniffi ends up generating a completely empty kotlin module for search.
@@ -49,6 +51,7 @@ void _local_megazord_dummy_symbol() {
     ffi_places_uniffi_contract_version();
     ffi_push_uniffi_contract_version();
     ffi_relay_uniffi_contract_version();
+    ffi_relevancy_uniffi_contract_version();
     ffi_remote_settings_uniffi_contract_version();
     ffi_rust_log_forwarder_uniffi_contract_version();
     ffi_search_uniffi_contract_version();
@@ -56,5 +59,6 @@ void _local_megazord_dummy_symbol() {
     ffi_sync15_uniffi_contract_version();
     ffi_sync_manager_uniffi_contract_version();
     ffi_tabs_uniffi_contract_version();
+    ffi_urlbar_uniffi_contract_version();
     uniffi_search_checksum_constructor_searchengineselector_new();
 }
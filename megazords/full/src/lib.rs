@@ -21,6 +21,7 @@ pub use nimbus;
 pub use places;
 pub use push;
 pub use relay;
+pub use relevancy;
 pub use remote_settings;
 pub use rust_log_forwarder;
 pub use search;
@@ -28,6 +29,7 @@ pub use suggest;
 pub use sync_manager;
 pub use tabs;
 pub use tracing_support;
+pub use urlbar;
 pub use viaduct;
 // NOTE if you add or remove crates above here, please make a corresponding change in ../fenix-dylib/megazord_stub.c
 
@@ -19,6 +19,7 @@ pub use nimbus;
 pub use places;
 pub use push;
 pub use relay;
+pub use relevancy;
 pub use remote_settings;
 pub use rust_log_forwarder;
 pub use search;
@@ -27,6 +28,7 @@ pub use sync15;
 pub use sync_manager;
 pub use tabs;
 pub use tracing_support;
+pub use urlbar;
 pub use viaduct;
 pub use viaduct_hyper;
 pub use viaduct_reqwest;
@@ -84,7 +84,7 @@ pub fn add_credit_card(
     s: &AutofillStore,
     c: UpdatableCreditCardFields,
 ) -> AutofillResult<CreditCard> {
-    let id = s.add_credit_card(c)?.guid;
+    let id = s.add_credit_card(c, None)?.guid;
     Ok(s.get_credit_card(id).expect("Credit card has been added"))
 }
 
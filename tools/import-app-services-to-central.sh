@@ -143,6 +143,7 @@ sed -e 's|  "security/mls/mls_gk",|  "security/mls/mls_gk",\
   "services/app-services/components/sync_manager",\
   "services/app-services/components/sync15",\
   "services/app-services/components/tabs",\
+  "services/app-services/components/urlbar",\
   "services/app-services/components/viaduct",\
   "services/app-services/components/webext-storage",\
   "services/app-services/components/webext-storage/ffi",\